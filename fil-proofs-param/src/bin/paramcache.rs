@@ -240,8 +240,12 @@ pub fn main() {
 
     // If no sector-sizes were given provided via. the CLI, display an interactive menu. Otherwise,
     // filter out invalid CLI sector-size arguments.
+    let published_sector_sizes = PUBLISHED_SECTOR_SIZES
+        .read()
+        .expect("PUBLISHED_SECTOR_SIZES poisoned")
+        .clone();
     if opts.sector_sizes.is_empty() {
-        let sector_size_strings: Vec<String> = PUBLISHED_SECTOR_SIZES
+        let sector_size_strings: Vec<String> = published_sector_sizes
             .iter()
             .map(|sector_size| {
                 let human_size = sector_size
@@ -261,11 +265,11 @@ pub fn main() {
             .interact()
             .expect("interaction failed")
             .into_iter()
-            .map(|i| PUBLISHED_SECTOR_SIZES[i])
+            .map(|i| published_sector_sizes[i])
             .collect();
     } else {
         opts.sector_sizes.retain(|size| {
-            if PUBLISHED_SECTOR_SIZES.contains(size) {
+            if published_sector_sizes.contains(size) {
                 true
             } else {
                 let human_size = size
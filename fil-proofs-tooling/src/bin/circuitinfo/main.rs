@@ -162,9 +162,14 @@ pub fn main() {
 
     let opts = Opt::from_args();
 
+    let published_sector_sizes = PUBLISHED_SECTOR_SIZES
+        .read()
+        .expect("PUBLISHED_SECTOR_SIZES poisoned")
+        .clone();
+
     // Display interactive menu if no sizes are given
     let sizes: Vec<u64> = if opts.constraints_for_sector_sizes.is_empty() {
-        let sector_sizes = PUBLISHED_SECTOR_SIZES
+        let sector_sizes = published_sector_sizes
             .iter()
             .map(|sector_size| {
                 // Right aligning the numbers makes them easier to read
@@ -184,7 +189,7 @@ pub fn main() {
             .expect("interaction failed");
 
         // Extract the selected sizes
-        PUBLISHED_SECTOR_SIZES
+        published_sector_sizes
             .iter()
             .enumerate()
             .filter_map(|(index, size)| {
@@ -199,7 +204,7 @@ pub fn main() {
         opts.constraints_for_sector_sizes
             .into_iter()
             .filter(|size| {
-                if PUBLISHED_SECTOR_SIZES.contains(size) {
+                if published_sector_sizes.contains(size) {
                     return true;
                 }
 
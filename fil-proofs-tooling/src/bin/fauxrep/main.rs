@@ -0,0 +1,214 @@
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use clap::{value_t, values_t, App, Arg};
+use filecoin_proofs::{
+    fauxrep_with_pieces, materialized_bytes, with_shape, Commitment, FauxPiece, FauxrepOutput,
+    MerkleTreeTrait, PieceInfo, PoRepConfig, PoRepProofPartitions, SectorSize, UnpaddedBytesAmount,
+    POREP_PARTITIONS,
+};
+use rayon::prelude::*;
+use serde::Serialize;
+use storage_proofs_core::api_version::ApiVersion;
+
+/// What a devnet needs to know about a faux sector, with hex encoded commitments.
+#[derive(Serialize)]
+struct SectorSummary {
+    sector: u64,
+    path: PathBuf,
+    comm_r: String,
+    comm_d: String,
+    pieces: Vec<PieceSummary>,
+    /// Bytes of the unsealed file which take up disk space, the rest are holes.
+    unsealed_materialized_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct PieceSummary {
+    comm_p: String,
+    size: u64,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn commitment_from_hex(hex: &str) -> Result<Commitment> {
+    ensure!(hex.len() == 64, "commitment must be 64 hex digits: {}", hex);
+    let mut commitment = [0u8; 32];
+    for (i, byte) in commitment.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .with_context(|| format!("invalid commitment: {}", hex))?;
+    }
+    Ok(commitment)
+}
+
+/// Parses `file:<path>` or `commp:<hex commitment>:<unpadded size>`.
+fn parse_piece(spec: &str) -> Result<FauxPiece> {
+    if let Some(path) = spec.strip_prefix("file:") {
+        return Ok(FauxPiece::File(PathBuf::from(path)));
+    }
+    if let Some(info) = spec.strip_prefix("commp:") {
+        let mut parts = info.splitn(2, ':');
+        let commitment = commitment_from_hex(parts.next().unwrap_or_default())?;
+        let size = parts
+            .next()
+            .ok_or_else(|| anyhow!("missing piece size: {}", spec))?
+            .parse()
+            .with_context(|| format!("invalid piece size: {}", spec))?;
+        return Ok(FauxPiece::Info(PieceInfo::new(
+            commitment,
+            UnpaddedBytesAmount(size),
+        )?));
+    }
+    Err(anyhow!(
+        "piece must be file:<path> or commp:<commitment>:<size>: {}",
+        spec
+    ))
+}
+
+/// Seed of the `sector`th sector generated from `seed`.
+fn sector_seed(seed: u64, sector: u64) -> [u8; 32] {
+    let mut sector_seed = [0u8; 32];
+    sector_seed[..8].copy_from_slice(&seed.to_le_bytes());
+    sector_seed[8..16].copy_from_slice(&sector.to_le_bytes());
+    sector_seed
+}
+
+fn fauxrep_sector<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    seed: &[u8; 32],
+    sector_dir: &Path,
+    pieces: &[FauxPiece],
+) -> Result<FauxrepOutput> {
+    let cache_dir = sector_dir.join("cache");
+    create_dir_all(&cache_dir)
+        .with_context(|| format!("could not create {}", cache_dir.display()))?;
+
+    fauxrep_with_pieces::<_, _, _, Tree>(
+        porep_config,
+        seed,
+        &cache_dir,
+        sector_dir.join("unsealed"),
+        sector_dir.join("sealed"),
+        pieces,
+    )
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let matches = App::new("fauxrep")
+        .version("0.1")
+        .about(
+            "Deterministically creates faux sealed sectors with real pieces and comm_d. \
+             Every sector is written to <output-dir>/<sector>/ as unsealed, sealed and cache/, \
+             and summarized as one line of JSON on stdout.",
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .default_value("2048")
+                .help("The sector size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .default_value("0")
+                .help("The seed all sectors are generated from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .default_value("1")
+                .help("The number of sectors to create")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output-dir")
+                .long("output-dir")
+                .required(true)
+                .help("The directory for the sectors")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("piece")
+                .long("piece")
+                .help(
+                    "A piece of every sector, in sector order: file:<path> for a piece file, \
+                     commp:<hex commitment>:<unpadded size> for a piece with generated data",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let sector_size = value_t!(matches, "size", u64)?;
+    let seed = value_t!(matches, "seed", u64)?;
+    let count = value_t!(matches, "count", u64)?;
+    let output_dir = PathBuf::from(value_t!(matches, "output-dir", String)?);
+    let pieces = if matches.is_present("piece") {
+        values_t!(matches, "piece", String)?
+            .iter()
+            .map(|spec| parse_piece(spec))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+
+    let partitions = *POREP_PARTITIONS
+        .read()
+        .expect("POREP_PARTITIONS poisoned")
+        .get(&sector_size)
+        .ok_or_else(|| anyhow!("unsupported sector size: {}", sector_size))?;
+    // Faux sectors do not depend on the PoRep id or API version.
+    let porep_config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(partitions),
+        porep_id: [0; 32],
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let summaries = (0..count)
+        .into_par_iter()
+        .map(|sector| {
+            let sector_dir = output_dir.join(sector.to_string());
+            let output = with_shape!(
+                sector_size,
+                fauxrep_sector,
+                porep_config,
+                &sector_seed(seed, sector),
+                &sector_dir,
+                &pieces
+            )
+            .with_context(|| format!("failed to create sector {}", sector))?;
+            let unsealed_materialized_bytes = materialized_bytes(sector_dir.join("unsealed"))?;
+
+            Ok(SectorSummary {
+                sector,
+                path: sector_dir,
+                comm_r: to_hex(&output.comm_r),
+                comm_d: to_hex(&output.comm_d),
+                pieces: output
+                    .piece_infos
+                    .iter()
+                    .map(|piece_info| PieceSummary {
+                        comm_p: to_hex(&piece_info.commitment),
+                        size: u64::from(piece_info.size),
+                    })
+                    .collect(),
+                unsealed_materialized_bytes,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for summary in summaries {
+        println!("{}", serde_json::to_string(&summary)?);
+    }
+
+    Ok(())
+}
@@ -1,30 +1,26 @@
-use std::fs::{self, create_dir_all, remove_dir_all, OpenOptions};
+use std::fs::{create_dir_all, OpenOptions};
 use std::path::{Path, PathBuf};
 
-use anyhow::{ensure, Context, Result};
-use bincode::deserialize;
+use anyhow::{Context, Result};
 use clap::{value_t, App, Arg, SubCommand};
 use filecoin_hashers::Hasher;
 use filecoin_proofs::{
-    is_sector_shape_base, is_sector_shape_sub2, is_sector_shape_sub4, is_sector_shape_sub8, is_sector_shape_top2,
-    with_shape, DefaultTreeDomain, PersistentAux, SectorShapeBase, SectorShapeSub2, SectorShapeSub4,
-    SectorShapeSub8, SectorShapeTop2, OCT_ARITY,
+    check_tree_r_last, with_shape, DefaultTreeDomain, SectorShapeBase, SectorSize, TreeRLastReport,
+    OCT_ARITY,
 };
 use generic_array::typenum::Unsigned;
 use memmap::MmapOptions;
 use merkletree::{
     merkle::get_merkle_tree_len,
-    store::{ExternalReader, ReplicaConfig, Store, StoreConfig},
+    store::{ReplicaConfig, StoreConfig},
 };
 use storage_proofs_core::{
     cache_key::CacheKey,
     merkle::{
-        create_lc_tree, get_base_tree_count, split_config_and_replica, LCStore, LCTree,
-        MerkleTreeTrait,
+        create_lc_tree, get_base_tree_count, split_config_and_replica, LCTree, MerkleTreeTrait,
     },
     util::{default_rows_to_discard, NODE_SIZE},
 };
-use tempfile::tempdir;
 
 fn get_tree_r_info(
     sector_size: usize,
@@ -59,71 +55,6 @@ fn get_tree_r_info(
     Ok((tree_count, base_tree_leafs, configs, replica_config))
 }
 
-fn get_tree_r_last_root(
-    base_tree_leafs: usize,
-    sector_size: u64,
-    configs: &[StoreConfig],
-    replica_config: &ReplicaConfig,
-) -> Result<DefaultTreeDomain> {
-    let base_tree_len = get_merkle_tree_len(base_tree_leafs, OCT_ARITY)?;
-    let tree_r_last_root = if is_sector_shape_base(sector_size) {
-        ensure!(configs.len() == 1, "Invalid tree-shape specified");
-        let store = LCStore::<DefaultTreeDomain>::new_from_disk_with_reader(
-            base_tree_len,
-            OCT_ARITY,
-            &configs[0],
-            ExternalReader::new_from_path(&replica_config.path)?,
-        )?;
-
-        let tree_r_last = SectorShapeBase::from_data_store(store, base_tree_leafs)?;
-        tree_r_last.root()
-    } else if is_sector_shape_sub2(sector_size) {
-        let tree_r_last = SectorShapeSub2::from_store_configs_and_replica(
-            base_tree_leafs,
-            configs,
-            replica_config,
-        )?;
-        tree_r_last.root()
-    } else if is_sector_shape_sub4(sector_size) {
-        let tree_r_last = SectorShapeSub4::from_store_configs_and_replica(
-            base_tree_leafs,
-            configs,
-            replica_config,
-        )?;
-        tree_r_last.root()
-    }else if is_sector_shape_sub8(sector_size) {
-        let tree_r_last = SectorShapeSub8::from_store_configs_and_replica(
-            base_tree_leafs,
-            configs,
-            replica_config,
-        )?;
-        tree_r_last.root()
-    } else if is_sector_shape_top2(sector_size) {
-        let tree_r_last = SectorShapeTop2::from_sub_tree_store_configs_and_replica(
-            base_tree_leafs,
-            configs,
-            replica_config,
-        )?;
-        tree_r_last.root()
-    } else {
-        panic!("Unsupported sector size");
-    };
-
-    Ok(tree_r_last_root)
-}
-
-fn get_persistent_aux(cache: &Path) -> Result<PersistentAux<DefaultTreeDomain>> {
-    let p_aux: PersistentAux<DefaultTreeDomain> = {
-        let p_aux_path = cache.join(CacheKey::PAux.to_string());
-        let p_aux_bytes = fs::read(&p_aux_path)
-            .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
-
-        deserialize(&p_aux_bytes)
-    }?;
-
-    Ok(p_aux)
-}
-
 fn build_tree_r_last<Tree: MerkleTreeTrait>(
     sector_size: usize,
     cache: &Path,
@@ -188,105 +119,57 @@ fn run_rebuild(
     )
 }
 
-fn run_inspect(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<()> {
-    let (_tree_count, base_tree_leafs, configs, replica_config) =
-        get_tree_r_info(sector_size, cache, replica_path)?;
-    let tree_r_last_root = get_tree_r_last_root(
-        base_tree_leafs,
+fn run_check(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<TreeRLastReport> {
+    with_shape!(
         sector_size as u64,
-        &configs,
-        &replica_config,
-    )?;
-    let p_aux = get_persistent_aux(cache)?;
+        check_tree_r_last,
+        SectorSize(sector_size as u64),
+        cache,
+        replica_path
+    )
+}
 
-    println!("CommRLast from p_aux: {:?}", p_aux.comm_r_last);
-    println!(
-        "CommRLast [cached tree_r_last root]: {:?}",
-        tree_r_last_root
-    );
-    let status = if tree_r_last_root == p_aux.comm_r_last {
+fn match_str<T: PartialEq>(a: T, b: T) -> &'static str {
+    if a == b {
         "MATCH"
     } else {
         "MISMATCH"
-    };
-    println!("Cached inspection shows a {} of CommRLast", status);
-
-    Ok(())
-}
-
-fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<()> {
-    let (tree_count, base_tree_leafs, configs, replica_config) =
-        get_tree_r_info(sector_size, cache, replica_path)?;
-    let base_tree_len = get_merkle_tree_len(base_tree_leafs, OCT_ARITY)?;
-
-    let match_str = |a, b| -> &str {
-        if a == b {
-            "MATCH"
-        } else {
-            "MISMATCH"
-        }
-    };
-
-    // First, read the roots from the cached trees on disk
-    let mut cached_base_tree_roots: Vec<DefaultTreeDomain> = Vec::with_capacity(tree_count);
-    for (i, config) in configs.iter().enumerate().take(tree_count) {
-        let store = LCStore::new_from_disk_with_reader(
-            base_tree_len,
-            OCT_ARITY,
-            config,
-            ExternalReader::new_from_config(&replica_config, i)?,
-        )?;
-        cached_base_tree_roots.push(store.last()?);
     }
+}
 
-    // Retrieve the tree_r_last root from the cached trees on disk.
-    let tree_r_last_root = get_tree_r_last_root(
-        base_tree_leafs,
-        sector_size as u64,
-        &configs,
-        &replica_config,
-    )?;
-
-    // Read comm_r_last from the persistent aux in the cache dir
-    let p_aux: PersistentAux<DefaultTreeDomain> = {
-        let p_aux_path = cache.join(CacheKey::PAux.to_string());
-        let p_aux_bytes = fs::read(&p_aux_path)
-            .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
-
-        deserialize(&p_aux_bytes)
-    }?;
-
-    // Rebuild each of the tree_r_last base trees (in a new temp dir so as not to interfere
-    // with any existing ones on disk) and check if the roots match what's cached on disk
-    let tmp_dir = tempdir().unwrap();
-    let tmp_path = tmp_dir.path();
-    create_dir_all(&tmp_path)?;
+fn run_inspect(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<()> {
+    let report = run_check(sector_size, cache, replica_path)?;
 
-    let (rebuilt_tree_r_last_root, rebuilt_base_tree_roots) =
-        run_rebuild(sector_size, tmp_path, replica_path)?;
+    println!("CommRLast from p_aux: {:?}", report.p_aux_comm_r_last);
+    println!(
+        "CommRLast [cached tree_r_last root]: {:?}",
+        report.cached_comm_r_last
+    );
+    println!(
+        "Cached inspection shows a {} of CommRLast",
+        match_str(report.cached_comm_r_last, report.p_aux_comm_r_last)
+    );
 
-    remove_dir_all(&tmp_path)?;
+    Ok(())
+}
 
-    let status = match_str(tree_r_last_root, p_aux.comm_r_last);
-    let rebuilt_status = match_str(rebuilt_tree_r_last_root, p_aux.comm_r_last);
+fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<()> {
+    let report = run_check(sector_size, cache, replica_path)?;
+    let tree_count = report.roots.len();
 
     println!();
-    for (i, (cached_root, rebuilt_root)) in cached_base_tree_roots
-        .iter()
-        .zip(rebuilt_base_tree_roots)
-        .enumerate()
-    {
+    for root in &report.roots {
         println!(
             "tree_r_last {}/{} inspection shows a {} of base tree root {:?}",
-            i + 1,
+            root.index + 1,
             tree_count,
-            match_str(*cached_root, rebuilt_root),
-            rebuilt_root
+            match_str(root.cached, Some(root.recomputed)),
+            root.recomputed
         );
-        if *cached_root != rebuilt_root {
+        if !root.matches() {
             println!(
                 "Cached root {:?}, Rebuilt root {:?}",
-                cached_root, rebuilt_root
+                root.cached, root.recomputed
             );
         }
     }
@@ -294,24 +177,26 @@ fn run_verify(sector_size: usize, cache: &Path, replica_path: &Path) -> Result<(
     println!();
     println!(
         "CommRLast from p_aux                : {:?}",
-        p_aux.comm_r_last
+        report.p_aux_comm_r_last
     );
     println!(
         "CommRLast [cached tree_r_last root] : {:?}",
-        tree_r_last_root
+        report.cached_comm_r_last
     );
     println!(
         "CommRLast [rebuilt tree_r_last root]: {:?}",
-        rebuilt_tree_r_last_root
+        report.comm_r_last
     );
     println!();
     println!(
         " Cached inspection shows a {} of CommRLast {:?}",
-        status, tree_r_last_root
+        match_str(report.cached_comm_r_last, report.p_aux_comm_r_last),
+        report.cached_comm_r_last
     );
     println!(
         "Rebuilt inspection shows a {} of CommRLast {:?}",
-        rebuilt_status, rebuilt_tree_r_last_root
+        match_str(Some(report.comm_r_last), report.p_aux_comm_r_last),
+        report.comm_r_last
     );
 
     Ok(())
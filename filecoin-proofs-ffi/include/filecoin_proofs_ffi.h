@@ -0,0 +1,439 @@
+/* Generated by cbindgen from filecoin-proofs-ffi, do not edit by hand. */
+
+#ifndef FILECOIN_PROOFS_FFI_H
+#define FILECOIN_PROOFS_FFI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Outcome of a call, see the `error_msg` of the response for details on failures.
+ */
+typedef enum fil_FCPResponseStatus {
+  fil_FCPResponseStatus_FCPNoError = 0,
+  /**
+   * The proofs failed for a reason other than the arguments.
+   */
+  fil_FCPResponseStatus_FCPUnclassifiedError = 1,
+  /**
+   * The arguments were invalid, e.g. a null pointer or a path that is not UTF-8.
+   */
+  fil_FCPResponseStatus_FCPCallerError = 2,
+  /**
+   * The call panicked.
+   */
+  fil_FCPResponseStatus_FCPReceiverError = 3,
+} fil_FCPResponseStatus;
+
+/**
+ * The registered PoSt proofs. Functions take them as `uint64_t`, values outside the enum are
+ * caller errors.
+ */
+typedef enum fil_RegisteredPoStProof {
+  fil_RegisteredPoStProof_StackedDrgWinning2KiBV1,
+  fil_RegisteredPoStProof_StackedDrgWinning8MiBV1,
+  fil_RegisteredPoStProof_StackedDrgWinning512MiBV1,
+  fil_RegisteredPoStProof_StackedDrgWinning32GiBV1,
+  fil_RegisteredPoStProof_StackedDrgWinning64GiBV1,
+  fil_RegisteredPoStProof_StackedDrgWindow2KiBV1,
+  fil_RegisteredPoStProof_StackedDrgWindow8MiBV1,
+  fil_RegisteredPoStProof_StackedDrgWindow512MiBV1,
+  fil_RegisteredPoStProof_StackedDrgWindow32GiBV1,
+  fil_RegisteredPoStProof_StackedDrgWindow64GiBV1,
+} fil_RegisteredPoStProof;
+
+/**
+ * The registered seal proofs. Functions take them as `uint64_t`, values outside the enum are
+ * caller errors.
+ */
+typedef enum fil_RegisteredSealProof {
+  fil_RegisteredSealProof_StackedDrg2KiBV1,
+  fil_RegisteredSealProof_StackedDrg8MiBV1,
+  fil_RegisteredSealProof_StackedDrg512MiBV1,
+  fil_RegisteredSealProof_StackedDrg32GiBV1,
+  fil_RegisteredSealProof_StackedDrg64GiBV1,
+  fil_RegisteredSealProof_StackedDrg2KiBV1_1,
+  fil_RegisteredSealProof_StackedDrg8MiBV1_1,
+  fil_RegisteredSealProof_StackedDrg512MiBV1_1,
+  fil_RegisteredSealProof_StackedDrg32GiBV1_1,
+  fil_RegisteredSealProof_StackedDrg64GiBV1_1,
+} fil_RegisteredSealProof;
+
+/**
+ * A commitment, id or randomness passed by value.
+ */
+typedef struct fil_ByteArray32 {
+  uint8_t inner[32];
+} fil_ByteArray32;
+
+typedef struct fil_AddPieceResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  fil_ByteArray32 comm_p;
+  /**
+   * Bytes written to the staged sector, including the alignment before the piece.
+   */
+  uint64_t num_bytes_aligned;
+} fil_AddPieceResponse;
+
+typedef struct fil_ClearCacheResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+} fil_ClearCacheResponse;
+
+/**
+ * A byte buffer owned by the response it is part of.
+ */
+typedef struct fil_ByteBuffer {
+  const uint8_t *ptr;
+  uintptr_t len;
+} fil_ByteBuffer;
+
+typedef struct fil_GeneratePoStResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  fil_ByteBuffer proof;
+} fil_GeneratePoStResponse;
+
+/**
+ * A `u64` buffer owned by the response it is part of.
+ */
+typedef struct fil_U64Buffer {
+  const uint64_t *ptr;
+  uintptr_t len;
+} fil_U64Buffer;
+
+typedef struct fil_GenerateWinningPoStSectorChallengeResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  /**
+   * Indices into the sector set of the challenged sectors.
+   */
+  fil_U64Buffer sector_indices;
+} fil_GenerateWinningPoStSectorChallengeResponse;
+
+typedef struct fil_PrivateReplicaInfo {
+  uint64_t sector_id;
+  fil_ByteArray32 comm_r;
+  const char *cache_dir_path;
+  const char *replica_path;
+} fil_PrivateReplicaInfo;
+
+typedef struct fil_PublicPieceInfo {
+  /**
+   * Unpadded size of the piece in bytes.
+   */
+  uint64_t num_bytes;
+  fil_ByteArray32 comm_p;
+} fil_PublicPieceInfo;
+
+typedef struct fil_PublicReplicaInfo {
+  uint64_t sector_id;
+  fil_ByteArray32 comm_r;
+} fil_PublicReplicaInfo;
+
+typedef struct fil_SealCommitPhase1Response {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  /**
+   * The JSON encoded phase 1 output, to be passed to `fil_seal_commit_phase2`.
+   */
+  fil_ByteBuffer seal_commit_phase1_output;
+} fil_SealCommitPhase1Response;
+
+typedef struct fil_SealCommitPhase2Response {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  fil_ByteBuffer proof;
+} fil_SealCommitPhase2Response;
+
+typedef struct fil_SealPreCommitPhase1Response {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  /**
+   * The JSON encoded phase 1 output, to be passed to `fil_seal_pre_commit_phase2`.
+   */
+  fil_ByteBuffer seal_pre_commit_phase1_output;
+} fil_SealPreCommitPhase1Response;
+
+typedef struct fil_SealPreCommitPhase2Response {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  fil_RegisteredSealProof registered_proof;
+  fil_ByteArray32 comm_r;
+  fil_ByteArray32 comm_d;
+} fil_SealPreCommitPhase2Response;
+
+typedef struct fil_UnsealRangeResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+} fil_UnsealRangeResponse;
+
+typedef struct fil_VerifyPoStResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  bool is_valid;
+} fil_VerifyPoStResponse;
+
+typedef struct fil_VerifySealResponse {
+  fil_FCPResponseStatus status_code;
+  const char *error_msg;
+  bool is_valid;
+} fil_VerifySealResponse;
+
+/**
+ * Pads the piece at `piece_path` and appends it to the staged sector, preceded by the alignment
+ * its position in the sector requires.
+ *
+ * `existing_piece_sizes` are the unpadded sizes of the pieces already in the staged sector.
+ *
+ * # Safety
+ *
+ * The paths must be nul-terminated strings, `existing_piece_sizes_ptr` must point to
+ * `existing_piece_sizes_len` values.
+ */
+fil_AddPieceResponse *fil_add_piece(uint64_t registered_proof,
+                                    const char *piece_path,
+                                    const char *staged_sector_path,
+                                    uint64_t piece_num_bytes,
+                                    const uint64_t *existing_piece_sizes_ptr,
+                                    uintptr_t existing_piece_sizes_len);
+
+/**
+ * # Safety
+ *
+ * `cache_dir_path` must be a nul-terminated string.
+ */
+fil_ClearCacheResponse *fil_clear_cache(uint64_t registered_proof,
+                                        const char *cache_dir_path);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_add_piece_response(fil_AddPieceResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_clear_cache_response(fil_ClearCacheResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_generate_post_response(fil_GeneratePoStResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_generate_winning_post_sector_challenge_response(fil_GenerateWinningPoStSectorChallengeResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_seal_commit_phase1_response(fil_SealCommitPhase1Response *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_seal_commit_phase2_response(fil_SealCommitPhase2Response *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_seal_pre_commit_phase1_response(fil_SealPreCommitPhase1Response *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_seal_pre_commit_phase2_response(fil_SealPreCommitPhase2Response *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_unseal_range_response(fil_UnsealRangeResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_verify_post_response(fil_VerifyPoStResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `ptr` must be null or a response of the matching function that was not destroyed
+ * before.
+ */
+void fil_destroy_verify_seal_response(fil_VerifySealResponse *ptr);
+
+/**
+ * # Safety
+ *
+ * `replicas_ptr` must point to `replicas_len` replicas with nul-terminated paths.
+ */
+fil_GeneratePoStResponse *fil_generate_window_post(uint64_t registered_proof,
+                                                   fil_ByteArray32 randomness,
+                                                   const fil_PrivateReplicaInfo *replicas_ptr,
+                                                   uintptr_t replicas_len,
+                                                   fil_ByteArray32 prover_id);
+
+/**
+ * # Safety
+ *
+ * `replicas_ptr` must point to `replicas_len` replicas with nul-terminated paths.
+ */
+fil_GeneratePoStResponse *fil_generate_winning_post(uint64_t registered_proof,
+                                                    fil_ByteArray32 randomness,
+                                                    const fil_PrivateReplicaInfo *replicas_ptr,
+                                                    uintptr_t replicas_len,
+                                                    fil_ByteArray32 prover_id);
+
+fil_GenerateWinningPoStSectorChallengeResponse *fil_generate_winning_post_sector_challenge(uint64_t registered_proof,
+                                                                                           fil_ByteArray32 randomness,
+                                                                                           uint64_t sector_set_len,
+                                                                                           fil_ByteArray32 prover_id);
+
+/**
+ * # Safety
+ *
+ * The paths must be nul-terminated strings, `pieces_ptr` must point to `pieces_len` pieces.
+ */
+fil_SealCommitPhase1Response *fil_seal_commit_phase1(uint64_t registered_proof,
+                                                     fil_ByteArray32 comm_r,
+                                                     fil_ByteArray32 comm_d,
+                                                     const char *cache_dir_path,
+                                                     const char *sealed_sector_path,
+                                                     uint64_t sector_id,
+                                                     fil_ByteArray32 prover_id,
+                                                     fil_ByteArray32 ticket,
+                                                     fil_ByteArray32 seed,
+                                                     const fil_PublicPieceInfo *pieces_ptr,
+                                                     uintptr_t pieces_len);
+
+/**
+ * # Safety
+ *
+ * `seal_commit_phase1_output_ptr` must point to `seal_commit_phase1_output_len` bytes.
+ */
+fil_SealCommitPhase2Response *fil_seal_commit_phase2(const uint8_t *seal_commit_phase1_output_ptr,
+                                                     uintptr_t seal_commit_phase1_output_len,
+                                                     uint64_t sector_id,
+                                                     fil_ByteArray32 prover_id);
+
+/**
+ * # Safety
+ *
+ * The paths must be nul-terminated strings, `pieces_ptr` must point to `pieces_len` pieces.
+ */
+fil_SealPreCommitPhase1Response *fil_seal_pre_commit_phase1(uint64_t registered_proof,
+                                                            const char *cache_dir_path,
+                                                            const char *staged_sector_path,
+                                                            const char *sealed_sector_path,
+                                                            uint64_t sector_id,
+                                                            fil_ByteArray32 prover_id,
+                                                            fil_ByteArray32 ticket,
+                                                            const fil_PublicPieceInfo *pieces_ptr,
+                                                            uintptr_t pieces_len);
+
+/**
+ * # Safety
+ *
+ * `seal_pre_commit_phase1_output_ptr` must point to `seal_pre_commit_phase1_output_len` bytes,
+ * the paths must be nul-terminated strings.
+ */
+fil_SealPreCommitPhase2Response *fil_seal_pre_commit_phase2(const uint8_t *seal_pre_commit_phase1_output_ptr,
+                                                            uintptr_t seal_pre_commit_phase1_output_len,
+                                                            const char *cache_dir_path,
+                                                            const char *sealed_sector_path);
+
+/**
+ * Unseals `length` unpadded bytes at `offset` of the sealed sector into the file at
+ * `unseal_output_path`, which is created if needed.
+ *
+ * # Safety
+ *
+ * The paths must be nul-terminated strings.
+ */
+fil_UnsealRangeResponse *fil_unseal_range(uint64_t registered_proof,
+                                          const char *cache_dir_path,
+                                          const char *sealed_sector_path,
+                                          const char *unseal_output_path,
+                                          uint64_t sector_id,
+                                          fil_ByteArray32 prover_id,
+                                          fil_ByteArray32 ticket,
+                                          fil_ByteArray32 comm_d,
+                                          uint64_t offset,
+                                          uint64_t length);
+
+/**
+ * # Safety
+ *
+ * `proof_ptr` must point to `proof_len` bytes.
+ */
+fil_VerifySealResponse *fil_verify_seal(uint64_t registered_proof,
+                                        fil_ByteArray32 comm_r,
+                                        fil_ByteArray32 comm_d,
+                                        fil_ByteArray32 prover_id,
+                                        fil_ByteArray32 ticket,
+                                        fil_ByteArray32 seed,
+                                        uint64_t sector_id,
+                                        const uint8_t *proof_ptr,
+                                        uintptr_t proof_len);
+
+/**
+ * # Safety
+ *
+ * `replicas_ptr` must point to `replicas_len` replicas, `proof_ptr` to `proof_len` bytes.
+ */
+fil_VerifyPoStResponse *fil_verify_window_post(uint64_t registered_proof,
+                                               fil_ByteArray32 randomness,
+                                               const fil_PublicReplicaInfo *replicas_ptr,
+                                               uintptr_t replicas_len,
+                                               fil_ByteArray32 prover_id,
+                                               const uint8_t *proof_ptr,
+                                               uintptr_t proof_len);
+
+/**
+ * # Safety
+ *
+ * `replicas_ptr` must point to `replicas_len` replicas, `proof_ptr` to `proof_len` bytes.
+ */
+fil_VerifyPoStResponse *fil_verify_winning_post(uint64_t registered_proof,
+                                                fil_ByteArray32 randomness,
+                                                const fil_PublicReplicaInfo *replicas_ptr,
+                                                uintptr_t replicas_len,
+                                                fil_ByteArray32 prover_id,
+                                                const uint8_t *proof_ptr,
+                                                uintptr_t proof_len);
+
+#endif /* FILECOIN_PROOFS_FFI_H */
@@ -0,0 +1,23 @@
+#![deny(clippy::all, clippy::perf, clippy::correctness, rust_2018_idioms)]
+#![warn(clippy::unwrap_used)]
+#![allow(clippy::upper_case_acronyms)]
+
+//! C bindings for `filecoin-proofs`.
+//!
+//! Every function returns a heap allocated response, which carries a status code and an error
+//! message next to the results, and has to be freed with the matching `fil_destroy_*` function.
+//! The header is generated with cbindgen, see `cbindgen.toml`.
+
+mod proofs;
+mod types;
+mod util;
+
+pub use proofs::*;
+pub use types::{
+    AddPieceResponse, ByteArray32, ByteBuffer, ClearCacheResponse, FCPResponseStatus,
+    GeneratePoStResponse, GenerateWinningPoStSectorChallengeResponse, PrivateReplicaInfo,
+    PublicPieceInfo, PublicReplicaInfo, RegisteredPoStProof, RegisteredSealProof,
+    SealCommitPhase1Response, SealCommitPhase2Response, SealPreCommitPhase1Response,
+    SealPreCommitPhase2Response, U64Buffer, UnsealRangeResponse, VerifyPoStResponse,
+    VerifySealResponse,
+};
@@ -0,0 +1,590 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::os::raw::c_char;
+
+use anyhow::{Context, Result};
+use filecoin_proofs::{
+    add_piece, registry, PieceInfo, PublicReplicaInfo as ReplicaInfo, SealPreCommitOutput,
+    SparseFileWriter, UnpaddedByteIndex, UnpaddedBytesAmount,
+};
+use storage_proofs_core::sector::SectorId;
+
+use crate::{
+    types::{
+        AddPieceResponse, ByteArray32, ClearCacheResponse, GeneratePoStResponse,
+        GenerateWinningPoStSectorChallengeResponse, PrivateReplicaInfo, PublicPieceInfo,
+        PublicReplicaInfo, RegisteredPoStProof, RegisteredSealProof, SealCommitPhase1Response,
+        SealCommitPhase2Response, SealPreCommitPhase1Response, SealPreCommitPhase2Response,
+        UnsealRangeResponse, VerifyPoStResponse, VerifySealResponse,
+    },
+    util::{c_slice, c_str_to_path, destroy, run, CallerError},
+};
+
+unsafe fn piece_infos(ptr: *const PublicPieceInfo, len: usize) -> Result<Vec<PieceInfo>> {
+    c_slice(ptr, len, "pieces")?
+        .iter()
+        .map(|piece| {
+            PieceInfo::new(piece.comm_p.inner, UnpaddedBytesAmount(piece.num_bytes))
+                .map_err(|err| CallerError(format!("invalid piece: {}", err)).into())
+        })
+        .collect()
+}
+
+unsafe fn private_replicas(
+    ptr: *const PrivateReplicaInfo,
+    len: usize,
+) -> Result<Vec<(SectorId, registry::PrivateReplicaInfo)>> {
+    c_slice(ptr, len, "replicas")?
+        .iter()
+        .map(|replica| {
+            Ok((
+                SectorId::from(replica.sector_id),
+                registry::PrivateReplicaInfo {
+                    replica: c_str_to_path(replica.replica_path, "replica_path")?,
+                    comm_r: replica.comm_r.inner,
+                    cache_dir: c_str_to_path(replica.cache_dir_path, "cache_dir_path")?,
+                },
+            ))
+        })
+        .collect()
+}
+
+unsafe fn public_replicas(
+    ptr: *const PublicReplicaInfo,
+    len: usize,
+) -> Result<Vec<(SectorId, ReplicaInfo)>> {
+    c_slice(ptr, len, "replicas")?
+        .iter()
+        .map(|replica| {
+            let info = ReplicaInfo::new(replica.comm_r.inner)
+                .map_err(|err| CallerError(format!("invalid replica: {}", err)))?;
+            Ok((SectorId::from(replica.sector_id), info))
+        })
+        .collect()
+}
+
+/// Converts a `RegisteredSealProof` value passed by the caller, which C does not constrain to the
+/// values of the enum.
+fn seal_proof(registered_proof: u64) -> Result<registry::RegisteredSealProof> {
+    RegisteredSealProof::try_from(registered_proof).map(Into::into)
+}
+
+/// Converts a `RegisteredPoStProof` value passed by the caller.
+fn post_proof(registered_proof: u64) -> Result<registry::RegisteredPoStProof> {
+    RegisteredPoStProof::try_from(registered_proof).map(Into::into)
+}
+
+/// Pads the piece at `piece_path` and appends it to the staged sector, preceded by the alignment
+/// its position in the sector requires, which is left as a hole in the file.
+///
+/// `existing_piece_sizes` are the unpadded sizes of the pieces already in the staged sector.
+///
+/// # Safety
+///
+/// The paths must be nul-terminated strings, `existing_piece_sizes_ptr` must point to
+/// `existing_piece_sizes_len` values.
+#[no_mangle]
+pub unsafe extern "C" fn fil_add_piece(
+    registered_proof: u64,
+    piece_path: *const c_char,
+    staged_sector_path: *const c_char,
+    piece_num_bytes: u64,
+    existing_piece_sizes_ptr: *const u64,
+    existing_piece_sizes_len: usize,
+) -> *mut AddPieceResponse {
+    run("fil_add_piece", |response: &mut AddPieceResponse| {
+        let piece_path = c_str_to_path(piece_path, "piece_path")?;
+        let staged_sector_path = c_str_to_path(staged_sector_path, "staged_sector_path")?;
+        let existing_piece_sizes: Vec<UnpaddedBytesAmount> = c_slice(
+            existing_piece_sizes_ptr,
+            existing_piece_sizes_len,
+            "existing_piece_sizes",
+        )?
+        .iter()
+        .copied()
+        .map(UnpaddedBytesAmount)
+        .collect();
+
+        let sector_size = seal_proof(registered_proof)?.sector_size();
+        let sector_bytes = UnpaddedBytesAmount::from(sector_size);
+        let used: u64 = existing_piece_sizes.iter().map(|size| size.0).sum();
+        if used + piece_num_bytes > sector_bytes.0 {
+            return Err(CallerError(format!(
+                "piece of {} bytes does not fit in a sector of {} bytes with {} bytes used",
+                piece_num_bytes, sector_bytes.0, used
+            ))
+            .into());
+        }
+
+        let source = File::open(&piece_path)
+            .with_context(|| format!("could not open piece {:?}", piece_path))?;
+        let mut target = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&staged_sector_path)
+            .with_context(|| format!("could not open staged sector {:?}", staged_sector_path))?;
+        target.seek(SeekFrom::End(0))?;
+
+        // Alignment is left as holes in the staged sector.
+        let mut target = SparseFileWriter::new(target)?;
+        let (piece_info, num_bytes_aligned) = add_piece(
+            source,
+            &mut target,
+            UnpaddedBytesAmount(piece_num_bytes),
+            &existing_piece_sizes,
+        )?;
+        target
+            .flush()
+            .with_context(|| format!("could not write staged sector {:?}", staged_sector_path))?;
+
+        response.comm_p = ByteArray32 {
+            inner: piece_info.commitment,
+        };
+        response.num_bytes_aligned = num_bytes_aligned.0;
+
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// The paths must be nul-terminated strings, `pieces_ptr` must point to `pieces_len` pieces.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_seal_pre_commit_phase1(
+    registered_proof: u64,
+    cache_dir_path: *const c_char,
+    staged_sector_path: *const c_char,
+    sealed_sector_path: *const c_char,
+    sector_id: u64,
+    prover_id: ByteArray32,
+    ticket: ByteArray32,
+    pieces_ptr: *const PublicPieceInfo,
+    pieces_len: usize,
+) -> *mut SealPreCommitPhase1Response {
+    run(
+        "fil_seal_pre_commit_phase1",
+        |response: &mut SealPreCommitPhase1Response| {
+            let cache_dir_path = c_str_to_path(cache_dir_path, "cache_dir_path")?;
+            let staged_sector_path = c_str_to_path(staged_sector_path, "staged_sector_path")?;
+            let sealed_sector_path = c_str_to_path(sealed_sector_path, "sealed_sector_path")?;
+            let piece_infos = piece_infos(pieces_ptr, pieces_len)?;
+
+            let output = seal_proof(registered_proof)?.seal_pre_commit_phase1(
+                cache_dir_path,
+                staged_sector_path,
+                sealed_sector_path,
+                prover_id.inner,
+                sector_id.into(),
+                ticket.inner,
+                &piece_infos,
+            )?;
+            response.seal_pre_commit_phase1_output = serde_json::to_vec(&output)?.into();
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `seal_pre_commit_phase1_output_ptr` must point to `seal_pre_commit_phase1_output_len` bytes,
+/// the paths must be nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn fil_seal_pre_commit_phase2(
+    seal_pre_commit_phase1_output_ptr: *const u8,
+    seal_pre_commit_phase1_output_len: usize,
+    cache_dir_path: *const c_char,
+    sealed_sector_path: *const c_char,
+) -> *mut SealPreCommitPhase2Response {
+    run(
+        "fil_seal_pre_commit_phase2",
+        |response: &mut SealPreCommitPhase2Response| {
+            let phase1_output: registry::SealPreCommitPhase1Output =
+                serde_json::from_slice(c_slice(
+                    seal_pre_commit_phase1_output_ptr,
+                    seal_pre_commit_phase1_output_len,
+                    "seal_pre_commit_phase1_output",
+                )?)
+                .map_err(|err| CallerError(format!("invalid phase 1 output: {}", err)))?;
+            let cache_dir_path = c_str_to_path(cache_dir_path, "cache_dir_path")?;
+            let sealed_sector_path = c_str_to_path(sealed_sector_path, "sealed_sector_path")?;
+
+            let registered_proof = phase1_output.registered_proof;
+            let output = registered_proof.seal_pre_commit_phase2(
+                phase1_output,
+                cache_dir_path,
+                sealed_sector_path,
+            )?;
+
+            response.registered_proof = RegisteredSealProof::try_from(registered_proof)?;
+            response.comm_r = ByteArray32 {
+                inner: output.comm_r,
+            };
+            response.comm_d = ByteArray32 {
+                inner: output.comm_d,
+            };
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// The paths must be nul-terminated strings, `pieces_ptr` must point to `pieces_len` pieces.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_seal_commit_phase1(
+    registered_proof: u64,
+    comm_r: ByteArray32,
+    comm_d: ByteArray32,
+    cache_dir_path: *const c_char,
+    sealed_sector_path: *const c_char,
+    sector_id: u64,
+    prover_id: ByteArray32,
+    ticket: ByteArray32,
+    seed: ByteArray32,
+    pieces_ptr: *const PublicPieceInfo,
+    pieces_len: usize,
+) -> *mut SealCommitPhase1Response {
+    run(
+        "fil_seal_commit_phase1",
+        |response: &mut SealCommitPhase1Response| {
+            let cache_dir_path = c_str_to_path(cache_dir_path, "cache_dir_path")?;
+            let sealed_sector_path = c_str_to_path(sealed_sector_path, "sealed_sector_path")?;
+            let piece_infos = piece_infos(pieces_ptr, pieces_len)?;
+
+            let output = seal_proof(registered_proof)?.seal_commit_phase1(
+                cache_dir_path,
+                sealed_sector_path,
+                prover_id.inner,
+                sector_id.into(),
+                ticket.inner,
+                seed.inner,
+                SealPreCommitOutput {
+                    comm_r: comm_r.inner,
+                    comm_d: comm_d.inner,
+                },
+                &piece_infos,
+            )?;
+            response.seal_commit_phase1_output = serde_json::to_vec(&output)?.into();
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `seal_commit_phase1_output_ptr` must point to `seal_commit_phase1_output_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fil_seal_commit_phase2(
+    seal_commit_phase1_output_ptr: *const u8,
+    seal_commit_phase1_output_len: usize,
+    sector_id: u64,
+    prover_id: ByteArray32,
+) -> *mut SealCommitPhase2Response {
+    run(
+        "fil_seal_commit_phase2",
+        |response: &mut SealCommitPhase2Response| {
+            let phase1_output: registry::SealCommitPhase1Output = serde_json::from_slice(c_slice(
+                seal_commit_phase1_output_ptr,
+                seal_commit_phase1_output_len,
+                "seal_commit_phase1_output",
+            )?)
+            .map_err(|err| CallerError(format!("invalid phase 1 output: {}", err)))?;
+
+            let output = phase1_output.registered_proof.seal_commit_phase2(
+                phase1_output,
+                prover_id.inner,
+                sector_id.into(),
+            )?;
+            response.proof = output.proof.into();
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `proof_ptr` must point to `proof_len` bytes.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_verify_seal(
+    registered_proof: u64,
+    comm_r: ByteArray32,
+    comm_d: ByteArray32,
+    prover_id: ByteArray32,
+    ticket: ByteArray32,
+    seed: ByteArray32,
+    sector_id: u64,
+    proof_ptr: *const u8,
+    proof_len: usize,
+) -> *mut VerifySealResponse {
+    run("fil_verify_seal", |response: &mut VerifySealResponse| {
+        let proof = c_slice(proof_ptr, proof_len, "proof")?;
+
+        response.is_valid = seal_proof(registered_proof)?.verify_seal(
+            comm_r.inner,
+            comm_d.inner,
+            prover_id.inner,
+            sector_id.into(),
+            ticket.inner,
+            seed.inner,
+            proof,
+        )?;
+
+        Ok(())
+    })
+}
+
+/// Unseals `length` unpadded bytes at `offset` of the sealed sector into the file at
+/// `unseal_output_path`, which is created if needed.
+///
+/// # Safety
+///
+/// The paths must be nul-terminated strings.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn fil_unseal_range(
+    registered_proof: u64,
+    cache_dir_path: *const c_char,
+    sealed_sector_path: *const c_char,
+    unseal_output_path: *const c_char,
+    sector_id: u64,
+    prover_id: ByteArray32,
+    ticket: ByteArray32,
+    comm_d: ByteArray32,
+    offset: u64,
+    length: u64,
+) -> *mut UnsealRangeResponse {
+    run("fil_unseal_range", |_: &mut UnsealRangeResponse| {
+        let cache_dir_path = c_str_to_path(cache_dir_path, "cache_dir_path")?;
+        let sealed_sector_path = c_str_to_path(sealed_sector_path, "sealed_sector_path")?;
+        let unseal_output_path = c_str_to_path(unseal_output_path, "unseal_output_path")?;
+
+        let sealed_sector = File::open(&sealed_sector_path)
+            .with_context(|| format!("could not open sealed sector {:?}", sealed_sector_path))?;
+        let unseal_output = File::create(&unseal_output_path)
+            .with_context(|| format!("could not create {:?}", unseal_output_path))?;
+
+        seal_proof(registered_proof)?.unseal_range(
+            cache_dir_path,
+            sealed_sector,
+            BufWriter::new(unseal_output),
+            prover_id.inner,
+            sector_id.into(),
+            comm_d.inner,
+            ticket.inner,
+            UnpaddedByteIndex(offset),
+            UnpaddedBytesAmount(length),
+        )?;
+
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `cache_dir_path` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn fil_clear_cache(
+    registered_proof: u64,
+    cache_dir_path: *const c_char,
+) -> *mut ClearCacheResponse {
+    run("fil_clear_cache", |_: &mut ClearCacheResponse| {
+        let cache_dir_path = c_str_to_path(cache_dir_path, "cache_dir_path")?;
+
+        seal_proof(registered_proof)?.clear_cache(cache_dir_path)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn fil_generate_winning_post_sector_challenge(
+    registered_proof: u64,
+    randomness: ByteArray32,
+    sector_set_len: u64,
+    prover_id: ByteArray32,
+) -> *mut GenerateWinningPoStSectorChallengeResponse {
+    run(
+        "fil_generate_winning_post_sector_challenge",
+        |response: &mut GenerateWinningPoStSectorChallengeResponse| {
+            let sector_indices = post_proof(registered_proof)?
+                .generate_winning_post_sector_challenge(
+                    &randomness.inner,
+                    sector_set_len,
+                    prover_id.inner,
+                )?;
+            response.sector_indices = sector_indices.into();
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `replicas_ptr` must point to `replicas_len` replicas with nul-terminated paths.
+#[no_mangle]
+pub unsafe extern "C" fn fil_generate_winning_post(
+    registered_proof: u64,
+    randomness: ByteArray32,
+    replicas_ptr: *const PrivateReplicaInfo,
+    replicas_len: usize,
+    prover_id: ByteArray32,
+) -> *mut GeneratePoStResponse {
+    run(
+        "fil_generate_winning_post",
+        |response: &mut GeneratePoStResponse| {
+            let replicas = private_replicas(replicas_ptr, replicas_len)?;
+
+            let proof = post_proof(registered_proof)?.generate_winning_post(
+                &randomness.inner,
+                &replicas,
+                prover_id.inner,
+            )?;
+            response.proof = proof.into();
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `replicas_ptr` must point to `replicas_len` replicas, `proof_ptr` to `proof_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fil_verify_winning_post(
+    registered_proof: u64,
+    randomness: ByteArray32,
+    replicas_ptr: *const PublicReplicaInfo,
+    replicas_len: usize,
+    prover_id: ByteArray32,
+    proof_ptr: *const u8,
+    proof_len: usize,
+) -> *mut VerifyPoStResponse {
+    run(
+        "fil_verify_winning_post",
+        |response: &mut VerifyPoStResponse| {
+            let replicas = public_replicas(replicas_ptr, replicas_len)?;
+            let proof = c_slice(proof_ptr, proof_len, "proof")?;
+
+            response.is_valid = post_proof(registered_proof)?.verify_winning_post(
+                &randomness.inner,
+                &replicas,
+                prover_id.inner,
+                proof,
+            )?;
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `replicas_ptr` must point to `replicas_len` replicas with nul-terminated paths.
+#[no_mangle]
+pub unsafe extern "C" fn fil_generate_window_post(
+    registered_proof: u64,
+    randomness: ByteArray32,
+    replicas_ptr: *const PrivateReplicaInfo,
+    replicas_len: usize,
+    prover_id: ByteArray32,
+) -> *mut GeneratePoStResponse {
+    run(
+        "fil_generate_window_post",
+        |response: &mut GeneratePoStResponse| {
+            let replicas: BTreeMap<_, _> = private_replicas(replicas_ptr, replicas_len)?
+                .into_iter()
+                .collect();
+
+            let proof = post_proof(registered_proof)?.generate_window_post(
+                &randomness.inner,
+                &replicas,
+                prover_id.inner,
+            )?;
+            response.proof = proof.into();
+
+            Ok(())
+        },
+    )
+}
+
+/// # Safety
+///
+/// `replicas_ptr` must point to `replicas_len` replicas, `proof_ptr` to `proof_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fil_verify_window_post(
+    registered_proof: u64,
+    randomness: ByteArray32,
+    replicas_ptr: *const PublicReplicaInfo,
+    replicas_len: usize,
+    prover_id: ByteArray32,
+    proof_ptr: *const u8,
+    proof_len: usize,
+) -> *mut VerifyPoStResponse {
+    run(
+        "fil_verify_window_post",
+        |response: &mut VerifyPoStResponse| {
+            let replicas: BTreeMap<_, _> = public_replicas(replicas_ptr, replicas_len)?
+                .into_iter()
+                .collect();
+            let proof = c_slice(proof_ptr, proof_len, "proof")?;
+
+            response.is_valid = post_proof(registered_proof)?.verify_window_post(
+                &randomness.inner,
+                &replicas,
+                prover_id.inner,
+                proof,
+            )?;
+
+            Ok(())
+        },
+    )
+}
+
+macro_rules! destructor {
+    ($name:ident, $response:ty) => {
+        /// # Safety
+        ///
+        /// `ptr` must be null or a response of the matching function that was not destroyed
+        /// before.
+        #[no_mangle]
+        pub unsafe extern "C" fn $name(ptr: *mut $response) {
+            destroy(ptr)
+        }
+    };
+}
+
+destructor!(fil_destroy_add_piece_response, AddPieceResponse);
+destructor!(
+    fil_destroy_seal_pre_commit_phase1_response,
+    SealPreCommitPhase1Response
+);
+destructor!(
+    fil_destroy_seal_pre_commit_phase2_response,
+    SealPreCommitPhase2Response
+);
+destructor!(
+    fil_destroy_seal_commit_phase1_response,
+    SealCommitPhase1Response
+);
+destructor!(
+    fil_destroy_seal_commit_phase2_response,
+    SealCommitPhase2Response
+);
+destructor!(fil_destroy_verify_seal_response, VerifySealResponse);
+destructor!(fil_destroy_unseal_range_response, UnsealRangeResponse);
+destructor!(fil_destroy_clear_cache_response, ClearCacheResponse);
+destructor!(
+    fil_destroy_generate_winning_post_sector_challenge_response,
+    GenerateWinningPoStSectorChallengeResponse
+);
+destructor!(fil_destroy_generate_post_response, GeneratePoStResponse);
+destructor!(fil_destroy_verify_post_response, VerifyPoStResponse);
@@ -0,0 +1,455 @@
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+use anyhow::{bail, Result};
+use filecoin_proofs::registry;
+
+use crate::util::CallerError;
+
+/// Outcome of a call, see the `error_msg` of the response for details on failures.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FCPResponseStatus {
+    FCPNoError = 0,
+    /// The proofs failed for a reason other than the arguments.
+    FCPUnclassifiedError = 1,
+    /// The arguments were invalid, e.g. a null pointer or a path that is not UTF-8.
+    FCPCallerError = 2,
+    /// The call panicked.
+    FCPReceiverError = 3,
+}
+
+/// The registered seal proofs. Functions take them as `uint64_t`, values outside the enum are
+/// caller errors.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisteredSealProof {
+    StackedDrg2KiBV1,
+    StackedDrg8MiBV1,
+    StackedDrg512MiBV1,
+    StackedDrg32GiBV1,
+    StackedDrg64GiBV1,
+    StackedDrg2KiBV1_1,
+    StackedDrg8MiBV1_1,
+    StackedDrg512MiBV1_1,
+    StackedDrg32GiBV1_1,
+    StackedDrg64GiBV1_1,
+}
+
+impl TryFrom<u64> for RegisteredSealProof {
+    type Error = anyhow::Error;
+
+    fn try_from(registered_proof: u64) -> Result<Self> {
+        use RegisteredSealProof::*;
+
+        let proof = match registered_proof {
+            0 => StackedDrg2KiBV1,
+            1 => StackedDrg8MiBV1,
+            2 => StackedDrg512MiBV1,
+            3 => StackedDrg32GiBV1,
+            4 => StackedDrg64GiBV1,
+            5 => StackedDrg2KiBV1_1,
+            6 => StackedDrg8MiBV1_1,
+            7 => StackedDrg512MiBV1_1,
+            8 => StackedDrg32GiBV1_1,
+            9 => StackedDrg64GiBV1_1,
+            _ => {
+                return Err(CallerError(format!(
+                    "unknown registered seal proof {}",
+                    registered_proof
+                ))
+                .into())
+            }
+        };
+
+        Ok(proof)
+    }
+}
+
+impl Default for RegisteredSealProof {
+    fn default() -> Self {
+        RegisteredSealProof::StackedDrg2KiBV1
+    }
+}
+
+impl From<RegisteredSealProof> for registry::RegisteredSealProof {
+    fn from(proof: RegisteredSealProof) -> Self {
+        use RegisteredSealProof::*;
+
+        match proof {
+            StackedDrg2KiBV1 => Self::StackedDrg2KiBV1,
+            StackedDrg8MiBV1 => Self::StackedDrg8MiBV1,
+            StackedDrg512MiBV1 => Self::StackedDrg512MiBV1,
+            StackedDrg32GiBV1 => Self::StackedDrg32GiBV1,
+            StackedDrg64GiBV1 => Self::StackedDrg64GiBV1,
+            StackedDrg2KiBV1_1 => Self::StackedDrg2KiBV1_1,
+            StackedDrg8MiBV1_1 => Self::StackedDrg8MiBV1_1,
+            StackedDrg512MiBV1_1 => Self::StackedDrg512MiBV1_1,
+            StackedDrg32GiBV1_1 => Self::StackedDrg32GiBV1_1,
+            StackedDrg64GiBV1_1 => Self::StackedDrg64GiBV1_1,
+        }
+    }
+}
+
+impl TryFrom<registry::RegisteredSealProof> for RegisteredSealProof {
+    type Error = anyhow::Error;
+
+    /// Fails for `StackedDrg`, which has no C counterpart.
+    fn try_from(proof: registry::RegisteredSealProof) -> Result<Self> {
+        use registry::RegisteredSealProof::*;
+
+        let proof = match proof {
+            StackedDrg2KiBV1 => Self::StackedDrg2KiBV1,
+            StackedDrg8MiBV1 => Self::StackedDrg8MiBV1,
+            StackedDrg512MiBV1 => Self::StackedDrg512MiBV1,
+            StackedDrg32GiBV1 => Self::StackedDrg32GiBV1,
+            StackedDrg64GiBV1 => Self::StackedDrg64GiBV1,
+            StackedDrg2KiBV1_1 => Self::StackedDrg2KiBV1_1,
+            StackedDrg8MiBV1_1 => Self::StackedDrg8MiBV1_1,
+            StackedDrg512MiBV1_1 => Self::StackedDrg512MiBV1_1,
+            StackedDrg32GiBV1_1 => Self::StackedDrg32GiBV1_1,
+            StackedDrg64GiBV1_1 => Self::StackedDrg64GiBV1_1,
+            StackedDrg { .. } => bail!("{:?} has no registered proof id", proof),
+        };
+
+        Ok(proof)
+    }
+}
+
+/// The registered PoSt proofs. Functions take them as `uint64_t`, values outside the enum are
+/// caller errors.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisteredPoStProof {
+    StackedDrgWinning2KiBV1,
+    StackedDrgWinning8MiBV1,
+    StackedDrgWinning512MiBV1,
+    StackedDrgWinning32GiBV1,
+    StackedDrgWinning64GiBV1,
+    StackedDrgWindow2KiBV1,
+    StackedDrgWindow8MiBV1,
+    StackedDrgWindow512MiBV1,
+    StackedDrgWindow32GiBV1,
+    StackedDrgWindow64GiBV1,
+}
+
+impl TryFrom<u64> for RegisteredPoStProof {
+    type Error = anyhow::Error;
+
+    fn try_from(registered_proof: u64) -> Result<Self> {
+        use RegisteredPoStProof::*;
+
+        let proof = match registered_proof {
+            0 => StackedDrgWinning2KiBV1,
+            1 => StackedDrgWinning8MiBV1,
+            2 => StackedDrgWinning512MiBV1,
+            3 => StackedDrgWinning32GiBV1,
+            4 => StackedDrgWinning64GiBV1,
+            5 => StackedDrgWindow2KiBV1,
+            6 => StackedDrgWindow8MiBV1,
+            7 => StackedDrgWindow512MiBV1,
+            8 => StackedDrgWindow32GiBV1,
+            9 => StackedDrgWindow64GiBV1,
+            _ => {
+                return Err(CallerError(format!(
+                    "unknown registered PoSt proof {}",
+                    registered_proof
+                ))
+                .into())
+            }
+        };
+
+        Ok(proof)
+    }
+}
+
+impl From<RegisteredPoStProof> for registry::RegisteredPoStProof {
+    fn from(proof: RegisteredPoStProof) -> Self {
+        use RegisteredPoStProof::*;
+
+        match proof {
+            StackedDrgWinning2KiBV1 => Self::StackedDrgWinning2KiBV1,
+            StackedDrgWinning8MiBV1 => Self::StackedDrgWinning8MiBV1,
+            StackedDrgWinning512MiBV1 => Self::StackedDrgWinning512MiBV1,
+            StackedDrgWinning32GiBV1 => Self::StackedDrgWinning32GiBV1,
+            StackedDrgWinning64GiBV1 => Self::StackedDrgWinning64GiBV1,
+            StackedDrgWindow2KiBV1 => Self::StackedDrgWindow2KiBV1,
+            StackedDrgWindow8MiBV1 => Self::StackedDrgWindow8MiBV1,
+            StackedDrgWindow512MiBV1 => Self::StackedDrgWindow512MiBV1,
+            StackedDrgWindow32GiBV1 => Self::StackedDrgWindow32GiBV1,
+            StackedDrgWindow64GiBV1 => Self::StackedDrgWindow64GiBV1,
+        }
+    }
+}
+
+/// A commitment, id or randomness passed by value.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ByteArray32 {
+    pub inner: [u8; 32],
+}
+
+/// A byte buffer owned by the response it is part of.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ByteBuffer {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Default for ByteBuffer {
+    fn default() -> Self {
+        ByteBuffer {
+            ptr: ptr::null(),
+            len: 0,
+        }
+    }
+}
+
+impl From<Vec<u8>> for ByteBuffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        let bytes = bytes.into_boxed_slice();
+        let len = bytes.len();
+        ByteBuffer {
+            ptr: Box::into_raw(bytes) as *const u8,
+            len,
+        }
+    }
+}
+
+impl Drop for ByteBuffer {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            // Safe, as `ptr` and `len` come from a boxed slice in `from`.
+            unsafe {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                    self.ptr as *mut u8,
+                    self.len,
+                )));
+            }
+        }
+    }
+}
+
+/// A `u64` buffer owned by the response it is part of.
+#[repr(C)]
+#[derive(Debug)]
+pub struct U64Buffer {
+    pub ptr: *const u64,
+    pub len: usize,
+}
+
+impl Default for U64Buffer {
+    fn default() -> Self {
+        U64Buffer {
+            ptr: ptr::null(),
+            len: 0,
+        }
+    }
+}
+
+impl From<Vec<u64>> for U64Buffer {
+    fn from(values: Vec<u64>) -> Self {
+        let values = values.into_boxed_slice();
+        let len = values.len();
+        U64Buffer {
+            ptr: Box::into_raw(values) as *const u64,
+            len,
+        }
+    }
+}
+
+impl Drop for U64Buffer {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            // Safe, as `ptr` and `len` come from a boxed slice in `from`.
+            unsafe {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                    self.ptr as *mut u64,
+                    self.len,
+                )));
+            }
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PublicPieceInfo {
+    /// Unpadded size of the piece in bytes.
+    pub num_bytes: u64,
+    pub comm_p: ByteArray32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PrivateReplicaInfo {
+    pub sector_id: u64,
+    pub comm_r: ByteArray32,
+    pub cache_dir_path: *const c_char,
+    pub replica_path: *const c_char,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PublicReplicaInfo {
+    pub sector_id: u64,
+    pub comm_r: ByteArray32,
+}
+
+/// Implemented by all responses, so that failures can be reported uniformly.
+pub(crate) trait Response: Default {
+    fn set_error(&mut self, status_code: FCPResponseStatus, error_msg: String);
+}
+
+macro_rules! impl_response {
+    ($name:ident { $($field:ident),* }) => {
+        impl Default for $name {
+            fn default() -> Self {
+                $name {
+                    status_code: FCPResponseStatus::FCPNoError,
+                    error_msg: ptr::null(),
+                    $($field: Default::default(),)*
+                }
+            }
+        }
+
+        impl Response for $name {
+            fn set_error(&mut self, status_code: FCPResponseStatus, error_msg: String) {
+                // Drops the partial results along with their buffers.
+                *self = Self::default();
+                self.status_code = status_code;
+                // Interior nul bytes are the only failure, and would truncate the message anyway.
+                self.error_msg = CString::new(error_msg.replace('\0', ""))
+                    .unwrap_or_default()
+                    .into_raw();
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                if !self.error_msg.is_null() {
+                    // Safe, as `error_msg` comes from `CString::into_raw` in `set_error`.
+                    unsafe { drop(CString::from_raw(self.error_msg as *mut c_char)) };
+                }
+            }
+        }
+    };
+}
+
+#[repr(C)]
+pub struct AddPieceResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    pub comm_p: ByteArray32,
+    /// Bytes written to the staged sector, including the alignment before the piece.
+    pub num_bytes_aligned: u64,
+}
+
+impl_response!(AddPieceResponse {
+    comm_p,
+    num_bytes_aligned
+});
+
+#[repr(C)]
+pub struct SealPreCommitPhase1Response {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    /// The JSON encoded phase 1 output, to be passed to `fil_seal_pre_commit_phase2`.
+    pub seal_pre_commit_phase1_output: ByteBuffer,
+}
+
+impl_response!(SealPreCommitPhase1Response {
+    seal_pre_commit_phase1_output
+});
+
+#[repr(C)]
+pub struct SealPreCommitPhase2Response {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    pub registered_proof: RegisteredSealProof,
+    pub comm_r: ByteArray32,
+    pub comm_d: ByteArray32,
+}
+
+impl_response!(SealPreCommitPhase2Response {
+    registered_proof,
+    comm_r,
+    comm_d
+});
+
+#[repr(C)]
+pub struct SealCommitPhase1Response {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    /// The JSON encoded phase 1 output, to be passed to `fil_seal_commit_phase2`.
+    pub seal_commit_phase1_output: ByteBuffer,
+}
+
+impl_response!(SealCommitPhase1Response {
+    seal_commit_phase1_output
+});
+
+#[repr(C)]
+pub struct SealCommitPhase2Response {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    pub proof: ByteBuffer,
+}
+
+impl_response!(SealCommitPhase2Response { proof });
+
+#[repr(C)]
+pub struct VerifySealResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    pub is_valid: bool,
+}
+
+impl_response!(VerifySealResponse { is_valid });
+
+#[repr(C)]
+pub struct UnsealRangeResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+}
+
+impl_response!(UnsealRangeResponse {});
+
+#[repr(C)]
+pub struct ClearCacheResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+}
+
+impl_response!(ClearCacheResponse {});
+
+#[repr(C)]
+pub struct GenerateWinningPoStSectorChallengeResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    /// Indices into the sector set of the challenged sectors.
+    pub sector_indices: U64Buffer,
+}
+
+impl_response!(GenerateWinningPoStSectorChallengeResponse { sector_indices });
+
+#[repr(C)]
+pub struct GeneratePoStResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    pub proof: ByteBuffer,
+}
+
+impl_response!(GeneratePoStResponse { proof });
+
+#[repr(C)]
+pub struct VerifyPoStResponse {
+    pub status_code: FCPResponseStatus,
+    pub error_msg: *const c_char,
+    pub is_valid: bool,
+}
+
+impl_response!(VerifyPoStResponse { is_valid });
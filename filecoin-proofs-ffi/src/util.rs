@@ -0,0 +1,102 @@
+use std::any::Any;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::slice;
+
+use anyhow::Result;
+use log::error;
+
+use crate::types::{FCPResponseStatus, Response};
+
+/// Marks errors caused by invalid arguments, reported as `FCPCallerError`.
+#[derive(Debug)]
+pub(crate) struct CallerError(pub String);
+
+impl fmt::Display for CallerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CallerError {}
+
+/// Runs `f` on a fresh response and hands the response over to the caller, who has to pass it
+/// to the matching destroy function.
+///
+/// Errors and panics never cross the FFI boundary, they end up in the status code and error
+/// message of the response instead.
+pub(crate) fn run<T: Response, F: FnOnce(&mut T) -> Result<()>>(name: &str, f: F) -> *mut T {
+    let mut response = T::default();
+
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut response))) {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            error!("{} failed: {:?}", name, err);
+            let status_code = if err.downcast_ref::<CallerError>().is_some() {
+                FCPResponseStatus::FCPCallerError
+            } else {
+                FCPResponseStatus::FCPUnclassifiedError
+            };
+            response.set_error(status_code, format!("{:?}", err));
+        }
+        Err(panic) => {
+            let msg = panic_message(&*panic);
+            error!("{} panicked: {}", name, msg);
+            response.set_error(FCPResponseStatus::FCPReceiverError, msg);
+        }
+    }
+
+    Box::into_raw(Box::new(response))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "panic with a non-string payload".to_string()
+    }
+}
+
+/// Frees a response returned by `run`.
+///
+/// # Safety
+///
+/// `ptr` must be null or come from `run` and not have been destroyed before.
+pub(crate) unsafe fn destroy<T>(ptr: *mut T) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr));
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or point to a nul-terminated string.
+pub(crate) unsafe fn c_str_to_path(ptr: *const c_char, name: &str) -> Result<PathBuf> {
+    if ptr.is_null() {
+        return Err(CallerError(format!("{} must not be null", name)).into());
+    }
+    let path = CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| CallerError(format!("{} is not valid UTF-8", name)))?;
+
+    Ok(PathBuf::from(path))
+}
+
+/// # Safety
+///
+/// `ptr` must be null or point to `len` initialized values of `T`.
+pub(crate) unsafe fn c_slice<'a, T>(ptr: *const T, len: usize, name: &str) -> Result<&'a [T]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(CallerError(format!("{} must not be null", name)).into());
+    }
+
+    Ok(slice::from_raw_parts(ptr, len))
+}
@@ -0,0 +1,105 @@
+/*
+ * Calls the C API for what needs no parameters: argument checks, error reporting and adding a
+ * piece to a staged sector.
+ *
+ * Usage: abi <work dir>
+ *
+ * Driven by tests/c_api.rs, which compiles it against the cdylib.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+
+#include "filecoin_proofs_ffi.h"
+
+#define PIECE_NUM_BYTES 127
+
+#define EXPECT(cond)                                                                   \
+  do {                                                                                 \
+    if (!(cond)) {                                                                     \
+      fprintf(stderr, "%s:%d: expected %s\n", __FILE__, __LINE__, #cond);              \
+      exit(1);                                                                         \
+    }                                                                                  \
+  } while (0)
+
+static char *join(const char *dir, const char *name) {
+  char *path = malloc(strlen(dir) + strlen(name) + 2);
+  sprintf(path, "%s/%s", dir, name);
+  return path;
+}
+
+int main(int argc, char **argv) {
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s <work dir>\n", argv[0]);
+    return 2;
+  }
+
+  /* The enum values are the registered proof ids. */
+  EXPECT(fil_RegisteredSealProof_StackedDrg2KiBV1 == 0);
+  EXPECT(fil_RegisteredSealProof_StackedDrg2KiBV1_1 == 5);
+  EXPECT(fil_RegisteredPoStProof_StackedDrgWindow2KiBV1 == 5);
+
+  char *piece_path = join(argv[1], "piece");
+  char *staged_sector_path = join(argv[1], "staged");
+
+  /* Unknown proofs and null paths are caller errors. */
+  fil_ClearCacheResponse *clear_cache = fil_clear_cache(42, argv[1]);
+  EXPECT(clear_cache->status_code == fil_FCPResponseStatus_FCPCallerError);
+  EXPECT(strstr(clear_cache->error_msg, "unknown registered seal proof 42") != NULL);
+  fil_destroy_clear_cache_response(clear_cache);
+
+  clear_cache = fil_clear_cache(fil_RegisteredSealProof_StackedDrg2KiBV1_1, NULL);
+  EXPECT(clear_cache->status_code == fil_FCPResponseStatus_FCPCallerError);
+  fil_destroy_clear_cache_response(clear_cache);
+
+  fil_GenerateWinningPoStSectorChallengeResponse *challenge =
+      fil_generate_winning_post_sector_challenge(1ULL << 40, (fil_ByteArray32){{0}}, 10,
+                                                 (fil_ByteArray32){{0}});
+  EXPECT(challenge->status_code == fil_FCPResponseStatus_FCPCallerError);
+  fil_destroy_generate_winning_post_sector_challenge_response(challenge);
+
+  /* All-zero commitments are rejected by the proofs, not by the bindings. */
+  uint8_t proof[192] = {0};
+  fil_VerifySealResponse *verify_seal =
+      fil_verify_seal(fil_RegisteredSealProof_StackedDrg2KiBV1_1, (fil_ByteArray32){{0}},
+                      (fil_ByteArray32){{0}}, (fil_ByteArray32){{0}}, (fil_ByteArray32){{0}},
+                      (fil_ByteArray32){{0}}, 0, proof, sizeof(proof));
+  EXPECT(verify_seal->status_code == fil_FCPResponseStatus_FCPUnclassifiedError);
+  EXPECT(!verify_seal->is_valid);
+  fil_destroy_verify_seal_response(verify_seal);
+
+  uint8_t piece[PIECE_NUM_BYTES];
+  memset(piece, 7, sizeof(piece));
+  FILE *piece_file = fopen(piece_path, "wb");
+  if (piece_file == NULL || fwrite(piece, 1, sizeof(piece), piece_file) != sizeof(piece)) {
+    fprintf(stderr, "could not write %s\n", piece_path);
+    return 1;
+  }
+  fclose(piece_file);
+
+  fil_AddPieceResponse *add_piece =
+      fil_add_piece(fil_RegisteredSealProof_StackedDrg2KiBV1_1, piece_path, staged_sector_path,
+                    PIECE_NUM_BYTES, NULL, 0);
+  EXPECT(add_piece->status_code == fil_FCPResponseStatus_FCPNoError);
+  EXPECT(add_piece->num_bytes_aligned == PIECE_NUM_BYTES);
+  fil_destroy_add_piece_response(add_piece);
+
+  /* The padded piece is on disk once the call returns. */
+  struct stat staged;
+  EXPECT(stat(staged_sector_path, &staged) == 0);
+  EXPECT(staged.st_size == 128);
+
+  /* A second piece does not fit. */
+  uint64_t existing_piece_sizes[] = {2032};
+  add_piece = fil_add_piece(fil_RegisteredSealProof_StackedDrg2KiBV1_1, piece_path,
+                            staged_sector_path, PIECE_NUM_BYTES, existing_piece_sizes, 1);
+  EXPECT(add_piece->status_code == fil_FCPResponseStatus_FCPCallerError);
+  fil_destroy_add_piece_response(add_piece);
+
+  free(piece_path);
+  free(staged_sector_path);
+
+  return 0;
+}
@@ -0,0 +1,174 @@
+/*
+ * Seals, verifies and unseals a 2 KiB sector through the C API.
+ *
+ * Usage: seal_2kib <work dir>
+ *
+ * Driven by tests/c_api.rs, which compiles it against the cdylib.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+
+#include "filecoin_proofs_ffi.h"
+
+#define PIECE_NUM_BYTES 2032
+#define SECTOR_ID 42
+
+#define CHECK(response, name)                                                          \
+  do {                                                                                 \
+    if ((response)->status_code != fil_FCPResponseStatus_FCPNoError) {                 \
+      fprintf(stderr, "%s failed with status %d: %s\n", (name),                       \
+              (int)(response)->status_code, (response)->error_msg);                    \
+      exit(1);                                                                         \
+    }                                                                                  \
+  } while (0)
+
+static fil_ByteArray32 filled(uint8_t start) {
+  fil_ByteArray32 bytes;
+  for (int i = 0; i < 32; i++) {
+    bytes.inner[i] = (uint8_t)(start + i);
+  }
+  /* Keeps the value below the field modulus, as the prover id has to be a field element. */
+  bytes.inner[31] = 0;
+  return bytes;
+}
+
+static char *join(const char *dir, const char *name) {
+  char *path = malloc(strlen(dir) + strlen(name) + 2);
+  sprintf(path, "%s/%s", dir, name);
+  return path;
+}
+
+int main(int argc, char **argv) {
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s <work dir>\n", argv[0]);
+    return 2;
+  }
+
+  const fil_RegisteredSealProof registered_proof = fil_RegisteredSealProof_StackedDrg2KiBV1_1;
+  char *piece_path = join(argv[1], "piece");
+  char *staged_sector_path = join(argv[1], "staged");
+  char *sealed_sector_path = join(argv[1], "sealed");
+  char *unsealed_path = join(argv[1], "unsealed");
+  char *cache_dir_path = join(argv[1], "cache");
+
+  if (mkdir(cache_dir_path, 0755) != 0) {
+    fprintf(stderr, "could not create %s\n", cache_dir_path);
+    return 1;
+  }
+
+  fil_ByteArray32 prover_id = filled(1);
+  fil_ByteArray32 ticket = filled(2);
+  fil_ByteArray32 seed = filled(3);
+
+  uint8_t piece[PIECE_NUM_BYTES];
+  srand(SECTOR_ID);
+  for (int i = 0; i < PIECE_NUM_BYTES; i++) {
+    piece[i] = (uint8_t)rand();
+  }
+  FILE *piece_file = fopen(piece_path, "wb");
+  if (piece_file == NULL || fwrite(piece, 1, sizeof(piece), piece_file) != sizeof(piece)) {
+    fprintf(stderr, "could not write %s\n", piece_path);
+    return 1;
+  }
+  fclose(piece_file);
+
+  /* Sealing replicates into an existing file. */
+  FILE *sealed_sector_file = fopen(sealed_sector_path, "wb");
+  if (sealed_sector_file == NULL) {
+    fprintf(stderr, "could not create %s\n", sealed_sector_path);
+    return 1;
+  }
+  fclose(sealed_sector_file);
+
+  /* Invalid arguments are reported as caller errors instead of crashing. */
+  fil_ClearCacheResponse *clear_cache = fil_clear_cache(registered_proof, NULL);
+  if (clear_cache->status_code != fil_FCPResponseStatus_FCPCallerError ||
+      clear_cache->error_msg == NULL) {
+    fprintf(stderr, "a null path should be a caller error\n");
+    return 1;
+  }
+  fil_destroy_clear_cache_response(clear_cache);
+
+  fil_AddPieceResponse *add_piece = fil_add_piece(registered_proof, piece_path,
+                                                  staged_sector_path, PIECE_NUM_BYTES, NULL, 0);
+  CHECK(add_piece, "fil_add_piece");
+  fil_PublicPieceInfo pieces[1] = {{PIECE_NUM_BYTES, add_piece->comm_p}};
+  fil_destroy_add_piece_response(add_piece);
+
+  fil_SealPreCommitPhase1Response *pre_commit_phase1 =
+      fil_seal_pre_commit_phase1(registered_proof, cache_dir_path, staged_sector_path,
+                                 sealed_sector_path, SECTOR_ID, prover_id, ticket, pieces, 1);
+  CHECK(pre_commit_phase1, "fil_seal_pre_commit_phase1");
+
+  fil_SealPreCommitPhase2Response *pre_commit_phase2 = fil_seal_pre_commit_phase2(
+      pre_commit_phase1->seal_pre_commit_phase1_output.ptr,
+      pre_commit_phase1->seal_pre_commit_phase1_output.len, cache_dir_path, sealed_sector_path);
+  CHECK(pre_commit_phase2, "fil_seal_pre_commit_phase2");
+  fil_destroy_seal_pre_commit_phase1_response(pre_commit_phase1);
+  fil_ByteArray32 comm_r = pre_commit_phase2->comm_r;
+  fil_ByteArray32 comm_d = pre_commit_phase2->comm_d;
+  fil_destroy_seal_pre_commit_phase2_response(pre_commit_phase2);
+
+  fil_SealCommitPhase1Response *commit_phase1 =
+      fil_seal_commit_phase1(registered_proof, comm_r, comm_d, cache_dir_path,
+                             sealed_sector_path, SECTOR_ID, prover_id, ticket, seed, pieces, 1);
+  CHECK(commit_phase1, "fil_seal_commit_phase1");
+
+  fil_SealCommitPhase2Response *commit_phase2 =
+      fil_seal_commit_phase2(commit_phase1->seal_commit_phase1_output.ptr,
+                             commit_phase1->seal_commit_phase1_output.len, SECTOR_ID, prover_id);
+  CHECK(commit_phase2, "fil_seal_commit_phase2");
+  fil_destroy_seal_commit_phase1_response(commit_phase1);
+
+  fil_VerifySealResponse *verify_seal =
+      fil_verify_seal(registered_proof, comm_r, comm_d, prover_id, ticket, seed, SECTOR_ID,
+                      commit_phase2->proof.ptr, commit_phase2->proof.len);
+  CHECK(verify_seal, "fil_verify_seal");
+  if (!verify_seal->is_valid) {
+    fprintf(stderr, "the seal proof is invalid\n");
+    return 1;
+  }
+  fil_destroy_verify_seal_response(verify_seal);
+
+  verify_seal = fil_verify_seal(registered_proof, comm_r, comm_d, prover_id, ticket, ticket,
+                                SECTOR_ID, commit_phase2->proof.ptr, commit_phase2->proof.len);
+  CHECK(verify_seal, "fil_verify_seal");
+  if (verify_seal->is_valid) {
+    fprintf(stderr, "the seal proof is valid for the wrong seed\n");
+    return 1;
+  }
+  fil_destroy_verify_seal_response(verify_seal);
+  fil_destroy_seal_commit_phase2_response(commit_phase2);
+
+  fil_UnsealRangeResponse *unseal_range =
+      fil_unseal_range(registered_proof, cache_dir_path, sealed_sector_path, unsealed_path,
+                       SECTOR_ID, prover_id, ticket, comm_d, 0, PIECE_NUM_BYTES);
+  CHECK(unseal_range, "fil_unseal_range");
+  fil_destroy_unseal_range_response(unseal_range);
+
+  uint8_t unsealed[PIECE_NUM_BYTES];
+  FILE *unsealed_file = fopen(unsealed_path, "rb");
+  if (unsealed_file == NULL ||
+      fread(unsealed, 1, sizeof(unsealed), unsealed_file) != sizeof(unsealed) ||
+      memcmp(piece, unsealed, sizeof(piece)) != 0) {
+    fprintf(stderr, "the unsealed data does not match the piece\n");
+    return 1;
+  }
+  fclose(unsealed_file);
+
+  clear_cache = fil_clear_cache(registered_proof, cache_dir_path);
+  CHECK(clear_cache, "fil_clear_cache");
+  fil_destroy_clear_cache_response(clear_cache);
+
+  free(piece_path);
+  free(staged_sector_path);
+  free(sealed_sector_path);
+  free(unsealed_path);
+  free(cache_dir_path);
+
+  printf("sealed, verified and unsealed a 2 KiB sector\n");
+  return 0;
+}
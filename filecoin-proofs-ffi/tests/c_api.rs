@@ -0,0 +1,116 @@
+use std::env;
+use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::ptr;
+
+use filecoin_proofs_ffi::{
+    fil_clear_cache, fil_destroy_clear_cache_response, fil_destroy_verify_seal_response,
+    fil_verify_seal, ByteArray32, FCPResponseStatus, RegisteredSealProof,
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_caller_errors() {
+    unsafe {
+        let response = fil_clear_cache(RegisteredSealProof::StackedDrg2KiBV1_1 as u64, ptr::null());
+        assert_eq!((*response).status_code, FCPResponseStatus::FCPCallerError);
+        let error_msg = CStr::from_ptr((*response).error_msg).to_str().unwrap();
+        assert!(error_msg.contains("cache_dir_path must not be null"));
+        fil_destroy_clear_cache_response(response);
+
+        let response = fil_verify_seal(
+            RegisteredSealProof::StackedDrg2KiBV1_1 as u64,
+            ByteArray32::default(),
+            ByteArray32::default(),
+            ByteArray32::default(),
+            ByteArray32::default(),
+            ByteArray32::default(),
+            0,
+            ptr::null(),
+            192,
+        );
+        assert_eq!((*response).status_code, FCPResponseStatus::FCPCallerError);
+        assert!(!(*response).is_valid);
+        fil_destroy_verify_seal_response(response);
+    }
+}
+
+#[test]
+fn test_unclassified_errors() {
+    unsafe {
+        let proof = [0u8; 192];
+        let response = fil_verify_seal(
+            RegisteredSealProof::StackedDrg2KiBV1_1 as u64,
+            ByteArray32::default(),
+            ByteArray32::default(),
+            ByteArray32::default(),
+            ByteArray32::default(),
+            ByteArray32::default(),
+            0,
+            proof.as_ptr(),
+            proof.len(),
+        );
+        // All-zero commitments are rejected by the proofs, not by the bindings.
+        assert_eq!(
+            (*response).status_code,
+            FCPResponseStatus::FCPUnclassifiedError
+        );
+        assert!(!(*response).error_msg.is_null());
+        fil_destroy_verify_seal_response(response);
+    }
+}
+
+/// The cdylib is built next to the test binaries, as cargo compiles the library with all of its
+/// crate types.
+fn library_dir() -> PathBuf {
+    env::current_exe()
+        .expect("failed to find the test binary")
+        .parent()
+        .expect("test binary has no parent directory")
+        .to_path_buf()
+}
+
+/// Compiles `tests/c/<name>.c` against the committed header and the cdylib, and runs it on a
+/// fresh work dir.
+fn run_c_harness(name: &str) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let work_dir = tempdir().expect("failed to create the work dir");
+    let harness = work_dir.path().join(name);
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest_dir.join("tests/c").join(format!("{}.c", name)))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .arg("-lfilecoin_proofs_ffi")
+        .arg("-o")
+        .arg(&harness)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile the C harness");
+
+    // cargo puts the target dir on the library path, whose copy of the cdylib is only refreshed
+    // by `cargo build` and would take precedence over the rpath.
+    let status = Command::new(&harness)
+        .arg(work_dir.path())
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .env("DYLD_LIBRARY_PATH", &library_dir)
+        .status()
+        .expect("failed to run the C harness");
+    assert!(status.success(), "the C harness failed");
+}
+
+#[test]
+fn test_c_abi() {
+    run_c_harness("abi");
+}
+
+#[test]
+#[ignore]
+fn test_c_seal_2kib() {
+    run_c_harness("seal_2kib");
+}
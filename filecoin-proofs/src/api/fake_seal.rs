@@ -1,19 +1,40 @@
+use std::cmp::min;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
-use anyhow::{Context, Result};
-use bincode::serialize;
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize};
 use filecoin_hashers::{Domain, Hasher};
-use rand::{thread_rng, Rng};
-use storage_proofs_core::{cache_key::CacheKey, merkle::MerkleTreeTrait};
+use log::info;
+use merkletree::store::StoreConfig;
+use rand::{thread_rng, Rng, RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::MerkleTreeTrait,
+    util::{default_rows_to_discard, NODE_SIZE},
+};
 use storage_proofs_porep::stacked::StackedDrg;
 
 use crate::{
-    constants::DefaultPieceHasher,
-    types::{Commitment, PaddedBytesAmount, PoRepConfig},
+    api::{add_piece, SparseFileReader, SparseFileWriter},
+    constants::{
+        DefaultPieceHasher,
+        MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
+    },
+    pieces::{compute_comm_d, get_piece_start_byte, piece_hash},
+    types::{
+        Commitment, FauxPiece, FauxrepOutput, PaddedBytesAmount, PoRepConfig, UnpaddedBytesAmount,
+        BINARY_ARITY,
+    },
 };
 
+/// Number of tree-d nodes `build_faux_tree_d` reads and hashes at once.
+const TREE_D_CHUNK_NODES: usize = 1 << 16;
+
 pub fn fauxrep<R: AsRef<Path>, S: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     cache_path: R,
@@ -81,3 +102,232 @@ pub fn fauxrep2<R: AsRef<Path>, S: AsRef<Path>, Tree: 'static + MerkleTreeTrait>
     commitment[..].copy_from_slice(&comm_r.into_bytes()[..]);
     Ok(commitment)
 }
+
+/// Deterministically creates a faux sector holding `pieces`: the unsealed data at
+/// `unsealed_path`, tree-d in `cache_path` and a faux replica at `out_path` with the cache
+/// `fauxrep` creates.
+///
+/// As opposed to comm_r, comm_d is real, it matches `compute_comm_d` of the returned piece
+/// infos. The same seed and pieces always result in the same files and commitments.
+pub fn fauxrep_with_pieces<R, S, T, Tree>(
+    porep_config: PoRepConfig,
+    seed: &[u8; 32],
+    cache_path: R,
+    unsealed_path: S,
+    out_path: T,
+    pieces: &[FauxPiece],
+) -> Result<FauxrepOutput>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+    Tree: 'static + MerkleTreeTrait,
+{
+    info!("fauxrep_with_pieces:start");
+
+    let sector_bytes = PaddedBytesAmount::from(porep_config);
+    let unsealed = File::create(&unsealed_path).with_context(|| {
+        format!(
+            "could not create unsealed_path={:?}",
+            unsealed_path.as_ref().display()
+        )
+    })?;
+    let mut unsealed = SparseFileWriter::new(unsealed)?;
+
+    let mut piece_infos = Vec::with_capacity(pieces.len());
+    let mut piece_sizes = Vec::with_capacity(pieces.len());
+    // Roots of the pieces with generated data, as tree-d level, node index and node.
+    let mut piece_roots = Vec::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        let (piece_size, source): (_, Box<dyn Read>) = match piece {
+            FauxPiece::File(path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("could not open piece {:?}", path.display()))?;
+                let len = file.metadata()?.len();
+                let piece_size = faux_piece_size(len);
+                let padding = io::repeat(0).take(u64::from(piece_size) - len);
+                (piece_size, Box::new(file.take(len).chain(padding)))
+            }
+            FauxPiece::Info(piece_info) => {
+                let rng = seeded_rng(seed, format!("piece-{}", i).as_bytes());
+                let data = RngReader(rng).take(u64::from(piece_info.size));
+                (piece_info.size, Box::new(data))
+            }
+        };
+
+        let start = UnpaddedBytesAmount::from(get_piece_start_byte(&piece_sizes, piece_size));
+        ensure!(
+            start + piece_size <= UnpaddedBytesAmount::from(sector_bytes),
+            "pieces do not fit into a sector of {} bytes",
+            u64::from(sector_bytes)
+        );
+
+        let (mut piece_info, _) = add_piece(source, &mut unsealed, piece_size, &piece_sizes)?;
+        if let FauxPiece::Info(info) = piece {
+            let leafs = u64::from(PaddedBytesAmount::from(piece_size)) / NODE_SIZE as u64;
+            let level = leafs.trailing_zeros() as usize;
+            let index = (u64::from(PaddedBytesAmount::from(start)) / NODE_SIZE as u64) >> level;
+            piece_roots.push((level, index as usize, info.commitment));
+            piece_info = info.clone();
+        }
+
+        piece_sizes.push(piece_size);
+        piece_infos.push(piece_info);
+    }
+    // Zero-pad the data to the sector size, leaving a hole.
+    unsealed.into_inner()?.set_len(u64::from(sector_bytes))?;
+
+    let base_tree_leafs = usize::from(sector_bytes) / NODE_SIZE;
+    let config = StoreConfig::new(
+        cache_path.as_ref(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    let comm_d = build_faux_tree_d(unsealed_path.as_ref(), &config, &piece_roots)?;
+    write_faux_piece_roots(cache_path.as_ref(), &piece_roots)?;
+    ensure!(
+        comm_d == compute_comm_d(porep_config.sector_size, &piece_infos)?,
+        "faux tree-d does not match the pieces"
+    );
+
+    let comm_r = fauxrep_aux::<_, _, _, Tree>(
+        &mut seeded_rng(seed, b"comm_c"),
+        porep_config,
+        cache_path,
+        out_path,
+    )?;
+
+    info!("fauxrep_with_pieces:finish");
+    Ok(FauxrepOutput {
+        comm_r,
+        comm_d,
+        piece_infos,
+    })
+}
+
+/// Records the tree-d nodes of the generated pieces in `cache_path`, so that no inclusion
+/// proofs are generated for the nodes below them.
+fn write_faux_piece_roots(
+    cache_path: &Path,
+    piece_roots: &[(usize, usize, Commitment)],
+) -> Result<()> {
+    let roots: Vec<(usize, u64)> = piece_roots
+        .iter()
+        .map(|(level, index, _)| (*level, *index as u64))
+        .collect();
+    let roots_path = cache_path.join(CacheKey::FauxPieceRoots.to_string());
+    std::fs::write(&roots_path, serialize(&roots)?)
+        .with_context(|| format!("could not write {:?}", roots_path.display()))
+}
+
+/// Reads the tree-d nodes of generated pieces recorded by `fauxrep_with_pieces` in
+/// `cache_path`, as level and index. Returns none for the tree-d of any other sector.
+pub(crate) fn read_faux_piece_roots(cache_path: &Path) -> Result<Vec<(usize, u64)>> {
+    let roots_path = cache_path.join(CacheKey::FauxPieceRoots.to_string());
+    if !roots_path.exists() {
+        return Ok(Vec::new());
+    }
+    let bytes = std::fs::read(&roots_path)
+        .with_context(|| format!("could not read {:?}", roots_path.display()))?;
+    Ok(deserialize(&bytes)?)
+}
+
+/// The smallest valid piece size which holds `len` unpadded bytes.
+fn faux_piece_size(len: u64) -> UnpaddedBytesAmount {
+    let mut padded = PaddedBytesAmount::from(UnpaddedBytesAmount(MINIMUM_PIECE_SIZE));
+    while u64::from(UnpaddedBytesAmount::from(padded)) < len {
+        padded = padded + padded;
+    }
+    UnpaddedBytesAmount::from(padded)
+}
+
+/// Rng for one `purpose` of the faux sector of `seed`.
+fn seeded_rng(seed: &[u8; 32], purpose: &[u8]) -> XorShiftRng {
+    let digest = Sha256::new().chain(seed).chain(purpose).finalize();
+    let mut rng_seed = [0u8; 16];
+    rng_seed.copy_from_slice(&digest[..16]);
+    XorShiftRng::from_seed(rng_seed)
+}
+
+/// An endless source of random bytes.
+struct RngReader<R>(R);
+
+impl<R: RngCore> Read for RngReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.fill_bytes(buf);
+        Ok(buf.len())
+    }
+}
+
+/// Writes tree-d over the unsealed data to the store of `config` and returns its root.
+///
+/// The nodes in `piece_roots` (level, index, node) replace the computed ones before the
+/// levels above them are hashed. Like the tree-d of sealing, the tree is built level by level,
+/// reading the previous level back from the store in chunks of `TREE_D_CHUNK_NODES`.
+fn build_faux_tree_d(
+    unsealed_path: &Path,
+    config: &StoreConfig,
+    piece_roots: &[(usize, usize, Commitment)],
+) -> Result<Commitment> {
+    fn hash_nodes(left: &[u8], right: &[u8]) -> Commitment {
+        let mut node = [0u8; 32];
+        node.copy_from_slice(AsRef::<[u8]>::as_ref(&piece_hash(left, right)));
+        node
+    }
+
+    let unsealed = File::open(unsealed_path)
+        .with_context(|| format!("could not open unsealed_path={:?}", unsealed_path.display()))?;
+    let mut unsealed = SparseFileReader::new(unsealed)?;
+
+    // The leafs are a copy of the unsealed data, of which the holes stay holes.
+    let tree_d_path = StoreConfig::data_path(&config.path, &config.id);
+    let mut tree_d = File::create(&tree_d_path)
+        .with_context(|| format!("could not create tree-d={:?}", tree_d_path.display()))?;
+    let leafs = unsealed.copy_to(&mut tree_d)? as usize / NODE_SIZE;
+    let mut tree_d = BufWriter::new(tree_d);
+    let mut tree_d_reader = File::open(&tree_d_path)
+        .with_context(|| format!("could not open tree-d={:?}", tree_d_path.display()))?;
+
+    ensure!(
+        leafs > 1 && leafs.is_power_of_two(),
+        "unsealed_path={:?} is not a sector",
+        unsealed_path.display()
+    );
+
+    let mut buf = vec![0u8; min(leafs, TREE_D_CHUNK_NODES) * NODE_SIZE];
+    let mut root = [0u8; 32];
+    let mut level_start = 0;
+    let mut level_len = leafs;
+    let mut level = 0;
+    while level_len > 1 {
+        tree_d.flush()?;
+        tree_d_reader.seek(SeekFrom::Start((level_start * NODE_SIZE) as u64))?;
+        level += 1;
+
+        for chunk_start in (0..level_len).step_by(TREE_D_CHUNK_NODES) {
+            let chunk = &mut buf[..min(TREE_D_CHUNK_NODES, level_len - chunk_start) * NODE_SIZE];
+            tree_d_reader.read_exact(chunk)?;
+
+            let mut nodes: Vec<Commitment> = chunk
+                .par_chunks(2 * NODE_SIZE)
+                .map(|pair| hash_nodes(&pair[..NODE_SIZE], &pair[NODE_SIZE..]))
+                .collect();
+            let first = chunk_start / 2;
+            for (_, index, node) in piece_roots.iter().filter(|root| root.0 == level) {
+                if (first..first + nodes.len()).contains(index) {
+                    nodes[index - first] = *node;
+                }
+            }
+            for node in &nodes {
+                tree_d.write_all(node)?;
+            }
+            root = nodes[nodes.len() - 1];
+        }
+
+        level_start += level_len;
+        level_len /= 2;
+    }
+    tree_d.flush()?;
+
+    Ok(root)
+}
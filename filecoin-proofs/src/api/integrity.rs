@@ -0,0 +1,646 @@
+use std::cmp::min;
+use std::fs::{self, metadata, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use bincode::deserialize;
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use fr32::Fr32Reader;
+use log::{info, trace};
+use memmap::MmapOptions;
+use merkletree::{
+    hash::Algorithm,
+    merkle::{get_merkle_tree_cache_size, get_merkle_tree_len, is_merkle_tree_size_valid},
+    store::{DiskStore, ExternalReader, LevelCacheStore, ReplicaConfig, Store, StoreConfig},
+};
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{
+        create_disk_tree, get_base_tree_count, split_config, split_config_and_replica, DiskTree,
+        MerkleProofTrait, MerkleTreeTrait,
+    },
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_porep::stacked::{PersistentAux, TemporaryAux};
+use typenum::Unsigned;
+
+use crate::{
+    api::{as_safe_commitment, commitment_from_fr, load_t_aux, SparseFileReader},
+    constants::{DefaultPieceDomain, DefaultPieceHasher, LAYERS},
+    parallel_commitment::{
+        generate_subtree_commitment_parallel, DenseSource, SubtreeSource, SubtreeStack,
+    },
+    pieces::{compute_comm_d, get_piece_start_byte, sum_piece_bytes_with_alignment, EmptySource},
+    types::{
+        CacheFileStatus, ColumnSample, Commitment, IntegrityCheck, PaddedBytesAmount, PieceInfo,
+        PoRepConfig, SectorIntegrityReport, SectorSize, TreeRLastReport, TreeRLastRoot,
+        UnpaddedBytesAmount, UnsealedFormat, UnsealedSectorReport, BINARY_ARITY,
+    },
+};
+
+/// Number of tree-c leaves compared against the label columns.
+const INTEGRITY_COLUMN_SAMPLES: usize = 16;
+
+/// Upper bound on the number of replica nodes hashed by one task when recomputing a
+/// tree-r-last root.
+const ROOT_CHUNK_LEAFS: usize = 1 << 16;
+
+/// Audits a sealed sector's replica and cache directory without modifying either.
+///
+/// The tree-r-last roots are recomputed from the replica and compared with the cached
+/// stores and with the comm_r_last recorded in `p_aux`, comm_r and comm_d are checked
+/// against the cached commitments, and, while the labels are still present, a sample
+/// of tree-c leaves is checked against the label columns. Problems with the cache are
+/// recorded in the returned report; only a missing or wrongly sized replica is an error.
+pub fn check_sector_integrity<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_dir: &Path,
+    replica_path: &Path,
+    comm_r: Commitment,
+    comm_d: Commitment,
+) -> Result<SectorIntegrityReport> {
+    info!("check_sector_integrity:start");
+
+    let sector_bytes = u64::from(porep_config.sector_size);
+    let nodes = sector_bytes as usize / NODE_SIZE;
+    let tree_count = get_base_tree_count::<Tree>();
+    let base_tree_leafs = nodes / tree_count;
+    let arity = Tree::Arity::to_usize();
+    let layers = *LAYERS
+        .read()
+        .expect("LAYERS poisoned")
+        .get(&sector_bytes)
+        .with_context(|| format!("unknown sector size {}", sector_bytes))?;
+
+    ensure_replica_len(replica_path, sector_bytes)?;
+
+    let p_aux = read_cache_file::<PersistentAux<<Tree::Hasher as Hasher>::Domain>>(
+        cache_dir,
+        CacheKey::PAux,
+    );
+    let t_aux = load_t_aux::<Tree>(cache_dir);
+
+    let tree_r_last_rows_to_discard = match &t_aux {
+        Ok(t_aux) => t_aux.tree_r_last_config.rows_to_discard,
+        Err(_) => default_rows_to_discard(base_tree_leafs, arity),
+    };
+    let base_tree_len = get_merkle_tree_len(base_tree_leafs, arity)?;
+    let tree_r_last_cache_len =
+        get_merkle_tree_cache_size(base_tree_leafs, arity, tree_r_last_rows_to_discard)?;
+    let tree_d_len = get_merkle_tree_len(nodes, BINARY_ARITY)?;
+
+    let mut cache_files = Vec::new();
+    for key in &[CacheKey::PAux, CacheKey::TAux] {
+        let id = key.to_string();
+        let path = cache_dir.join(&id);
+        cache_files.push(cache_file_status(id, path, true, None));
+    }
+    cache_files.push(store_status(
+        cache_dir,
+        CacheKey::CommDTree.to_string(),
+        false,
+        tree_d_len,
+    ));
+    for id in split_ids(CacheKey::CommCTree, tree_count) {
+        cache_files.push(store_status(cache_dir, id, false, base_tree_len));
+    }
+    for id in split_ids(CacheKey::CommRLastTree, tree_count) {
+        cache_files.push(store_status(cache_dir, id, true, tree_r_last_cache_len));
+    }
+    for layer in 1..=layers {
+        cache_files.push(store_status(
+            cache_dir,
+            CacheKey::label_layer(layer),
+            false,
+            nodes,
+        ));
+    }
+    let is_valid = |id: &str| {
+        cache_files
+            .iter()
+            .any(|file| file.id == id && file.is_valid())
+    };
+
+    let tree_r_last = tree_r_last_report::<Tree>(
+        cache_dir,
+        replica_path,
+        nodes,
+        tree_r_last_rows_to_discard,
+        p_aux.as_ref().ok(),
+    )?;
+
+    let (comm_r_last_check, comm_r_check) = match &p_aux {
+        Ok(p_aux) => {
+            let comm_r_last_check = if tree_r_last.p_aux_matches() {
+                IntegrityCheck::Passed
+            } else {
+                IntegrityCheck::Failed(
+                    "comm_r_last recomputed from the replica does not match p_aux".to_string(),
+                )
+            };
+            let comm_r_check = match as_safe_commitment(&comm_r, "comm_r") {
+                Ok(comm_r) => {
+                    let cached_comm_r = <Tree::Hasher as Hasher>::Function::hash2(
+                        &p_aux.comm_c,
+                        &p_aux.comm_r_last,
+                    );
+                    if cached_comm_r == comm_r {
+                        IntegrityCheck::Passed
+                    } else {
+                        IntegrityCheck::Failed(
+                            "comm_r does not match H(comm_c, comm_r_last) from p_aux".to_string(),
+                        )
+                    }
+                }
+                Err(err) => IntegrityCheck::Failed(format!("{:#}", err)),
+            };
+
+            (comm_r_last_check, comm_r_check)
+        }
+        Err(_) => (
+            IntegrityCheck::Skipped("p_aux is unavailable".to_string()),
+            IntegrityCheck::Skipped("p_aux is unavailable".to_string()),
+        ),
+    };
+
+    let tree_d_id = CacheKey::CommDTree.to_string();
+    let comm_d_check = if is_valid(&tree_d_id) {
+        match check_tree_d_root(cache_dir, &tree_d_id, tree_d_len, &comm_d) {
+            Ok(true) => IntegrityCheck::Passed,
+            Ok(false) => IntegrityCheck::Failed("tree-d root does not match comm_d".to_string()),
+            Err(err) => IntegrityCheck::Failed(format!("{:#}", err)),
+        }
+    } else {
+        IntegrityCheck::Skipped("tree-d is not in the cache".to_string())
+    };
+
+    let labels_present = (1..=layers).all(|layer| is_valid(&CacheKey::label_layer(layer)))
+        && split_ids(CacheKey::CommCTree, tree_count)
+            .iter()
+            .all(|id| is_valid(id));
+    let (columns_check, column_samples) = match &t_aux {
+        Ok(t_aux) if labels_present => {
+            match sample_columns(t_aux, base_tree_len, tree_count, nodes, &comm_r) {
+                Ok(samples) => {
+                    let mismatches = samples.iter().filter(|sample| !sample.matches).count();
+                    let check = if mismatches == 0 {
+                        IntegrityCheck::Passed
+                    } else {
+                        IntegrityCheck::Failed(format!(
+                            "{} of {} sampled tree-c leaves do not match the label columns",
+                            mismatches,
+                            samples.len()
+                        ))
+                    };
+                    (check, samples)
+                }
+                Err(err) => (IntegrityCheck::Failed(format!("{:#}", err)), Vec::new()),
+            }
+        }
+        Ok(_) => (
+            IntegrityCheck::Skipped("labels or tree-c are not in the cache".to_string()),
+            Vec::new(),
+        ),
+        Err(_) => (
+            IntegrityCheck::Skipped("t_aux is unavailable".to_string()),
+            Vec::new(),
+        ),
+    };
+
+    let report = SectorIntegrityReport {
+        p_aux: load_check(&p_aux),
+        t_aux: load_check(&t_aux),
+        cache_files,
+        tree_r_last_roots: tree_r_last.roots,
+        comm_r_last: comm_r_last_check,
+        comm_r: comm_r_check,
+        comm_d: comm_d_check,
+        columns: columns_check,
+        column_samples,
+    };
+
+    info!("check_sector_integrity:finish");
+    Ok(report)
+}
+
+/// Checks a sealed sector's tree-r-last against its replica, without modifying either.
+///
+/// This is the part of `check_sector_integrity` which needs neither comm_r nor comm_d, for
+/// sectors of which only the replica and the cache directory are at hand.
+pub fn check_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    sector_size: SectorSize,
+    cache_dir: &Path,
+    replica_path: &Path,
+) -> Result<TreeRLastReport> {
+    info!("check_tree_r_last:start");
+
+    let sector_bytes = u64::from(sector_size);
+    let nodes = sector_bytes as usize / NODE_SIZE;
+    ensure_replica_len(replica_path, sector_bytes)?;
+
+    let p_aux = read_cache_file::<PersistentAux<<Tree::Hasher as Hasher>::Domain>>(
+        cache_dir,
+        CacheKey::PAux,
+    )
+    .ok();
+    let rows_to_discard = match load_t_aux::<Tree>(cache_dir) {
+        Ok(t_aux) => t_aux.tree_r_last_config.rows_to_discard,
+        Err(_) => default_rows_to_discard(
+            nodes / get_base_tree_count::<Tree>(),
+            Tree::Arity::to_usize(),
+        ),
+    };
+
+    let report = tree_r_last_report::<Tree>(
+        cache_dir,
+        replica_path,
+        nodes,
+        rows_to_discard,
+        p_aux.as_ref(),
+    )?;
+
+    info!("check_tree_r_last:finish");
+    Ok(report)
+}
+
+/// Checks an unsealed sector file of `format` against the comm_d the sector was sealed with and
+/// against the commitments of its pieces.
+///
+/// Raw bytes are bit padded again before hashing, the holes of a padded file are not hashed.
+/// Tree-d is rebuilt one aligned subtree at a time, so that memory use does not grow with the
+/// sector size.
+pub fn verify_unsealed_sector<P: AsRef<Path>>(
+    sector_size: SectorSize,
+    unsealed_path: P,
+    format: UnsealedFormat,
+    piece_infos: &[PieceInfo],
+    expected_comm_d: Commitment,
+) -> Result<UnsealedSectorReport> {
+    info!("verify_unsealed_sector:start");
+
+    let unsealed_path = unsealed_path.as_ref();
+    let sector_bytes = u64::from(sector_size);
+    let unpadded_sector_bytes = u64::from(UnpaddedBytesAmount::from(sector_size));
+
+    let piece_sizes: Vec<UnpaddedBytesAmount> =
+        piece_infos.iter().map(|piece| piece.size).collect();
+    let piece_infos_match = compute_comm_d(sector_size, piece_infos)? == expected_comm_d;
+    ensure!(
+        u64::from(sum_piece_bytes_with_alignment(&piece_sizes)) <= unpadded_sector_bytes,
+        "pieces do not fit in the sector"
+    );
+
+    let file = File::open(unsealed_path)
+        .with_context(|| format!("could not open unsealed_path={:?}", unsealed_path))?;
+    let file_len = file.metadata()?.len();
+    let mut source: Box<dyn SubtreeSource> = match format {
+        UnsealedFormat::Padded => {
+            ensure!(
+                file_len == sector_bytes,
+                "padded unsealed file {:?} of {} bytes does not have the sector size",
+                unsealed_path,
+                file_len
+            );
+            Box::new(SparseFileReader::new(file)?)
+        }
+        UnsealedFormat::Unpadded => {
+            ensure!(
+                file_len <= unpadded_sector_bytes,
+                "unpadded unsealed file {:?} of {} bytes is larger than the sector",
+                unsealed_path,
+                file_len
+            );
+            let zeroes = EmptySource::new((unpadded_sector_bytes - file_len) as usize);
+            Box::new(DenseSource(Fr32Reader::new(
+                BufReader::new(file).chain(zeroes),
+            )))
+        }
+    };
+
+    let mut stack = SubtreeStack::default();
+    let mut position = 0;
+    let mut corrupted_pieces = Vec::new();
+    for (i, piece) in piece_infos.iter().enumerate() {
+        let offset = get_piece_start_byte(&piece_sizes[..i], piece.size);
+        let offset = u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount::from(offset)));
+        let size = PaddedBytesAmount::from(piece.size);
+
+        push_subtrees(&mut *source, &mut stack, position, offset)?;
+        let comm_p = generate_subtree_commitment_parallel(&mut *source, size)
+            .with_context(|| format!("could not read piece {}", i))?;
+        if comm_p != piece.commitment {
+            trace!("piece {} does not match its commitment", i);
+            corrupted_pieces.push(i);
+        }
+        stack.push(
+            subtree_height(u64::from(size)),
+            DefaultPieceDomain::try_from_bytes(&comm_p)?,
+        );
+        position = offset + u64::from(size);
+    }
+    push_subtrees(&mut *source, &mut stack, position, sector_bytes)?;
+    let comm_d = stack.finish()?;
+
+    info!("verify_unsealed_sector:finish");
+    Ok(UnsealedSectorReport {
+        comm_d,
+        comm_d_matches: comm_d == expected_comm_d,
+        piece_infos_match,
+        corrupted_pieces,
+    })
+}
+
+fn ensure_replica_len(replica_path: &Path, sector_bytes: u64) -> Result<()> {
+    let replica_len = metadata(replica_path)
+        .with_context(|| format!("could not read replica metadata {:?}", replica_path))?
+        .len();
+    ensure!(
+        replica_len == sector_bytes,
+        "replica {:?} is {} bytes, expected {}",
+        replica_path,
+        replica_len,
+        sector_bytes
+    );
+
+    Ok(())
+}
+
+/// Recomputes the tree-r-last roots from the replica and reads the cached ones, where the
+/// cached stores have the expected size.
+fn tree_r_last_report<Tree: 'static + MerkleTreeTrait>(
+    cache_dir: &Path,
+    replica_path: &Path,
+    nodes: usize,
+    rows_to_discard: usize,
+    p_aux: Option<&PersistentAux<<Tree::Hasher as Hasher>::Domain>>,
+) -> Result<TreeRLastReport> {
+    let tree_count = get_base_tree_count::<Tree>();
+    let base_tree_leafs = nodes / tree_count;
+    let arity = Tree::Arity::to_usize();
+    let base_tree_len = get_merkle_tree_len(base_tree_leafs, arity)?;
+    let cache_len = get_merkle_tree_cache_size(base_tree_leafs, arity, rows_to_discard)?;
+
+    trace!("recomputing {} tree-r-last root(s)", tree_count);
+    let replica = File::open(replica_path)
+        .with_context(|| format!("could not open replica {:?}", replica_path))?;
+    let data = unsafe { MmapOptions::new().map(&replica) }
+        .with_context(|| format!("could not mmap replica {:?}", replica_path))?;
+    let recomputed_roots = data
+        .chunks(base_tree_leafs * NODE_SIZE)
+        .map(|base_tree| base_tree_root::<Tree::Hasher>(base_tree, arity))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (configs, replica_config) = split_config_and_replica(
+        StoreConfig::new(
+            cache_dir,
+            CacheKey::CommRLastTree.to_string(),
+            rows_to_discard,
+        ),
+        replica_path.to_path_buf(),
+        base_tree_leafs,
+        tree_count,
+    )?;
+    let cached_roots: Vec<_> = configs
+        .iter()
+        .enumerate()
+        .map(|(index, config)| {
+            if !store_status(cache_dir, config.id.clone(), true, cache_len).is_valid() {
+                return None;
+            }
+            cached_tree_r_last_root::<Tree>(base_tree_len, config, &replica_config, index)
+                .map_err(|err| trace!("could not read cached root of {}: {:?}", config.id, err))
+                .ok()
+        })
+        .collect();
+
+    let to_commitment = |root: <Tree::Hasher as Hasher>::Domain| commitment_from_fr(root.into());
+    let roots = recomputed_roots
+        .iter()
+        .zip(cached_roots.iter())
+        .enumerate()
+        .map(|(index, (recomputed, cached))| TreeRLastRoot {
+            index,
+            cached: cached.map(to_commitment),
+            recomputed: to_commitment(*recomputed),
+        })
+        .collect();
+    let cached_comm_r_last = cached_roots
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .map(|roots| to_commitment(combine_base_tree_roots::<Tree>(roots)));
+
+    Ok(TreeRLastReport {
+        roots,
+        comm_r_last: to_commitment(combine_base_tree_roots::<Tree>(recomputed_roots)),
+        cached_comm_r_last,
+        p_aux_comm_r_last: p_aux.map(|p_aux| to_commitment(p_aux.comm_r_last)),
+    })
+}
+
+fn read_cache_file<T: DeserializeOwned>(cache_dir: &Path, key: CacheKey) -> Result<T> {
+    let path = cache_dir.join(key.to_string());
+    let bytes = fs::read(&path).with_context(|| format!("could not read file {:?}", path))?;
+
+    deserialize(&bytes).with_context(|| format!("could not decode file {:?}", path))
+}
+
+fn load_check<T>(loaded: &Result<T>) -> IntegrityCheck {
+    match loaded {
+        Ok(_) => IntegrityCheck::Passed,
+        Err(err) => IntegrityCheck::Failed(format!("{:#}", err)),
+    }
+}
+
+/// Store ids as produced by `split_config` for a tree made of `count` base trees.
+fn split_ids(key: CacheKey, count: usize) -> Vec<String> {
+    if count == 1 {
+        vec![key.to_string()]
+    } else {
+        (0..count).map(|i| format!("{}-{}", key, i)).collect()
+    }
+}
+
+fn store_status(cache_dir: &Path, id: String, required: bool, nodes: usize) -> CacheFileStatus {
+    let path = StoreConfig::data_path(&cache_dir.to_path_buf(), &id);
+    cache_file_status(id, path, required, Some((nodes * NODE_SIZE) as u64))
+}
+
+fn cache_file_status(
+    id: String,
+    path: PathBuf,
+    required: bool,
+    expected_size: Option<u64>,
+) -> CacheFileStatus {
+    let size = metadata(&path).ok().map(|metadata| metadata.len());
+
+    CacheFileStatus {
+        id,
+        path,
+        required,
+        expected_size,
+        size,
+    }
+}
+
+/// Computes the root of a base tree over `data` without building the tree, hashing
+/// subtrees of at most `ROOT_CHUNK_LEAFS` leaves in parallel.
+fn base_tree_root<H: Hasher>(data: &[u8], arity: usize) -> Result<H::Domain> {
+    let leafs = data.len() / NODE_SIZE;
+    ensure!(
+        is_merkle_tree_size_valid(leafs, arity),
+        "invalid base tree size {} for arity {}",
+        leafs,
+        arity
+    );
+
+    let mut chunk_leafs = 1;
+    while chunk_leafs * arity <= min(leafs, ROOT_CHUNK_LEAFS) {
+        chunk_leafs *= arity;
+    }
+
+    let chunk_roots = data
+        .par_chunks(chunk_leafs * NODE_SIZE)
+        .map(|chunk| {
+            let leaves = chunk
+                .chunks(NODE_SIZE)
+                .map(H::Domain::try_from_bytes)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(reduce_to_root::<H>(leaves, arity))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(reduce_to_root::<H>(chunk_roots, arity))
+}
+
+fn reduce_to_root<H: Hasher>(mut nodes: Vec<H::Domain>, arity: usize) -> H::Domain {
+    while nodes.len() > 1 {
+        nodes = hash_row::<H>(&nodes, arity);
+    }
+    nodes[0]
+}
+
+fn hash_row<H: Hasher>(nodes: &[H::Domain], arity: usize) -> Vec<H::Domain> {
+    nodes
+        .chunks(arity)
+        .map(|children| H::Function::default().multi_node(children, 0))
+        .collect()
+}
+
+/// Combines the base tree roots the same way the sub and top tree layers of `Tree` do.
+fn combine_base_tree_roots<Tree: MerkleTreeTrait>(
+    mut roots: Vec<<Tree::Hasher as Hasher>::Domain>,
+) -> <Tree::Hasher as Hasher>::Domain {
+    for &arity in &[
+        Tree::SubTreeArity::to_usize(),
+        Tree::TopTreeArity::to_usize(),
+    ] {
+        if arity > 0 {
+            roots = hash_row::<Tree::Hasher>(&roots, arity);
+        }
+    }
+    roots[0]
+}
+
+fn cached_tree_r_last_root<Tree: MerkleTreeTrait>(
+    base_tree_len: usize,
+    config: &StoreConfig,
+    replica_config: &ReplicaConfig,
+    index: usize,
+) -> Result<<Tree::Hasher as Hasher>::Domain> {
+    let store =
+        LevelCacheStore::<<Tree::Hasher as Hasher>::Domain, File>::new_from_disk_with_reader(
+            base_tree_len,
+            Tree::Arity::to_usize(),
+            config,
+            ExternalReader::new_from_config(replica_config, index)?,
+        )?;
+
+    store.last()
+}
+
+fn check_tree_d_root(
+    cache_dir: &Path,
+    id: &str,
+    tree_d_len: usize,
+    comm_d: &Commitment,
+) -> Result<bool> {
+    let comm_d = as_safe_commitment::<DefaultPieceDomain, _>(comm_d, "comm_d")?;
+    let config = StoreConfig::new(cache_dir, id, 0);
+    let store: DiskStore<DefaultPieceDomain> =
+        DiskStore::new_from_disk(tree_d_len, BINARY_ARITY, &config)?;
+
+    Ok(store.last()? == comm_d)
+}
+
+/// Compares tree-c leaves at pseudo-random nodes, derived from comm_r so that repeated
+/// audits check the same nodes, with the hash of the label column at that node.
+fn sample_columns<Tree: 'static + MerkleTreeTrait>(
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    base_tree_len: usize,
+    tree_count: usize,
+    nodes: usize,
+    comm_r: &Commitment,
+) -> Result<Vec<ColumnSample>> {
+    let configs = split_config(t_aux.tree_c_config.clone(), tree_count)?;
+    let tree_c = create_disk_tree::<
+        DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    >(base_tree_len, &configs)?;
+
+    (0..INTEGRITY_COLUMN_SAMPLES as u64)
+        .map(|i| {
+            let digest = Sha256::new()
+                .chain(comm_r)
+                .chain(i.to_le_bytes())
+                .finalize();
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&digest[..8]);
+            let node = u64::from_le_bytes(bytes) % nodes as u64;
+
+            let leaf = tree_c.gen_proof(node as usize)?.leaf();
+            let column = t_aux.column(node as u32)?;
+
+            Ok(ColumnSample {
+                node,
+                matches: column.hash() == leaf.into(),
+            })
+        })
+        .collect()
+}
+
+/// Hashes the padded bytes from `start` to `end` of `source`, which are not part of a piece,
+/// as the largest subtrees that are aligned to their size.
+fn push_subtrees<S: SubtreeSource + ?Sized>(
+    source: &mut S,
+    stack: &mut SubtreeStack,
+    mut start: u64,
+    end: u64,
+) -> Result<()> {
+    while start < end {
+        let mut size = if start == 0 {
+            end.next_power_of_two()
+        } else {
+            1 << start.trailing_zeros()
+        };
+        while size > end - start {
+            size /= 2;
+        }
+
+        let root = generate_subtree_commitment_parallel(&mut *source, PaddedBytesAmount(size))?;
+        stack.push(
+            subtree_height(size),
+            DefaultPieceDomain::try_from_bytes(&root)?,
+        );
+        start += size;
+    }
+
+    Ok(())
+}
+
+/// Returns the height of a subtree of `size` padded bytes above the leafs.
+fn subtree_height(size: u64) -> usize {
+    (size / NODE_SIZE as u64).trailing_zeros() as usize
+}
@@ -1,3 +1,4 @@
+use std::cmp::{max, min};
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
@@ -5,22 +6,19 @@ use std::path::{Path, PathBuf};
 use anyhow::{ensure, Context, Result};
 use bincode::deserialize;
 use filecoin_hashers::Hasher;
-use fr32::{write_unpadded, Fr32Reader};
-use log::{info, trace};
+use fr32::{write_unpadded, Fr32Layout, Fr32Reader};
+use log::{info, trace, warn};
 use memmap::MmapOptions;
 use merkletree::store::{DiskStore, LevelCacheStore, StoreConfig};
 use storage_proofs_core::{
     cache_key::CacheKey,
+    drgraph::Graph,
     measurements::{measure_op, Operation},
     merkle::get_base_tree_count,
-    pieces::generate_piece_commitment_bytes_from_source,
     sector::SectorId,
-    util::default_rows_to_discard,
-};
-use storage_proofs_porep::{
-    stacked::{generate_replica_id, PersistentAux, StackedDrg, TemporaryAux},
-    PoRep,
+    util::{default_rows_to_discard, NODE_SIZE},
 };
+use storage_proofs_porep::stacked::{self, generate_replica_id, PersistentAux, StackedDrg};
 use typenum::Unsigned;
 
 use crate::{
@@ -29,33 +27,44 @@ use crate::{
         DefaultBinaryTree, DefaultOctTree, DefaultPieceDomain, DefaultPieceHasher,
         MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
     },
+    parallel_commitment::generate_piece_commitment_bytes_parallel,
     parameters::public_params,
     pieces::{get_piece_alignment, sum_piece_bytes_with_alignment},
     types::{
-        Commitment, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-        PoRepProofPartitions, ProverId, SealPreCommitPhase1Output, Ticket, UnpaddedByteIndex,
-        UnpaddedBytesAmount,
+        layer_digest_from_disk, Commitment, LayerFingerprint, MerkleTreeTrait, PaddedBytesAmount,
+        PieceInfo, PoRepConfig, PoRepProofPartitions, ProverId, SealPreCommitPhase1Output,
+        SealPreCommitPhase1Progress, Ticket, UnpaddedByteIndex, UnpaddedBytesAmount,
     },
 };
 
 mod fake_seal;
+mod integrity;
+mod piece_inclusion;
 mod post_util;
 mod seal;
+mod seal_envelope;
+mod sparse;
+mod unseal_piece;
+mod update;
 mod util;
 mod window_post;
 mod winning_post;
 
 pub use fake_seal::*;
+pub use integrity::*;
+pub use piece_inclusion::*;
 pub use post_util::*;
 pub use seal::*;
+pub use seal_envelope::*;
+pub use sparse::*;
+pub use unseal_piece::*;
+pub use update::*;
 pub use util::*;
 pub use window_post::*;
 pub use winning_post::*;
 
-/// Unseals the sector at `sealed_path` and returns the bytes for a piece
-/// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
-/// `num_bytes`, inclusive. Note that the entire sector is unsealed each time
-/// this function is called.
+/// Like `unseal_range_mapped`, but writes the requested range to a new file at
+/// `output_path`.
 ///
 /// # Arguments
 ///
@@ -108,8 +117,16 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + Merkle
 
 /// Unseals the sector read from `sealed_sector` and returns the bytes for a
 /// piece whose first (unpadded) byte begins at `offset` and ends at `offset`
-/// plus `num_bytes`, inclusive. Note that the entire sector is unsealed each
-/// time this function is called.
+/// plus `num_bytes`, inclusive.
+///
+/// Only the nodes covering the requested range are decoded. Their keys are the
+/// last layer labels, which are read from `cache_path` if sealing left them
+/// there and they belong to this replica. Otherwise all the layers are
+/// regenerated into `cache_path` first, which takes about as long as sealing.
+///
+/// The sealed sector is streamed: it is read sequentially up to the end of the
+/// requested range and decoded in windows of `UNSEAL_WINDOW_BYTES`, so it may
+/// come from a pipe or a network stream and is never held in memory as a whole.
 ///
 /// # Arguments
 ///
@@ -127,7 +144,7 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + Merkle
 pub fn unseal_range<P, R, W, Tree>(
     porep_config: PoRepConfig,
     cache_path: P,
-    mut sealed_sector: R,
+    sealed_sector: R,
     unsealed_output: W,
     prover_id: ProverId,
     sector_id: SectorId,
@@ -156,13 +173,10 @@ where
         &porep_config.porep_id,
     );
 
-    let mut data = Vec::new();
-    sealed_sector.read_to_end(&mut data)?;
-
-    let res = unseal_range_inner::<_, _, Tree>(
+    let res = unseal_range_streaming::<_, _, _, Tree>(
         porep_config,
         cache_path,
-        &mut data,
+        sealed_sector,
         unsealed_output,
         replica_id,
         offset,
@@ -174,16 +188,14 @@ where
     Ok(res)
 }
 
-/// Unseals the sector read from `sealed_sector` and returns the bytes for a
-/// piece whose first (unpadded) byte begins at `offset` and ends at `offset`
-/// plus `num_bytes`, inclusive. Note that the entire sector is unsealed each
-/// time this function is called.
+/// Like `unseal_range`, but maps the sealed sector at `sealed_path` into memory
+/// instead of streaming it.
 ///
 /// # Arguments
 ///
 /// * `porep_config` - porep configuration containing the sector size.
 /// * `cache_path` - path to the directory in which the sector data's Merkle Tree is written.
-/// * `sealed_sector` - a byte source from which we read sealed sector data.
+/// * `sealed_path` - path to the sealed sector file that we will unseal and read a byte range.
 /// * `unsealed_output` - a byte sink to which we write unsealed, un-bit-padded sector bytes.
 /// * `prover_id` - the prover-id that sealed the sector.
 /// * `sector_id` - the sector-id of the sealed sector.
@@ -243,21 +255,16 @@ where
     result
 }
 
-/// Unseals the sector read from `sealed_sector` and returns the bytes for a
-/// piece whose first (unpadded) byte begins at `offset` and ends at `offset`
-/// plus `num_bytes`, inclusive. Note that the entire sector is unsealed each
-/// time this function is called.
+/// Decodes the requested range of the sealed sector held in `data`, for
+/// `unseal_range_mapped`. Only the nodes covering the range are decoded, in place.
 ///
 /// # Arguments
 ///
 /// * `porep_config` - porep configuration containing the sector size.
 /// * `cache_path` - path to the directory in which the sector data's Merkle Tree is written.
-/// * `sealed_sector` - a byte source from which we read sealed sector data.
+/// * `data` - the sealed sector data.
 /// * `unsealed_output` - a byte sink to which we write unsealed, un-bit-padded sector bytes.
-/// * `prover_id` - the prover-id that sealed the sector.
-/// * `sector_id` - the sector-id of the sealed sector.
-/// * `comm_d` - the commitment to the sector's data.
-/// * `ticket` - the ticket that was used to generate the sector's replica-id.
+/// * `replica_id` - the replica-id of the sealed sector.
 /// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
 /// * `num_bytes` - the number of bytes that we want to read.
 #[allow(clippy::too_many_arguments)]
@@ -277,10 +284,184 @@ where
 {
     trace!("unseal_range_inner:start");
 
+    let (pp, config) = unseal_params::<Tree>(porep_config, cache_path.as_ref())?;
+
+    // Decode from the start of a padded block, so the unpadded offset within it is byte aligned,
+    // to the end of the last element holding the range.
+    let block_start =
+        u64::from(offset) / Fr32Layout::UNPADDED_BLOCK_BYTES * Fr32Layout::UNPADDED_BLOCK_BYTES;
+    let range_end = u64::from(offset) + u64::from(num_bytes);
+    let padded = Fr32Layout::padded_range(block_start..range_end).element_bytes();
+    let (start, end) = (padded.start as usize, padded.end as usize);
+    ensure!(
+        end <= data.len(),
+        "range {}..{} exceeds the sealed sector size {}",
+        start,
+        end,
+        data.len()
+    );
+
+    // Only decode the nodes covering the requested range.
+    let first_node = start / NODE_SIZE;
+    let last_node = end / NODE_SIZE;
+    let last_layer_labels =
+        last_layer_labels_for_decoding::<Tree>(&pp, &replica_id, config, cache_path.as_ref())?;
+    StackedDrg::<Tree, DefaultPieceHasher>::decode_range(
+        &last_layer_labels,
+        &mut data[first_node * NODE_SIZE..last_node * NODE_SIZE],
+        first_node,
+        last_node - first_node,
+    )?;
+    let unsealed = &data[start..end];
+
+    let skip = (u64::from(offset) - block_start) as usize;
+    let written = write_unpadded(unsealed, &mut unsealed_output, skip, num_bytes.into())
+        .context("write_unpadded failed")?;
+
+    let amount = UnpaddedBytesAmount(written as u64);
+
+    trace!("unseal_range_inner:finish");
+    Ok(amount)
+}
+
+/// Number of sealed bytes decoded at once by `unseal_range`. This is a multiple of
+/// `Fr32Layout::PADDED_BLOCK_BYTES`, so that every window starts on a byte boundary of the
+/// unpadded data.
+const UNSEAL_WINDOW_BYTES: usize = 1 << 20;
+
+/// Decodes the requested range from `sealed_sector`, reading it sequentially in windows of
+/// `UNSEAL_WINDOW_BYTES` and writing the unpadded bytes of each window as it is decoded.
+fn unseal_range_streaming<P, R, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_sector: R,
+    mut unsealed_output: W,
+    replica_id: <Tree::Hasher as Hasher>::Domain,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    R: Read,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    trace!("unseal_range_streaming:start");
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let start = usize::from(offset);
+    let end = start + usize::from(num_bytes);
+    let unpadded_sector_bytes = Fr32Layout::unpadded_len(sector_bytes as u64) as usize;
+    ensure!(
+        end <= unpadded_sector_bytes,
+        "range {}..{} exceeds the unpadded sector size {}",
+        start,
+        end,
+        unpadded_sector_bytes
+    );
+
+    let (pp, config) = unseal_params::<Tree>(porep_config, cache_path.as_ref())?;
+
+    // Decode whole padded blocks, so the unpadded offsets within each window are byte aligned.
+    let unpadded_block_bytes = Fr32Layout::UNPADDED_BLOCK_BYTES as usize;
+    let first_block = start / unpadded_block_bytes;
+    let end_block = (end + unpadded_block_bytes - 1) / unpadded_block_bytes;
+    let nodes_per_block = Fr32Layout::PADDED_BLOCK_BYTES as usize / NODE_SIZE;
+
+    let last_layer_labels =
+        last_layer_labels_for_decoding::<Tree>(&pp, &replica_id, config, cache_path.as_ref())?;
+    let mut windows = SealedWindowReader::new(
+        sealed_sector,
+        first_block * nodes_per_block,
+        end_block * nodes_per_block,
+        UNSEAL_WINDOW_BYTES,
+    )
+    .context("could not skip to the start of the range")?;
+
+    let mut window_start = first_block * unpadded_block_bytes;
+    let mut written = 0;
+    while let Some((first_node, window)) = windows
+        .next_window()
+        .context("could not read the sealed sector")?
+    {
+        let num_nodes = window.len() / NODE_SIZE;
+        StackedDrg::<Tree, DefaultPieceHasher>::decode_range(
+            &last_layer_labels,
+            window,
+            first_node,
+            num_nodes,
+        )?;
+
+        let window_end = window_start + Fr32Layout::unpadded_len(window.len() as u64) as usize;
+        let from = max(start, window_start);
+        let to = min(end, window_end);
+        if from < to {
+            written += write_unpadded(window, &mut unsealed_output, from - window_start, to - from)
+                .context("write_unpadded failed")?;
+        }
+        window_start = window_end;
+    }
+
+    trace!("unseal_range_streaming:finish");
+    Ok(UnpaddedBytesAmount(written as u64))
+}
+
+/// Reads the sealed bytes of a node range sequentially, one window of bounded size at a time.
+struct SealedWindowReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    next_node: usize,
+    end_node: usize,
+}
+
+impl<R: Read> SealedWindowReader<R> {
+    /// Skips the nodes before `first_node`, without buffering them.
+    fn new(mut reader: R, first_node: usize, end_node: usize, window_bytes: usize) -> Result<Self> {
+        let skip = (first_node * NODE_SIZE) as u64;
+        let skipped = io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+        ensure!(
+            skipped == skip,
+            "sealed sector ended after {} bytes, expected at least {}",
+            skipped,
+            skip
+        );
+
+        let buf_len = min(window_bytes, (end_node - first_node) * NODE_SIZE);
+        Ok(SealedWindowReader {
+            reader,
+            buf: vec![0; buf_len],
+            next_node: first_node,
+            end_node,
+        })
+    }
+
+    /// Reads the next window, returning the index of its first node and its sealed bytes.
+    fn next_window(&mut self) -> io::Result<Option<(usize, &mut [u8])>> {
+        if self.next_node == self.end_node {
+            return Ok(None);
+        }
+
+        let num_nodes = min(self.buf.len() / NODE_SIZE, self.end_node - self.next_node);
+        let window = &mut self.buf[..num_nodes * NODE_SIZE];
+        self.reader.read_exact(window)?;
+
+        let first_node = self.next_node;
+        self.next_node += num_nodes;
+
+        Ok(Some((first_node, window)))
+    }
+}
+
+/// Returns the porep public parameters and the store config used to decode a sector whose
+/// cache directory is `cache_path`.
+fn unseal_params<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+) -> Result<(stacked::PublicParams<Tree>, StoreConfig)> {
     let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
     let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
     let config = StoreConfig::new(
-        cache_path.as_ref(),
+        cache_path,
         CacheKey::CommDTree.to_string(),
         default_rows_to_discard(
             base_tree_leafs,
@@ -294,24 +475,77 @@ where
         porep_config.api_version,
     )?;
 
-    let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
-    let num_bytes_padded: PaddedBytesAmount = num_bytes.into();
+    Ok((pp, config))
+}
 
-    StackedDrg::<Tree, DefaultPieceHasher>::extract_all(&pp, &replica_id, data, Some(config))?;
-    let start: usize = offset_padded.into();
-    let end = start + usize::from(num_bytes_padded);
-    let unsealed = &data[start..end];
+/// Returns the last layer labels to decode a sector with, like
+/// `StackedDrg::last_layer_labels_for_decoding`, but regenerates them if the
+/// `SealPreCommitPhase1Progress` manifest in `cache_path` shows that the cached ones do not
+/// belong to this replica.
+fn last_layer_labels_for_decoding<Tree: 'static + MerkleTreeTrait>(
+    pp: &stacked::PublicParams<Tree>,
+    replica_id: &<Tree::Hasher as Hasher>::Domain,
+    config: StoreConfig,
+    cache_path: &Path,
+) -> Result<DiskStore<<Tree::Hasher as Hasher>::Domain>> {
+    if cached_labels_match_manifest::<Tree>(pp, replica_id, &config, cache_path)? {
+        StackedDrg::<Tree, DefaultPieceHasher>::last_layer_labels_for_decoding(
+            pp, replica_id, config,
+        )
+    } else {
+        info!("cached last layer labels do not match the progress manifest, regenerating");
+        StackedDrg::<Tree, DefaultPieceHasher>::regenerate_last_layer_labels(pp, replica_id, config)
+    }
+}
 
-    // If the call to `extract_range` was successful, the `unsealed` vector must
-    // have a length which equals `num_bytes_padded`. The byte at its 0-index
-    // byte will be the the byte at index `offset_padded` in the sealed sector.
-    let written = write_unpadded(unsealed, &mut unsealed_output, 0, num_bytes.into())
-        .context("write_unpadded failed")?;
+/// Checks the replica id recorded in the progress manifest, if there is one, against
+/// `replica_id`, and the last layer in the cache directory against the recorded layer.
+///
+/// The last layer is only hashed and compared with the recorded digest the first time; once it
+/// matched, its length and modification time are recorded in the manifest and later calls only
+/// compare those.
+fn cached_labels_match_manifest<Tree: 'static + MerkleTreeTrait>(
+    pp: &stacked::PublicParams<Tree>,
+    replica_id: &<Tree::Hasher as Hasher>::Domain,
+    config: &StoreConfig,
+    cache_path: &Path,
+) -> Result<bool> {
+    let mut progress = match SealPreCommitPhase1Progress::load(cache_path)? {
+        Some(progress) => progress,
+        None => return Ok(true),
+    };
+    if let Some(recorded_id) = progress.replica_id {
+        if AsRef::<[u8]>::as_ref(replica_id) != &recorded_id[..] {
+            return Ok(false);
+        }
+    }
 
-    let amount = UnpaddedBytesAmount(written as u64);
+    let layers = pp.layer_challenges.layers();
+    let layer_config =
+        StoreConfig::from_config(config, CacheKey::label_layer(layers), Some(pp.graph.size()));
+    let data_path = StoreConfig::data_path(&layer_config.path, &layer_config.id);
+    let recorded = match progress.layers.iter_mut().find(|l| l.layer == layers) {
+        Some(recorded) if data_path.exists() => recorded,
+        _ => return Ok(true),
+    };
 
-    trace!("unseal_range_inner:finish");
-    Ok(amount)
+    let fingerprint = LayerFingerprint::from_disk(&layer_config)?;
+    if recorded.fingerprint == Some(fingerprint) {
+        return Ok(true);
+    }
+    if layer_digest_from_disk(&layer_config)? != recorded.digest {
+        return Ok(false);
+    }
+
+    recorded.fingerprint = Some(fingerprint);
+    if let Err(err) = progress.persist(cache_path) {
+        warn!(
+            "could not record the fingerprint of the last layer: {:?}",
+            err
+        );
+    }
+
+    Ok(true)
 }
 
 /// Generates a piece commitment for the provided byte source. Returns an error
@@ -335,10 +569,8 @@ pub fn generate_piece_commitment<T: Read>(
         let source = BufReader::new(source);
         let mut fr32_reader = Fr32Reader::new(source);
 
-        let commitment = generate_piece_commitment_bytes_from_source::<DefaultPieceHasher>(
-            &mut fr32_reader,
-            PaddedBytesAmount::from(piece_size).into(),
-        )?;
+        let commitment =
+            generate_piece_commitment_bytes_parallel(&mut fr32_reader, piece_size.into())?;
 
         PieceInfo::new(commitment, piece_size)
     });
@@ -363,7 +595,8 @@ pub fn generate_piece_commitment<T: Read>(
 /// # Arguments
 ///
 /// * `source` - a readable source of unprocessed piece bytes.
-/// * `target` - a writer where we will write the processed piece bytes.
+/// * `target` - a writer where we will write the processed piece bytes. A staged sector file
+///   wrapped in a `SparseFileWriter` keeps the alignment as holes.
 /// * `piece_size` - the number of unpadded user-bytes which can be read from source before EOF.
 /// * `piece_lengths` - the number of bytes for each previous piece in the sector.
 pub fn add_piece<R, W>(
@@ -388,10 +621,8 @@ where
         let piece_alignment = get_piece_alignment(written_bytes, piece_size);
         let fr32_reader = Fr32Reader::new(source);
 
-        // write left alignment
-        for _ in 0..usize::from(PaddedBytesAmount::from(piece_alignment.left_bytes)) {
-            target.write_all(&[0u8][..])?;
-        }
+        // write left alignment, as whole zero blocks which a `SparseFileWriter` leaves as holes
+        write_alignment(&mut target, piece_alignment.left_bytes)?;
 
         let mut commitment_reader = CommitmentReader::new(fr32_reader);
         let n = io::copy(&mut commitment_reader, &mut target)
@@ -404,9 +635,8 @@ where
         ensure!(n == piece_size, "add_piece: invalid bytes amount written");
 
         // write right alignment
-        for _ in 0..usize::from(PaddedBytesAmount::from(piece_alignment.right_bytes)) {
-            target.write_all(&[0u8][..])?;
-        }
+        write_alignment(&mut target, piece_alignment.right_bytes)?;
+        target.flush().context("failed to flush the target")?;
 
         let commitment = commitment_reader.finish()?;
         let mut comm = [0u8; 32];
@@ -421,6 +651,13 @@ where
     result
 }
 
+/// Writes the bit padded zeros of `alignment` to `target`.
+fn write_alignment<W: Write>(target: &mut W, alignment: UnpaddedBytesAmount) -> Result<()> {
+    let padded = u64::from(PaddedBytesAmount::from(alignment));
+    io::copy(&mut io::repeat(0).take(padded), target).context("failed to write alignment")?;
+    Ok(())
+}
+
 fn ensure_piece_size(piece_size: UnpaddedBytesAmount) -> Result<()> {
     ensure!(
         piece_size >= UnpaddedBytesAmount(MINIMUM_PIECE_SIZE),
@@ -675,17 +912,7 @@ where
     drop(p_aux_bytes);
 
     // Make sure t_aux exists and is valid.
-    let t_aux = {
-        let t_aux_path = cache.join(CacheKey::TAux.to_string());
-        let t_aux_bytes = fs::read(&t_aux_path)
-            .with_context(|| format!("could not read file t_aux={:?}", t_aux_path))?;
-
-        let mut res: TemporaryAux<Tree, DefaultPieceHasher> = deserialize(&t_aux_bytes)?;
-
-        // Switch t_aux to the passed in cache_path
-        res.set_cache_path(&cache_path);
-        res
-    };
+    let t_aux = load_t_aux::<Tree>(cache)?;
 
     // Verify all stores/labels within the Labels object.
     let cache = cache_path.as_ref().to_path_buf();
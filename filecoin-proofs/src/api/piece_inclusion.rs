@@ -0,0 +1,154 @@
+use std::fs::metadata;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+use merkletree::store::{DiskStore, Store, StoreConfig};
+use storage_proofs_core::{cache_key::CacheKey, error::Error, util::NODE_SIZE};
+
+use crate::{
+    api::read_faux_piece_roots,
+    constants::{
+        DefaultPieceDomain,
+        MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
+    },
+    pieces::{get_piece_start_byte, verify_inclusion_path},
+    types::{
+        Commitment, PaddedBytesAmount, PieceInclusionProof, PieceInfo, SectorSize,
+        UnpaddedByteIndex, UnpaddedBytesAmount, BINARY_ARITY,
+    },
+};
+
+/// Generates a proof that the piece at `piece_index` of `piece_infos`, the pieces of the sector
+/// in the order they were added, is included in the comm_d of the sector.
+///
+/// The proof is read from the tree-d store in `cache_dir`, so that it has to be generated
+/// before the cache is cleared. For a sector created by `fauxrep_with_pieces`, pieces inside a
+/// `FauxPiece::Info` piece are refused, as tree-d is not consistent with them.
+pub fn generate_piece_inclusion_proof<P: AsRef<Path>>(
+    cache_dir: P,
+    piece_infos: &[PieceInfo],
+    piece_index: usize,
+) -> Result<PieceInclusionProof> {
+    info!("generate_piece_inclusion_proof:start");
+    ensure!(
+        piece_index < piece_infos.len(),
+        "piece index {} is out of range for {} pieces",
+        piece_index,
+        piece_infos.len()
+    );
+
+    let piece_sizes: Vec<UnpaddedBytesAmount> =
+        piece_infos.iter().map(|piece| piece.size).collect();
+    let piece = &piece_infos[piece_index];
+    let offset = get_piece_start_byte(&piece_sizes[..piece_index], piece.size);
+    let (level, mut index) = piece_node(offset, piece.size)?;
+
+    for (root_level, root_index) in read_faux_piece_roots(cache_dir.as_ref())? {
+        ensure!(
+            level >= root_level || index >> (root_level - level) != root_index,
+            "piece {} is inside a generated faux piece, tree-d is not consistent with it",
+            piece_index
+        );
+    }
+
+    let config = StoreConfig::new(cache_dir.as_ref(), CacheKey::CommDTree.to_string(), 0);
+    let tree_d_path = StoreConfig::data_path(&config.path, &config.id);
+    let tree_d_len = metadata(&tree_d_path)
+        .with_context(|| format!("could not read tree-d={:?}", tree_d_path.display()))?
+        .len() as usize
+        / NODE_SIZE;
+    ensure!(
+        tree_d_len > 1 && (tree_d_len + 1).is_power_of_two(),
+        "tree-d={:?} is not a complete binary tree",
+        tree_d_len
+    );
+    let leafs = (tree_d_len + 1) / 2;
+    ensure!(
+        (index + 1) << level <= leafs as u64,
+        "piece {} does not fit in a sector of {} nodes",
+        piece_index,
+        leafs
+    );
+
+    let tree_d: DiskStore<DefaultPieceDomain> =
+        DiskStore::new_from_disk(tree_d_len, BINARY_ARITY, &config)?;
+    // Levels are stored one after the other, starting with the leafs.
+    let level_start = |level: usize| 2 * leafs - 2 * (leafs >> level);
+
+    let piece_root = tree_d.read_at(level_start(level) + index as usize)?;
+    ensure!(
+        AsRef::<[u8]>::as_ref(&piece_root) == &piece.commitment[..],
+        Error::BadPieceCommitment
+    );
+
+    let height = leafs.trailing_zeros() as usize;
+    let mut path = Vec::with_capacity(height - level);
+    for level in level..height {
+        let sibling = tree_d.read_at(level_start(level) + (index ^ 1) as usize)?;
+        let mut node = [0u8; 32];
+        node.copy_from_slice(sibling.as_ref());
+        path.push(node);
+        index >>= 1;
+    }
+
+    info!("generate_piece_inclusion_proof:finish");
+    Ok(PieceInclusionProof { path })
+}
+
+/// Verifies that the piece with `comm_p` of `size` bytes starting at `offset` in a sector of
+/// `sector_size` is included in `comm_d`.
+///
+/// The proof is only valid if its path leads from the level of the piece root to the root of
+/// the sector. Returns an error if the piece could not have been placed at `offset` by
+/// `add_piece`.
+pub fn verify_piece_inclusion_proof(
+    sector_size: SectorSize,
+    comm_d: &Commitment,
+    comm_p: &Commitment,
+    offset: UnpaddedByteIndex,
+    size: UnpaddedBytesAmount,
+    proof: &PieceInclusionProof,
+) -> Result<bool> {
+    info!("verify_piece_inclusion_proof:start");
+    let (level, index) = piece_node(offset, size)?;
+    let leafs = u64::from(sector_size) / NODE_SIZE as u64;
+    ensure!(
+        leafs.is_power_of_two() && (index + 1) << level <= leafs,
+        "piece of {} bytes at {} does not fit in a sector of {} bytes",
+        u64::from(size),
+        u64::from(offset),
+        u64::from(sector_size)
+    );
+
+    let height = leafs.trailing_zeros() as usize;
+    let is_valid = proof.path.len() + level == height
+        && verify_inclusion_path(comm_d, comm_p, index, &proof.path);
+
+    info!("verify_piece_inclusion_proof:finish");
+    Ok(is_valid)
+}
+
+/// Returns the level and index of the tree-d node that is the root of a piece of `size` bytes
+/// starting at `offset`.
+fn piece_node(offset: UnpaddedByteIndex, size: UnpaddedBytesAmount) -> Result<(usize, u64)> {
+    let size = u64::from(size);
+    let offset = u64::from(offset);
+    let padded_size = u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount(size)));
+    ensure!(
+        size >= MINIMUM_PIECE_SIZE
+            && size % MINIMUM_PIECE_SIZE == 0
+            && padded_size.is_power_of_two(),
+        "invalid piece size {}",
+        size
+    );
+    ensure!(
+        offset % size == 0,
+        "piece of {} bytes at {} is not aligned",
+        size,
+        offset
+    );
+
+    let level = (padded_size / NODE_SIZE as u64).trailing_zeros() as usize;
+    Ok((level, offset / size))
+}
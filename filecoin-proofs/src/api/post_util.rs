@@ -1,18 +1,14 @@
 use std::collections::BTreeMap;
-use std::fs;
 use std::path::Path;
 
 use anyhow::{anyhow, ensure, Context, Result};
-use bincode::deserialize;
 use filecoin_hashers::Hasher;
 use log::{debug, info};
-use storage_proofs_core::{
-    cache_key::CacheKey, merkle::MerkleTreeTrait, proof::ProofScheme, sector::SectorId,
-};
+use storage_proofs_core::{merkle::MerkleTreeTrait, proof::ProofScheme, sector::SectorId};
 use storage_proofs_post::fallback::{self, generate_leaf_challenge, FallbackPoSt, SectorProof};
 
 use crate::{
-    api::as_safe_commitment,
+    api::{as_safe_commitment, load_t_aux},
     constants::DefaultPieceHasher,
     types::{
         ChallengeSeed, FallbackPoStSectorProof, PoStConfig, PrivateReplicaInfo, ProverId,
@@ -25,14 +21,7 @@ use crate::{
 pub fn clear_cache<Tree: MerkleTreeTrait>(cache_dir: &Path) -> Result<()> {
     info!("clear_cache:start");
 
-    let t_aux = {
-        let f_aux_path = cache_dir.to_path_buf().join(CacheKey::TAux.to_string());
-        let aux_bytes = fs::read(&f_aux_path)
-            .with_context(|| format!("could not read from path={:?}", f_aux_path))?;
-
-        deserialize(&aux_bytes)
-    }?;
-
+    let t_aux = load_t_aux::<Tree>(cache_dir)?;
     let result = TemporaryAux::<Tree, DefaultPieceHasher>::clear_temp(t_aux);
 
     info!("clear_cache:finish");
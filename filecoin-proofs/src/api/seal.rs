@@ -7,17 +7,22 @@ use bellperson::groth16;
 use bincode::{deserialize, serialize};
 use blstrs::{Bls12, Scalar as Fr};
 use filecoin_hashers::{Domain, Hasher};
-use log::{info, trace};
+use log::{info, trace, warn};
 use memmap::MmapOptions;
-use merkletree::store::{DiskStore, Store, StoreConfig};
+use merkletree::{
+    merkle::get_merkle_tree_len,
+    store::{DiskStore, Store, StoreConfig},
+};
+use rand::rngs::OsRng;
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 use storage_proofs_core::{
     cache_key::CacheKey,
     compound_proof::{self, CompoundProof},
+    control::{Progress, SealControl},
     drgraph::Graph,
     measurements::{measure_op, Operation},
-    merkle::{create_base_merkle_tree, BinaryMerkleTree, MerkleTreeTrait},
+    merkle::{create_base_merkle_tree, get_base_tree_count, BinaryMerkleTree, MerkleTreeTrait},
     multi_proof::MultiProof,
     parameter_cache::SRS_MAX_PROOFS_TO_AGGREGATE,
     proof::ProofScheme,
@@ -27,11 +32,14 @@ use storage_proofs_core::{
 };
 use storage_proofs_porep::stacked::{
     self, generate_replica_id, ChallengeRequirements, StackedCompound, StackedDrg, Tau,
-    TemporaryAux, TemporaryAuxCache,
+    TemporaryAuxCache,
 };
 
 use crate::{
-    api::{as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size},
+    api::{
+        as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size,
+        load_t_aux, persist_t_aux, verify_store,
+    },
     caches::{
         get_stacked_params, get_stacked_srs_key, get_stacked_srs_verifier_key,
         get_stacked_verifying_key,
@@ -43,9 +51,11 @@ use crate::{
     parameters::setup_params,
     pieces::{self, verify_pieces},
     types::{
-        AggregateSnarkProof, Commitment, PaddedBytesAmount, PieceInfo, PoRepConfig,
-        PoRepProofPartitions, ProverId, SealCommitOutput, SealCommitPhase1Output,
-        SealPreCommitOutput, SealPreCommitPhase1Output, SectorSize, Ticket, BINARY_ARITY,
+        layer_digest, layer_digest_from_disk, AggregateSnarkProof, Commitment,
+        CompactSealCommitPhase1Output, PaddedBytesAmount, PartitionSnarkProof, PieceInfo,
+        PoRepConfig, PoRepProofPartitions, ProverId, SealCommitOutput, SealCommitPhase1Output,
+        SealPreCommitOutput, SealPreCommitPhase1Output, SealPreCommitPhase1Progress, SectorSize,
+        Ticket, BINARY_ARITY,
     },
 };
 
@@ -60,6 +70,39 @@ pub fn seal_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
     ticket: Ticket,
     piece_infos: &[PieceInfo],
 ) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    seal_pre_commit_phase1_with_control::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        &SealControl::default(),
+    )
+}
+
+/// Like `seal_pre_commit_phase1`, but reports the labelling progress to `control` and stops
+/// with `Error::Cancelled` once it is cancelled. Completed layers are kept, so a cancelled
+/// run can be continued with `resume_pre_commit_phase1`.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_with_control<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    control: &SealControl,
+) -> Result<SealPreCommitPhase1Output<Tree>>
 where
     R: AsRef<Path>,
     S: AsRef<Path>,
@@ -67,6 +110,117 @@ where
 {
     info!("seal_pre_commit_phase1:start: {:?}", sector_id);
 
+    let out = seal_pre_commit_phase1_inner::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        false,
+        control,
+    )?;
+
+    info!("seal_pre_commit_phase1:finish: {:?}", sector_id);
+    Ok(out)
+}
+
+/// Continues an interrupted `seal_pre_commit_phase1` from the progress manifest that it
+/// keeps in `cache_path`.
+///
+/// If tree-d was completed and is still consistent on disk, neither the unsealed data is
+/// copied again nor tree-d rebuilt. The last completed label layer is re-verified against
+/// its recorded digest and labelling continues with the first layer that was not
+/// completed. Label layers on disk which the manifest does not know about are discarded.
+///
+/// The arguments must be identical to the ones of the interrupted call.
+#[allow(clippy::too_many_arguments)]
+pub fn resume_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    resume_pre_commit_phase1_with_control::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        &SealControl::default(),
+    )
+}
+
+/// Like `resume_pre_commit_phase1`, but reports progress to and can be cancelled through
+/// `control`, see `seal_pre_commit_phase1_with_control`.
+#[allow(clippy::too_many_arguments)]
+pub fn resume_pre_commit_phase1_with_control<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    control: &SealControl,
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    info!("resume_pre_commit_phase1:start: {:?}", sector_id);
+
+    let out = seal_pre_commit_phase1_inner::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        true,
+        control,
+    )?;
+
+    info!("resume_pre_commit_phase1:finish: {:?}", sector_id);
+    Ok(out)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn seal_pre_commit_phase1_inner<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    resume: bool,
+    control: &SealControl,
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
     // Sanity check all input path types.
     ensure!(
         metadata(in_path.as_ref())?.is_file(),
@@ -81,6 +235,143 @@ where
         "cache_path must be a directory"
     );
 
+    let mut progress = if resume {
+        let progress =
+            SealPreCommitPhase1Progress::load(cache_path.as_ref())?.with_context(|| {
+                format!(
+                    "no progress manifest found in cache_path={:?}",
+                    cache_path.as_ref().display()
+                )
+            })?;
+        progress.ensure_matches(&porep_config)?;
+        progress
+    } else {
+        SealPreCommitPhase1Progress::new(&porep_config)
+    };
+
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: setup_params(
+            PaddedBytesAmount::from(porep_config),
+            usize::from(PoRepProofPartitions::from(porep_config)),
+            porep_config.porep_id,
+            porep_config.api_version,
+        )?,
+        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
+        priority: false,
+    };
+
+    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<'_, Tree, DefaultPieceHasher>,
+        _,
+    >>::setup(&compound_setup_params)?;
+
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+    ensure!(
+        compound_public_params.vanilla_params.graph.size() == base_tree_leafs,
+        "graph size and leaf size don't match"
+    );
+
+    let mut config = StoreConfig::new(
+        cache_path.as_ref(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    config.size = Some(get_merkle_tree_len(base_tree_leafs, BINARY_ARITY)?);
+
+    let completed_comm_d = match progress.comm_d {
+        Some(comm_d) if resume => {
+            match verify_store(&config, BINARY_ARITY, get_base_tree_count::<Tree>()) {
+                Ok(()) => {
+                    info!("found completed tree-d, skipping");
+                    Some(comm_d)
+                }
+                Err(err) => {
+                    warn!("rebuilding tree-d: {}", err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let comm_d = match completed_comm_d {
+        Some(comm_d) => comm_d,
+        None => {
+            let comm_d = build_tree_d(porep_config, &config, in_path, out_path, base_tree_leafs)?;
+            if progress.comm_d != Some(comm_d) {
+                // Labels depend on comm_d (through the replica id), hence
+                // layers recorded for different data cannot be reused.
+                progress.layers.clear();
+            }
+            progress.comm_d = Some(comm_d);
+            comm_d
+        }
+    };
+
+    trace!("verifying pieces");
+
+    ensure!(
+        verify_pieces(&comm_d, piece_infos, porep_config.into())?,
+        "pieces and comm_d do not match"
+    );
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        &prover_id,
+        sector_id.into(),
+        &ticket,
+        comm_d,
+        &porep_config.porep_id,
+    );
+    let replica_id_bytes = commitment_from_fr(replica_id.into());
+    if progress.replica_id != Some(replica_id_bytes) {
+        if !progress.layers.is_empty() {
+            warn!("progress manifest was recorded for a different replica, discarding layers");
+            progress.layers.clear();
+        }
+        progress.replica_id = Some(replica_id_bytes);
+    }
+
+    if resume {
+        prepare_resumed_layers(
+            &mut progress,
+            &config,
+            compound_public_params.vanilla_params.graph.size(),
+            compound_public_params
+                .vanilla_params
+                .layer_challenges
+                .layers(),
+        )?;
+    }
+    progress.persist(cache_path.as_ref())?;
+
+    let labels = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1_with_callback(
+        &compound_public_params.vanilla_params,
+        &replica_id,
+        config.clone(),
+        control,
+        &mut |layer, _, labels| {
+            progress.record_layer(layer, layer_digest(labels));
+            progress.persist(cache_path.as_ref())
+        },
+    )?;
+
+    Ok(SealPreCommitPhase1Output {
+        labels,
+        config,
+        comm_d,
+    })
+}
+
+/// Copies the unsealed data to `out_path`, where it will be sealed in place, and builds
+/// tree-d over it. Returns comm_d.
+fn build_tree_d<S: AsRef<Path>, T: AsRef<Path>>(
+    porep_config: PoRepConfig,
+    config: &StoreConfig,
+    in_path: S,
+    out_path: T,
+    base_tree_leafs: usize,
+) -> Result<Commitment> {
     let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
     fs::metadata(&in_path)
         .with_context(|| format!("could not read in_path={:?})", in_path.as_ref().display()))?;
@@ -112,44 +403,14 @@ where
             .with_context(|| format!("could not mmap out_path={:?}", out_path.as_ref().display()))?
     };
 
-    let compound_setup_params = compound_proof::SetupParams {
-        vanilla_params: setup_params(
-            PaddedBytesAmount::from(porep_config),
-            usize::from(PoRepProofPartitions::from(porep_config)),
-            porep_config.porep_id,
-            porep_config.api_version,
-        )?,
-        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
-        priority: false,
-    };
-
-    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
-        _,
-    >>::setup(&compound_setup_params)?;
-
     trace!("building merkle tree for the original data");
-    let (config, comm_d) = measure_op(Operation::CommD, || -> Result<_> {
-        let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
-        let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
-        ensure!(
-            compound_public_params.vanilla_params.graph.size() == base_tree_leafs,
-            "graph size and leaf size don't match"
-        );
-
+    measure_op(Operation::CommD, || -> Result<_> {
         trace!(
-            "seal phase 1: sector_size {}, base tree size {}, base tree leafs {}",
+            "seal phase 1: sector_size {}, base tree leafs {}",
             u64::from(porep_config.sector_size),
-            base_tree_size,
             base_tree_leafs,
         );
 
-        let mut config = StoreConfig::new(
-            cache_path.as_ref(),
-            CacheKey::CommDTree.to_string(),
-            default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
-        );
-
         let data_tree = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
             Some(config.clone()),
             base_tree_leafs,
@@ -157,44 +418,55 @@ where
         )?;
         drop(data);
 
-        config.size = Some(data_tree.len());
+        ensure!(
+            config.size == Some(data_tree.len()),
+            "unexpected tree-d size"
+        );
         let comm_d_root: Fr = data_tree.root().into();
-        let comm_d = commitment_from_fr(comm_d_root);
-
-        drop(data_tree);
 
-        Ok((config, comm_d))
-    })?;
-
-    trace!("verifying pieces");
-
-    ensure!(
-        verify_pieces(&comm_d, piece_infos, porep_config.into())?,
-        "pieces and comm_d do not match"
-    );
-
-    let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
-        sector_id.into(),
-        &ticket,
-        comm_d,
-        &porep_config.porep_id,
-    );
+        Ok(commitment_from_fr(comm_d_root))
+    })
+}
 
-    let labels = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1(
-        &compound_public_params.vanilla_params,
-        &replica_id,
-        config.clone(),
-    )?;
+/// Brings the label layers in the cache directory in line with `progress`: the last completed
+/// layer is re-verified against its digest, and layers that were not recorded as completed are
+/// removed, so that labelling continues right after the last completed layer.
+fn prepare_resumed_layers(
+    progress: &mut SealPreCommitPhase1Progress,
+    config: &StoreConfig,
+    nodes: usize,
+    layers: usize,
+) -> Result<()> {
+    let label_config =
+        |layer| StoreConfig::from_config(config, CacheKey::label_layer(layer), Some(nodes));
+
+    let mut completed = progress.completed_layers().min(layers);
+    progress.layers.truncate(completed);
+
+    if completed > 0 {
+        let last = &progress.layers[completed - 1];
+        let digest = layer_digest_from_disk(&label_config(last.layer)).unwrap_or_default();
+        if digest == last.digest {
+            info!("verified completed layer {}", last.layer);
+        } else {
+            warn!("layer {} failed verification, regenerating", last.layer);
+            progress.layers.truncate(completed - 1);
+            completed -= 1;
+        }
+    }
 
-    let out = SealPreCommitPhase1Output {
-        labels,
-        config,
-        comm_d,
-    };
+    for layer in (completed + 1)..=layers {
+        let layer_config = label_config(layer);
+        let data_path = StoreConfig::data_path(&layer_config.path, &layer_config.id);
+        if data_path.exists() {
+            info!("removing unrecorded layer {}", layer);
+            fs::remove_file(&data_path)
+                .with_context(|| format!("could not remove layer={:?}", data_path))?;
+        }
+    }
+    info!("resuming labelling at layer {}", completed + 1);
 
-    info!("seal_pre_commit_phase1:finish: {:?}", sector_id);
-    Ok(out)
+    Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -204,6 +476,29 @@ pub fn seal_pre_commit_phase2<R, S, Tree: 'static + MerkleTreeTrait>(
     cache_path: S,
     replica_path: R,
 ) -> Result<SealPreCommitOutput>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+{
+    seal_pre_commit_phase2_with_control::<_, _, Tree>(
+        porep_config,
+        phase1_output,
+        cache_path,
+        replica_path,
+        &SealControl::default(),
+    )
+}
+
+/// Like `seal_pre_commit_phase2`, but reports the progress of building tree_c and
+/// tree_r_last to `control`. Cancellation stops the sealing while tree_c is built; once the
+/// replica is being encoded it runs to completion.
+pub fn seal_pre_commit_phase2_with_control<R, S, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: SealPreCommitPhase1Output<Tree>,
+    cache_path: S,
+    replica_path: R,
+    control: &SealControl,
+) -> Result<SealPreCommitOutput>
 where
     R: AsRef<Path>,
     S: AsRef<Path>,
@@ -287,14 +582,16 @@ where
         _,
     >>::setup(&compound_setup_params)?;
 
-    let (tau, (p_aux, t_aux)) = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase2(
-        &compound_public_params.vanilla_params,
-        labels,
-        data,
-        data_tree,
-        config,
-        replica_path.as_ref().to_path_buf(),
-    )?;
+    let (tau, (p_aux, t_aux)) =
+        StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase2_with_control(
+            &compound_public_params.vanilla_params,
+            labels,
+            data,
+            data_tree,
+            config,
+            replica_path.as_ref().to_path_buf(),
+            control,
+        )?;
 
     let comm_r = commitment_from_fr(tau.comm_r.into());
 
@@ -307,13 +604,7 @@ where
         .write_all(&p_aux_bytes)
         .with_context(|| format!("could not write to file p_aux={:?}", p_aux_path))?;
 
-    let t_aux_path = cache_path.as_ref().join(CacheKey::TAux.to_string());
-    let mut f_t_aux = File::create(&t_aux_path)
-        .with_context(|| format!("could not create file t_aux={:?}", t_aux_path))?;
-    let t_aux_bytes = serialize(&t_aux)?;
-    f_t_aux
-        .write_all(&t_aux_bytes)
-        .with_context(|| format!("could not write to file t_aux={:?}", t_aux_path))?;
+    persist_t_aux(cache_path.as_ref(), &t_aux)?;
 
     let out = SealPreCommitOutput { comm_r, comm_d };
 
@@ -332,6 +623,35 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     seed: Ticket,
     pre_commit: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output<Tree>> {
+    seal_commit_phase1_with_control::<_, Tree>(
+        porep_config,
+        cache_path,
+        replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        piece_infos,
+        &SealControl::default(),
+    )
+}
+
+/// Like `seal_commit_phase1`, but stops with `Error::Cancelled` before generating the vanilla
+/// proofs if `control` was cancelled, and reports them to `control` once generated.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1_with_control<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: T,
+    replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+    control: &SealControl,
 ) -> Result<SealCommitPhase1Output<Tree>> {
     info!("seal_commit_phase1:start: {:?}", sector_id);
 
@@ -362,17 +682,7 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         deserialize(&p_aux_bytes)
     }?;
 
-    let t_aux = {
-        let t_aux_path = cache_path.as_ref().join(CacheKey::TAux.to_string());
-        let t_aux_bytes = fs::read(&t_aux_path)
-            .with_context(|| format!("could not read file t_aux={:?}", t_aux_path))?;
-
-        let mut res: TemporaryAux<_, _> = deserialize(&t_aux_bytes)?;
-
-        // Switch t_aux to the passed in cache_path
-        res.set_cache_path(cache_path);
-        res
-    };
+    let t_aux = load_t_aux::<Tree>(cache_path.as_ref())?;
 
     // Convert TemporaryAux to TemporaryAuxCache, which instantiates all
     // elements based on the configs stored in TemporaryAux.
@@ -422,12 +732,18 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         _,
     >>::setup(&compound_setup_params)?;
 
+    let partitions = StackedCompound::partition_count(&compound_public_params);
+    control.check()?;
     let vanilla_proofs = StackedDrg::prove_all_partitions(
         &compound_public_params.vanilla_params,
         &public_inputs,
         &private_inputs,
-        StackedCompound::partition_count(&compound_public_params),
+        partitions,
     )?;
+    control.report(Progress::VanillaProofs {
+        done: partitions,
+        total: partitions,
+    });
 
     let sanity_check = StackedDrg::<Tree, DefaultPieceHasher>::verify_all_partitions(
         &compound_public_params.vanilla_params,
@@ -449,40 +765,107 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     Ok(out)
 }
 
+/// Converts `phase1_output` into its compact form, which stores the merkle path nodes shared
+/// between the vanilla proofs of different challenges only once. It serializes to a fraction
+/// of the size of the output itself, which matters when it is shipped to a remote
+/// `seal_commit_phase2` worker.
+pub fn compact_seal_commit_phase1_output<Tree: 'static + MerkleTreeTrait>(
+    phase1_output: &SealCommitPhase1Output<Tree>,
+) -> CompactSealCommitPhase1Output<Tree> {
+    CompactSealCommitPhase1Output {
+        vanilla_proofs: stacked::CompactProofs::from_proofs(&phase1_output.vanilla_proofs),
+        comm_r: phase1_output.comm_r,
+        comm_d: phase1_output.comm_d,
+        replica_id: phase1_output.replica_id,
+        seed: phase1_output.seed,
+        ticket: phase1_output.ticket,
+    }
+}
+
+/// Restores the `SealCommitPhase1Output` that `compact` was created from, and verifies its
+/// vanilla proofs, so that a corrupted or mismatched transfer is caught before proving.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the output was created with.
+/// * `compact` - the output of `compact_seal_commit_phase1_output`.
+pub fn expand_seal_commit_phase1_output<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    compact: CompactSealCommitPhase1Output<Tree>,
+) -> Result<SealCommitPhase1Output<Tree>> {
+    info!("expand_seal_commit_phase1_output:start");
+
+    let phase1_output = SealCommitPhase1Output {
+        vanilla_proofs: compact.vanilla_proofs.into_proofs()?,
+        comm_r: compact.comm_r,
+        comm_d: compact.comm_d,
+        replica_id: compact.replica_id,
+        seed: compact.seed,
+        ticket: compact.ticket,
+    };
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        phase1_output.vanilla_proofs.len() == partitions,
+        "expected {} partitions of vanilla proofs, got {}",
+        partitions,
+        phase1_output.vanilla_proofs.len()
+    );
+
+    let public_inputs = seal_commit_public_inputs(&phase1_output)?;
+    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<'_, Tree, DefaultPieceHasher>,
+        _,
+    >>::setup(&seal_commit_setup_params::<Tree>(porep_config)?)?;
+
+    let verified = StackedDrg::<Tree, DefaultPieceHasher>::verify_all_partitions(
+        &compound_public_params.vanilla_params,
+        &public_inputs,
+        &phase1_output.vanilla_proofs,
+    )?;
+    ensure!(verified, "expanded vanilla proofs failed to verify");
+
+    info!("expand_seal_commit_phase1_output:finish");
+    Ok(phase1_output)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     phase1_output: SealCommitPhase1Output<Tree>,
     prover_id: ProverId,
     sector_id: SectorId,
+) -> Result<SealCommitOutput> {
+    seal_commit_phase2_with_control(
+        porep_config,
+        phase1_output,
+        prover_id,
+        sector_id,
+        &SealControl::default(),
+    )
+}
+
+/// Like `seal_commit_phase2`, but stops with `Error::Cancelled` before synthesizing the
+/// circuits if `control` was cancelled, and reports the proofs to `control` once generated.
+pub fn seal_commit_phase2_with_control<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: SealCommitPhase1Output<Tree>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    control: &SealControl,
 ) -> Result<SealCommitOutput> {
     info!("seal_commit_phase2:start: {:?}", sector_id);
 
+    let public_inputs = seal_commit_public_inputs(&phase1_output)?;
     let SealCommitPhase1Output {
         vanilla_proofs,
         comm_d,
         comm_r,
-        replica_id,
         seed,
         ticket,
+        ..
     } = phase1_output;
 
-    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
-    ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
-
-    let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
-    let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
-
-    let public_inputs = stacked::PublicInputs {
-        replica_id,
-        tau: Some(stacked::Tau {
-            comm_d: comm_d_safe,
-            comm_r: comm_r_safe,
-        }),
-        k: None,
-        seed,
-    };
-
     let groth_params = get_stacked_params::<Tree>(porep_config)?;
 
     trace!(
@@ -490,22 +873,13 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
         u64::from(PaddedBytesAmount::from(porep_config))
     );
 
-    let compound_setup_params = compound_proof::SetupParams {
-        vanilla_params: setup_params(
-            PaddedBytesAmount::from(porep_config),
-            usize::from(PoRepProofPartitions::from(porep_config)),
-            porep_config.porep_id,
-            porep_config.api_version,
-        )?,
-        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
-        priority: false,
-    };
-
     let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
         StackedDrg<'_, Tree, DefaultPieceHasher>,
         _,
-    >>::setup(&compound_setup_params)?;
+    >>::setup(&seal_commit_setup_params(porep_config)?)?;
 
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    control.check()?;
     trace!("snark_proof:start");
     let groth_proofs = StackedCompound::<Tree, DefaultPieceHasher>::circuit_proofs(
         &public_inputs,
@@ -515,6 +889,10 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
         compound_public_params.priority,
     )?;
     trace!("snark_proof:finish");
+    control.report(Progress::SnarkProofs {
+        done: partitions,
+        total: partitions,
+    });
 
     let proof = MultiProof::new(groth_proofs, &groth_params.pvk);
 
@@ -544,6 +922,162 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     Ok(out)
 }
 
+/// Generates the SNARK for a single partition of a sector's PoRep, so that the partitions
+/// proven together by `seal_commit_phase2` can be spread across several provers. The
+/// partition proofs are combined with `merge_seal_commit_partition_proofs`.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
+/// * `phase1_output` - the output of `seal_commit_phase1` for the sector.
+/// * `partition_k` - the index of the partition to prove.
+pub fn seal_commit_phase2_partition<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: &SealCommitPhase1Output<Tree>,
+    partition_k: usize,
+) -> Result<PartitionSnarkProof> {
+    info!("seal_commit_phase2_partition:start: {}", partition_k);
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        partition_k < partitions,
+        "invalid partition {} (sector has {} partitions)",
+        partition_k,
+        partitions
+    );
+    ensure!(
+        phase1_output.vanilla_proofs.len() == partitions,
+        "expected vanilla proofs for {} partitions, found {}",
+        partitions,
+        phase1_output.vanilla_proofs.len()
+    );
+
+    let public_inputs = seal_commit_public_inputs(phase1_output)?;
+    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<'_, Tree, DefaultPieceHasher>,
+        _,
+    >>::setup(&seal_commit_setup_params(porep_config)?)?;
+    let groth_params = get_stacked_params::<Tree>(porep_config)?;
+
+    let circuit = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<'_, Tree, DefaultPieceHasher>,
+        _,
+    >>::circuit(
+        &public_inputs,
+        Default::default(),
+        &phase1_output.vanilla_proofs[partition_k],
+        &compound_public_params.vanilla_params,
+        Some(partition_k),
+    )?;
+
+    trace!("snark_proof:start");
+    let groth_proofs =
+        groth16::create_random_proof_batch(vec![circuit], &*groth_params, &mut OsRng)?;
+    trace!("snark_proof:finish");
+
+    let mut proof = Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN);
+    for groth_proof in &groth_proofs {
+        groth_proof.write(&mut proof)?;
+    }
+
+    info!("seal_commit_phase2_partition:finish: {}", partition_k);
+    Ok(PartitionSnarkProof(proof))
+}
+
+/// Combines the partition proofs of a sector, ordered by partition index, into the
+/// `SealCommitOutput` that `seal_commit_phase2` would have produced, and verifies it.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in the sector.
+/// * `phase1_output` - the output of `seal_commit_phase1` the partitions were proven from.
+/// * `prover_id` - the prover-id that sealed the sector.
+/// * `sector_id` - the sector-id of the sealed sector.
+/// * `proofs` - the output of `seal_commit_phase2_partition` for every partition.
+pub fn merge_seal_commit_partition_proofs<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: &SealCommitPhase1Output<Tree>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    proofs: Vec<PartitionSnarkProof>,
+) -> Result<SealCommitOutput> {
+    info!("merge_seal_commit_partition_proofs:start: {:?}", sector_id);
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        proofs.len() == partitions,
+        "expected {} partition proofs, found {}",
+        partitions,
+        proofs.len()
+    );
+
+    let mut buf = Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN * partitions);
+    for (k, proof) in proofs.iter().enumerate() {
+        ensure!(
+            proof.0.len() == SINGLE_PARTITION_PROOF_LEN,
+            "invalid proof length {} for partition {}",
+            proof.0.len(),
+            k
+        );
+        buf.extend_from_slice(&proof.0);
+    }
+
+    let is_valid = verify_seal::<Tree>(
+        porep_config,
+        phase1_output.comm_r,
+        phase1_output.comm_d,
+        prover_id,
+        sector_id,
+        phase1_output.ticket,
+        phase1_output.seed,
+        &buf,
+    )?;
+    ensure!(is_valid, "merged seal proof failed to verify");
+
+    info!("merge_seal_commit_partition_proofs:finish: {:?}", sector_id);
+    Ok(SealCommitOutput { proof: buf })
+}
+
+fn seal_commit_public_inputs<Tree: MerkleTreeTrait>(
+    phase1_output: &SealCommitPhase1Output<Tree>,
+) -> Result<stacked::PublicInputs<<Tree::Hasher as Hasher>::Domain, DefaultPieceDomain>> {
+    let comm_d = phase1_output.comm_d;
+    let comm_r = phase1_output.comm_r;
+
+    ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
+    ensure!(comm_r != [0; 32], "Invalid all zero commitment (comm_r)");
+
+    let comm_r_safe = as_safe_commitment(&comm_r, "comm_r")?;
+    let comm_d_safe = DefaultPieceDomain::try_from_bytes(&comm_d)?;
+
+    Ok(stacked::PublicInputs {
+        replica_id: phase1_output.replica_id,
+        tau: Some(stacked::Tau {
+            comm_d: comm_d_safe,
+            comm_r: comm_r_safe,
+        }),
+        k: None,
+        seed: phase1_output.seed,
+    })
+}
+
+fn seal_commit_setup_params<'a, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<compound_proof::SetupParams<'a, StackedDrg<'a, Tree, DefaultPieceHasher>>> {
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+
+    Ok(compound_proof::SetupParams {
+        vanilla_params: setup_params(
+            PaddedBytesAmount::from(porep_config),
+            partitions,
+            porep_config.porep_id,
+            porep_config.api_version,
+        )?,
+        partitions: Some(partitions),
+        priority: false,
+    })
+}
+
 /// Given the specified arguments, this method returns the inputs that were used to
 /// generate the seal proof.  This can be useful for proof aggregation, as verification
 /// requires these inputs.
@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use anyhow::Result;
+use storage_proofs_core::{merkle::MerkleTreeTrait, sector::SectorId};
+
+use crate::{
+    api::{seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2},
+    types::{
+        PieceInfo, PoRepConfig, ProverId, SealCommitOutput, SealCommitPhase1Output, SealEnvelope,
+        SealPreCommitOutput, SealPreCommitPhase1Output, Ticket,
+    },
+};
+
+/// Like `seal_pre_commit_phase1`, but returns the output in a `SealEnvelope`, with its store
+/// paths relative to `cache_path`.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_enveloped<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+) -> Result<SealEnvelope<SealPreCommitPhase1Output<Tree>>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    let output = seal_pre_commit_phase1::<_, _, _, Tree>(
+        porep_config,
+        cache_path.as_ref(),
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+    )?;
+
+    SealEnvelope::new(porep_config, output, cache_path.as_ref())
+}
+
+/// Like `seal_pre_commit_phase2`, but takes the phase 1 output in a `SealEnvelope`, which is
+/// checked against `porep_config` and whose store paths are resolved against `cache_path`.
+pub fn seal_pre_commit_phase2_enveloped<R, S, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    envelope: SealEnvelope<SealPreCommitPhase1Output<Tree>>,
+    cache_path: S,
+    replica_path: R,
+) -> Result<SealPreCommitOutput>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+{
+    let phase1_output = envelope.into_payload(porep_config, cache_path.as_ref())?;
+
+    seal_pre_commit_phase2::<_, _, Tree>(porep_config, phase1_output, cache_path, replica_path)
+}
+
+/// Like `seal_commit_phase1`, but returns the output in a `SealEnvelope`.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1_enveloped<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: T,
+    replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+) -> Result<SealEnvelope<SealCommitPhase1Output<Tree>>> {
+    let output = seal_commit_phase1::<_, Tree>(
+        porep_config,
+        &cache_path,
+        &replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        piece_infos,
+    )?;
+
+    SealEnvelope::new(porep_config, output, cache_path.as_ref())
+}
+
+/// Like `seal_commit_phase2`, but takes the phase 1 output in a `SealEnvelope`, which is
+/// checked against `porep_config`.
+pub fn seal_commit_phase2_enveloped<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    envelope: SealEnvelope<SealCommitPhase1Output<Tree>>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+) -> Result<SealCommitOutput> {
+    let phase1_output = envelope.into_commit_phase1_output(porep_config)?;
+
+    seal_commit_phase2(porep_config, phase1_output, prover_id, sector_id)
+}
@@ -0,0 +1,242 @@
+use std::cmp::{max, min};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Granularity of the holes, the block size of common file systems.
+const HOLE_BYTES: u64 = 4096;
+
+/// Writes to a file like the file itself, except that zero blocks past the end of the file are
+/// not written but left as holes, which read as zeros without taking up disk space.
+///
+/// This makes staging half-full or committed capacity sectors cheap: the alignment `add_piece`
+/// writes to a `SparseFileWriter` is left as holes. The FFI stages sectors this way.
+#[derive(Debug)]
+pub struct SparseFileWriter {
+    file: File,
+    position: u64,
+    /// The length of the file, including trailing holes.
+    len: u64,
+}
+
+impl SparseFileWriter {
+    /// Wraps `file`, writing from its current position on.
+    pub fn new(mut file: File) -> Result<Self> {
+        let position = file
+            .stream_position()
+            .context("could not get file position")?;
+        let len = file.metadata().context("could not get file length")?.len();
+
+        Ok(SparseFileWriter {
+            file,
+            position,
+            len,
+        })
+    }
+
+    /// Returns the underlying file, positioned after the last byte written.
+    pub fn into_inner(mut self) -> Result<File> {
+        self.file.seek(SeekFrom::Start(self.position))?;
+        Ok(self.file)
+    }
+}
+
+impl Write for SparseFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            // Split at hole boundaries, so that every zero block can be skipped.
+            let block_end = (self.position / HOLE_BYTES + 1) * HOLE_BYTES;
+            let chunk_len = min(buf.len() - written, (block_end - self.position) as usize);
+            let chunk = &buf[written..written + chunk_len];
+
+            if self.position >= self.len && chunk.iter().all(|byte| *byte == 0) {
+                self.position += chunk_len as u64;
+            } else {
+                if self.file.stream_position()? != self.position {
+                    self.file.seek(SeekFrom::Start(self.position))?;
+                }
+                self.file.write_all(chunk)?;
+                self.position += chunk_len as u64;
+                self.len = max(self.len, self.position);
+            }
+            written += chunk_len;
+        }
+
+        // Trailing holes only exist once the file is extended to cover them.
+        if self.position > self.len {
+            self.file.set_len(self.position)?;
+            self.len = self.position;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Reads a file like the file itself, except that holes are not read but served as zeros.
+///
+/// The data regions are looked up with `SEEK_DATA` and `SEEK_HOLE` where the platform supports
+/// them, elsewhere the whole file is taken to be data. Commitments over a `SparseFileReader`
+/// skip the subtrees in holes instead of hashing them.
+#[derive(Debug)]
+pub struct SparseFileReader {
+    file: File,
+    position: u64,
+    len: u64,
+    /// Start and end of the data regions, in order.
+    data: Vec<(u64, u64)>,
+}
+
+impl SparseFileReader {
+    /// Wraps `file`, reading from its current position on.
+    pub fn new(mut file: File) -> Result<Self> {
+        let position = file
+            .stream_position()
+            .context("could not get file position")?;
+        let len = file.metadata().context("could not get file length")?.len();
+        let data = data_regions(&file, len).context("could not find the holes of the file")?;
+        // Looking up the regions moves the file position.
+        file.seek(SeekFrom::Start(position))?;
+
+        Ok(SparseFileReader {
+            file,
+            position,
+            len,
+            data,
+        })
+    }
+
+    /// Returns whether the next `len` bytes are all in a hole.
+    pub(crate) fn is_hole(&self, len: u64) -> bool {
+        let end = self.position + len;
+        end <= self.len && !matches!(self.next_data(), Some(data) if data.0 < end)
+    }
+
+    /// Skips the next `len` bytes.
+    pub(crate) fn skip(&mut self, len: u64) {
+        self.position += len;
+    }
+
+    /// Copies the rest of the file to `target` from its current position on, leaving the holes
+    /// as holes. Returns the number of bytes copied.
+    pub(crate) fn copy_to(&mut self, target: &mut File) -> io::Result<u64> {
+        let start = min(self.position, self.len);
+        let target_start = target.stream_position()?;
+        for (data_start, data_end) in self.data.clone() {
+            let data_start = max(data_start, start);
+            if data_end <= data_start {
+                continue;
+            }
+            self.file.seek(SeekFrom::Start(data_start))?;
+            target.seek(SeekFrom::Start(target_start + data_start - start))?;
+            io::copy(&mut (&mut self.file).take(data_end - data_start), target)?;
+        }
+
+        let len = self.len - start;
+        let target_end = target_start + len;
+        if target.metadata()?.len() < target_end {
+            target.set_len(target_end)?;
+        }
+        target.seek(SeekFrom::Start(target_end))?;
+        self.position = self.len;
+
+        Ok(len)
+    }
+
+    /// Returns the first data region which ends after the current position.
+    fn next_data(&self) -> Option<&(u64, u64)> {
+        let index = self.data.partition_point(|data| data.1 <= self.position);
+        self.data.get(index)
+    }
+}
+
+impl Read for SparseFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len {
+            return Ok(0);
+        }
+        let max_len = min(buf.len() as u64, self.len - self.position);
+
+        let n = match self.next_data().copied() {
+            Some((start, end)) if start <= self.position => {
+                if self.file.stream_position()? != self.position {
+                    self.file.seek(SeekFrom::Start(self.position))?;
+                }
+                let len = min(max_len, end - self.position) as usize;
+                self.file.read(&mut buf[..len])?
+            }
+            next => {
+                let hole_end = next.map_or(self.len, |data| data.0);
+                let len = min(max_len, hole_end - self.position) as usize;
+                buf[..len].fill(0);
+                len
+            }
+        };
+        self.position += n as u64;
+
+        Ok(n)
+    }
+}
+
+/// Returns the start and end of the data regions of `file`, which is `len` bytes long.
+#[cfg(target_os = "linux")]
+fn data_regions(file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut regions = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if start < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // There is no data after `offset`.
+                Some(libc::ENXIO) => Ok(regions),
+                // The file system does not support looking up holes.
+                Some(libc::EINVAL) => Ok(vec![(0, len)]),
+                _ => Err(err),
+            };
+        }
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        regions.push((start as u64, min(end as u64, len)));
+        offset = end as u64;
+    }
+
+    Ok(regions)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn data_regions(_file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    Ok(vec![(0, len)])
+}
+
+/// Returns the number of bytes of the file at `path` which take up disk space, as opposed to
+/// holes.
+pub fn materialized_bytes<P: AsRef<Path>>(path: P) -> Result<u64> {
+    let metadata = fs::metadata(path.as_ref())
+        .with_context(|| format!("could not read metadata of {:?}", path.as_ref().display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // `blocks` counts 512 byte units, whatever the block size of the file system.
+        Ok(metadata.blocks() * 512)
+    }
+
+    #[cfg(not(unix))]
+    {
+        Ok(metadata.len())
+    }
+}
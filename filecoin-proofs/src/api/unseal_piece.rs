@@ -0,0 +1,167 @@
+use std::cmp::min;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::Domain;
+use fr32::{write_unpadded, Fr32Layout};
+use log::{info, trace};
+use storage_proofs_core::{merkle::MerkleTreeTrait, sector::SectorId, util::NODE_SIZE};
+
+use crate::{
+    api::{unseal_range, SparseFileReader, UNSEAL_WINDOW_BYTES},
+    constants::DefaultPieceDomain,
+    parallel_commitment::{subtree_root, SubtreeSource, SubtreeStack},
+    pieces::{get_piece_start_byte, zero_commitment},
+    types::{
+        Commitment, PaddedBytesAmount, PieceInfo, PoRepConfig, ProverId, Ticket, UnpaddedByteIndex,
+        UnpaddedBytesAmount,
+    },
+};
+
+/// Writes the unpadded bytes of the piece with `comm_p` to `writer`, reading them from the bit
+/// padded unsealed sector file at `unsealed_path`, as written by `add_piece`.
+///
+/// `piece_infos` are the pieces of the sector in the order they were added, which locate the
+/// piece. The piece is read in windows of `UNSEAL_WINDOW_BYTES`. With `verify_comm_p`, its
+/// commitment is recomputed while it is written, and a mismatch is an error once the whole
+/// piece has been written.
+pub fn read_piece_from_unsealed<P: AsRef<Path>, W: Write>(
+    unsealed_path: P,
+    piece_infos: &[PieceInfo],
+    comm_p: &Commitment,
+    mut writer: W,
+    verify_comm_p: bool,
+) -> Result<UnpaddedBytesAmount> {
+    info!("read_piece_from_unsealed:start");
+
+    let unsealed_path = unsealed_path.as_ref();
+    let (offset, size) = locate_piece(piece_infos, comm_p)?;
+    let padded_offset = u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount::from(offset)));
+    let padded_size = u64::from(PaddedBytesAmount::from(size));
+
+    let mut file = File::open(unsealed_path)
+        .with_context(|| format!("could not open unsealed_path={:?}", unsealed_path))?;
+    file.seek(SeekFrom::Start(padded_offset))?;
+    let mut source = SparseFileReader::new(file)?;
+
+    // Pieces are powers of two, so that every window is a complete subtree of the piece.
+    let window_len = min(UNSEAL_WINDOW_BYTES as u64, padded_size) as usize;
+    let window_height = (window_len / NODE_SIZE).trailing_zeros() as usize;
+    let zero_root = DefaultPieceDomain::try_from_bytes(&zero_commitment(PaddedBytesAmount(
+        window_len as u64,
+    ))?)?;
+    let mut window = vec![0u8; window_len];
+    let mut stack = SubtreeStack::default();
+    for _ in 0..padded_size / window_len as u64 {
+        // Windows in holes of the unsealed file are not read or hashed.
+        let is_data = source
+            .read_subtree(&mut window)
+            .context("unsealed sector ended before the end of the piece")?;
+        if !is_data {
+            window.fill(0);
+        }
+        let unpadded_len = Fr32Layout::unpadded_len(window_len as u64) as usize;
+        write_unpadded(&window, &mut writer, 0, unpadded_len)?;
+
+        if verify_comm_p {
+            stack.push(
+                window_height,
+                if is_data {
+                    subtree_root(&window)
+                } else {
+                    zero_root
+                },
+            );
+        }
+    }
+
+    if verify_comm_p {
+        ensure!(
+            &stack.finish()? == comm_p,
+            "piece data does not match comm_p={:?}",
+            hex::encode(comm_p)
+        );
+    }
+
+    info!("read_piece_from_unsealed:finish");
+    Ok(size)
+}
+
+/// Writes the unpadded bytes of the piece with `comm_p` to `writer`, reading them from the
+/// unsealed copy at `unsealed_path` if it exists and unsealing them from the replica at
+/// `sealed_path` otherwise.
+///
+/// Only a piece read from the unsealed copy is checked against `comm_p`, as the replica
+/// decodes to the data it was sealed with.
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_piece<P, Q, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    sealed_path: Q,
+    unsealed_path: Q,
+    writer: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    comm_p: &Commitment,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    info!("unseal_piece:start");
+
+    let result = if unsealed_path.as_ref().exists() {
+        read_piece_from_unsealed(unsealed_path, piece_infos, comm_p, writer, true)
+    } else {
+        trace!("no unsealed copy, unsealing the piece from the replica");
+        let (offset, size) = locate_piece(piece_infos, comm_p)?;
+        let sealed_path = sealed_path.as_ref();
+        let sealed = File::open(sealed_path)
+            .with_context(|| format!("could not open sealed_path={:?}", sealed_path))?;
+
+        unseal_range::<_, _, _, Tree>(
+            porep_config,
+            cache_path.as_ref(),
+            BufReader::new(sealed),
+            writer,
+            prover_id,
+            sector_id,
+            comm_d,
+            ticket,
+            offset,
+            size,
+        )
+    };
+
+    info!("unseal_piece:finish");
+    result
+}
+
+/// Returns the offset and size of the first piece with `comm_p` in `piece_infos`.
+fn locate_piece(
+    piece_infos: &[PieceInfo],
+    comm_p: &Commitment,
+) -> Result<(UnpaddedByteIndex, UnpaddedBytesAmount)> {
+    let index = piece_infos
+        .iter()
+        .position(|piece| &piece.commitment == comm_p)
+        .with_context(|| format!("no piece with comm_p={:?}", hex::encode(comm_p)))?;
+
+    let piece_sizes: Vec<UnpaddedBytesAmount> =
+        piece_infos.iter().map(|piece| piece.size).collect();
+    let size = piece_sizes[index];
+    ensure!(
+        u64::from(PaddedBytesAmount::from(size)).is_power_of_two(),
+        "Piece size ({:?}) must be a power of 2.",
+        PaddedBytesAmount::from(size)
+    );
+
+    Ok((get_piece_start_byte(&piece_sizes[..index], size), size))
+}
@@ -0,0 +1,514 @@
+use std::cmp::min;
+use std::fs::{self, metadata, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize};
+use filecoin_hashers::{HashFunction, Hasher};
+use log::{info, trace};
+use memmap::MmapOptions;
+use merkletree::{
+    merkle::get_merkle_tree_len,
+    store::{DiskStore, Store, StoreConfig},
+};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    compound_proof::{self, CompoundProof},
+    merkle::{
+        create_base_merkle_tree, create_tree, get_base_tree_count, split_config_and_replica,
+        BinaryMerkleTree, MerkleTreeTrait, MerkleTreeWrapper,
+    },
+    multi_proof::MultiProof,
+    proof::NoRequirements,
+    util::default_rows_to_discard,
+};
+use storage_proofs_porep::stacked::PersistentAux;
+use storage_proofs_update::{self as update, EmptySectorUpdate, EmptySectorUpdateCompound};
+use typenum::Unsigned;
+
+use crate::{
+    api::{as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size},
+    caches::{get_empty_sector_update_params, get_empty_sector_update_verifying_key},
+    constants::{
+        DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher, SINGLE_PARTITION_PROOF_LEN,
+    },
+    parameters::empty_sector_update_setup_params,
+    pieces::verify_pieces,
+    types::{
+        Commitment, EmptySectorUpdateEncoded, EmptySectorUpdateProof, PaddedBytesAmount, PieceInfo,
+        PoRepConfig, PoRepProofPartitions, BINARY_ARITY,
+    },
+};
+
+/// Number of bytes encoded at once, bounding the memory needed to hold a copy of the data.
+const ENCODE_CHUNK_BYTES: usize = 1 << 24;
+
+/// Encodes the data staged at `staged_data_path` into the committed capacity sector whose
+/// replica, the sector key, is at `sector_key_path`. The updated replica is written to
+/// `new_replica_path`, its tree-d, tree-r-last and p_aux to `new_cache_path`.
+///
+/// The updated replica shares comm_c with the sector key, hence it can be proven with window
+/// and winning PoSt like any sealed sector, using `new_cache_path` as its cache directory.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the committed capacity sector was sealed with.
+/// * `new_replica_path` - path the updated replica will be written to.
+/// * `new_cache_path` - directory in which the trees of the updated replica will be written.
+/// * `sector_key_path` - path to the replica of the committed capacity sector.
+/// * `sector_key_cache_path` - cache directory of the committed capacity sector.
+/// * `staged_data_path` - path to the unsealed data, as written by `add_piece`.
+/// * `piece_infos` - the pieces of the staged data, which must match the new comm_d.
+pub fn encode_into<P: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    new_replica_path: P,
+    new_cache_path: P,
+    sector_key_path: P,
+    sector_key_cache_path: P,
+    staged_data_path: P,
+    piece_infos: &[PieceInfo],
+) -> Result<EmptySectorUpdateEncoded> {
+    info!("encode_into:start");
+
+    ensure!(
+        metadata(new_cache_path.as_ref())?.is_dir(),
+        "new_cache_path must be a directory"
+    );
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let p_aux_old = read_p_aux::<Tree>(sector_key_cache_path.as_ref())?;
+
+    // Copy the staged data to the new replica location, where it will be encoded in place.
+    fs::copy(&staged_data_path, &new_replica_path).with_context(|| {
+        format!(
+            "could not copy staged_data_path={:?} to new_replica_path={:?}",
+            staged_data_path.as_ref().display(),
+            new_replica_path.as_ref().display()
+        )
+    })?;
+    let f_replica = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&new_replica_path)
+        .with_context(|| {
+            format!(
+                "could not open new_replica_path={:?}",
+                new_replica_path.as_ref().display()
+            )
+        })?;
+    // Zero-pad the data to the sector size by extending the underlying file if needed.
+    f_replica.set_len(sector_bytes as u64)?;
+    let mut replica = unsafe {
+        MmapOptions::new().map_mut(&f_replica).with_context(|| {
+            format!(
+                "could not mmap new_replica_path={:?}",
+                new_replica_path.as_ref().display()
+            )
+        })?
+    };
+
+    trace!("building tree-d for the new data");
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+    let mut tree_d_config = StoreConfig::new(
+        new_cache_path.as_ref(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    tree_d_config.size = Some(get_merkle_tree_len(base_tree_leafs, BINARY_ARITY)?);
+    let tree_d = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
+        Some(tree_d_config),
+        base_tree_leafs,
+        &replica,
+    )?;
+    let comm_d_new = commitment_from_fr(tree_d.root().into());
+    drop(tree_d);
+
+    ensure!(
+        verify_pieces(&comm_d_new, piece_infos, porep_config.into())?,
+        "pieces and comm_d do not match"
+    );
+
+    trace!("encoding the new data into the sector key");
+    let f_sector_key = File::open(&sector_key_path).with_context(|| {
+        format!(
+            "could not open sector_key_path={:?}",
+            sector_key_path.as_ref().display()
+        )
+    })?;
+    let sector_key = unsafe {
+        MmapOptions::new().map(&f_sector_key).with_context(|| {
+            format!(
+                "could not mmap sector_key_path={:?}",
+                sector_key_path.as_ref().display()
+            )
+        })?
+    };
+    ensure!(
+        sector_key.len() == sector_bytes,
+        "sector key has {} bytes, expected {}",
+        sector_key.len(),
+        sector_bytes
+    );
+
+    let mut data = vec![0u8; min(ENCODE_CHUNK_BYTES, sector_bytes)];
+    for (replica_chunk, key_chunk) in replica
+        .chunks_mut(ENCODE_CHUNK_BYTES)
+        .zip(sector_key.chunks(ENCODE_CHUNK_BYTES))
+    {
+        let data = &mut data[..replica_chunk.len()];
+        data.copy_from_slice(replica_chunk);
+        EmptySectorUpdate::<Tree, DefaultPieceHasher>::encode_into(replica_chunk, key_chunk, data)?;
+    }
+    replica.flush()?;
+
+    trace!("building tree-r-last for the updated replica");
+    let base_tree_size = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let mut tree_r_last_config = StoreConfig::new(
+        new_cache_path.as_ref(),
+        CacheKey::CommRLastTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+    );
+    tree_r_last_config.size = Some(base_tree_size);
+    let tree_r_last = EmptySectorUpdate::<Tree, DefaultPieceHasher>::generate_tree_r_last(
+        &replica,
+        tree_r_last_config,
+        new_replica_path.as_ref().to_path_buf(),
+    )?;
+    let comm_r_last_new = tree_r_last.root();
+    let comm_r_new = <Tree::Hasher as Hasher>::Function::hash2(&p_aux_old.comm_c, &comm_r_last_new);
+
+    write_p_aux::<Tree>(
+        new_cache_path.as_ref(),
+        &PersistentAux {
+            comm_c: p_aux_old.comm_c,
+            comm_r_last: comm_r_last_new,
+        },
+    )?;
+
+    info!("encode_into:finish");
+    Ok(EmptySectorUpdateEncoded {
+        comm_r_new: commitment_from_fr(comm_r_new.into()),
+        comm_r_last_new: commitment_from_fr(comm_r_last_new.into()),
+        comm_d_new,
+    })
+}
+
+/// Recovers the data of an updated sector, by decoding the replica at `replica_path` with the
+/// sector key at `sector_key_path`. The (still bit-padded) data is written to `out_data_path`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the committed capacity sector was sealed with.
+/// * `out_data_path` - path the decoded data will be written to.
+/// * `replica_path` - path to the updated replica.
+/// * `sector_key_path` - path to the replica of the committed capacity sector.
+pub fn decode_from<P: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    out_data_path: P,
+    replica_path: P,
+    sector_key_path: P,
+) -> Result<()> {
+    info!("decode_from:start");
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+
+    let replica = map_sector_file(replica_path.as_ref(), sector_bytes, "replica_path")?;
+    let sector_key = map_sector_file(sector_key_path.as_ref(), sector_bytes, "sector_key_path")?;
+
+    let f_data = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&out_data_path)
+        .with_context(|| {
+            format!(
+                "could not create out_data_path={:?}",
+                out_data_path.as_ref().display()
+            )
+        })?;
+    f_data.set_len(sector_bytes as u64)?;
+    let mut data = unsafe {
+        MmapOptions::new().map_mut(&f_data).with_context(|| {
+            format!(
+                "could not mmap out_data_path={:?}",
+                out_data_path.as_ref().display()
+            )
+        })?
+    };
+
+    EmptySectorUpdate::<Tree, DefaultPieceHasher>::decode_from(&mut data, &sector_key, &replica)?;
+    data.flush()?;
+
+    info!("decode_from:finish");
+    Ok(())
+}
+
+/// Generates a proof that the updated replica at `replica_path` is the encoding of the data
+/// committed to by `comm_d_new` into the committed capacity sector `comm_r_old`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the committed capacity sector was sealed with.
+/// * `comm_r_old` - replica commitment of the committed capacity sector.
+/// * `comm_r_new` - replica commitment of the updated sector, as returned by `encode_into`.
+/// * `comm_d_new` - commitment to the data of the updated sector.
+/// * `sector_key_path` - path to the replica of the committed capacity sector.
+/// * `sector_key_cache_path` - cache directory of the committed capacity sector.
+/// * `replica_path` - path to the updated replica.
+/// * `replica_cache_path` - cache directory of the updated replica.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_replica_update<P: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: P,
+    sector_key_cache_path: P,
+    replica_path: P,
+    replica_cache_path: P,
+) -> Result<EmptySectorUpdateProof> {
+    info!("prove_replica_update:start");
+
+    ensure!(
+        comm_r_old != [0; 32],
+        "Invalid all zero commitment (comm_r_old)"
+    );
+    ensure!(
+        comm_r_new != [0; 32],
+        "Invalid all zero commitment (comm_r_new)"
+    );
+    ensure!(
+        comm_d_new != [0; 32],
+        "Invalid all zero commitment (comm_d_new)"
+    );
+
+    let public_inputs = update::PublicInputs {
+        comm_r_old: as_safe_commitment(&comm_r_old, "comm_r_old")?,
+        comm_d_new: as_safe_commitment::<DefaultPieceDomain, _>(&comm_d_new, "comm_d_new")?,
+        comm_r_new: as_safe_commitment(&comm_r_new, "comm_r_new")?,
+        k: None,
+    };
+
+    let p_aux_old = read_p_aux::<Tree>(sector_key_cache_path.as_ref())?;
+    let p_aux_new = read_p_aux::<Tree>(replica_cache_path.as_ref())?;
+    ensure!(
+        p_aux_old.comm_c == p_aux_new.comm_c,
+        "sector key and replica do not share comm_c"
+    );
+
+    let tree_r_last_old = open_tree_r_last::<Tree>(
+        porep_config,
+        sector_key_cache_path.as_ref(),
+        sector_key_path.as_ref(),
+    )?;
+    let tree_r_last_new = open_tree_r_last::<Tree>(
+        porep_config,
+        replica_cache_path.as_ref(),
+        replica_path.as_ref(),
+    )?;
+    let tree_d_new = open_tree_d(porep_config, replica_cache_path.as_ref())?;
+
+    let private_inputs = update::PrivateInputs::<Tree, DefaultPieceHasher> {
+        comm_c: p_aux_new.comm_c,
+        tree_r_last_old: &tree_r_last_old,
+        tree_r_last_new: &tree_r_last_new,
+        tree_d_new: &tree_d_new,
+    };
+
+    let compound_public_params = compound_public_params::<Tree>(porep_config)?;
+    let groth_params = get_empty_sector_update_params::<Tree>(porep_config)?;
+
+    trace!("snark_proof:start");
+    let proof = EmptySectorUpdateCompound::prove(
+        &compound_public_params,
+        &public_inputs,
+        &private_inputs,
+        &groth_params,
+    )?;
+    trace!("snark_proof:finish");
+
+    let mut buf = Vec::with_capacity(
+        SINGLE_PARTITION_PROOF_LEN * usize::from(PoRepProofPartitions::from(porep_config)),
+    );
+    proof.write(&mut buf)?;
+
+    info!("prove_replica_update:finish");
+    Ok(buf)
+}
+
+/// Verifies a proof generated by `prove_replica_update`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration the committed capacity sector was sealed with.
+/// * `comm_r_old` - replica commitment of the committed capacity sector.
+/// * `comm_r_new` - replica commitment of the updated sector.
+/// * `comm_d_new` - commitment to the data of the updated sector.
+/// * `proof` - the proof, as returned by `prove_replica_update`.
+pub fn verify_replica_update<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    proof: &[u8],
+) -> Result<bool> {
+    info!("verify_replica_update:start");
+
+    ensure!(
+        comm_r_old != [0; 32],
+        "Invalid all zero commitment (comm_r_old)"
+    );
+    ensure!(
+        comm_r_new != [0; 32],
+        "Invalid all zero commitment (comm_r_new)"
+    );
+    ensure!(
+        comm_d_new != [0; 32],
+        "Invalid all zero commitment (comm_d_new)"
+    );
+    ensure!(!proof.is_empty(), "Invalid proof bytes (empty vector)");
+
+    let public_inputs = update::PublicInputs::<<Tree::Hasher as Hasher>::Domain, _> {
+        comm_r_old: as_safe_commitment(&comm_r_old, "comm_r_old")?,
+        comm_d_new: as_safe_commitment::<DefaultPieceDomain, _>(&comm_d_new, "comm_d_new")?,
+        comm_r_new: as_safe_commitment(&comm_r_new, "comm_r_new")?,
+        k: None,
+    };
+
+    let compound_public_params = compound_public_params::<Tree>(porep_config)?;
+    let verifying_key = get_empty_sector_update_verifying_key::<Tree>(porep_config)?;
+    let multi_proof = MultiProof::new_from_reader(
+        Some(usize::from(PoRepProofPartitions::from(porep_config))),
+        proof,
+        &verifying_key,
+    )?;
+
+    let result = EmptySectorUpdateCompound::verify(
+        &compound_public_params,
+        &public_inputs,
+        &multi_proof,
+        &NoRequirements,
+    );
+
+    info!("verify_replica_update:finish");
+    result
+}
+
+fn compound_public_params<'a, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<compound_proof::PublicParams<'a, EmptySectorUpdate<'a, Tree, DefaultPieceHasher>>> {
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: empty_sector_update_setup_params(
+            PaddedBytesAmount::from(porep_config),
+            partitions,
+        )?,
+        partitions: Some(partitions),
+        priority: false,
+    };
+
+    EmptySectorUpdateCompound::<Tree, DefaultPieceHasher>::setup(&compound_setup_params)
+}
+
+fn read_p_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+) -> Result<PersistentAux<<Tree::Hasher as Hasher>::Domain>> {
+    let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+    let p_aux_bytes = fs::read(&p_aux_path)
+        .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
+
+    deserialize(&p_aux_bytes).map_err(Into::into)
+}
+
+fn write_p_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+    p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+) -> Result<()> {
+    let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+    let mut f_p_aux = File::create(&p_aux_path)
+        .with_context(|| format!("could not create file p_aux={:?}", p_aux_path))?;
+    let p_aux_bytes = serialize(p_aux)?;
+    f_p_aux
+        .write_all(&p_aux_bytes)
+        .with_context(|| format!("could not write to file p_aux={:?}", p_aux_path))?;
+
+    Ok(())
+}
+
+fn map_sector_file(path: &Path, sector_bytes: usize, name: &str) -> Result<memmap::Mmap> {
+    let f = File::open(path).with_context(|| format!("could not open {}={:?}", name, path))?;
+    let mapped = unsafe {
+        MmapOptions::new()
+            .map(&f)
+            .with_context(|| format!("could not mmap {}={:?}", name, path))?
+    };
+    ensure!(
+        mapped.len() == sector_bytes,
+        "{} has {} bytes, expected {}",
+        name,
+        mapped.len(),
+        sector_bytes
+    );
+
+    Ok(mapped)
+}
+
+/// Opens the tree-r-last of the replica at `replica_path` from `cache_path`.
+fn open_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<
+    MerkleTreeWrapper<
+        Tree::Hasher,
+        Tree::Store,
+        Tree::Arity,
+        Tree::SubTreeArity,
+        Tree::TopTreeArity,
+    >,
+> {
+    let base_tree_size = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+
+    let mut config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommRLastTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+    );
+    config.size = Some(base_tree_size);
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let (configs, replica_config) = split_config_and_replica(
+        config,
+        replica_path.to_path_buf(),
+        base_tree_leafs,
+        tree_count,
+    )?;
+
+    create_tree::<Tree>(base_tree_size, &configs, Some(&replica_config))
+}
+
+/// Opens the tree-d persisted in `cache_path`.
+fn open_tree_d(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+) -> Result<BinaryMerkleTree<DefaultPieceHasher>> {
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+
+    let config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    let tree_d_size = get_merkle_tree_len(base_tree_leafs, BINARY_ARITY)?;
+    let tree_d_store: DiskStore<DefaultPieceDomain> =
+        DiskStore::new_from_disk(tree_d_size, BINARY_ARITY, &config).context("tree_d_store")?;
+
+    BinaryMerkleTree::<DefaultPieceHasher>::from_data_store(tree_d_store, base_tree_leafs)
+        .context("tree_d")
+}
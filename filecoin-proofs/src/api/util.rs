@@ -1,14 +1,24 @@
+use std::fs;
 use std::mem::size_of;
+use std::path::Path;
 
 use anyhow::{Context, Result};
+use bincode::{deserialize, serialize};
 use blstrs::Scalar as Fr;
 use filecoin_hashers::{Domain, Hasher};
 use fr32::{bytes_into_fr, fr_into_bytes};
 use merkletree::merkle::{get_merkle_tree_leafs, get_merkle_tree_len};
-use storage_proofs_core::merkle::{get_base_tree_count, MerkleTreeTrait};
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{get_base_tree_count, MerkleTreeTrait},
+};
+use storage_proofs_porep::stacked::TemporaryAux;
 use typenum::Unsigned;
 
-use crate::types::{Commitment, SectorSize};
+use crate::{
+    constants::DefaultPieceHasher,
+    types::{relativize_store_path, Commitment, SectorSize},
+};
 
 pub fn as_safe_commitment<H: Domain, T: AsRef<str>>(
     comm: &[u8; 32],
@@ -38,3 +48,38 @@ pub fn get_base_tree_size<Tree: MerkleTreeTrait>(sector_size: SectorSize) -> Res
 pub fn get_base_tree_leafs<Tree: MerkleTreeTrait>(base_tree_size: usize) -> Result<usize> {
     get_merkle_tree_leafs(base_tree_size, Tree::Arity::to_usize())
 }
+
+/// Writes `t_aux` to `cache_path`, with its store paths relative to `cache_path`, so that the
+/// cache directory can be moved.
+pub(crate) fn persist_t_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+) -> Result<()> {
+    let mut t_aux = t_aux.clone();
+    for config in t_aux.labels.labels.iter_mut() {
+        relativize_store_path(config, cache_path)?;
+    }
+    relativize_store_path(&mut t_aux.tree_d_config, cache_path)?;
+    relativize_store_path(&mut t_aux.tree_r_last_config, cache_path)?;
+    relativize_store_path(&mut t_aux.tree_c_config, cache_path)?;
+
+    let t_aux_path = cache_path.join(CacheKey::TAux.to_string());
+    fs::write(&t_aux_path, serialize(&t_aux)?)
+        .with_context(|| format!("could not write to file t_aux={:?}", t_aux_path))
+}
+
+/// Reads `t_aux` from `cache_path` and resolves its store paths against `cache_path`. Files
+/// written with absolute paths are rebased as well.
+pub(crate) fn load_t_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+) -> Result<TemporaryAux<Tree, DefaultPieceHasher>> {
+    let t_aux_path = cache_path.join(CacheKey::TAux.to_string());
+    let t_aux_bytes = fs::read(&t_aux_path)
+        .with_context(|| format!("could not read file t_aux={:?}", t_aux_path))?;
+
+    let mut t_aux: TemporaryAux<Tree, DefaultPieceHasher> = deserialize(&t_aux_bytes)
+        .with_context(|| format!("could not decode file t_aux={:?}", t_aux_path))?;
+    t_aux.set_cache_path(cache_path);
+
+    Ok(t_aux)
+}
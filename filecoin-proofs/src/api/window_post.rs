@@ -8,6 +8,7 @@ use bellperson::groth16;
 use rayon::prelude::*;
 use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
+    control::SealControl,
     merkle::MerkleTreeTrait,
     parameter_cache::SRS_MAX_PROOFS_TO_AGGREGATE,
     multi_proof::MultiProof,
@@ -106,6 +107,25 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     randomness: &ChallengeSeed,
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
     prover_id: ProverId,
+) -> Result<SnarkProof> {
+    generate_window_post_with_control(
+        post_config,
+        randomness,
+        replicas,
+        prover_id,
+        &SealControl::default(),
+    )
+}
+
+/// Generates a Window proof-of-spacetime, reporting the proving progress to `control` and
+/// stopping with `Error::Cancelled` before the vanilla or the circuit proofs are generated if
+/// `control` was cancelled.
+pub fn generate_window_post_with_control<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    control: &SealControl,
 ) -> Result<SnarkProof> {
     info!("generate_window_post:start");
     ensure!(
@@ -173,7 +193,13 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
         sectors: &priv_sectors,
     };
 
-    let proof = FallbackPoStCompound::prove(&pub_params, &pub_inputs, &priv_inputs, &groth_params)?;
+    let proof = FallbackPoStCompound::prove_with_control(
+        &pub_params,
+        &pub_inputs,
+        &priv_inputs,
+        &groth_params,
+        control,
+    )?;
 
     info!("generate_window_post:finish");
 
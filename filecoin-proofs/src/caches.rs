@@ -11,10 +11,16 @@ use rand::rngs::OsRng;
 use storage_proofs_core::{compound_proof::CompoundProof, merkle::MerkleTreeTrait};
 use storage_proofs_porep::stacked::{StackedCompound, StackedDrg};
 use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
+use storage_proofs_update::{
+    EmptySectorUpdate, EmptySectorUpdateCircuit, EmptySectorUpdateCompound,
+};
 
 use crate::{
     constants::{DefaultPieceHasher, PUBLISHED_SECTOR_SIZES},
-    parameters::{public_params, window_post_public_params, winning_post_public_params},
+    parameters::{
+        empty_sector_update_public_params, public_params, window_post_public_params,
+        winning_post_public_params,
+    },
     types::{PaddedBytesAmount, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType},
 };
 
@@ -61,9 +67,13 @@ impl<G> SRSCache<G> {
     pub fn with_defaults(identifier: &str) -> Self {
         let mut data = HashMap::new();
         let mut num_proofs_to_aggregate = PROOFS_TESTS_MIN_SNARKS;
+        let sector_sizes = PUBLISHED_SECTOR_SIZES
+            .read()
+            .expect("PUBLISHED_SECTOR_SIZES poisoned")
+            .clone();
 
         loop {
-            for sector_size in &PUBLISHED_SECTOR_SIZES {
+            for sector_size in &sector_sizes {
                 let stacked_key = format!(
                     "STACKED[{}-{}]-{}",
                     sector_size, num_proofs_to_aggregate, identifier,
@@ -268,6 +278,31 @@ pub fn get_post_params<Tree: 'static + MerkleTreeTrait>(
     }
 }
 
+pub fn get_empty_sector_update_params<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<Arc<Bls12GrothParams>> {
+    let public_params = empty_sector_update_public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+    )?;
+
+    let parameters_generator = || {
+        <EmptySectorUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            EmptySectorUpdate<'_, Tree, DefaultPieceHasher>,
+            EmptySectorUpdateCircuit<Tree, DefaultPieceHasher>,
+        >>::groth_params::<OsRng>(None, &public_params)
+        .map_err(Into::into)
+    };
+
+    lookup_groth_params(
+        format!(
+            "EMPTY_SECTOR_UPDATE[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        parameters_generator,
+    )
+}
+
 pub fn get_stacked_verifying_key<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
 ) -> Result<Arc<Bls12PreparedVerifyingKey>> {
@@ -340,6 +375,31 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
     }
 }
 
+pub fn get_empty_sector_update_verifying_key<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<Arc<Bls12PreparedVerifyingKey>> {
+    let public_params = empty_sector_update_public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+    )?;
+
+    let vk_generator = || {
+        let vk = <EmptySectorUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            EmptySectorUpdate<'_, Tree, DefaultPieceHasher>,
+            EmptySectorUpdateCircuit<Tree, DefaultPieceHasher>,
+        >>::verifying_key::<OsRng>(None, &public_params)?;
+        Ok(prepare_verifying_key(&vk))
+    };
+
+    lookup_verifying_key(
+        format!(
+            "EMPTY_SECTOR_UPDATE[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        vk_generator,
+    )
+}
+
 pub fn get_stacked_srs_key<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
     num_proofs_to_aggregate: usize,
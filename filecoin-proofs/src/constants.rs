@@ -4,8 +4,10 @@ use std::sync::RwLock;
 pub use storage_proofs_core::drgraph::BASE_DEGREE as DRG_DEGREE;
 pub use storage_proofs_porep::stacked::EXP_DEGREE;
 
+use anyhow::{Context, Result};
 use filecoin_hashers::{poseidon::PoseidonHasher, sha256::Sha256Hasher, Hasher};
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use storage_proofs_core::{
     merkle::{BinaryMerkleTree, LCTree, OctLCMerkleTree, OctMerkleTree},
     util::NODE_SIZE,
@@ -37,25 +39,25 @@ pub const WINDOW_POST_CHALLENGE_COUNT: usize = 10;
 
 pub const MAX_LEGACY_REGISTERED_SEAL_PROOF_ID: u64 = MAX_LEGACY_POREP_REGISTERED_PROOF_ID;
 
-/// Sector sizes for which parameters have been published.
-pub const PUBLISHED_SECTOR_SIZES: [u64; 14] = [
-    SECTOR_SIZE_2_KIB,
-    SECTOR_SIZE_4_KIB,
-    SECTOR_SIZE_16_KIB,
-    SECTOR_SIZE_32_KIB,
-    SECTOR_SIZE_8_MIB,
-    SECTOR_SIZE_16_MIB,
-    SECTOR_SIZE_32_MIB,
-    SECTOR_SIZE_64_MIB,
-    SECTOR_SIZE_128_MIB,
-    SECTOR_SIZE_256_MIB,
-    SECTOR_SIZE_512_MIB,
-    SECTOR_SIZE_1_GIB,
-    SECTOR_SIZE_32_GIB,
-    SECTOR_SIZE_64_GIB,
-];
-
 lazy_static! {
+    /// Sector sizes for which parameters have been published, followed by the sizes
+    /// registered at runtime with `SectorProfile::register`.
+    pub static ref PUBLISHED_SECTOR_SIZES: RwLock<Vec<u64>> = RwLock::new(vec![
+        SECTOR_SIZE_2_KIB,
+        SECTOR_SIZE_4_KIB,
+        SECTOR_SIZE_16_KIB,
+        SECTOR_SIZE_32_KIB,
+        SECTOR_SIZE_8_MIB,
+        SECTOR_SIZE_16_MIB,
+        SECTOR_SIZE_32_MIB,
+        SECTOR_SIZE_64_MIB,
+        SECTOR_SIZE_128_MIB,
+        SECTOR_SIZE_256_MIB,
+        SECTOR_SIZE_512_MIB,
+        SECTOR_SIZE_1_GIB,
+        SECTOR_SIZE_32_GIB,
+        SECTOR_SIZE_64_GIB,
+    ]);
     pub static ref POREP_MINIMUM_CHALLENGES: RwLock<HashMap<u64, u64>> = RwLock::new(
         [
             (SECTOR_SIZE_2_KIB, 2),
@@ -77,6 +79,28 @@ lazy_static! {
         .copied()
         .collect()
     );
+    /// Minimum number of challenged nodes of an empty sector update proof, over all partitions.
+    pub static ref EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES: RwLock<HashMap<u64, u64>> = RwLock::new(
+        [
+            (SECTOR_SIZE_2_KIB, 2),
+            (SECTOR_SIZE_4_KIB, 2),
+            (SECTOR_SIZE_16_KIB, 2),
+            (SECTOR_SIZE_32_KIB, 2),
+            (SECTOR_SIZE_8_MIB, 2),
+            (SECTOR_SIZE_16_MIB, 2),
+            (SECTOR_SIZE_32_MIB, 2),
+            (SECTOR_SIZE_64_MIB, 2),
+            (SECTOR_SIZE_128_MIB, 2),
+            (SECTOR_SIZE_256_MIB, 2),
+            (SECTOR_SIZE_512_MIB, 2),
+            (SECTOR_SIZE_1_GIB, 2),
+            (SECTOR_SIZE_32_GIB, 1376),
+            (SECTOR_SIZE_64_GIB, 1376),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    );
     pub static ref POREP_PARTITIONS: RwLock<HashMap<u64, u8>> = RwLock::new(
         [
             (SECTOR_SIZE_2_KIB, 1),
@@ -143,6 +167,27 @@ lazy_static! {
         .copied()
         .collect()
     );
+    pub static ref SECTOR_SHAPES: RwLock<HashMap<u64, SectorShape>> = RwLock::new(
+        [
+            (SECTOR_SIZE_2_KIB, SectorShape::Base),
+            (SECTOR_SIZE_4_KIB, SectorShape::Sub2),
+            (SECTOR_SIZE_16_KIB, SectorShape::Sub8),
+            (SECTOR_SIZE_32_KIB, SectorShape::Top2),
+            (SECTOR_SIZE_8_MIB, SectorShape::Base),
+            (SECTOR_SIZE_16_MIB, SectorShape::Sub2),
+            (SECTOR_SIZE_32_MIB, SectorShape::Sub4),
+            (SECTOR_SIZE_64_MIB, SectorShape::Sub8),
+            (SECTOR_SIZE_128_MIB, SectorShape::Top2),
+            (SECTOR_SIZE_256_MIB, SectorShape::Sub4),
+            (SECTOR_SIZE_512_MIB, SectorShape::Base),
+            (SECTOR_SIZE_1_GIB, SectorShape::Sub2),
+            (SECTOR_SIZE_32_GIB, SectorShape::Sub8),
+            (SECTOR_SIZE_64_GIB, SectorShape::Top2),
+        ]
+        .iter()
+        .copied()
+        .collect()
+    );
 }
 
 /// The size of a single snark proof.
@@ -196,90 +241,106 @@ pub type SectorShape32KiB = SectorShapeTop2;
 pub type SectorShape128MiB = SectorShapeTop2;
 pub type SectorShape64GiB = SectorShapeTop2;
 
+/// The tree shapes a sector can have, named after the `SectorShape*` types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SectorShape {
+    Base,
+    Sub2,
+    Sub4,
+    Sub8,
+    Top2,
+}
+
+impl SectorShape {
+    /// Arities of the sub and top tree layers, 0 if there is no such layer.
+    pub fn sub_top_arities(self) -> (usize, usize) {
+        match self {
+            SectorShape::Base => (0, 0),
+            SectorShape::Sub2 => (2, 0),
+            SectorShape::Sub4 => (4, 0),
+            SectorShape::Sub8 => (8, 0),
+            SectorShape::Top2 => (8, 2),
+        }
+    }
+}
+
+/// Returns the shape of the trees of sectors of `sector_size`, or an error if no shape is
+/// registered for it.
+pub fn sector_shape(sector_size: u64) -> Result<SectorShape> {
+    SECTOR_SHAPES
+        .read()
+        .expect("SECTOR_SHAPES poisoned")
+        .get(&sector_size)
+        .copied()
+        .with_context(|| format!("unsupported sector size: {}", sector_size))
+}
+
 pub fn is_sector_shape_base(sector_size: u64) -> bool {
-    matches!(
-        sector_size,
-        SECTOR_SIZE_2_KIB | SECTOR_SIZE_8_MIB | SECTOR_SIZE_512_MIB
-    )
+    matches!(sector_shape(sector_size), Ok(SectorShape::Base))
 }
 
 pub fn is_sector_shape_sub2(sector_size: u64) -> bool {
-    matches!(
-        sector_size,
-        SECTOR_SIZE_4_KIB | SECTOR_SIZE_16_MIB | SECTOR_SIZE_1_GIB
-    )
+    matches!(sector_shape(sector_size), Ok(SectorShape::Sub2))
 }
 
 pub fn is_sector_shape_sub4(sector_size: u64) -> bool {
-    matches!(
-        sector_size,
-        SECTOR_SIZE_32_MIB | SECTOR_SIZE_256_MIB
-    )
+    matches!(sector_shape(sector_size), Ok(SectorShape::Sub4))
 }
 
 pub fn is_sector_shape_sub8(sector_size: u64) -> bool {
-    matches!(
-        sector_size, 
-        SECTOR_SIZE_16_KIB | SECTOR_SIZE_64_MIB | SECTOR_SIZE_32_GIB
-    )
+    matches!(sector_shape(sector_size), Ok(SectorShape::Sub8))
 }
 
 pub fn is_sector_shape_top2(sector_size: u64) -> bool {
-    matches!(sector_size, SECTOR_SIZE_32_KIB | SECTOR_SIZE_64_GIB | SECTOR_SIZE_128_MIB)
+    matches!(sector_shape(sector_size), Ok(SectorShape::Top2))
+}
+
+/// Calls a function with the type hint of the sector shape registered for the provided sector
+/// size, and evaluates to its result wrapped in `Ok`. Evaluates to an error if no shape is
+/// registered for the sector size.
+#[macro_export]
+macro_rules! dispatch_shape {
+    ($size:expr, $f:ident) => {
+        $crate::dispatch_shape!($size, $f,)
+    };
+    ($size:expr, $f:ident, $($args:expr,)*) => {
+        match $crate::constants::sector_shape($size) {
+            Ok($crate::constants::SectorShape::Base) => {
+                Ok($f::<$crate::constants::SectorShapeBase>($($args),*))
+            }
+            Ok($crate::constants::SectorShape::Sub2) => {
+                Ok($f::<$crate::constants::SectorShapeSub2>($($args),*))
+            }
+            Ok($crate::constants::SectorShape::Sub4) => {
+                Ok($f::<$crate::constants::SectorShapeSub4>($($args),*))
+            }
+            Ok($crate::constants::SectorShape::Sub8) => {
+                Ok($f::<$crate::constants::SectorShapeSub8>($($args),*))
+            }
+            Ok($crate::constants::SectorShape::Top2) => {
+                Ok($f::<$crate::constants::SectorShapeTop2>($($args),*))
+            }
+            Err(err) => Err(err),
+        }
+    };
+    ($size:expr, $f:ident, $($args:expr),*) => {
+        $crate::dispatch_shape!($size, $f, $($args,)*)
+    };
 }
 
 /// Calls a function with the type hint of the sector shape matching the provided sector.
-/// Panics if provided with an unknown sector size.
+/// Panics if provided with an unknown sector size, see `dispatch_shape!` for a fallible
+/// version.
 #[macro_export]
 macro_rules! with_shape {
     ($size:expr, $f:ident) => {
         with_shape!($size, $f,)
     };
     ($size:expr, $f:ident, $($args:expr,)*) => {
-        match $size {
-            _x if $size == $crate::constants::SECTOR_SIZE_2_KIB => {
-              $f::<$crate::constants::SectorShape2KiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_4_KIB => {
-              $f::<$crate::constants::SectorShape4KiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_16_KIB => {
-              $f::<$crate::constants::SectorShape16KiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_32_KIB => {
-              $f::<$crate::constants::SectorShape32KiB>($($args),*)
-            },
-            _xx if $size == $crate::constants::SECTOR_SIZE_8_MIB => {
-              $f::<$crate::constants::SectorShape8MiB>($($args),*)
-            },
-            _xx if $size == $crate::constants::SECTOR_SIZE_16_MIB => {
-              $f::<$crate::constants::SectorShape16MiB>($($args),*)
-            },
-            _xx if $size == $crate::constants::SECTOR_SIZE_32_MIB => {
-              $f::<$crate::constants::SectorShape32MiB>($($args),*)
-            },
-            _xx if $size == $crate::constants::SECTOR_SIZE_64_MIB => {
-              $f::<$crate::constants::SectorShape64MiB>($($args),*)
-            },
-            _xx if $size == $crate::constants::SECTOR_SIZE_128_MIB => {
-              $f::<$crate::constants::SectorShape128MiB>($($args),*)
-            },
-            _xx if $size == $crate::constants::SECTOR_SIZE_256_MIB => {
-              $f::<$crate::constants::SectorShape256MiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_512_MIB => {
-              $f::<$crate::constants::SectorShape512MiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_1_GIB => {
-              $f::<$crate::constants::SectorShape1GiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_32_GIB => {
-              $f::<$crate::constants::SectorShape32GiB>($($args),*)
-            },
-            _x if $size == $crate::constants::SECTOR_SIZE_64_GIB => {
-              $f::<$crate::constants::SectorShape64GiB>($($args),*)
-            },
-            _ => panic!("unsupported sector size: {}", $size),
+        match $crate::dispatch_shape!($size, $f, $($args,)*) {
+            Ok(res) => res,
+            Err(err) => panic!("{}", err),
         }
     };
     ($size:expr, $f:ident, $($args:expr),*) => {
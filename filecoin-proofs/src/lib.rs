@@ -8,12 +8,15 @@ pub mod constants;
 pub mod param;
 pub mod parameters;
 pub mod pieces;
+pub mod registry;
 pub mod types;
 
 mod api;
 mod commitment_reader;
+mod parallel_commitment;
 
 pub use api::*;
 pub use commitment_reader::*;
 pub use constants::*;
+pub use parallel_commitment::*;
 pub use types::*;
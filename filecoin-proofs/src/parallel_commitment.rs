@@ -0,0 +1,263 @@
+use std::cmp::min;
+use std::io::{self, Read};
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::{Domain, Hasher};
+use rayon::prelude::{IndexedParallelIterator, ParallelIterator, ParallelSlice};
+use storage_proofs_core::util::NODE_SIZE;
+
+use crate::{
+    api::SparseFileReader,
+    constants::DefaultPieceHasher,
+    pieces::{piece_hash, zero_commitment},
+    types::{Commitment, PaddedBytesAmount},
+};
+
+type PieceDomain = <DefaultPieceHasher as Hasher>::Domain;
+
+/// Bit padded bytes of the subtrees hashed by a single task.
+const SUBTREE_BYTES: usize = 1 << 20;
+
+/// Calculates comm_p of the `padded_piece_size` bit padded bytes read from `source`.
+///
+/// Gives the same commitment as `CommitmentReader`, but hashes aligned subtrees on the rayon
+/// pool and only keeps their roots, so that memory use is bounded by one subtree per thread.
+pub fn generate_piece_commitment_bytes_parallel<R: Read>(
+    source: R,
+    padded_piece_size: PaddedBytesAmount,
+) -> Result<Commitment> {
+    piece_commitment_with_subtrees(&mut DenseSource(source), padded_piece_size, SUBTREE_BYTES)
+}
+
+/// Calculates comm_p like `generate_piece_commitment_bytes_parallel`, without reading or
+/// hashing the subtrees which `source` knows to be zero.
+pub(crate) fn generate_subtree_commitment_parallel<S: SubtreeSource + ?Sized>(
+    source: &mut S,
+    padded_piece_size: PaddedBytesAmount,
+) -> Result<Commitment> {
+    piece_commitment_with_subtrees(source, padded_piece_size, SUBTREE_BYTES)
+}
+
+/// Source of the bit padded bytes of a piece, read one aligned subtree at a time.
+pub(crate) trait SubtreeSource {
+    /// Fills `buf` with the next subtree and returns true, or skips it and returns false if it
+    /// is known to be zero.
+    fn read_subtree(&mut self, buf: &mut [u8]) -> io::Result<bool>;
+}
+
+/// A source of which no subtree is known to be zero.
+pub(crate) struct DenseSource<R>(pub(crate) R);
+
+impl<R: Read> SubtreeSource for DenseSource<R> {
+    fn read_subtree(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        self.0.read_exact(buf)?;
+        Ok(true)
+    }
+}
+
+/// Subtrees in holes of the file are zero.
+impl SubtreeSource for SparseFileReader {
+    fn read_subtree(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        if self.is_hole(buf.len() as u64) {
+            self.skip(buf.len() as u64);
+            return Ok(false);
+        }
+        self.read_exact(buf)?;
+        Ok(true)
+    }
+}
+
+fn piece_commitment_with_subtrees<S: SubtreeSource + ?Sized>(
+    source: &mut S,
+    padded_piece_size: PaddedBytesAmount,
+    subtree_bytes: usize,
+) -> Result<Commitment> {
+    let padded_piece_size = u64::from(padded_piece_size);
+    ensure!(
+        padded_piece_size >= 2 * NODE_SIZE as u64 && padded_piece_size.is_power_of_two(),
+        "invalid padded piece size {}",
+        padded_piece_size
+    );
+
+    let subtree_bytes = min(subtree_bytes as u64, padded_piece_size) as usize;
+    let zero_root =
+        PieceDomain::try_from_bytes(&zero_commitment(PaddedBytesAmount(subtree_bytes as u64))?)?;
+    let mut batch = vec![0u8; subtree_bytes * rayon::current_num_threads()];
+    let mut stack = SubtreeStack::default();
+
+    let mut remaining = padded_piece_size;
+    while remaining > 0 {
+        // The piece size is a multiple of the subtree size, so only whole subtrees are read.
+        let len = min(remaining, batch.len() as u64) as usize;
+        let is_data = batch[..len]
+            .chunks_mut(subtree_bytes)
+            .map(|subtree| source.read_subtree(subtree))
+            .collect::<io::Result<Vec<bool>>>()
+            .context("source ended before the end of the piece")?;
+
+        let roots: Vec<PieceDomain> = batch[..len]
+            .par_chunks(subtree_bytes)
+            .zip(is_data)
+            .map(|(subtree, is_data)| {
+                if is_data {
+                    subtree_root(subtree)
+                } else {
+                    zero_root
+                }
+            })
+            .collect();
+        for root in roots {
+            stack.push(0, root);
+        }
+
+        remaining -= len as u64;
+    }
+
+    stack.finish()
+}
+
+/// Hashes `data`, a power of two number of nodes, into the root of its binary tree.
+pub(crate) fn subtree_root(data: &[u8]) -> PieceDomain {
+    let mut row: Vec<PieceDomain> = data
+        .chunks(2 * NODE_SIZE)
+        .map(|pair| piece_hash(&pair[..NODE_SIZE], &pair[NODE_SIZE..]))
+        .collect();
+
+    while row.len() > 1 {
+        let half = row.len() / 2;
+        for i in 0..half {
+            row[i] = piece_hash(row[2 * i].as_ref(), row[2 * i + 1].as_ref());
+        }
+        row.truncate(half);
+    }
+
+    row[0]
+}
+
+/// Roots of complete subtrees in piece order, of which neighbours of the same height are
+/// combined as soon as both are known.
+///
+/// Every subtree has to be aligned to its size, which always holds for subtrees of the same
+/// height.
+#[derive(Default)]
+pub(crate) struct SubtreeStack {
+    roots: Vec<(usize, PieceDomain)>,
+}
+
+impl SubtreeStack {
+    pub(crate) fn push(&mut self, mut height: usize, mut root: PieceDomain) {
+        while let Some((last_height, last_root)) = self.roots.last() {
+            if *last_height != height {
+                break;
+            }
+            root = piece_hash(last_root.as_ref(), root.as_ref());
+            height += 1;
+            self.roots.pop();
+        }
+        self.roots.push((height, root));
+    }
+
+    pub(crate) fn finish(self) -> Result<Commitment> {
+        ensure!(self.roots.len() == 1, "piece is not a complete tree");
+
+        let mut commitment = [0u8; 32];
+        commitment.copy_from_slice(self.roots[0].1.as_ref());
+        Ok(commitment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{self, Cursor, Seek, SeekFrom, Write};
+
+    use fr32::Fr32Reader;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::{commitment_reader::CommitmentReader, constants::TEST_SEED};
+
+    fn sequential_commitment(data: &[u8]) -> Commitment {
+        let mut commitment_reader = CommitmentReader::new(Cursor::new(data));
+        io::copy(&mut commitment_reader, &mut io::sink()).expect("io copy failed");
+
+        let mut commitment = [0u8; 32];
+        commitment.copy_from_slice(
+            commitment_reader
+                .finish()
+                .expect("failed to finish")
+                .as_ref(),
+        );
+        commitment
+    }
+
+    #[test]
+    fn test_parallel_commitment_matches_commitment_reader() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+        for unpadded_size in &[127, 127 * 8, 127 * 1024, 127 * 32 * 1024] {
+            let source: Vec<u8> = (0..*unpadded_size).map(|_| rng.gen()).collect();
+            let mut padded = Vec::new();
+            Fr32Reader::new(Cursor::new(&source))
+                .read_to_end(&mut padded)
+                .expect("failed to pad");
+            let padded_size = PaddedBytesAmount(padded.len() as u64);
+
+            let expected = sequential_commitment(&padded);
+            // Small subtrees exercise combining many roots, large ones a single subtree.
+            for subtree_bytes in &[128, 1024, SUBTREE_BYTES] {
+                let commitment = piece_commitment_with_subtrees(
+                    &mut DenseSource(Cursor::new(&padded)),
+                    padded_size,
+                    *subtree_bytes,
+                )
+                .expect("failed to generate commitment");
+                assert_eq!(commitment, expected, "subtree of {} bytes", subtree_bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_commitment_matches_dense() {
+        let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+        // Data, a hole and data again, with the hole spanning whole subtrees.
+        let padded_size = 4 * SUBTREE_BYTES;
+        let mut padded = vec![0u8; padded_size];
+        rng.fill(&mut padded[..SUBTREE_BYTES / 2]);
+        rng.fill(&mut padded[3 * SUBTREE_BYTES..]);
+
+        let mut file = tempfile::tempfile().expect("failed to create file");
+        file.write_all(&padded[..SUBTREE_BYTES / 2])
+            .expect("failed to write data");
+        file.seek(SeekFrom::Start(3 * SUBTREE_BYTES as u64))
+            .expect("failed to seek");
+        file.write_all(&padded[3 * SUBTREE_BYTES..])
+            .expect("failed to write data");
+        file.seek(SeekFrom::Start(0)).expect("failed to seek");
+
+        let mut source = SparseFileReader::new(file).expect("failed to open sparse file");
+        let commitment = generate_subtree_commitment_parallel(
+            &mut source,
+            PaddedBytesAmount(padded_size as u64),
+        )
+        .expect("failed to generate commitment");
+        assert_eq!(commitment, sequential_commitment(&padded));
+    }
+
+    #[test]
+    fn test_parallel_commitment_invalid_source() {
+        let padded = vec![0u8; 1024];
+        assert!(generate_piece_commitment_bytes_parallel(
+            Cursor::new(&padded[..512]),
+            PaddedBytesAmount(1024)
+        )
+        .is_err());
+        assert!(generate_piece_commitment_bytes_parallel(
+            Cursor::new(&padded),
+            PaddedBytesAmount(1000)
+        )
+        .is_err());
+    }
+}
@@ -1,10 +1,14 @@
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
 use storage_proofs_core::{api_version::ApiVersion, proof::ProofScheme};
 use storage_proofs_porep::stacked::{self, LayerChallenges, StackedDrg};
 use storage_proofs_post::fallback::{self, FallbackPoSt};
+use storage_proofs_update::{self as update, EmptySectorUpdate};
 
 use crate::{
-    constants::{DefaultPieceHasher, DRG_DEGREE, EXP_DEGREE, LAYERS, POREP_MINIMUM_CHALLENGES},
+    constants::{
+        DefaultPieceHasher, DRG_DEGREE, EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES, EXP_DEGREE, LAYERS,
+        POREP_MINIMUM_CHALLENGES,
+    },
     types::{MerkleTreeTrait, PaddedBytesAmount, PoStConfig},
 };
 
@@ -14,6 +18,9 @@ pub type WinningPostPublicParams = fallback::PublicParams;
 type WindowPostSetupParams = fallback::SetupParams;
 pub type WindowPostPublicParams = fallback::PublicParams;
 
+type EmptySectorUpdateSetupParams = update::SetupParams;
+pub type EmptySectorUpdatePublicParams = update::PublicParams;
+
 pub fn public_params<Tree: 'static + MerkleTreeTrait>(
     sector_bytes: PaddedBytesAmount,
     partitions: usize,
@@ -74,6 +81,35 @@ pub fn window_post_setup_params(post_config: &PoStConfig) -> WindowPostSetupPara
     }
 }
 
+pub fn empty_sector_update_public_params<Tree: 'static + MerkleTreeTrait>(
+    sector_bytes: PaddedBytesAmount,
+    partitions: usize,
+) -> Result<EmptySectorUpdatePublicParams> {
+    EmptySectorUpdate::<Tree, DefaultPieceHasher>::setup(&empty_sector_update_setup_params(
+        sector_bytes,
+        partitions,
+    )?)
+}
+
+pub fn empty_sector_update_setup_params(
+    sector_bytes: PaddedBytesAmount,
+    partitions: usize,
+) -> Result<EmptySectorUpdateSetupParams> {
+    ensure!(partitions > 0, "partitions must not be zero");
+
+    let minimum_challenges = *EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES
+        .read()
+        .expect("EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES poisoned")
+        .get(&u64::from(sector_bytes))
+        .with_context(|| format!("unknown sector size: {}", u64::from(sector_bytes)))?
+        as usize;
+
+    Ok(update::SetupParams {
+        sector_size: sector_bytes.into(),
+        challenge_count: (minimum_challenges + partitions - 1) / partitions,
+    })
+}
+
 pub fn setup_params(
     sector_bytes: PaddedBytesAmount,
     partitions: usize,
@@ -86,12 +122,13 @@ pub fn setup_params(
             .read()
             .expect("POREP_MINIMUM_CHALLENGES poisoned")
             .get(&u64::from(sector_bytes))
-            .expect("unknown sector size") as usize,
+            .with_context(|| format!("unknown sector size: {}", u64::from(sector_bytes)))?
+            as usize,
         *LAYERS
             .read()
             .expect("LAYERS poisoned")
             .get(&u64::from(sector_bytes))
-            .expect("unknown sector size"),
+            .with_context(|| format!("unknown sector size: {}", u64::from(sector_bytes)))?,
     );
     let sector_bytes = u64::from(sector_bytes);
 
@@ -164,4 +201,16 @@ mod tests {
         assert_eq!(params.challenge_count, 1);
         assert_eq!(params.sector_size, 2048);
     }
+
+    #[test]
+    fn test_empty_sector_update_params() {
+        let params =
+            empty_sector_update_setup_params(PaddedBytesAmount(1 << 35), 10).expect("setup failed");
+        assert_eq!(params.challenge_count, 138);
+        assert_eq!(params.sector_size, 1 << 35);
+
+        let params =
+            empty_sector_update_setup_params(PaddedBytesAmount(2048), 1).expect("setup failed");
+        assert_eq!(params.challenge_count, 2);
+    }
 }
@@ -1,25 +1,24 @@
-use std::cmp::min;
-use std::collections::HashMap;
-use std::io::{self, Cursor, Read};
+use std::cmp::{max, min, Reverse};
+use std::io::{self, Cursor, Read, Write};
 use std::iter::Iterator;
-use std::sync::Mutex;
 
 use anyhow::{ensure, Context, Result};
 use filecoin_hashers::{HashFunction, Hasher};
-use fr32::Fr32Reader;
+use fr32::{to_unpadded_bytes, write_unpadded, Fr32Reader};
 use lazy_static::lazy_static;
 use log::trace;
 use storage_proofs_core::util::NODE_SIZE;
 
 use crate::{
-    commitment_reader::CommitmentReader,
     constants::{
         DefaultPieceHasher,
         MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
     },
+    parallel_commitment::generate_piece_commitment_bytes_parallel,
     types::{
-        Commitment, PaddedBytesAmount, PieceInfo, SectorSize, UnpaddedByteIndex,
-        UnpaddedBytesAmount,
+        AggregatePiece, Commitment, PaddedBytesAmount, PieceInclusionProof, PieceInfo, SectorSize,
+        SegmentDescriptor, SubPieceInclusionProof, UnpaddedByteIndex, UnpaddedBytesAmount,
+        SEGMENT_DESCRIPTOR_SIZE,
     },
 };
 
@@ -35,7 +34,16 @@ pub fn verify_pieces(
 }
 
 lazy_static! {
-    static ref COMMITMENTS: Mutex<HashMap<SectorSize, Commitment>> = Mutex::new(HashMap::new());
+    /// Roots of all zero subtrees, indexed by their height above the leafs.
+    static ref ZERO_COMMITMENTS: Vec<Commitment> = {
+        let mut roots = vec![[0u8; 32]];
+        for height in 0..63 {
+            let mut root = [0u8; 32];
+            root.copy_from_slice(piece_hash(&roots[height], &roots[height]).as_ref());
+            roots.push(root);
+        }
+        roots
+    };
 }
 
 #[derive(Debug, Clone)]
@@ -62,24 +70,20 @@ impl Read for EmptySource {
 }
 
 fn empty_comm_d(sector_size: SectorSize) -> Commitment {
-    let map = &mut *COMMITMENTS.lock().expect("COMMITMENTS poisoned");
-
-    *map.entry(sector_size).or_insert_with(|| {
-        let size: UnpaddedBytesAmount = sector_size.into();
-        let fr32_reader = Fr32Reader::new(EmptySource::new(size.into()));
-        let mut commitment_reader = CommitmentReader::new(fr32_reader);
-        io::copy(&mut commitment_reader, &mut io::sink())
-            .expect("failed to copy commitment to sink");
-
-        let mut comm = [0u8; 32];
-        comm.copy_from_slice(
-            commitment_reader
-                .finish()
-                .expect("failed to create commitment")
-                .as_ref(),
-        );
-        comm
-    })
+    zero_commitment(sector_size.into()).expect("sector size is not a power of two")
+}
+
+/// Returns the commitment of `size` bit padded zero bytes, as read from a hole in a sparse
+/// unsealed file, without hashing them.
+pub fn zero_commitment(size: PaddedBytesAmount) -> Result<Commitment> {
+    let size = u64::from(size);
+    ensure!(
+        size >= NODE_SIZE as u64 && size.is_power_of_two(),
+        "invalid zero subtree size {}",
+        size
+    );
+
+    Ok(ZERO_COMMITMENTS[(size / NODE_SIZE as u64).trailing_zeros() as usize])
 }
 
 pub fn compute_comm_d(sector_size: SectorSize, piece_infos: &[PieceInfo]) -> Result<Commitment> {
@@ -206,26 +210,7 @@ impl Stack {
 
 /// Create a padding `PieceInfo` of size `size`.
 pub fn zero_padding(size: UnpaddedBytesAmount) -> Result<PieceInfo> {
-    let padded_size: PaddedBytesAmount = size.into();
-    let mut commitment = [0u8; 32];
-
-    // TODO: cache common piece hashes
-    let mut hashed_size = 64;
-    let h1 = piece_hash(&commitment, &commitment);
-    commitment.copy_from_slice(h1.as_ref());
-
-    while hashed_size < u64::from(padded_size) {
-        let h = piece_hash(&commitment, &commitment);
-        commitment.copy_from_slice(h.as_ref());
-        hashed_size *= 2;
-    }
-
-    ensure!(
-        hashed_size == u64::from(padded_size),
-        "Hashed size must equal padded size"
-    );
-
-    PieceInfo::new(commitment, size)
+    PieceInfo::new(zero_commitment(size.into())?, size)
 }
 
 /// Join two equally sized `PieceInfo`s together, by hashing them and adding their sizes.
@@ -352,3 +337,518 @@ pub fn get_aligned_source<T: Read>(
         with_alignment(source, piece_alignment),
     )
 }
+
+/// Piece that may be added to a sector by `plan_sector_layout`.
+#[derive(Debug, Clone)]
+pub struct PieceCandidate {
+    pub piece_info: PieceInfo,
+    /// Epoch by which the piece has to be sealed, if any. Pieces with earlier deadlines are
+    /// placed first.
+    pub deadline: Option<u64>,
+    /// Among pieces with the same deadline, pieces with a higher priority are placed first.
+    pub priority: u32,
+}
+
+/// Piece of a `SectorLayout`, as it will be written by `add_piece`.
+#[derive(Debug, Clone)]
+pub struct PlannedPiece {
+    /// Index of the piece in the candidates.
+    pub candidate: usize,
+    pub piece_info: PieceInfo,
+    pub alignment: PieceAlignment,
+    pub offset: UnpaddedByteIndex,
+}
+
+/// Pieces to add to a sector, in order, and what the sector will commit to.
+#[derive(Debug, Clone)]
+pub struct SectorLayout {
+    pub pieces: Vec<PlannedPiece>,
+    /// Indexes of the candidates that did not fit.
+    pub unplaced: Vec<usize>,
+    /// Unpadded bytes of the sector that are not taken by a piece.
+    pub free_bytes: UnpaddedBytesAmount,
+    /// The piece infos to pass to `compute_comm_d`, and the resulting comm_d.
+    pub piece_infos: Vec<PieceInfo>,
+    pub comm_d: Commitment,
+}
+
+/// Chooses which of `candidate_pieces` to add to a sector of `sector_size`, and in which order.
+///
+/// Candidates are taken by deadline, priority and then size, as long as they fit. The chosen
+/// pieces are added from the largest to the smallest, which never needs alignment padding
+/// between them, so that as many bytes as possible of the sector are filled.
+pub fn plan_sector_layout(
+    sector_size: SectorSize,
+    candidate_pieces: &[PieceCandidate],
+) -> Result<SectorLayout> {
+    for candidate in candidate_pieces {
+        ensure!(
+            u64::from(PaddedBytesAmount::from(candidate.piece_info.size)).is_power_of_two()
+                && u64::from(candidate.piece_info.size) % MINIMUM_PIECE_SIZE == 0,
+            "Piece size ({:?}) must be a power of 2.",
+            PaddedBytesAmount::from(candidate.piece_info.size)
+        );
+    }
+
+    let mut by_rank: Vec<usize> = (0..candidate_pieces.len()).collect();
+    by_rank.sort_by_key(|i| {
+        let candidate = &candidate_pieces[*i];
+        (
+            candidate.deadline.unwrap_or(u64::MAX),
+            Reverse(candidate.priority),
+            Reverse(candidate.piece_info.size),
+        )
+    });
+
+    let mut free = u64::from(UnpaddedBytesAmount::from(sector_size));
+    let mut chosen = Vec::new();
+    let mut unplaced = Vec::new();
+    for i in by_rank {
+        let size = u64::from(candidate_pieces[i].piece_info.size);
+        if size <= free {
+            free -= size;
+            chosen.push(i);
+        } else {
+            unplaced.push(i);
+        }
+    }
+    // The sort is stable, so pieces of the same size keep their rank.
+    chosen.sort_by_key(|i| Reverse(candidate_pieces[*i].piece_info.size));
+    unplaced.sort_unstable();
+
+    let mut written = UnpaddedBytesAmount(0);
+    let pieces: Vec<PlannedPiece> = chosen
+        .into_iter()
+        .map(|candidate| {
+            let piece_info = candidate_pieces[candidate].piece_info.clone();
+            let alignment = get_piece_alignment(written, piece_info.size);
+            let offset = UnpaddedByteIndex::from(written + alignment.left_bytes);
+            written = written + alignment.sum(piece_info.size);
+
+            PlannedPiece {
+                candidate,
+                piece_info,
+                alignment,
+                offset,
+            }
+        })
+        .collect();
+
+    let piece_infos: Vec<PieceInfo> = pieces
+        .iter()
+        .map(|piece| piece.piece_info.clone())
+        .collect();
+    let comm_d = compute_comm_d(sector_size, &piece_infos)?;
+
+    Ok(SectorLayout {
+        pieces,
+        unplaced,
+        free_bytes: UnpaddedBytesAmount(free),
+        piece_infos,
+        comm_d,
+    })
+}
+
+/// Returns the number of entries in the index at the end of an aggregate piece.
+pub fn aggregate_index_entries(aggregate_size: PaddedBytesAmount) -> u64 {
+    let entries = u64::from(aggregate_size) / 2048 / SEGMENT_DESCRIPTOR_SIZE as u64;
+    max(entries.next_power_of_two(), 4)
+}
+
+/// Returns the offset of the index in a padded aggregate piece.
+pub fn aggregate_index_start(aggregate_size: PaddedBytesAmount) -> PaddedBytesAmount {
+    let index_size = aggregate_index_entries(aggregate_size) * SEGMENT_DESCRIPTOR_SIZE as u64;
+    PaddedBytesAmount(u64::from(aggregate_size).saturating_sub(index_size))
+}
+
+/// Places `pieces` in an aggregate piece of `aggregate_size` in the given order, each at the
+/// next offset aligned to its size, and returns their index entries.
+pub fn plan_aggregate(
+    aggregate_size: PaddedBytesAmount,
+    pieces: &[PieceInfo],
+) -> Result<Vec<SegmentDescriptor>> {
+    let index_start = u64::from(aggregate_index_start(aggregate_size));
+    let entries = aggregate_index_entries(aggregate_size);
+    ensure!(
+        u64::from(aggregate_size).is_power_of_two() && index_start > 0,
+        "invalid aggregate size {:?}",
+        aggregate_size
+    );
+    ensure!(
+        pieces.len() as u64 <= entries,
+        "{} pieces do not fit in an index of {} entries",
+        pieces.len(),
+        entries
+    );
+
+    let mut offset = 0;
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let size = u64::from(PaddedBytesAmount::from(piece.size));
+            ensure!(
+                size.is_power_of_two() && size >= NODE_SIZE as u64 * 4,
+                "Piece size ({:?}) must be a power of 2.",
+                PaddedBytesAmount::from(piece.size)
+            );
+
+            let start = offset + (size - offset % size) % size;
+            ensure!(
+                start + size <= index_start,
+                "piece {} does not fit in the aggregate",
+                i
+            );
+            offset = start + size;
+
+            Ok(SegmentDescriptor::new(
+                piece.commitment,
+                PaddedBytesAmount(start),
+                PaddedBytesAmount(size),
+            ))
+        })
+        .collect()
+}
+
+/// Writes an aggregate piece of `aggregate_size`, which packs the pieces read from their sources
+/// as laid out by `plan_aggregate` and ends with their index, to `target`.
+///
+/// The written bytes are not bit padded, so that the aggregate can be added to a sector with
+/// `add_piece` like any other piece. Fails if a source does not match the commitment of its
+/// piece.
+pub fn write_aggregate<R: Read, W: Write>(
+    aggregate_size: PaddedBytesAmount,
+    pieces: Vec<(PieceInfo, R)>,
+    mut target: W,
+) -> Result<AggregatePiece> {
+    let piece_infos: Vec<PieceInfo> = pieces.iter().map(|(info, _)| info.clone()).collect();
+    let segments = plan_aggregate(aggregate_size, &piece_infos)?;
+
+    let mut written = 0;
+    for (i, ((piece_info, source), segment)) in pieces.into_iter().zip(&segments).enumerate() {
+        write_zeroes(&mut target, u64::from(segment.offset) - written)?;
+
+        let tee = TeeReader {
+            source: source.take(u64::from(piece_info.size)),
+            target: &mut target,
+        };
+        let commitment =
+            generate_piece_commitment_bytes_parallel(Fr32Reader::new(tee), segment.size)
+                .with_context(|| format!("could not read piece {}", i))?;
+        ensure!(
+            commitment == piece_info.commitment,
+            "piece {} does not match its commitment",
+            i
+        );
+        written = u64::from(segment.offset) + u64::from(segment.size);
+    }
+
+    let index_start = u64::from(aggregate_index_start(aggregate_size));
+    write_zeroes(&mut target, index_start - written)?;
+
+    let mut index = vec![0u8; u64::from(aggregate_size) as usize - index_start as usize];
+    for (entry, segment) in index.chunks_mut(SEGMENT_DESCRIPTOR_SIZE).zip(&segments) {
+        entry.copy_from_slice(&segment.to_bytes());
+    }
+    let index_len = to_unpadded_bytes(index.len() as u64) as usize;
+    write_unpadded(&index, &mut target, 0, index_len)?;
+
+    let commitment = AggregateTree::new(aggregate_size, &segments)?.root()?;
+    Ok(AggregatePiece {
+        piece_info: PieceInfo::new(commitment, aggregate_size.into())?,
+        segments,
+    })
+}
+
+/// Reads the index of an aggregate piece of `aggregate_size` from the bytes written by
+/// `write_aggregate`, skipping entries whose checksum does not match.
+pub fn read_aggregate_index<R: Read>(
+    mut source: R,
+    aggregate_size: PaddedBytesAmount,
+) -> Result<Vec<SegmentDescriptor>> {
+    let index_start = u64::from(aggregate_index_start(aggregate_size));
+    let skip = to_unpadded_bytes(index_start);
+    let skipped = io::copy(&mut (&mut source).take(skip), &mut io::sink())?;
+    ensure!(skipped == skip, "source ended before the index");
+
+    let mut index = vec![0u8; u64::from(aggregate_size) as usize - index_start as usize];
+    let index_len = to_unpadded_bytes(index.len() as u64);
+    Fr32Reader::new(source.take(index_len))
+        .read_exact(&mut index)
+        .context("source ended before the end of the index")?;
+
+    Ok(index
+        .chunks(SEGMENT_DESCRIPTOR_SIZE)
+        .filter_map(|entry| {
+            let mut bytes = [0u8; SEGMENT_DESCRIPTOR_SIZE];
+            bytes.copy_from_slice(entry);
+            SegmentDescriptor::from_bytes(&bytes)
+        })
+        .collect())
+}
+
+/// Generates a proof that the piece of the entry at `segment_index` is packed into `aggregate`
+/// and listed in its index.
+pub fn generate_sub_piece_inclusion_proof(
+    aggregate: &AggregatePiece,
+    segment_index: usize,
+) -> Result<SubPieceInclusionProof> {
+    ensure!(
+        segment_index < aggregate.segments.len(),
+        "segment index {} is out of range for {} segments",
+        segment_index,
+        aggregate.segments.len()
+    );
+
+    let aggregate_size = PaddedBytesAmount::from(aggregate.piece_info.size);
+    let tree = AggregateTree::new(aggregate_size, &aggregate.segments)?;
+    ensure!(
+        tree.root()? == aggregate.piece_info.commitment,
+        "segments do not match the aggregate commitment"
+    );
+
+    let (level, index) = segment_node(&aggregate.segments[segment_index])?;
+    let entry_index = index_entry_node(aggregate_size, segment_index as u64);
+
+    Ok(SubPieceInclusionProof {
+        index_entry: segment_index as u64,
+        piece_proof: PieceInclusionProof {
+            path: tree.path(level, index)?,
+        },
+        entry_proof: PieceInclusionProof {
+            path: tree.path(1, entry_index)?,
+        },
+    })
+}
+
+/// Verifies that the piece of `segment` is packed into the aggregate piece of `aggregate` and
+/// listed in its index.
+pub fn verify_sub_piece_inclusion_proof(
+    aggregate: &PieceInfo,
+    segment: &SegmentDescriptor,
+    proof: &SubPieceInclusionProof,
+) -> Result<bool> {
+    let aggregate_size = PaddedBytesAmount::from(aggregate.size);
+    let height = (u64::from(aggregate_size) / NODE_SIZE as u64).trailing_zeros() as usize;
+    let (level, index) = segment_node(segment)?;
+    ensure!(
+        proof.index_entry < aggregate_index_entries(aggregate_size),
+        "index entry {} is out of range",
+        proof.index_entry
+    );
+    let entry_index = index_entry_node(aggregate_size, proof.index_entry);
+
+    // The length of the paths fixes the levels of the nodes, which are not bound by the hashes.
+    Ok(segment.is_valid()
+        && u64::from(segment.offset) + u64::from(segment.size)
+            <= u64::from(aggregate_index_start(aggregate_size))
+        && proof.piece_proof.path.len() + level == height
+        && proof.entry_proof.path.len() + 1 == height
+        && verify_inclusion_path(
+            &aggregate.commitment,
+            &segment.comm_p,
+            index,
+            &proof.piece_proof.path,
+        )
+        && verify_inclusion_path(
+            &aggregate.commitment,
+            &entry_root(segment),
+            entry_index,
+            &proof.entry_proof.path,
+        ))
+}
+
+/// Checks that `segments` are aligned to their size and fit in an aggregate piece of
+/// `aggregate_size` without overlapping each other or the index.
+pub(crate) fn check_aggregate_segments(
+    aggregate_size: PaddedBytesAmount,
+    segments: &[SegmentDescriptor],
+) -> Result<()> {
+    let index_start = u64::from(aggregate_index_start(aggregate_size));
+    ensure!(
+        u64::from(aggregate_size).is_power_of_two() && index_start > 0,
+        "invalid aggregate size {:?}",
+        aggregate_size
+    );
+    ensure!(
+        segments.len() as u64 <= aggregate_index_entries(aggregate_size),
+        "{} segments do not fit in the index",
+        segments.len()
+    );
+
+    let mut ranges = segments
+        .iter()
+        .map(|segment| {
+            segment_node(segment)?;
+            let start = u64::from(segment.offset);
+            let end = start
+                .checked_add(u64::from(segment.size))
+                .context("segment ends beyond the aggregate")?;
+            Ok((start, end))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ranges.sort_unstable();
+
+    let mut end = 0;
+    for (start, segment_end) in ranges {
+        ensure!(start >= end, "segments overlap at offset {}", start);
+        end = segment_end;
+    }
+    ensure!(end <= index_start, "segments overlap the index");
+
+    Ok(())
+}
+
+/// Checks that hashing `node`, the node at `index` of its level, with the siblings in `path`
+/// results in `root`.
+pub(crate) fn verify_inclusion_path(
+    root: &Commitment,
+    node: &Commitment,
+    mut index: u64,
+    path: &[Commitment],
+) -> bool {
+    let mut node = *node;
+    for sibling in path {
+        node = if index & 1 == 0 {
+            hash_nodes(&node, sibling)
+        } else {
+            hash_nodes(sibling, &node)
+        };
+        index >>= 1;
+    }
+
+    index == 0 && &node == root
+}
+
+fn hash_nodes(left: &[u8], right: &[u8]) -> Commitment {
+    let mut node = [0u8; 32];
+    node.copy_from_slice(AsRef::<[u8]>::as_ref(&piece_hash(left, right)));
+    node
+}
+
+/// Returns the level and index of the root of the piece of `segment` in the aggregate tree.
+fn segment_node(segment: &SegmentDescriptor) -> Result<(usize, u64)> {
+    let offset = u64::from(segment.offset);
+    let size = u64::from(segment.size);
+    ensure!(
+        size.is_power_of_two() && size >= NODE_SIZE as u64 * 4 && offset % size == 0,
+        "segment of {} bytes at {} is not aligned",
+        size,
+        offset
+    );
+
+    let level = (size / NODE_SIZE as u64).trailing_zeros() as usize;
+    Ok((level, offset / size))
+}
+
+/// Returns the index of the root of the two nodes of an index entry, one level above the leafs.
+fn index_entry_node(aggregate_size: PaddedBytesAmount, index_entry: u64) -> u64 {
+    u64::from(aggregate_index_start(aggregate_size)) / SEGMENT_DESCRIPTOR_SIZE as u64 + index_entry
+}
+
+fn entry_root(segment: &SegmentDescriptor) -> Commitment {
+    let bytes = segment.to_bytes();
+    hash_nodes(&bytes[..NODE_SIZE], &bytes[NODE_SIZE..])
+}
+
+fn write_zeroes<W: Write>(target: &mut W, padded_bytes: u64) -> io::Result<u64> {
+    io::copy(
+        &mut EmptySource::new(to_unpadded_bytes(padded_bytes) as usize),
+        target,
+    )
+}
+
+/// Writes everything read from `source` to `target`.
+struct TeeReader<R, W> {
+    source: R,
+    target: W,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.source.read(buf)?;
+        self.target.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Tree of an aggregate piece, of which only the roots of the packed pieces and of the index
+/// entries are known, while everything else is zero.
+struct AggregateTree {
+    height: usize,
+    /// Offset, size and root of the known subtrees in padded bytes, sorted by offset.
+    subtrees: Vec<(u64, u64, Commitment)>,
+}
+
+impl AggregateTree {
+    fn new(aggregate_size: PaddedBytesAmount, segments: &[SegmentDescriptor]) -> Result<Self> {
+        check_aggregate_segments(aggregate_size, segments)?;
+
+        let height = (u64::from(aggregate_size) / NODE_SIZE as u64).trailing_zeros() as usize;
+        let index_start = u64::from(aggregate_index_start(aggregate_size));
+
+        let mut subtrees: Vec<(u64, u64, Commitment)> = segments
+            .iter()
+            .map(|segment| {
+                (
+                    u64::from(segment.offset),
+                    u64::from(segment.size),
+                    segment.comm_p,
+                )
+            })
+            .collect();
+        subtrees.extend(segments.iter().enumerate().map(|(i, segment)| {
+            (
+                index_start + (i * SEGMENT_DESCRIPTOR_SIZE) as u64,
+                SEGMENT_DESCRIPTOR_SIZE as u64,
+                entry_root(segment),
+            )
+        }));
+        subtrees.sort_by_key(|subtree| subtree.0);
+
+        Ok(AggregateTree { height, subtrees })
+    }
+
+    fn root(&self) -> Result<Commitment> {
+        self.node(self.height, 0)
+    }
+
+    /// Returns the siblings of the nodes on the path from the node at `index` of `level` up
+    /// to the root.
+    fn path(&self, level: usize, mut index: u64) -> Result<Vec<Commitment>> {
+        (level..self.height)
+            .map(|level| {
+                let sibling = self.node(level, index ^ 1)?;
+                index >>= 1;
+                Ok(sibling)
+            })
+            .collect()
+    }
+
+    fn node(&self, level: usize, index: u64) -> Result<Commitment> {
+        let size = (NODE_SIZE as u64) << level;
+        let start = index * size;
+
+        // Known subtrees are aligned to their size and do not overlap, so one that overlaps a
+        // node either is that node or lies within it.
+        let first = self
+            .subtrees
+            .partition_point(|subtree| subtree.0 + subtree.1 <= start);
+        match self.subtrees.get(first) {
+            Some(subtree) if subtree.0 < start + size => {
+                if subtree.0 == start && subtree.1 == size {
+                    Ok(subtree.2)
+                } else {
+                    let child_level = level
+                        .checked_sub(1)
+                        .context("subtrees of the aggregate overlap")?;
+                    Ok(hash_nodes(
+                        &self.node(child_level, 2 * index)?,
+                        &self.node(child_level, 2 * index + 1)?,
+                    ))
+                }
+            }
+            _ => Ok(ZERO_COMMITMENTS[level]),
+        }
+    }
+}
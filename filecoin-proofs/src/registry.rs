@@ -0,0 +1,1075 @@
+//! Non-generic entry points for the seal and PoSt lifecycles.
+//!
+//! The functions of the `api` module are generic over the merkle tree shape of the sector. The
+//! enums of this module identify a proof by what the chain knows about it instead, and pick the
+//! tree shape from the sector size internally, so callers only deal with paths and byte-level
+//! commitments.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Error, Result};
+use filecoin_hashers::{Domain, Hasher};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{api_version::ApiVersion, sector::SectorId};
+
+use crate::{
+    api,
+    constants::{
+        sector_shape, SectorShapeBase, SectorShapeSub2, SectorShapeSub4, SectorShapeSub8,
+        SectorShapeTop2, POREP_PARTITIONS, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB,
+        SECTOR_SIZE_512_MIB, SECTOR_SIZE_64_GIB, SECTOR_SIZE_8_MIB, WINDOW_POST_CHALLENGE_COUNT,
+        WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+    },
+    dispatch_shape,
+    types::{
+        self, ChallengeSeed, Commitment, Labels, MerkleTreeTrait, PieceInfo, PoRepConfig,
+        PoRepProofPartitions, PoStConfig, PoStType, ProverId, PublicReplicaInfo, SealCommitOutput,
+        SealPreCommitOutput, SectorSize, SnarkProof, StoreConfig, Ticket, UnpaddedByteIndex,
+        UnpaddedBytesAmount, VanillaSealProof,
+    },
+};
+
+/// The proof of replication variants, by sector size and version.
+///
+/// `V1` variants seal with `ApiVersion::V1_0_0` and `V1_1` variants with `ApiVersion::V1_1_0`.
+/// The position of these variants is their registered proof id, which is the start of their
+/// porep id. Sector sizes the chain has no proof id for, i.e. the other built-in sizes and the
+/// ones registered with `register_sector_profiles`, use `StackedDrg`, see `new`.
+///
+/// A `StackedDrg` with a sector size and api version that have a chain proof id is rejected by
+/// `porep_id`, `as_porep_config` and when deserializing, as its porep id would differ from the
+/// chain's.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedRegisteredSealProof")]
+pub enum RegisteredSealProof {
+    StackedDrg2KiBV1,
+    StackedDrg8MiBV1,
+    StackedDrg512MiBV1,
+    StackedDrg32GiBV1,
+    StackedDrg64GiBV1,
+
+    StackedDrg2KiBV1_1,
+    StackedDrg8MiBV1_1,
+    StackedDrg512MiBV1_1,
+    StackedDrg32GiBV1_1,
+    StackedDrg64GiBV1_1,
+
+    StackedDrg {
+        sector_size: u64,
+        api_version: ApiVersion,
+    },
+}
+
+/// A `RegisteredSealProof` as deserialized, before `StackedDrg` is checked.
+#[allow(non_camel_case_types)]
+#[derive(Deserialize)]
+#[serde(rename = "RegisteredSealProof")]
+enum UncheckedRegisteredSealProof {
+    StackedDrg2KiBV1,
+    StackedDrg8MiBV1,
+    StackedDrg512MiBV1,
+    StackedDrg32GiBV1,
+    StackedDrg64GiBV1,
+
+    StackedDrg2KiBV1_1,
+    StackedDrg8MiBV1_1,
+    StackedDrg512MiBV1_1,
+    StackedDrg32GiBV1_1,
+    StackedDrg64GiBV1_1,
+
+    StackedDrg {
+        sector_size: u64,
+        api_version: ApiVersion,
+    },
+}
+
+impl TryFrom<UncheckedRegisteredSealProof> for RegisteredSealProof {
+    type Error = Error;
+
+    fn try_from(proof: UncheckedRegisteredSealProof) -> Result<Self> {
+        use RegisteredSealProof::*;
+
+        let proof = match proof {
+            UncheckedRegisteredSealProof::StackedDrg2KiBV1 => StackedDrg2KiBV1,
+            UncheckedRegisteredSealProof::StackedDrg8MiBV1 => StackedDrg8MiBV1,
+            UncheckedRegisteredSealProof::StackedDrg512MiBV1 => StackedDrg512MiBV1,
+            UncheckedRegisteredSealProof::StackedDrg32GiBV1 => StackedDrg32GiBV1,
+            UncheckedRegisteredSealProof::StackedDrg64GiBV1 => StackedDrg64GiBV1,
+            UncheckedRegisteredSealProof::StackedDrg2KiBV1_1 => StackedDrg2KiBV1_1,
+            UncheckedRegisteredSealProof::StackedDrg8MiBV1_1 => StackedDrg8MiBV1_1,
+            UncheckedRegisteredSealProof::StackedDrg512MiBV1_1 => StackedDrg512MiBV1_1,
+            UncheckedRegisteredSealProof::StackedDrg32GiBV1_1 => StackedDrg32GiBV1_1,
+            UncheckedRegisteredSealProof::StackedDrg64GiBV1_1 => StackedDrg64GiBV1_1,
+            UncheckedRegisteredSealProof::StackedDrg {
+                sector_size,
+                api_version,
+            } => StackedDrg {
+                sector_size,
+                api_version,
+            },
+        };
+        proof.ensure_no_proof_id()?;
+
+        Ok(proof)
+    }
+}
+
+/// The proof of spacetime variants, by sector size and kind.
+///
+/// As for `RegisteredSealProof`, sector sizes without a chain proof id use `StackedDrgWinning`
+/// and `StackedDrgWindow`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RegisteredPoStProof {
+    StackedDrgWinning2KiBV1,
+    StackedDrgWinning8MiBV1,
+    StackedDrgWinning512MiBV1,
+    StackedDrgWinning32GiBV1,
+    StackedDrgWinning64GiBV1,
+
+    StackedDrgWindow2KiBV1,
+    StackedDrgWindow8MiBV1,
+    StackedDrgWindow512MiBV1,
+    StackedDrgWindow32GiBV1,
+    StackedDrgWindow64GiBV1,
+
+    StackedDrgWinning {
+        sector_size: u64,
+        api_version: ApiVersion,
+    },
+    StackedDrgWindow {
+        sector_size: u64,
+        api_version: ApiVersion,
+    },
+}
+
+/// Output of `RegisteredSealProof::seal_pre_commit_phase1`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealPreCommitPhase1Output {
+    pub registered_proof: RegisteredSealProof,
+    pub labels: Vec<StoreConfig>,
+    pub config: StoreConfig,
+    pub comm_d: Commitment,
+}
+
+/// The vanilla proofs of a commit phase 1 output, by tree shape.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum VanillaSealProofs {
+    Base(Vec<Vec<VanillaSealProof<SectorShapeBase>>>),
+    Sub2(Vec<Vec<VanillaSealProof<SectorShapeSub2>>>),
+    Sub4(Vec<Vec<VanillaSealProof<SectorShapeSub4>>>),
+    Sub8(Vec<Vec<VanillaSealProof<SectorShapeSub8>>>),
+    Top2(Vec<Vec<VanillaSealProof<SectorShapeTop2>>>),
+}
+
+/// Output of `RegisteredSealProof::seal_commit_phase1`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealCommitPhase1Output {
+    pub registered_proof: RegisteredSealProof,
+    pub vanilla_proofs: VanillaSealProofs,
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub replica_id: Commitment,
+    pub seed: Ticket,
+    pub ticket: Ticket,
+}
+
+/// What a prover needs to know about a sealed sector to generate a PoSt over it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivateReplicaInfo {
+    pub replica: PathBuf,
+    pub comm_r: Commitment,
+    pub cache_dir: PathBuf,
+}
+
+impl RegisteredSealProof {
+    /// Returns the proof sealing sectors of `sector_size` with `api_version`: the variant with
+    /// the chain proof id if there is one, `StackedDrg` otherwise. Fails if no profile is
+    /// registered for the sector size.
+    pub fn new(sector_size: SectorSize, api_version: ApiVersion) -> Result<Self> {
+        use RegisteredSealProof::*;
+
+        let sector_size = u64::from(sector_size);
+        sector_shape(sector_size)?;
+
+        let proof = Self::with_proof_id(sector_size, api_version).unwrap_or(StackedDrg {
+            sector_size,
+            api_version,
+        });
+
+        Ok(proof)
+    }
+
+    /// The variant with a chain proof id for `sector_size` and `api_version`, if there is one.
+    fn with_proof_id(sector_size: u64, api_version: ApiVersion) -> Option<Self> {
+        use RegisteredSealProof::*;
+
+        let proof = match (sector_size, api_version) {
+            (SECTOR_SIZE_2_KIB, ApiVersion::V1_0_0) => StackedDrg2KiBV1,
+            (SECTOR_SIZE_8_MIB, ApiVersion::V1_0_0) => StackedDrg8MiBV1,
+            (SECTOR_SIZE_512_MIB, ApiVersion::V1_0_0) => StackedDrg512MiBV1,
+            (SECTOR_SIZE_32_GIB, ApiVersion::V1_0_0) => StackedDrg32GiBV1,
+            (SECTOR_SIZE_64_GIB, ApiVersion::V1_0_0) => StackedDrg64GiBV1,
+            (SECTOR_SIZE_2_KIB, ApiVersion::V1_1_0) => StackedDrg2KiBV1_1,
+            (SECTOR_SIZE_8_MIB, ApiVersion::V1_1_0) => StackedDrg8MiBV1_1,
+            (SECTOR_SIZE_512_MIB, ApiVersion::V1_1_0) => StackedDrg512MiBV1_1,
+            (SECTOR_SIZE_32_GIB, ApiVersion::V1_1_0) => StackedDrg32GiBV1_1,
+            (SECTOR_SIZE_64_GIB, ApiVersion::V1_1_0) => StackedDrg64GiBV1_1,
+            _ => return None,
+        };
+
+        Some(proof)
+    }
+
+    /// Fails for a `StackedDrg` that should be the variant with a chain proof id.
+    fn ensure_no_proof_id(self) -> Result<()> {
+        if let RegisteredSealProof::StackedDrg {
+            sector_size,
+            api_version,
+        } = self
+        {
+            if let Some(proof) = Self::with_proof_id(sector_size, api_version) {
+                bail!("{:?} has a registered proof id, use {:?}", self, proof);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn sector_size(self) -> SectorSize {
+        use RegisteredSealProof::*;
+
+        let size = match self {
+            StackedDrg2KiBV1 | StackedDrg2KiBV1_1 => SECTOR_SIZE_2_KIB,
+            StackedDrg8MiBV1 | StackedDrg8MiBV1_1 => SECTOR_SIZE_8_MIB,
+            StackedDrg512MiBV1 | StackedDrg512MiBV1_1 => SECTOR_SIZE_512_MIB,
+            StackedDrg32GiBV1 | StackedDrg32GiBV1_1 => SECTOR_SIZE_32_GIB,
+            StackedDrg64GiBV1 | StackedDrg64GiBV1_1 => SECTOR_SIZE_64_GIB,
+            StackedDrg { sector_size, .. } => sector_size,
+        };
+        SectorSize(size)
+    }
+
+    pub fn api_version(self) -> ApiVersion {
+        use RegisteredSealProof::*;
+
+        match self {
+            StackedDrg2KiBV1 | StackedDrg8MiBV1 | StackedDrg512MiBV1 | StackedDrg32GiBV1
+            | StackedDrg64GiBV1 => ApiVersion::V1_0_0,
+            StackedDrg2KiBV1_1 | StackedDrg8MiBV1_1 | StackedDrg512MiBV1_1
+            | StackedDrg32GiBV1_1 | StackedDrg64GiBV1_1 => ApiVersion::V1_1_0,
+            StackedDrg { api_version, .. } => api_version,
+        }
+    }
+
+    /// The chain's registered proof id, `None` for `StackedDrg`.
+    pub fn registered_proof_id(self) -> Option<u64> {
+        use RegisteredSealProof::*;
+
+        let id = match self {
+            StackedDrg2KiBV1 => 0,
+            StackedDrg8MiBV1 => 1,
+            StackedDrg512MiBV1 => 2,
+            StackedDrg32GiBV1 => 3,
+            StackedDrg64GiBV1 => 4,
+            StackedDrg2KiBV1_1 => 5,
+            StackedDrg8MiBV1_1 => 6,
+            StackedDrg512MiBV1_1 => 7,
+            StackedDrg32GiBV1_1 => 8,
+            StackedDrg64GiBV1_1 => 9,
+            StackedDrg { .. } => return None,
+        };
+        Some(id)
+    }
+
+    /// The registered proof id in little endian, followed by a zero nonce.
+    ///
+    /// `StackedDrg` has the sector size in place of the proof id, which is larger than any
+    /// chain proof id, followed by the api version as `major.minor.patch` bytes.
+    pub fn porep_id(self) -> Result<[u8; 32]> {
+        self.ensure_no_proof_id()?;
+
+        let mut porep_id = [0; 32];
+        match self.registered_proof_id() {
+            Some(id) => porep_id[..8].copy_from_slice(&id.to_le_bytes()),
+            None => {
+                let version = self.api_version().as_semver();
+                porep_id[..8].copy_from_slice(&u64::from(self.sector_size()).to_le_bytes());
+                porep_id[8] = version.major as u8;
+                porep_id[9] = version.minor as u8;
+                porep_id[10] = version.patch as u8;
+            }
+        }
+        Ok(porep_id)
+    }
+
+    pub fn partitions(self) -> Result<PoRepProofPartitions> {
+        let sector_size = u64::from(self.sector_size());
+        let partitions = *POREP_PARTITIONS
+            .read()
+            .expect("POREP_PARTITIONS poisoned")
+            .get(&sector_size)
+            .with_context(|| format!("unknown sector size {}", sector_size))?;
+
+        Ok(PoRepProofPartitions(partitions))
+    }
+
+    pub fn as_porep_config(self) -> Result<PoRepConfig> {
+        Ok(PoRepConfig {
+            sector_size: self.sector_size(),
+            partitions: self.partitions()?,
+            porep_id: self.porep_id()?,
+            api_version: self.api_version(),
+        })
+    }
+
+    /// The Winning PoSt proof for sectors sealed with this proof.
+    pub fn registered_winning_post_proof(self) -> RegisteredPoStProof {
+        use RegisteredPoStProof::*;
+        use RegisteredSealProof::*;
+
+        match self {
+            StackedDrg2KiBV1 | StackedDrg2KiBV1_1 => StackedDrgWinning2KiBV1,
+            StackedDrg8MiBV1 | StackedDrg8MiBV1_1 => StackedDrgWinning8MiBV1,
+            StackedDrg512MiBV1 | StackedDrg512MiBV1_1 => StackedDrgWinning512MiBV1,
+            StackedDrg32GiBV1 | StackedDrg32GiBV1_1 => StackedDrgWinning32GiBV1,
+            StackedDrg64GiBV1 | StackedDrg64GiBV1_1 => StackedDrgWinning64GiBV1,
+            StackedDrg {
+                sector_size,
+                api_version,
+            } => StackedDrgWinning {
+                sector_size,
+                api_version,
+            },
+        }
+    }
+
+    /// The Window PoSt proof for sectors sealed with this proof.
+    pub fn registered_window_post_proof(self) -> RegisteredPoStProof {
+        use RegisteredPoStProof::*;
+        use RegisteredSealProof::*;
+
+        match self {
+            StackedDrg2KiBV1 | StackedDrg2KiBV1_1 => StackedDrgWindow2KiBV1,
+            StackedDrg8MiBV1 | StackedDrg8MiBV1_1 => StackedDrgWindow8MiBV1,
+            StackedDrg512MiBV1 | StackedDrg512MiBV1_1 => StackedDrgWindow512MiBV1,
+            StackedDrg32GiBV1 | StackedDrg32GiBV1_1 => StackedDrgWindow32GiBV1,
+            StackedDrg64GiBV1 | StackedDrg64GiBV1_1 => StackedDrgWindow64GiBV1,
+            StackedDrg {
+                sector_size,
+                api_version,
+            } => StackedDrgWindow {
+                sector_size,
+                api_version,
+            },
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal_pre_commit_phase1<R, S, T>(
+        self,
+        cache_path: R,
+        in_path: S,
+        out_path: T,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        piece_infos: &[PieceInfo],
+    ) -> Result<SealPreCommitPhase1Output>
+    where
+        R: AsRef<Path>,
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            seal_pre_commit_phase1_inner,
+            self,
+            cache_path.as_ref(),
+            in_path.as_ref(),
+            out_path.as_ref(),
+            prover_id,
+            sector_id,
+            ticket,
+            piece_infos
+        )?
+    }
+
+    pub fn seal_pre_commit_phase2<R, S>(
+        self,
+        phase1_output: SealPreCommitPhase1Output,
+        cache_path: R,
+        out_path: S,
+    ) -> Result<SealPreCommitOutput>
+    where
+        R: AsRef<Path>,
+        S: AsRef<Path>,
+    {
+        ensure!(
+            phase1_output.registered_proof == self,
+            "phase 1 output was produced by {:?}, not {:?}",
+            phase1_output.registered_proof,
+            self
+        );
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            seal_pre_commit_phase2_inner,
+            self,
+            phase1_output,
+            cache_path.as_ref(),
+            out_path.as_ref()
+        )?
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal_commit_phase1<T: AsRef<Path>>(
+        self,
+        cache_path: T,
+        replica_path: T,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        seed: Ticket,
+        pre_commit: SealPreCommitOutput,
+        piece_infos: &[PieceInfo],
+    ) -> Result<SealCommitPhase1Output> {
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            seal_commit_phase1_inner,
+            self,
+            cache_path.as_ref(),
+            replica_path.as_ref(),
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+            pre_commit,
+            piece_infos
+        )?
+    }
+
+    pub fn seal_commit_phase2(
+        self,
+        phase1_output: SealCommitPhase1Output,
+        prover_id: ProverId,
+        sector_id: SectorId,
+    ) -> Result<SealCommitOutput> {
+        ensure!(
+            phase1_output.registered_proof == self,
+            "phase 1 output was produced by {:?}, not {:?}",
+            phase1_output.registered_proof,
+            self
+        );
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            seal_commit_phase2_inner,
+            self,
+            phase1_output,
+            prover_id,
+            sector_id
+        )?
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_seal(
+        self,
+        comm_r_in: Commitment,
+        comm_d_in: Commitment,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        seed: Ticket,
+        proof: &[u8],
+    ) -> Result<bool> {
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            verify_seal_inner,
+            self,
+            comm_r_in,
+            comm_d_in,
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+            proof
+        )?
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_batch_seal(
+        self,
+        comm_r_ins: &[Commitment],
+        comm_d_ins: &[Commitment],
+        prover_ids: &[ProverId],
+        sector_ids: &[SectorId],
+        tickets: &[Ticket],
+        seeds: &[Ticket],
+        proofs: &[&[u8]],
+    ) -> Result<bool> {
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            verify_batch_seal_inner,
+            self,
+            comm_r_ins,
+            comm_d_ins,
+            prover_ids,
+            sector_ids,
+            tickets,
+            seeds,
+            proofs
+        )?
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn unseal_range<P, R, W>(
+        self,
+        cache_path: P,
+        mut sealed_sector: R,
+        mut unsealed_output: W,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        comm_d: Commitment,
+        ticket: Ticket,
+        offset: UnpaddedByteIndex,
+        num_bytes: UnpaddedBytesAmount,
+    ) -> Result<UnpaddedBytesAmount>
+    where
+        P: AsRef<Path>,
+        R: Read,
+        W: Write,
+    {
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            unseal_range_inner,
+            self,
+            cache_path.as_ref(),
+            &mut sealed_sector,
+            &mut unsealed_output,
+            prover_id,
+            sector_id,
+            comm_d,
+            ticket,
+            offset,
+            num_bytes
+        )?
+    }
+
+    /// Removes the intermediate files of sealing from the cache, keeping what PoSt needs.
+    pub fn clear_cache<P: AsRef<Path>>(self, cache_path: P) -> Result<()> {
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            clear_cache_inner,
+            cache_path.as_ref()
+        )?
+    }
+}
+
+impl RegisteredPoStProof {
+    pub fn sector_size(self) -> SectorSize {
+        use RegisteredPoStProof::*;
+
+        let size = match self {
+            StackedDrgWinning2KiBV1 | StackedDrgWindow2KiBV1 => SECTOR_SIZE_2_KIB,
+            StackedDrgWinning8MiBV1 | StackedDrgWindow8MiBV1 => SECTOR_SIZE_8_MIB,
+            StackedDrgWinning512MiBV1 | StackedDrgWindow512MiBV1 => SECTOR_SIZE_512_MIB,
+            StackedDrgWinning32GiBV1 | StackedDrgWindow32GiBV1 => SECTOR_SIZE_32_GIB,
+            StackedDrgWinning64GiBV1 | StackedDrgWindow64GiBV1 => SECTOR_SIZE_64_GIB,
+            StackedDrgWinning { sector_size, .. } | StackedDrgWindow { sector_size, .. } => {
+                sector_size
+            }
+        };
+        SectorSize(size)
+    }
+
+    /// `ApiVersion::V1_0_0` for the `V1` variants, the version carried by the others.
+    pub fn api_version(self) -> ApiVersion {
+        use RegisteredPoStProof::*;
+
+        match self {
+            StackedDrgWinning { api_version, .. } | StackedDrgWindow { api_version, .. } => {
+                api_version
+            }
+            _ => ApiVersion::V1_0_0,
+        }
+    }
+
+    pub fn typ(self) -> PoStType {
+        use RegisteredPoStProof::*;
+
+        match self {
+            StackedDrgWinning2KiBV1
+            | StackedDrgWinning8MiBV1
+            | StackedDrgWinning512MiBV1
+            | StackedDrgWinning32GiBV1
+            | StackedDrgWinning64GiBV1
+            | StackedDrgWinning { .. } => PoStType::Winning,
+            StackedDrgWindow2KiBV1
+            | StackedDrgWindow8MiBV1
+            | StackedDrgWindow512MiBV1
+            | StackedDrgWindow32GiBV1
+            | StackedDrgWindow64GiBV1
+            | StackedDrgWindow { .. } => PoStType::Window,
+        }
+    }
+
+    pub fn as_post_config(self) -> Result<PoStConfig> {
+        let sector_size = self.sector_size();
+
+        let config = match self.typ() {
+            PoStType::Winning => PoStConfig {
+                sector_size,
+                challenge_count: WINNING_POST_CHALLENGE_COUNT,
+                sector_count: WINNING_POST_SECTOR_COUNT,
+                typ: PoStType::Winning,
+                priority: true,
+                api_version: self.api_version(),
+            },
+            PoStType::Window => PoStConfig {
+                sector_size,
+                challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+                sector_count: *WINDOW_POST_SECTOR_COUNT
+                    .read()
+                    .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+                    .get(&u64::from(sector_size))
+                    .with_context(|| format!("unknown sector size {}", u64::from(sector_size)))?,
+                typ: PoStType::Window,
+                priority: false,
+                api_version: self.api_version(),
+            },
+        };
+
+        Ok(config)
+    }
+
+    pub fn generate_winning_post_sector_challenge(
+        self,
+        randomness: &ChallengeSeed,
+        sector_set_size: u64,
+        prover_id: ProverId,
+    ) -> Result<Vec<u64>> {
+        self.ensure_typ(PoStType::Winning)?;
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            generate_winning_post_sector_challenge_inner,
+            self,
+            randomness,
+            sector_set_size,
+            prover_id
+        )?
+    }
+
+    pub fn generate_winning_post(
+        self,
+        randomness: &ChallengeSeed,
+        replicas: &[(SectorId, PrivateReplicaInfo)],
+        prover_id: ProverId,
+    ) -> Result<SnarkProof> {
+        self.ensure_typ(PoStType::Winning)?;
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            generate_winning_post_inner,
+            self,
+            randomness,
+            replicas,
+            prover_id
+        )?
+    }
+
+    pub fn verify_winning_post(
+        self,
+        randomness: &ChallengeSeed,
+        replicas: &[(SectorId, PublicReplicaInfo)],
+        prover_id: ProverId,
+        proof: &[u8],
+    ) -> Result<bool> {
+        self.ensure_typ(PoStType::Winning)?;
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            verify_winning_post_inner,
+            self,
+            randomness,
+            replicas,
+            prover_id,
+            proof
+        )?
+    }
+
+    pub fn generate_window_post(
+        self,
+        randomness: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+        prover_id: ProverId,
+    ) -> Result<SnarkProof> {
+        self.ensure_typ(PoStType::Window)?;
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            generate_window_post_inner,
+            self,
+            randomness,
+            replicas,
+            prover_id
+        )?
+    }
+
+    pub fn verify_window_post(
+        self,
+        randomness: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+        prover_id: ProverId,
+        proof: &[u8],
+    ) -> Result<bool> {
+        self.ensure_typ(PoStType::Window)?;
+
+        dispatch_shape!(
+            u64::from(self.sector_size()),
+            verify_window_post_inner,
+            self,
+            randomness,
+            replicas,
+            prover_id,
+            proof
+        )?
+    }
+
+    fn ensure_typ(self, typ: PoStType) -> Result<()> {
+        ensure!(
+            self.typ() == typ,
+            "{:?} is not a {:?} PoSt proof",
+            self,
+            typ
+        );
+        Ok(())
+    }
+}
+
+/// Maps the vanilla proofs of a tree shape to and from their `VanillaSealProofs` variant.
+trait RegisteredShape: 'static + MerkleTreeTrait + Sized {
+    fn wrap_proofs(proofs: Vec<Vec<VanillaSealProof<Self>>>) -> VanillaSealProofs;
+
+    fn unwrap_proofs(proofs: VanillaSealProofs) -> Result<Vec<Vec<VanillaSealProof<Self>>>>;
+}
+
+macro_rules! impl_registered_shape {
+    ($tree:ty, $variant:ident) => {
+        impl RegisteredShape for $tree {
+            fn wrap_proofs(proofs: Vec<Vec<VanillaSealProof<Self>>>) -> VanillaSealProofs {
+                VanillaSealProofs::$variant(proofs)
+            }
+
+            fn unwrap_proofs(
+                proofs: VanillaSealProofs,
+            ) -> Result<Vec<Vec<VanillaSealProof<Self>>>> {
+                match proofs {
+                    VanillaSealProofs::$variant(proofs) => Ok(proofs),
+                    _ => bail!("vanilla proofs do not match the sector shape"),
+                }
+            }
+        }
+    };
+}
+
+impl_registered_shape!(SectorShapeBase, Base);
+impl_registered_shape!(SectorShapeSub2, Sub2);
+impl_registered_shape!(SectorShapeSub4, Sub4);
+impl_registered_shape!(SectorShapeSub8, Sub8);
+impl_registered_shape!(SectorShapeTop2, Top2);
+
+#[allow(clippy::too_many_arguments)]
+fn seal_pre_commit_phase1_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredSealProof,
+    cache_path: &Path,
+    in_path: &Path,
+    out_path: &Path,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+) -> Result<SealPreCommitPhase1Output> {
+    let output = api::seal_pre_commit_phase1::<_, _, _, Tree>(
+        registered_proof.as_porep_config()?,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+    )?;
+
+    Ok(SealPreCommitPhase1Output {
+        registered_proof,
+        labels: output.labels.labels,
+        config: output.config,
+        comm_d: output.comm_d,
+    })
+}
+
+fn seal_pre_commit_phase2_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredSealProof,
+    phase1_output: SealPreCommitPhase1Output,
+    cache_path: &Path,
+    out_path: &Path,
+) -> Result<SealPreCommitOutput> {
+    let SealPreCommitPhase1Output {
+        labels,
+        config,
+        comm_d,
+        ..
+    } = phase1_output;
+
+    api::seal_pre_commit_phase2::<_, _, Tree>(
+        registered_proof.as_porep_config()?,
+        types::SealPreCommitPhase1Output {
+            labels: Labels::new(labels),
+            config,
+            comm_d,
+        },
+        cache_path,
+        out_path,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn seal_commit_phase1_inner<Tree: RegisteredShape>(
+    registered_proof: RegisteredSealProof,
+    cache_path: &Path,
+    replica_path: &Path,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output> {
+    let output = api::seal_commit_phase1::<_, Tree>(
+        registered_proof.as_porep_config()?,
+        cache_path,
+        replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        piece_infos,
+    )?;
+
+    let mut replica_id = [0; 32];
+    output.replica_id.write_bytes(&mut replica_id)?;
+
+    Ok(SealCommitPhase1Output {
+        registered_proof,
+        vanilla_proofs: Tree::wrap_proofs(output.vanilla_proofs),
+        comm_r: output.comm_r,
+        comm_d: output.comm_d,
+        replica_id,
+        seed: output.seed,
+        ticket: output.ticket,
+    })
+}
+
+fn seal_commit_phase2_inner<Tree: RegisteredShape>(
+    registered_proof: RegisteredSealProof,
+    phase1_output: SealCommitPhase1Output,
+    prover_id: ProverId,
+    sector_id: SectorId,
+) -> Result<SealCommitOutput> {
+    let SealCommitPhase1Output {
+        vanilla_proofs,
+        comm_r,
+        comm_d,
+        replica_id,
+        seed,
+        ticket,
+        ..
+    } = phase1_output;
+
+    api::seal_commit_phase2::<Tree>(
+        registered_proof.as_porep_config()?,
+        types::SealCommitPhase1Output {
+            vanilla_proofs: Tree::unwrap_proofs(vanilla_proofs)?,
+            comm_r,
+            comm_d,
+            replica_id: <Tree::Hasher as Hasher>::Domain::try_from_bytes(&replica_id)?,
+            seed,
+            ticket,
+        },
+        prover_id,
+        sector_id,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn verify_seal_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredSealProof,
+    comm_r_in: Commitment,
+    comm_d_in: Commitment,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    proof: &[u8],
+) -> Result<bool> {
+    api::verify_seal::<Tree>(
+        registered_proof.as_porep_config()?,
+        comm_r_in,
+        comm_d_in,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        proof,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn verify_batch_seal_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredSealProof,
+    comm_r_ins: &[Commitment],
+    comm_d_ins: &[Commitment],
+    prover_ids: &[ProverId],
+    sector_ids: &[SectorId],
+    tickets: &[Ticket],
+    seeds: &[Ticket],
+    proofs: &[&[u8]],
+) -> Result<bool> {
+    api::verify_batch_seal::<Tree>(
+        registered_proof.as_porep_config()?,
+        comm_r_ins,
+        comm_d_ins,
+        prover_ids,
+        sector_ids,
+        tickets,
+        seeds,
+        proofs,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn unseal_range_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredSealProof,
+    cache_path: &Path,
+    sealed_sector: &mut dyn Read,
+    unsealed_output: &mut dyn Write,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount> {
+    api::unseal_range::<_, _, _, Tree>(
+        registered_proof.as_porep_config()?,
+        cache_path,
+        sealed_sector,
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+    )
+}
+
+fn clear_cache_inner<Tree: MerkleTreeTrait>(cache_path: &Path) -> Result<()> {
+    api::clear_cache::<Tree>(cache_path)
+}
+
+fn private_replica_info<Tree: 'static + MerkleTreeTrait>(
+    info: &PrivateReplicaInfo,
+) -> Result<types::PrivateReplicaInfo<Tree>> {
+    types::PrivateReplicaInfo::new(info.replica.clone(), info.comm_r, info.cache_dir.clone())
+}
+
+fn generate_winning_post_sector_challenge_inner<Tree: MerkleTreeTrait>(
+    registered_proof: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    sector_set_size: u64,
+    prover_id: ProverId,
+) -> Result<Vec<u64>> {
+    api::generate_winning_post_sector_challenge::<Tree>(
+        &registered_proof.as_post_config()?,
+        randomness,
+        sector_set_size,
+        prover_id,
+    )
+}
+
+fn generate_winning_post_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PrivateReplicaInfo)],
+    prover_id: ProverId,
+) -> Result<SnarkProof> {
+    let replicas = replicas
+        .iter()
+        .map(|(sector_id, info)| Ok((*sector_id, private_replica_info::<Tree>(info)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    api::generate_winning_post::<Tree>(
+        &registered_proof.as_post_config()?,
+        randomness,
+        &replicas,
+        prover_id,
+    )
+}
+
+fn verify_winning_post_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    replicas: &[(SectorId, PublicReplicaInfo)],
+    prover_id: ProverId,
+    proof: &[u8],
+) -> Result<bool> {
+    api::verify_winning_post::<Tree>(
+        &registered_proof.as_post_config()?,
+        randomness,
+        replicas,
+        prover_id,
+        proof,
+    )
+}
+
+fn generate_window_post_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo>,
+    prover_id: ProverId,
+) -> Result<SnarkProof> {
+    let replicas = replicas
+        .iter()
+        .map(|(sector_id, info)| Ok((*sector_id, private_replica_info::<Tree>(info)?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    api::generate_window_post::<Tree>(
+        &registered_proof.as_post_config()?,
+        randomness,
+        &replicas,
+        prover_id,
+    )
+}
+
+fn verify_window_post_inner<Tree: 'static + MerkleTreeTrait>(
+    registered_proof: RegisteredPoStProof,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+    proof: &[u8],
+) -> Result<bool> {
+    api::verify_window_post::<Tree>(
+        &registered_proof.as_post_config()?,
+        randomness,
+        replicas,
+        prover_id,
+        proof,
+    )
+}
@@ -0,0 +1,145 @@
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Formatter};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::pieces::check_aggregate_segments;
+use crate::types::{Commitment, PaddedBytesAmount, PieceInclusionProof, PieceInfo};
+
+/// Padded bytes of an entry in the index of an aggregate piece.
+pub const SEGMENT_DESCRIPTOR_SIZE: usize = 64;
+
+/// Entry in the index of an aggregate piece, which describes one of the pieces packed into it.
+///
+/// An entry takes two nodes of the aggregate: the first is `comm_p`, the second the little
+/// endian `offset` and `size` followed by the `checksum`.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentDescriptor {
+    pub comm_p: Commitment,
+    /// Offset of the piece in the padded aggregate.
+    pub offset: PaddedBytesAmount,
+    pub size: PaddedBytesAmount,
+    /// Truncated sha256 of the other fields, with the top two bits cleared so that the second
+    /// node is a valid field element.
+    pub checksum: [u8; 16],
+}
+
+impl Debug for SegmentDescriptor {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("SegmentDescriptor")
+            .field("comm_p", &hex::encode(self.comm_p))
+            .field("offset", &self.offset)
+            .field("size", &self.size)
+            .field("checksum", &hex::encode(self.checksum))
+            .finish()
+    }
+}
+
+impl SegmentDescriptor {
+    pub fn new(comm_p: Commitment, offset: PaddedBytesAmount, size: PaddedBytesAmount) -> Self {
+        let checksum = descriptor_checksum(&comm_p, offset, size);
+        SegmentDescriptor {
+            comm_p,
+            offset,
+            size,
+            checksum,
+        }
+    }
+
+    /// Decodes an index entry, returns `None` if its checksum does not match.
+    pub fn from_bytes(bytes: &[u8; SEGMENT_DESCRIPTOR_SIZE]) -> Option<Self> {
+        let mut comm_p = [0u8; 32];
+        comm_p.copy_from_slice(&bytes[..32]);
+        let mut offset = [0u8; 8];
+        offset.copy_from_slice(&bytes[32..40]);
+        let mut size = [0u8; 8];
+        size.copy_from_slice(&bytes[40..48]);
+        let mut checksum = [0u8; 16];
+        checksum.copy_from_slice(&bytes[48..]);
+
+        let descriptor = SegmentDescriptor {
+            comm_p,
+            offset: PaddedBytesAmount(u64::from_le_bytes(offset)),
+            size: PaddedBytesAmount(u64::from_le_bytes(size)),
+            checksum,
+        };
+        if descriptor.is_valid() {
+            Some(descriptor)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; SEGMENT_DESCRIPTOR_SIZE] {
+        let mut bytes = [0u8; SEGMENT_DESCRIPTOR_SIZE];
+        bytes[..32].copy_from_slice(&self.comm_p);
+        bytes[32..40].copy_from_slice(&u64::from(self.offset).to_le_bytes());
+        bytes[40..48].copy_from_slice(&u64::from(self.size).to_le_bytes());
+        bytes[48..].copy_from_slice(&self.checksum);
+        bytes
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.checksum == descriptor_checksum(&self.comm_p, self.offset, self.size)
+    }
+}
+
+fn descriptor_checksum(
+    comm_p: &Commitment,
+    offset: PaddedBytesAmount,
+    size: PaddedBytesAmount,
+) -> [u8; 16] {
+    let digest = Sha256::new()
+        .chain(comm_p)
+        .chain(u64::from(offset).to_le_bytes())
+        .chain(u64::from(size).to_le_bytes())
+        .finalize();
+
+    let mut checksum = [0u8; 16];
+    checksum.copy_from_slice(&digest[..16]);
+    checksum[15] &= 0b0011_1111;
+    checksum
+}
+
+/// Aggregate piece, of which `piece_info` can be added to a sector like any other piece.
+///
+/// Deserializing fails if the segments overlap each other or the index.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedAggregatePiece")]
+pub struct AggregatePiece {
+    pub piece_info: PieceInfo,
+    /// Pieces packed into the aggregate, in the order of its index.
+    pub segments: Vec<SegmentDescriptor>,
+}
+
+/// An `AggregatePiece` as deserialized, before its segments are checked.
+#[derive(Deserialize)]
+struct UncheckedAggregatePiece {
+    piece_info: PieceInfo,
+    segments: Vec<SegmentDescriptor>,
+}
+
+impl TryFrom<UncheckedAggregatePiece> for AggregatePiece {
+    type Error = Error;
+
+    fn try_from(aggregate: UncheckedAggregatePiece) -> Result<Self> {
+        check_aggregate_segments(aggregate.piece_info.size.into(), &aggregate.segments)?;
+        Ok(AggregatePiece {
+            piece_info: aggregate.piece_info,
+            segments: aggregate.segments,
+        })
+    }
+}
+
+/// Proof that a piece is packed into an aggregate piece and listed in its index.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubPieceInclusionProof {
+    /// Position of the piece's descriptor in the index.
+    pub index_entry: u64,
+    /// Path from the piece's root to the aggregate comm_p.
+    pub piece_proof: PieceInclusionProof,
+    /// Path from the root of the descriptor's two nodes to the aggregate comm_p.
+    pub entry_proof: PieceInclusionProof,
+}
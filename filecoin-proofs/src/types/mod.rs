@@ -1,7 +1,10 @@
 pub use merkletree::store::StoreConfig;
+pub use storage_proofs_core::control::{is_cancelled, Progress, ProgressSink, SealControl};
 pub use storage_proofs_core::merkle::{MerkleProof, MerkleTreeTrait};
 pub use storage_proofs_porep::stacked::{Labels, PersistentAux, TemporaryAux};
 
+use std::path::PathBuf;
+
 use filecoin_hashers::Hasher;
 use serde::{Deserialize, Serialize};
 use storage_proofs_core::{merkle::BinaryMerkleTree, sector::SectorId};
@@ -11,6 +14,9 @@ use storage_proofs_post::fallback;
 use crate::constants::DefaultPieceHasher;
 
 mod bytes_amount;
+mod data_segment;
+mod piece_cid;
+mod piece_inclusion_proof;
 mod piece_info;
 mod porep_config;
 mod porep_proof_partitions;
@@ -18,10 +24,17 @@ mod post_config;
 mod post_proof_partitions;
 mod private_replica_info;
 mod public_replica_info;
+mod seal_envelope;
+mod seal_progress;
 mod sector_class;
+mod sector_integrity;
+mod sector_profile;
 mod sector_size;
 
 pub use bytes_amount::*;
+pub use data_segment::*;
+pub use piece_cid::*;
+pub use piece_inclusion_proof::*;
 pub use piece_info::*;
 pub use porep_config::*;
 pub use porep_proof_partitions::*;
@@ -29,7 +42,11 @@ pub use post_config::*;
 pub use post_proof_partitions::*;
 pub use private_replica_info::*;
 pub use public_replica_info::*;
+pub use seal_envelope::*;
+pub use seal_progress::*;
 pub use sector_class::*;
+pub use sector_integrity::*;
+pub use sector_profile::*;
 pub use sector_size::*;
 
 pub type Commitment = [u8; 32];
@@ -66,6 +83,23 @@ pub struct SealCommitPhase1Output<Tree: MerkleTreeTrait> {
     pub ticket: Ticket,
 }
 
+/// A `SealCommitPhase1Output` with its vanilla proofs in the compact encoding of
+/// `stacked::CompactProofs`, for sending it to another host. Created by
+/// `compact_seal_commit_phase1_output` and read back with `expand_seal_commit_phase1_output`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompactSealCommitPhase1Output<Tree: MerkleTreeTrait> {
+    #[serde(bound(
+        serialize = "stacked::CompactProofs<Tree, DefaultPieceHasher>: Serialize",
+        deserialize = "stacked::CompactProofs<Tree, DefaultPieceHasher>: Deserialize<'de>"
+    ))]
+    pub vanilla_proofs: stacked::CompactProofs<Tree, DefaultPieceHasher>,
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    pub replica_id: <Tree::Hasher as Hasher>::Domain,
+    pub seed: Ticket,
+    pub ticket: Ticket,
+}
+
 #[derive(Clone, Debug)]
 pub struct SealCommitOutput {
     pub proof: Vec<u8>,
@@ -103,3 +137,32 @@ pub struct FallbackPoStSectorProof<Tree: MerkleTreeTrait> {
     ))]
     pub vanilla_proof: VanillaProof<Tree>, // Has comm_c, comm_r_last, inclusion_proofs
 }
+
+/// Commitments of a committed capacity sector after encoding new data into it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EmptySectorUpdateEncoded {
+    pub comm_r_new: Commitment,
+    pub comm_r_last_new: Commitment,
+    pub comm_d_new: Commitment,
+}
+
+pub type EmptySectorUpdateProof = Vec<u8>;
+
+/// A piece of a sector created by `fauxrep_with_pieces`.
+#[derive(Clone, Debug)]
+pub enum FauxPiece {
+    /// The unpadded piece data, zero padded to the next valid piece size.
+    File(PathBuf),
+    /// A piece of which only the commitment is known. Its data is generated from the seed, so
+    /// tree-d is consistent with the commitment only from the piece root upwards, and
+    /// `generate_piece_inclusion_proof` refuses proofs for pieces inside it.
+    Info(PieceInfo),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FauxrepOutput {
+    pub comm_r: Commitment,
+    pub comm_d: Commitment,
+    /// The pieces in sector order, with the commitments computed for `FauxPiece::File`.
+    pub piece_infos: Vec<PieceInfo>,
+}
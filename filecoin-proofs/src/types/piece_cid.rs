@@ -0,0 +1,438 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use fr32::bytes_into_fr;
+use storage_proofs_core::util::NODE_SIZE;
+
+use crate::types::{Commitment, PaddedBytesAmount, PieceInfo, UnpaddedBytesAmount};
+
+/// Multicodec of comm_d and comm_p CIDs.
+pub const FIL_COMMITMENT_UNSEALED: u64 = 0xf101;
+/// Multicodec of comm_r CIDs.
+pub const FIL_COMMITMENT_SEALED: u64 = 0xf102;
+/// Multicodec of CommPv2 CIDs, see FRC-0069.
+pub const RAW: u64 = 0x55;
+
+/// Multihash of the binary sha256 trees of comm_d and comm_p.
+pub const SHA2_256_TRUNC254_PADDED: u64 = 0x1012;
+/// Multihash of the poseidon trees of comm_r.
+pub const POSEIDON_BLS12_381_A2_FC1: u64 = 0xb401;
+/// Multihash of CommPv2, which carries the padding and tree height next to the root.
+pub const FR32_SHA256_TRUNC254_PADBINTREE: u64 = 0x1011;
+
+const CID_VERSION: u64 = 1;
+/// Multibase prefix of lowercase, unpadded RFC 4648 base32, the default for CIDv1 strings.
+const MULTIBASE_BASE32: char = 'b';
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// The commitment a CID identifies, which determines its codec and multihash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommitmentKind {
+    CommD,
+    CommP,
+    CommR,
+}
+
+impl CommitmentKind {
+    pub fn codec(self) -> u64 {
+        match self {
+            CommitmentKind::CommD | CommitmentKind::CommP => FIL_COMMITMENT_UNSEALED,
+            CommitmentKind::CommR => FIL_COMMITMENT_SEALED,
+        }
+    }
+
+    pub fn multihash(self) -> u64 {
+        match self {
+            CommitmentKind::CommD | CommitmentKind::CommP => SHA2_256_TRUNC254_PADDED,
+            CommitmentKind::CommR => POSEIDON_BLS12_381_A2_FC1,
+        }
+    }
+}
+
+/// Returns the binary CIDv1 of `commitment`, as it is embedded in on-chain messages.
+pub fn commitment_to_cid_bytes(commitment: &Commitment, kind: CommitmentKind) -> Result<Vec<u8>> {
+    ensure_valid_commitment(commitment, kind)?;
+
+    let mut cid = Vec::with_capacity(40);
+    write_varint(&mut cid, CID_VERSION);
+    write_varint(&mut cid, kind.codec());
+    write_varint(&mut cid, kind.multihash());
+    write_varint(&mut cid, commitment.len() as u64);
+    cid.extend_from_slice(commitment);
+
+    Ok(cid)
+}
+
+/// Returns the base32 CIDv1 string of `commitment`, e.g. `baga6ea4seaq...` for comm_d and
+/// comm_p and `bagboea4b5abc...` for comm_r.
+pub fn commitment_to_cid(commitment: &Commitment, kind: CommitmentKind) -> Result<String> {
+    let cid = commitment_to_cid_bytes(commitment, kind)?;
+
+    Ok(format!("{}{}", MULTIBASE_BASE32, base32_encode(&cid)))
+}
+
+/// Returns the commitment of the binary CIDv1 `cid`, which must have the codec and multihash
+/// of `kind`.
+pub fn cid_bytes_to_commitment(cid: &[u8], kind: CommitmentKind) -> Result<Commitment> {
+    let mut rest = cid;
+    let (codec, multihash, digest) = read_cid(&mut rest)?;
+    ensure!(
+        codec == kind.codec(),
+        "invalid codec {:#x} for {:?}, expected {:#x}",
+        codec,
+        kind,
+        kind.codec()
+    );
+    ensure!(
+        multihash == kind.multihash(),
+        "invalid multihash {:#x} for {:?}, expected {:#x}",
+        multihash,
+        kind,
+        kind.multihash()
+    );
+    ensure!(
+        digest.len() == NODE_SIZE,
+        "invalid digest length {}, expected {}",
+        digest.len(),
+        NODE_SIZE
+    );
+
+    let mut commitment = [0u8; 32];
+    commitment.copy_from_slice(digest);
+    ensure_valid_commitment(&commitment, kind)?;
+
+    Ok(commitment)
+}
+
+/// Returns the commitment of the CIDv1 string `cid`, which must have the codec and multihash
+/// of `kind`.
+pub fn cid_to_commitment(cid: &str, kind: CommitmentKind) -> Result<Commitment> {
+    cid_bytes_to_commitment(&decode_cid_string(cid)?, kind)
+}
+
+/// Returns the CommPv2 CID (FRC-0069) of a piece holding `payload_size` bytes of data, which
+/// were zero padded to the piece size before computing the commitment.
+///
+/// Other than CommPv1 the CID identifies the piece size and payload size, so a `PieceInfo` can
+/// be recovered from it alone.
+pub fn piece_info_to_cid_v2(
+    piece_info: &PieceInfo,
+    payload_size: UnpaddedBytesAmount,
+) -> Result<String> {
+    ensure_valid_commitment(&piece_info.commitment, CommitmentKind::CommP)?;
+    let height = piece_height(piece_info.size)?;
+    ensure!(
+        payload_size <= piece_info.size,
+        "payload of {} bytes does not fit into a piece of {} bytes",
+        u64::from(payload_size),
+        u64::from(piece_info.size)
+    );
+
+    let mut digest = Vec::with_capacity(NODE_SIZE + 10);
+    write_varint(&mut digest, u64::from(piece_info.size - payload_size));
+    digest.push(height);
+    digest.extend_from_slice(&piece_info.commitment);
+
+    let mut cid = Vec::with_capacity(digest.len() + 8);
+    write_varint(&mut cid, CID_VERSION);
+    write_varint(&mut cid, RAW);
+    write_varint(&mut cid, FR32_SHA256_TRUNC254_PADBINTREE);
+    write_varint(&mut cid, digest.len() as u64);
+    cid.extend_from_slice(&digest);
+
+    Ok(format!("{}{}", MULTIBASE_BASE32, base32_encode(&cid)))
+}
+
+/// Returns the piece and payload size of the CommPv2 CID (FRC-0069) `cid`.
+pub fn cid_v2_to_piece_info(cid: &str) -> Result<(PieceInfo, UnpaddedBytesAmount)> {
+    let cid = decode_cid_string(cid)?;
+    let mut rest = &cid[..];
+    let (codec, multihash, mut digest) = read_cid(&mut rest)?;
+    ensure!(
+        codec == RAW,
+        "invalid codec {:#x} for CommPv2, expected {:#x}",
+        codec,
+        RAW
+    );
+    ensure!(
+        multihash == FR32_SHA256_TRUNC254_PADBINTREE,
+        "invalid multihash {:#x} for CommPv2, expected {:#x}",
+        multihash,
+        FR32_SHA256_TRUNC254_PADBINTREE
+    );
+
+    let padding = read_varint(&mut digest).context("invalid CommPv2 padding")?;
+    ensure!(
+        digest.len() == 1 + NODE_SIZE,
+        "invalid CommPv2 digest length"
+    );
+    let height = digest[0];
+    // A piece holds at least 127 bytes and its size has to fit into a u64.
+    ensure!(
+        (2..=58).contains(&height),
+        "invalid CommPv2 tree height {}",
+        height
+    );
+    let size = UnpaddedBytesAmount::from(PaddedBytesAmount((NODE_SIZE as u64) << height));
+    ensure!(
+        padding <= u64::from(size),
+        "CommPv2 padding of {} bytes exceeds the piece size of {} bytes",
+        padding,
+        u64::from(size)
+    );
+
+    let mut commitment = [0u8; 32];
+    commitment.copy_from_slice(&digest[1..]);
+    ensure_valid_commitment(&commitment, CommitmentKind::CommP)?;
+
+    Ok((
+        PieceInfo::new(commitment, size)?,
+        size - UnpaddedBytesAmount(padding),
+    ))
+}
+
+/// Height of the binary tree of a piece of `size` unpadded bytes.
+fn piece_height(size: UnpaddedBytesAmount) -> Result<u8> {
+    let padded = u64::from(PaddedBytesAmount::from(size));
+    ensure!(
+        padded >= 4 * NODE_SIZE as u64 && padded.is_power_of_two(),
+        "invalid piece size {}",
+        u64::from(size)
+    );
+
+    Ok((padded / NODE_SIZE as u64).trailing_zeros() as u8)
+}
+
+fn ensure_valid_commitment(commitment: &Commitment, kind: CommitmentKind) -> Result<()> {
+    match kind {
+        // Truncated sha256 digests have the two most significant bits cleared.
+        CommitmentKind::CommD | CommitmentKind::CommP => ensure!(
+            commitment[31] & 0b1100_0000 == 0,
+            "invalid {:?}, not a truncated sha256 digest",
+            kind
+        ),
+        CommitmentKind::CommR => {
+            bytes_into_fr(commitment).with_context(|| format!("invalid {:?}", kind))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_cid_string(cid: &str) -> Result<Vec<u8>> {
+    let mut chars = cid.chars();
+    match chars.next() {
+        Some(MULTIBASE_BASE32) => base32_decode(chars.as_str()),
+        Some(prefix) => Err(anyhow!("unsupported multibase prefix {:?}", prefix)),
+        None => Err(anyhow!("empty CID")),
+    }
+}
+
+/// Reads a CIDv1 and returns its codec, multihash code and digest.
+fn read_cid<'a>(cid: &mut &'a [u8]) -> Result<(u64, u64, &'a [u8])> {
+    let version = read_varint(cid).context("invalid CID version")?;
+    ensure!(
+        version == CID_VERSION,
+        "unsupported CID version {}",
+        version
+    );
+    let codec = read_varint(cid).context("invalid CID codec")?;
+    let multihash = read_varint(cid).context("invalid multihash code")?;
+    let len = read_varint(cid).context("invalid multihash length")?;
+    ensure!(
+        cid.len() as u64 == len,
+        "multihash length {} does not match the {} digest bytes",
+        len,
+        cid.len()
+    );
+
+    Ok((codec, multihash, cid))
+}
+
+/// Appends `value` as unsigned LEB128, the varint of multiformats.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads an unsigned LEB128 varint, which multiformats limits to 9 bytes.
+fn read_varint(bytes: &mut &[u8]) -> Result<u64> {
+    let slice: &[u8] = bytes;
+    let mut value = 0u64;
+    for (i, byte) in slice.iter().enumerate().take(9) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            ensure!(i == 0 || *byte != 0, "varint is not minimally encoded");
+            *bytes = &slice[i + 1..];
+            return Ok(value);
+        }
+    }
+
+    Err(anyhow!("truncated or overlong varint"))
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+
+    out
+}
+
+fn base32_decode(encoded: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| anyhow!("invalid base32 character {:?}", c as char))?;
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    ensure!(bits < 5 && buffer == 0, "invalid base32 padding");
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pieces::piece_hash;
+
+    /// comm_d of an empty 32 GiB sector, i.e. the root of 2^30 zero nodes.
+    fn zero_root_32gib() -> Commitment {
+        let mut root = [0u8; 32];
+        for _ in 0..30 {
+            let parent = piece_hash(&root, &root);
+            root.copy_from_slice(AsRef::<[u8]>::as_ref(&parent));
+        }
+        root
+    }
+
+    #[test]
+    fn test_commitment_cid_vectors() {
+        // The CIDs of the empty 32 GiB sector used by deal markets.
+        let comm_d = zero_root_32gib();
+        let cid = "baga6ea4seaqao7s73y24kcutaosvacpdjgfe5pw76ooefnyqw4ynr3d2y6x2mpq";
+        assert_eq!(
+            commitment_to_cid(&comm_d, CommitmentKind::CommD).expect("failed to encode"),
+            cid
+        );
+        assert_eq!(
+            cid_to_commitment(cid, CommitmentKind::CommP).expect("failed to decode"),
+            comm_d
+        );
+
+        let piece_info = PieceInfo::new(
+            comm_d,
+            UnpaddedBytesAmount::from(PaddedBytesAmount(1 << 35)),
+        )
+        .expect("invalid piece info");
+        let cid_v2 = "bafkzcibcaapao7s73y24kcutaosvacpdjgfe5pw76ooefnyqw4ynr3d2y6x2mpq";
+        assert_eq!(
+            piece_info_to_cid_v2(&piece_info, piece_info.size).expect("failed to encode"),
+            cid_v2
+        );
+        assert_eq!(
+            cid_v2_to_piece_info(cid_v2).expect("failed to decode"),
+            (piece_info, UnpaddedBytesAmount(34_091_302_912))
+        );
+    }
+
+    #[test]
+    fn test_comm_r_cid_roundtrip() {
+        let mut comm_r = [7u8; 32];
+        comm_r[31] = 0x0f;
+        let cid = commitment_to_cid(&comm_r, CommitmentKind::CommR).expect("failed to encode");
+        assert!(cid.starts_with("bagboea4b5abc"));
+        assert_eq!(
+            cid_to_commitment(&cid, CommitmentKind::CommR).expect("failed to decode"),
+            comm_r
+        );
+
+        // A comm_r is not a comm_d, and neither are values beyond the field modulus.
+        assert!(cid_to_commitment(&cid, CommitmentKind::CommD).is_err());
+        assert!(commitment_to_cid(&[0xff; 32], CommitmentKind::CommR).is_err());
+        assert!(commitment_to_cid(&[0xff; 32], CommitmentKind::CommP).is_err());
+    }
+
+    #[test]
+    fn test_cid_v2_padding() {
+        let commitment = zero_root_32gib();
+        // 1000 bytes of payload fill a 1016 byte (1 KiB padded) piece, with 16 bytes padding.
+        let piece_info =
+            PieceInfo::new(commitment, UnpaddedBytesAmount(1016)).expect("invalid piece info");
+        let cid =
+            piece_info_to_cid_v2(&piece_info, UnpaddedBytesAmount(1000)).expect("failed to encode");
+        let bytes = decode_cid_string(&cid).expect("invalid CID");
+        // version, raw, multihash, length, padding, height
+        assert_eq!(&bytes[..7], &[0x01, 0x55, 0x91, 0x20, 0x22, 16, 5]);
+        assert_eq!(
+            cid_v2_to_piece_info(&cid).expect("failed to decode"),
+            (piece_info.clone(), UnpaddedBytesAmount(1000))
+        );
+
+        assert!(piece_info_to_cid_v2(&piece_info, UnpaddedBytesAmount(1017)).is_err());
+        let invalid_size =
+            PieceInfo::new(commitment, UnpaddedBytesAmount(1000)).expect("invalid piece info");
+        assert!(piece_info_to_cid_v2(&invalid_size, UnpaddedBytesAmount(1000)).is_err());
+
+        // CommPv1 and CommPv2 CIDs are not interchangeable.
+        assert!(cid_to_commitment(&cid, CommitmentKind::CommP).is_err());
+        let cid_v1 =
+            commitment_to_cid(&commitment, CommitmentKind::CommP).expect("failed to encode");
+        assert!(cid_v2_to_piece_info(&cid_v1).is_err());
+    }
+
+    #[test]
+    fn test_invalid_cids() {
+        for cid in &[
+            "",
+            "zbaga6ea4seaq",
+            "baga6ea4seaq",
+            "BAGA6EA4SEAQAO7S73Y24KCUTAOSVACPDJGFE5PW76OOEFNYQW4YNR3D2Y6X2MPQ",
+            "baga6ea4seaqao7s73y24kcutaosvacpdjgfe5pw76ooefnyqw4ynr3d2y6x2mp",
+            "baga6ea4seaqao7s73y24kcutaosvacpdjgfe5pw76ooefnyqw4ynr3d2y6x2mpq2",
+        ] {
+            assert!(
+                cid_to_commitment(cid, CommitmentKind::CommD).is_err(),
+                "{:?} should be invalid",
+                cid
+            );
+        }
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in &[0, 1, 0x7f, 0x80, 0xf101, 0x1011, u64::MAX >> 1] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, *value);
+            let mut rest = &bytes[..];
+            assert_eq!(read_varint(&mut rest).expect("invalid varint"), *value);
+            assert!(rest.is_empty());
+        }
+        assert!(read_varint(&mut &[0x80][..]).is_err());
+        assert!(read_varint(&mut &[0x80, 0x00][..]).is_err());
+    }
+}
@@ -0,0 +1,23 @@
+use std::fmt::{self, Debug, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::Commitment;
+
+/// Proof that a piece commitment is the root of the subtree of tree-d that holds the piece.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceInclusionProof {
+    /// Siblings of the nodes on the path from the piece's root up to comm_d, lowest first.
+    pub path: Vec<Commitment>,
+}
+
+impl Debug for PieceInclusionProof {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("PieceInclusionProof")
+            .field(
+                "path",
+                &self.path.iter().map(hex::encode).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
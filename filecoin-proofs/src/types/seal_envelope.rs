@@ -0,0 +1,369 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use merkletree::store::StoreConfig;
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use storage_proofs_core::api_version::ApiVersion;
+
+use crate::types::{
+    MerkleTreeTrait, PoRepConfig, SealCommitPhase1Output, SealPreCommitPhase1Output,
+};
+
+/// Version of the envelope layout written by this crate.
+pub const SEAL_ENVELOPE_VERSION: u32 = 1;
+
+/// Leading bytes of the binary encoding, so that bare bincode blobs are rejected early.
+const SEAL_ENVELOPE_MAGIC: [u8; 4] = *b"FSEV";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SealOutputKind {
+    PreCommitPhase1,
+    CommitPhase1,
+}
+
+/// Describes the sector an enveloped seal output was produced for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealEnvelopeHeader {
+    pub version: u32,
+    pub kind: SealOutputKind,
+    pub sector_size: u64,
+    /// The `ApiVersion` the output was produced with, as a semver string.
+    pub api_version: String,
+    pub porep_id: [u8; 32],
+}
+
+impl SealEnvelopeHeader {
+    pub fn new(kind: SealOutputKind, porep_config: &PoRepConfig) -> Self {
+        SealEnvelopeHeader {
+            version: SEAL_ENVELOPE_VERSION,
+            kind,
+            sector_size: u64::from(porep_config.sector_size),
+            api_version: porep_config.api_version.to_string(),
+            porep_id: porep_config.porep_id,
+        }
+    }
+
+    /// Checks that the header describes an output of `kind` produced for `porep_config`.
+    pub fn verify(&self, kind: SealOutputKind, porep_config: &PoRepConfig) -> Result<()> {
+        self.verify_format(kind)?;
+
+        let sector_size = u64::from(porep_config.sector_size);
+        ensure!(
+            self.sector_size == sector_size,
+            "seal envelope is for sector size {}, expected {}",
+            self.sector_size,
+            sector_size
+        );
+        let api_version: ApiVersion = self
+            .api_version
+            .parse()
+            .context("invalid api_version in seal envelope")?;
+        ensure!(
+            api_version == porep_config.api_version,
+            "seal envelope is for api version {}, expected {}",
+            api_version,
+            porep_config.api_version
+        );
+        ensure!(
+            self.porep_id == porep_config.porep_id,
+            "seal envelope porep_id does not match"
+        );
+
+        Ok(())
+    }
+
+    fn verify_format(&self, kind: SealOutputKind) -> Result<()> {
+        ensure!(
+            self.version == SEAL_ENVELOPE_VERSION,
+            "unsupported seal envelope version {} (supported: {})",
+            self.version,
+            SEAL_ENVELOPE_VERSION
+        );
+        ensure!(
+            self.kind == kind,
+            "seal envelope holds a {:?} output, expected {:?}",
+            self.kind,
+            kind
+        );
+
+        Ok(())
+    }
+}
+
+/// A seal phase output that can be carried in a `SealEnvelope`.
+pub trait SealEnvelopePayload: Serialize + DeserializeOwned {
+    const KIND: SealOutputKind;
+
+    /// Rewrites the store paths held by the output, which must lie under `cache_path`,
+    /// relative to it.
+    fn relativize_paths(&mut self, _cache_path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Resolves relative store paths against `cache_path`.
+    fn resolve_paths(&mut self, _cache_path: &Path) {}
+}
+
+impl<Tree: MerkleTreeTrait> SealEnvelopePayload for SealPreCommitPhase1Output<Tree> {
+    const KIND: SealOutputKind = SealOutputKind::PreCommitPhase1;
+
+    fn relativize_paths(&mut self, cache_path: &Path) -> Result<()> {
+        for config in self.labels.labels.iter_mut() {
+            relativize_store_path(config, cache_path)?;
+        }
+        relativize_store_path(&mut self.config, cache_path)
+    }
+
+    fn resolve_paths(&mut self, cache_path: &Path) {
+        for config in self.labels.labels.iter_mut() {
+            config.path = cache_path.join(&config.path);
+        }
+        self.config.path = cache_path.join(&self.config.path);
+    }
+}
+
+impl<Tree: MerkleTreeTrait> SealEnvelopePayload for SealCommitPhase1Output<Tree> {
+    const KIND: SealOutputKind = SealOutputKind::CommitPhase1;
+}
+
+/// Rewrites the path of `config`, which must lie under `cache_path`, relative to it.
+pub(crate) fn relativize_store_path(config: &mut StoreConfig, cache_path: &Path) -> Result<()> {
+    let relative = config
+        .path
+        .strip_prefix(cache_path)
+        .with_context(|| {
+            format!(
+                "store {} at {:?} is not under cache path {:?}",
+                config.id, config.path, cache_path
+            )
+        })?
+        .to_path_buf();
+    config.path = relative;
+
+    Ok(())
+}
+
+/// Self-describing, portable container for a seal phase output.
+///
+/// Store paths inside the payload are kept relative to the sector's cache directory, so
+/// an envelope can be written on one machine and opened against a different cache path
+/// on another.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealEnvelope<T> {
+    pub header: SealEnvelopeHeader,
+    pub payload: T,
+}
+
+impl<T: SealEnvelopePayload> SealEnvelope<T> {
+    /// Wraps an output produced for `porep_config` with its cache in `cache_path`.
+    pub fn new(porep_config: PoRepConfig, mut payload: T, cache_path: &Path) -> Result<Self> {
+        payload.relativize_paths(cache_path)?;
+
+        Ok(SealEnvelope {
+            header: SealEnvelopeHeader::new(T::KIND, &porep_config),
+            payload,
+        })
+    }
+
+    /// Migrates an output serialized with bincode before envelopes existed.
+    /// `legacy_cache_path` is the cache directory the output was produced with.
+    pub fn from_legacy_bytes(
+        porep_config: PoRepConfig,
+        bytes: &[u8],
+        legacy_cache_path: &Path,
+    ) -> Result<Self> {
+        let payload = bincode::deserialize(bytes).context("could not decode legacy seal output")?;
+        Self::new(porep_config, payload, legacy_cache_path)
+    }
+
+    /// Migrates an output serialized as JSON before envelopes existed.
+    /// `legacy_cache_path` is the cache directory the output was produced with.
+    pub fn from_legacy_json(
+        porep_config: PoRepConfig,
+        json: &str,
+        legacy_cache_path: &Path,
+    ) -> Result<Self> {
+        let payload = serde_json::from_str(json).context("could not decode legacy seal output")?;
+        Self::new(porep_config, payload, legacy_cache_path)
+    }
+
+    /// Checks the header against `porep_config` and returns the output with its store
+    /// paths resolved against `cache_path`.
+    pub fn into_payload(self, porep_config: PoRepConfig, cache_path: &Path) -> Result<T> {
+        self.header.verify(T::KIND, &porep_config)?;
+
+        let mut payload = self.payload;
+        payload.resolve_paths(cache_path);
+
+        Ok(payload)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(Into::into)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        // Check the header before decoding the payload, so that a version or kind
+        // mismatch is reported as such rather than as a malformed payload.
+        let envelope: SealEnvelope<IgnoredAny> =
+            serde_json::from_str(json).context("not a seal envelope")?;
+        envelope.header.verify_format(T::KIND)?;
+
+        serde_json::from_str(json).context("could not decode seal envelope payload")
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = SEAL_ENVELOPE_MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self)?;
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.starts_with(&SEAL_ENVELOPE_MAGIC),
+            "not a seal envelope (bad magic)"
+        );
+        let body = &bytes[SEAL_ENVELOPE_MAGIC.len()..];
+
+        // The header is encoded first, so it can be decoded on its own.
+        let header: SealEnvelopeHeader =
+            bincode::deserialize(body).context("could not decode seal envelope header")?;
+        header.verify_format(T::KIND)?;
+
+        bincode::deserialize(body).context("could not decode seal envelope payload")
+    }
+}
+
+impl<Tree: MerkleTreeTrait> SealEnvelope<SealCommitPhase1Output<Tree>> {
+    /// Checks the header against `porep_config` and returns the output, which holds no store
+    /// paths to resolve.
+    pub fn into_commit_phase1_output(
+        self,
+        porep_config: PoRepConfig,
+    ) -> Result<SealCommitPhase1Output<Tree>> {
+        self.header
+            .verify(SealOutputKind::CommitPhase1, &porep_config)?;
+
+        Ok(self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use storage_proofs_porep::stacked::Labels;
+
+    use crate::constants::{SectorShape2KiB, SECTOR_SIZE_2_KIB};
+    use crate::types::{PoRepProofPartitions, SectorSize};
+
+    type Output = SealPreCommitPhase1Output<SectorShape2KiB>;
+
+    fn porep_config(porep_id: [u8; 32]) -> PoRepConfig {
+        PoRepConfig {
+            sector_size: SectorSize(SECTOR_SIZE_2_KIB),
+            partitions: PoRepProofPartitions(1),
+            porep_id,
+            api_version: ApiVersion::V1_1_0,
+        }
+    }
+
+    fn output(cache_path: &Path) -> Output {
+        SealPreCommitPhase1Output {
+            labels: Labels::new(vec![
+                StoreConfig::new(cache_path, "layer-1", 0),
+                StoreConfig::new(cache_path, "layer-2", 0),
+            ]),
+            config: StoreConfig::new(cache_path, "tree-d", 0),
+            comm_d: [7u8; 32],
+        }
+    }
+
+    fn store_paths(output: &Output) -> Vec<PathBuf> {
+        output
+            .labels
+            .labels
+            .iter()
+            .chain(Some(&output.config))
+            .map(|config| config.path.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_seal_envelope_relocates_store_paths() -> Result<()> {
+        let config = porep_config([1u8; 32]);
+        let old_cache = Path::new("/var/old/cache");
+        let new_cache = Path::new("/mnt/new/cache");
+
+        let envelope = SealEnvelope::new(config, output(old_cache), old_cache)?;
+        assert!(store_paths(&envelope.payload)
+            .iter()
+            .all(|path| path.as_os_str().is_empty()));
+
+        let from_json = SealEnvelope::<Output>::from_json(&envelope.to_json()?)?;
+        let from_bytes = SealEnvelope::<Output>::from_bytes(&envelope.to_bytes()?)?;
+        for decoded in [from_json, from_bytes] {
+            assert_eq!(decoded.header, envelope.header);
+            let payload = decoded.into_payload(config, new_cache)?;
+            assert_eq!(payload.comm_d, [7u8; 32]);
+            assert!(store_paths(&payload).iter().all(|path| path == new_cache));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seal_envelope_rejects_mismatches() -> Result<()> {
+        let config = porep_config([1u8; 32]);
+        let cache = Path::new("/cache");
+        let envelope = SealEnvelope::new(config, output(cache), cache)?;
+
+        assert!(
+            SealEnvelope::<SealCommitPhase1Output<SectorShape2KiB>>::from_bytes(
+                &envelope.to_bytes()?
+            )
+            .is_err()
+        );
+        assert!(SealEnvelope::<Output>::from_bytes(&bincode::serialize(&envelope)?).is_err());
+
+        let mut future = SealEnvelope::new(config, output(cache), cache)?;
+        future.header.version += 1;
+        assert!(SealEnvelope::<Output>::from_json(&future.to_json()?).is_err());
+
+        let decoded = SealEnvelope::<Output>::from_json(&envelope.to_json()?)?;
+        assert!(decoded
+            .into_payload(porep_config([2u8; 32]), cache)
+            .is_err());
+
+        assert!(SealEnvelope::new(config, output(cache), Path::new("/elsewhere")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seal_envelope_migrates_legacy_outputs() -> Result<()> {
+        let config = porep_config([1u8; 32]);
+        let old_cache = Path::new("/var/old/cache");
+        let new_cache = Path::new("/mnt/new/cache");
+
+        let legacy_bytes = bincode::serialize(&output(old_cache))?;
+        let legacy_json = serde_json::to_string(&output(old_cache))?;
+        let migrated = vec![
+            SealEnvelope::<Output>::from_legacy_bytes(config, &legacy_bytes, old_cache)?,
+            SealEnvelope::<Output>::from_legacy_json(config, &legacy_json, old_cache)?,
+        ];
+        for envelope in migrated {
+            assert_eq!(envelope.header.version, SEAL_ENVELOPE_VERSION);
+            let payload = envelope.into_payload(config, new_cache)?;
+            assert!(store_paths(&payload).iter().all(|path| path == new_cache));
+        }
+
+        Ok(())
+    }
+}
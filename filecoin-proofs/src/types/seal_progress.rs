@@ -0,0 +1,191 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{ensure, Context, Result};
+use merkletree::store::StoreConfig;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage_proofs_core::cache_key::CacheKey;
+
+use crate::types::{Commitment, PoRepConfig};
+
+/// The current version of the `SealPreCommitPhase1Progress` manifest format.
+pub const SEAL_PRE_COMMIT_PHASE1_PROGRESS_VERSION: u32 = 1;
+
+/// A label layer which has been completely written to the cache directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerProgress {
+    /// The (1-based) index of the layer.
+    pub layer: usize,
+    /// SHA-256 digest over the layer's labels, as stored on disk.
+    pub digest: [u8; 32],
+    /// Fingerprint of the layer file, recorded once the file on disk was verified against
+    /// `digest`, so that later checks do not need to hash the whole layer again.
+    #[serde(default)]
+    pub fingerprint: Option<LayerFingerprint>,
+}
+
+/// Length and modification time of a layer file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerFingerprint {
+    pub len: u64,
+    pub modified_secs: u64,
+    pub modified_nanos: u32,
+}
+
+impl LayerFingerprint {
+    /// Reads the fingerprint of the layer stored on disk.
+    pub fn from_disk(config: &StoreConfig) -> Result<Self> {
+        let data_path = StoreConfig::data_path(&config.path, &config.id);
+        let metadata = fs::metadata(&data_path)
+            .with_context(|| format!("could not stat layer={:?}", data_path))?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .with_context(|| format!("invalid modification time of layer={:?}", data_path))?;
+
+        Ok(LayerFingerprint {
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+/// On-disk manifest recording how far `seal_pre_commit_phase1` got for a sector, so that an
+/// interrupted run can be continued with `resume_pre_commit_phase1`.
+///
+/// The manifest is stored as JSON in the cache directory under
+/// `CacheKey::PreCommitPhase1Progress` and is replaced atomically after every step.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealPreCommitPhase1Progress {
+    pub version: u32,
+    pub sector_size: u64,
+    pub porep_id: [u8; 32],
+    pub api_version: String,
+    /// Set once the unsealed data has been copied to the replica and tree-d was persisted.
+    pub comm_d: Option<Commitment>,
+    /// Set together with `comm_d`, as the replica id depends on it.
+    pub replica_id: Option<[u8; 32]>,
+    /// Completed label layers, ordered by layer index.
+    pub layers: Vec<LayerProgress>,
+}
+
+impl SealPreCommitPhase1Progress {
+    pub fn new(porep_config: &PoRepConfig) -> Self {
+        SealPreCommitPhase1Progress {
+            version: SEAL_PRE_COMMIT_PHASE1_PROGRESS_VERSION,
+            sector_size: u64::from(porep_config.sector_size),
+            porep_id: porep_config.porep_id,
+            api_version: porep_config.api_version.to_string(),
+            comm_d: None,
+            replica_id: None,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Returns the location of the manifest inside of `cache_path`.
+    pub fn path<P: AsRef<Path>>(cache_path: P) -> PathBuf {
+        cache_path
+            .as_ref()
+            .join(CacheKey::PreCommitPhase1Progress.to_string())
+    }
+
+    /// Reads the manifest from `cache_path`, returning `None` if there is none.
+    pub fn load<P: AsRef<Path>>(cache_path: P) -> Result<Option<Self>> {
+        let path = Self::path(cache_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = fs::read(&path)
+            .with_context(|| format!("could not read progress manifest={:?}", path))?;
+        let progress: Self = serde_json::from_slice(&bytes)
+            .with_context(|| format!("could not parse progress manifest={:?}", path))?;
+        ensure!(
+            progress.version == SEAL_PRE_COMMIT_PHASE1_PROGRESS_VERSION,
+            "unsupported progress manifest version {} (expected {})",
+            progress.version,
+            SEAL_PRE_COMMIT_PHASE1_PROGRESS_VERSION
+        );
+
+        Ok(Some(progress))
+    }
+
+    /// Writes the manifest to `cache_path`, by writing to a temporary file first and then
+    /// renaming it, so that a crash never leaves a partially written manifest behind.
+    pub fn persist<P: AsRef<Path>>(&self, cache_path: P) -> Result<()> {
+        let path = Self::path(cache_path);
+        let tmp_path = path.with_extension("tmp");
+
+        let bytes = serde_json::to_vec_pretty(self)?;
+        fs::write(&tmp_path, bytes)
+            .with_context(|| format!("could not write progress manifest={:?}", tmp_path))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("could not rename progress manifest={:?}", tmp_path))?;
+
+        Ok(())
+    }
+
+    /// Ensures the manifest was written for a sector sealed with the given configuration.
+    pub fn ensure_matches(&self, porep_config: &PoRepConfig) -> Result<()> {
+        ensure!(
+            self.sector_size == u64::from(porep_config.sector_size),
+            "progress manifest sector size {} does not match {}",
+            self.sector_size,
+            u64::from(porep_config.sector_size)
+        );
+        ensure!(
+            self.porep_id == porep_config.porep_id,
+            "progress manifest porep_id does not match"
+        );
+        ensure!(
+            self.api_version == porep_config.api_version.to_string(),
+            "progress manifest api version {} does not match {}",
+            self.api_version,
+            porep_config.api_version
+        );
+
+        Ok(())
+    }
+
+    /// Records `layer` as completed, discarding any previous record for it.
+    pub fn record_layer(&mut self, layer: usize, digest: [u8; 32]) {
+        self.layers.retain(|l| l.layer != layer);
+        self.layers.push(LayerProgress {
+            layer,
+            digest,
+            fingerprint: None,
+        });
+        self.layers.sort_by_key(|l| l.layer);
+    }
+
+    /// Returns the number of layers which were completed without gaps, starting at layer 1.
+    pub fn completed_layers(&self) -> usize {
+        self.layers
+            .iter()
+            .zip(1..)
+            .take_while(|(l, expected)| l.layer == *expected)
+            .count()
+    }
+}
+
+/// Computes the digest recorded in `LayerProgress` for in-memory layer labels.
+pub fn layer_digest(labels: &[u8]) -> [u8; 32] {
+    Sha256::digest(labels).into()
+}
+
+/// Computes the digest recorded in `LayerProgress` for a layer stored on disk.
+pub fn layer_digest_from_disk(config: &StoreConfig) -> Result<[u8; 32]> {
+    let data_path = StoreConfig::data_path(&config.path, &config.id);
+    let file =
+        File::open(&data_path).with_context(|| format!("could not open layer={:?}", data_path))?;
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(file), &mut hasher)
+        .with_context(|| format!("could not read layer={:?}", data_path))?;
+
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::Commitment;
+
+/// Outcome of a single consistency check performed by `check_sector_integrity`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IntegrityCheck {
+    Passed,
+    /// The check ran and found a fault, described by the message.
+    Failed(String),
+    /// The check could not run, usually because the cache files it needs were cleared.
+    Skipped(String),
+}
+
+impl IntegrityCheck {
+    pub fn is_failed(&self) -> bool {
+        matches!(self, IntegrityCheck::Failed(_))
+    }
+}
+
+/// Presence and size of one file in a sector's cache directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheFileStatus {
+    /// Store id, e.g. `tree-r-last-3` or `layer-1`.
+    pub id: String,
+    pub path: PathBuf,
+    /// Whether the file is still needed once the sector is sealed. Files that are not
+    /// required are removed by `clear_cache`.
+    pub required: bool,
+    /// Expected size in bytes, when it can be derived from the sector shape.
+    pub expected_size: Option<u64>,
+    /// Size in bytes on disk, or `None` if the file is missing.
+    pub size: Option<u64>,
+}
+
+impl CacheFileStatus {
+    /// Returns true if the file exists and, when an expected size is known, matches it.
+    pub fn is_valid(&self) -> bool {
+        match (self.size, self.expected_size) {
+            (Some(size), Some(expected)) => size == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+/// Root of one tree-r-last base tree, as cached and as recomputed from the replica.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeRLastRoot {
+    pub index: usize,
+    /// Root read from the cached store, or `None` if the store could not be opened.
+    pub cached: Option<Commitment>,
+    pub recomputed: Commitment,
+}
+
+impl TreeRLastRoot {
+    pub fn matches(&self) -> bool {
+        self.cached == Some(self.recomputed)
+    }
+}
+
+/// Result of checking a sealed sector's tree-r-last against its replica with
+/// `check_tree_r_last`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeRLastReport {
+    pub roots: Vec<TreeRLastRoot>,
+    /// comm_r_last recomputed from the replica.
+    pub comm_r_last: Commitment,
+    /// comm_r_last of the cached stores, or `None` if any of them could not be opened.
+    pub cached_comm_r_last: Option<Commitment>,
+    /// comm_r_last stored in `p_aux`, or `None` if `p_aux` could not be read.
+    pub p_aux_comm_r_last: Option<Commitment>,
+}
+
+impl TreeRLastReport {
+    /// Returns true if comm_r_last recomputed from the replica matches the one in `p_aux`.
+    pub fn p_aux_matches(&self) -> bool {
+        self.p_aux_comm_r_last == Some(self.comm_r_last)
+    }
+
+    /// Returns true if the cached stores and `p_aux` both match the replica.
+    pub fn is_healthy(&self) -> bool {
+        self.p_aux_matches() && self.roots.iter().all(TreeRLastRoot::matches)
+    }
+}
+
+/// A tree-c leaf compared against the hash of the label column it commits to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnSample {
+    pub node: u64,
+    pub matches: bool,
+}
+
+/// Structured result of auditing a sealed sector's replica and cache directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorIntegrityReport {
+    /// Whether `p_aux` could be read and decoded.
+    pub p_aux: IntegrityCheck,
+    /// Whether `t_aux` could be read and decoded.
+    pub t_aux: IntegrityCheck,
+    pub cache_files: Vec<CacheFileStatus>,
+    pub tree_r_last_roots: Vec<TreeRLastRoot>,
+    /// comm_r_last recomputed from the replica against the one stored in `p_aux`.
+    pub comm_r_last: IntegrityCheck,
+    /// H(comm_c, comm_r_last) from `p_aux` against the expected comm_r.
+    pub comm_r: IntegrityCheck,
+    /// Root of the cached tree-d against the expected comm_d.
+    pub comm_d: IntegrityCheck,
+    /// Sampled tree-c leaves against the label columns.
+    pub columns: IntegrityCheck,
+    pub column_samples: Vec<ColumnSample>,
+}
+
+impl SectorIntegrityReport {
+    /// Returns true if no check failed and every required cache file is valid, i.e. the
+    /// sector can still be proven from this cache.
+    pub fn is_healthy(&self) -> bool {
+        let checks = [
+            &self.p_aux,
+            &self.t_aux,
+            &self.comm_r_last,
+            &self.comm_r,
+            &self.comm_d,
+            &self.columns,
+        ];
+
+        checks.iter().all(|check| !check.is_failed())
+            && self
+                .cache_files
+                .iter()
+                .filter(|file| file.required)
+                .all(CacheFileStatus::is_valid)
+            && self.tree_r_last_roots.iter().all(TreeRLastRoot::matches)
+    }
+}
+
+/// Layout of an unsealed sector file checked by `verify_unsealed_sector`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnsealedFormat {
+    /// The bit padded sector of the sector size, as written by `add_piece`.
+    Padded,
+    /// Raw bytes, as written by `unseal_range`. Missing trailing bytes are taken to be zero.
+    Unpadded,
+}
+
+/// Result of checking an unsealed sector file with `verify_unsealed_sector`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnsealedSectorReport {
+    /// comm_d recomputed from the unsealed file.
+    pub comm_d: Commitment,
+    /// Whether the recomputed comm_d is the expected one.
+    pub comm_d_matches: bool,
+    /// Whether the piece infos result in the expected comm_d. If they do not, the piece list
+    /// does not describe the sector, whatever the state of the file.
+    pub piece_infos_match: bool,
+    /// Indexes of the pieces whose data no longer matches their commitment.
+    pub corrupted_pieces: Vec<usize>,
+}
+
+impl UnsealedSectorReport {
+    pub fn is_healthy(&self) -> bool {
+        self.comm_d_matches && self.piece_infos_match && self.corrupted_pieces.is_empty()
+    }
+}
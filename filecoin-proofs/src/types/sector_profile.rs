@@ -0,0 +1,432 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::util::NODE_SIZE;
+
+use crate::constants::{
+    sector_shape, SectorShape, EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES, LAYERS,
+    POREP_MINIMUM_CHALLENGES, POREP_PARTITIONS, PUBLISHED_SECTOR_SIZES, SECTOR_SHAPES,
+    WINDOW_POST_SECTOR_COUNT,
+};
+
+/// Everything the proofs need to know about a sector size, as kept in the per-size tables of
+/// `constants`.
+///
+/// The built-in sector sizes are registered from the start; further sizes can be declared in a
+/// TOML or JSON file and registered at runtime with `register_sector_profiles`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorProfile {
+    pub sector_size: u64,
+    pub shape: SectorShape,
+    pub layers: usize,
+    pub porep_partitions: u8,
+    pub porep_minimum_challenges: u64,
+    pub empty_sector_update_minimum_challenges: u64,
+    pub window_post_sector_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectorProfiles {
+    sector_profiles: Vec<SectorProfile>,
+}
+
+impl SectorProfile {
+    /// Returns the profile registered for `sector_size`.
+    pub fn for_sector_size(sector_size: u64) -> Result<Self> {
+        let shape = sector_shape(sector_size)?;
+        let unknown = || format!("incomplete profile for sector size {}", sector_size);
+
+        Ok(SectorProfile {
+            sector_size,
+            shape,
+            layers: *LAYERS
+                .read()
+                .expect("LAYERS poisoned")
+                .get(&sector_size)
+                .with_context(unknown)?,
+            porep_partitions: *POREP_PARTITIONS
+                .read()
+                .expect("POREP_PARTITIONS poisoned")
+                .get(&sector_size)
+                .with_context(unknown)?,
+            porep_minimum_challenges: *POREP_MINIMUM_CHALLENGES
+                .read()
+                .expect("POREP_MINIMUM_CHALLENGES poisoned")
+                .get(&sector_size)
+                .with_context(unknown)?,
+            empty_sector_update_minimum_challenges: *EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES
+                .read()
+                .expect("EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES poisoned")
+                .get(&sector_size)
+                .with_context(unknown)?,
+            window_post_sector_count: *WINDOW_POST_SECTOR_COUNT
+                .read()
+                .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+                .get(&sector_size)
+                .with_context(unknown)?,
+        })
+    }
+
+    /// Checks that the sector size can be built with the declared tree shape and that none of
+    /// the counts is zero.
+    pub fn validate(&self) -> Result<()> {
+        let sector_size = self.sector_size;
+        ensure!(
+            sector_size.is_power_of_two() && sector_size >= NODE_SIZE as u64,
+            "sector size {} is not a power of two number of nodes",
+            sector_size
+        );
+
+        // The base trees are oct trees, the sub and top layers split the nodes among them.
+        let (sub_arity, top_arity) = self.shape.sub_top_arities();
+        let base_trees = (sub_arity.max(1) * top_arity.max(1)) as u64;
+        let nodes = sector_size / NODE_SIZE as u64;
+        ensure!(
+            nodes % base_trees == 0,
+            "sector size {} is too small for shape {:?}",
+            sector_size,
+            self.shape
+        );
+        let base_leaves = nodes / base_trees;
+        ensure!(
+            base_leaves >= 8 && base_leaves.trailing_zeros() % 3 == 0,
+            "sector size {} does not fit shape {:?}: {} leaves per base tree is not a power of 8",
+            sector_size,
+            self.shape,
+            base_leaves
+        );
+
+        ensure!(self.layers > 0, "layers must not be zero");
+        ensure!(
+            self.porep_partitions > 0,
+            "porep partitions must not be zero"
+        );
+        ensure!(
+            self.porep_minimum_challenges > 0,
+            "porep minimum challenges must not be zero"
+        );
+        ensure!(
+            self.empty_sector_update_minimum_challenges > 0,
+            "empty sector update minimum challenges must not be zero"
+        );
+        ensure!(
+            self.window_post_sector_count > 0,
+            "window post sector count must not be zero"
+        );
+
+        Ok(())
+    }
+
+    /// Fails if a different profile is already registered for this sector size. Returns
+    /// whether this very profile is registered.
+    pub fn check_conflicts(&self) -> Result<bool> {
+        if sector_shape(self.sector_size).is_err() {
+            return Ok(false);
+        }
+
+        let existing = Self::for_sector_size(self.sector_size)?;
+        ensure!(
+            &existing == self,
+            "sector size {} is already registered with a different profile",
+            self.sector_size
+        );
+
+        Ok(true)
+    }
+
+    /// Registers the profile, making its sector size usable with the rest of the API, and adds
+    /// the size to `PUBLISHED_SECTOR_SIZES`.
+    ///
+    /// Registering a profile identical to an existing one is a no-op, registering a different
+    /// profile for a known sector size is an error.
+    pub fn register(&self) -> Result<()> {
+        self.validate()
+            .with_context(|| format!("invalid profile for sector size {}", self.sector_size))?;
+
+        // Hold the shape lock throughout, so that concurrent registrations of the same size
+        // cannot interleave.
+        let mut shapes = SECTOR_SHAPES.write().expect("SECTOR_SHAPES poisoned");
+        if shapes.contains_key(&self.sector_size) {
+            drop(shapes);
+            self.check_conflicts()?;
+            return Ok(());
+        }
+
+        let sector_size = self.sector_size;
+        LAYERS
+            .write()
+            .expect("LAYERS poisoned")
+            .insert(sector_size, self.layers);
+        POREP_PARTITIONS
+            .write()
+            .expect("POREP_PARTITIONS poisoned")
+            .insert(sector_size, self.porep_partitions);
+        POREP_MINIMUM_CHALLENGES
+            .write()
+            .expect("POREP_MINIMUM_CHALLENGES poisoned")
+            .insert(sector_size, self.porep_minimum_challenges);
+        EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES
+            .write()
+            .expect("EMPTY_SECTOR_UPDATE_MINIMUM_CHALLENGES poisoned")
+            .insert(sector_size, self.empty_sector_update_minimum_challenges);
+        WINDOW_POST_SECTOR_COUNT
+            .write()
+            .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+            .insert(sector_size, self.window_post_sector_count);
+        let mut published = PUBLISHED_SECTOR_SIZES
+            .write()
+            .expect("PUBLISHED_SECTOR_SIZES poisoned");
+        if !published.contains(&sector_size) {
+            published.push(sector_size);
+        }
+        drop(published);
+        // Last, as the shape is what makes the size known.
+        shapes.insert(sector_size, self.shape);
+
+        Ok(())
+    }
+}
+
+/// Parses a list of sector profiles under a `sector_profiles` key, from a `.toml` or `.json`
+/// file.
+pub fn load_sector_profiles<P: AsRef<Path>>(path: P) -> Result<Vec<SectorProfile>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("could not read sector profiles {:?}", path))?;
+
+    let profiles: SectorProfiles = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&contents)
+            .with_context(|| format!("invalid sector profiles {:?}", path))?,
+        Some("json") => serde_json::from_str(&contents)
+            .with_context(|| format!("invalid sector profiles {:?}", path))?,
+        _ => bail!("sector profiles {:?} must be a .toml or .json file", path),
+    };
+
+    Ok(profiles.sector_profiles)
+}
+
+/// Loads the sector profiles in `path` and registers them all, returning them.
+///
+/// All profiles are validated, and checked against the registered ones and each other, before
+/// any is registered.
+pub fn register_sector_profiles<P: AsRef<Path>>(path: P) -> Result<Vec<SectorProfile>> {
+    let profiles = load_sector_profiles(path)?;
+    for (i, profile) in profiles.iter().enumerate() {
+        profile
+            .validate()
+            .with_context(|| format!("invalid profile for sector size {}", profile.sector_size))?;
+        profile.check_conflicts()?;
+        ensure!(
+            profiles[..i]
+                .iter()
+                .all(|other| other.sector_size != profile.sector_size || other == profile),
+            "sector size {} is declared twice with different profiles",
+            profile.sector_size
+        );
+    }
+    for profile in &profiles {
+        profile.register()?;
+    }
+
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use generic_array::typenum::Unsigned;
+    use tempfile::Builder;
+
+    use crate::{
+        constants::{SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB},
+        dispatch_shape,
+        types::MerkleTreeTrait,
+    };
+
+    // Sizes no built-in profile uses, distinct per test as the registry is global.
+    const SECTOR_SIZE_128_KIB: u64 = 1 << 17;
+    const SECTOR_SIZE_1_MIB: u64 = 1 << 20;
+    const SECTOR_SIZE_2_MIB: u64 = 1 << 21;
+    const SECTOR_SIZE_4_MIB: u64 = 1 << 22;
+
+    fn profile(sector_size: u64, shape: SectorShape) -> SectorProfile {
+        SectorProfile {
+            sector_size,
+            shape,
+            layers: 2,
+            porep_partitions: 1,
+            porep_minimum_challenges: 2,
+            empty_sector_update_minimum_challenges: 2,
+            window_post_sector_count: 8,
+        }
+    }
+
+    fn arities<Tree: MerkleTreeTrait>() -> (usize, usize, usize) {
+        (
+            Tree::Arity::to_usize(),
+            Tree::SubTreeArity::to_usize(),
+            Tree::TopTreeArity::to_usize(),
+        )
+    }
+
+    fn write_profiles(suffix: &str, contents: &str) -> tempfile::NamedTempFile {
+        let mut file = Builder::new()
+            .suffix(suffix)
+            .tempfile()
+            .expect("tempfile failure");
+        file.write_all(contents.as_bytes())
+            .expect("failed to write profiles");
+        file
+    }
+
+    #[test]
+    fn test_builtin_sector_profiles() {
+        let profile = SectorProfile::for_sector_size(SECTOR_SIZE_32_GIB).expect("unknown size");
+        assert_eq!(profile.shape, SectorShape::Sub8);
+        assert_eq!(profile.layers, 11);
+        assert_eq!(profile.porep_partitions, 10);
+        assert!(profile.validate().is_ok());
+
+        // Re-registering a built-in profile as is changes nothing.
+        assert!(profile.register().is_ok());
+        assert!(SectorProfile::for_sector_size(SECTOR_SIZE_4_MIB).is_err());
+    }
+
+    #[test]
+    fn test_register_sector_profile() {
+        assert!(dispatch_shape!(SECTOR_SIZE_128_KIB, arities).is_err());
+
+        // 4096 nodes, an oct tree of height 4.
+        profile(SECTOR_SIZE_128_KIB, SectorShape::Base)
+            .register()
+            .expect("failed to register");
+        assert_eq!(
+            dispatch_shape!(SECTOR_SIZE_128_KIB, arities).expect("unknown size"),
+            (8, 0, 0)
+        );
+        assert_eq!(
+            SectorProfile::for_sector_size(SECTOR_SIZE_128_KIB).expect("unknown size"),
+            profile(SECTOR_SIZE_128_KIB, SectorShape::Base)
+        );
+        assert!(PUBLISHED_SECTOR_SIZES
+            .read()
+            .expect("PUBLISHED_SECTOR_SIZES poisoned")
+            .contains(&SECTOR_SIZE_128_KIB));
+
+        // Same profile again is fine, a different one is not.
+        assert!(profile(SECTOR_SIZE_128_KIB, SectorShape::Base)
+            .register()
+            .is_ok());
+        let mut changed = profile(SECTOR_SIZE_128_KIB, SectorShape::Base);
+        changed.layers = 3;
+        assert!(changed.register().is_err());
+        assert!(profile(SECTOR_SIZE_2_KIB, SectorShape::Sub2)
+            .register()
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_sector_profiles() {
+        // 131072 nodes, only splitting them in four leaves a power of 8 per base tree.
+        assert!(profile(SECTOR_SIZE_4_MIB, SectorShape::Base)
+            .validate()
+            .is_err());
+        assert!(profile(SECTOR_SIZE_4_MIB, SectorShape::Sub2)
+            .validate()
+            .is_err());
+        assert!(profile(SECTOR_SIZE_4_MIB, SectorShape::Sub4)
+            .validate()
+            .is_ok());
+        assert!(profile(3 << 20, SectorShape::Sub8).validate().is_err());
+        assert!(profile(64, SectorShape::Top2).validate().is_err());
+
+        let mut no_layers = profile(SECTOR_SIZE_4_MIB, SectorShape::Sub4);
+        no_layers.layers = 0;
+        assert!(no_layers.register().is_err());
+        assert!(sector_shape(SECTOR_SIZE_4_MIB).is_err());
+    }
+
+    #[test]
+    fn test_load_sector_profiles() {
+        let toml = write_profiles(
+            ".toml",
+            r#"
+            [[sector_profiles]]
+            sector_size = 2097152
+            shape = "sub2"
+            layers = 2
+            porep_partitions = 1
+            porep_minimum_challenges = 2
+            empty_sector_update_minimum_challenges = 2
+            window_post_sector_count = 8
+            "#,
+        );
+        let profiles = load_sector_profiles(toml.path()).expect("failed to load toml");
+        assert_eq!(
+            profiles,
+            vec![profile(SECTOR_SIZE_2_MIB, SectorShape::Sub2)]
+        );
+
+        let json = write_profiles(
+            ".json",
+            &serde_json::json!({ "sector_profiles": profiles }).to_string(),
+        );
+        assert_eq!(
+            load_sector_profiles(json.path()).expect("failed to load json"),
+            profiles
+        );
+
+        let yaml = write_profiles(".yaml", "sector_profiles: []");
+        assert!(load_sector_profiles(yaml.path()).is_err());
+
+        let incomplete = write_profiles(".toml", "[[sector_profiles]]\nsector_size = 2097152\n");
+        assert!(load_sector_profiles(incomplete.path()).is_err());
+
+        let invalid = write_profiles(
+            ".json",
+            &serde_json::json!({ "sector_profiles": profiles })
+                .to_string()
+                .replace("sub2", "sub3"),
+        );
+        assert!(load_sector_profiles(invalid.path()).is_err());
+
+        register_sector_profiles(toml.path()).expect("failed to register");
+        assert_eq!(
+            dispatch_shape!(SECTOR_SIZE_2_MIB, arities).expect("unknown size"),
+            (8, 2, 0)
+        );
+    }
+
+    #[test]
+    fn test_register_sector_profiles_conflicts() {
+        let new_profile = profile(SECTOR_SIZE_1_MIB, SectorShape::Base);
+        let mut conflicting = SectorProfile::for_sector_size(SECTOR_SIZE_2_KIB).expect("unknown");
+        conflicting.layers += 1;
+
+        // The conflict with the built-in 2KiB profile is found before the new size is registered.
+        let file = write_profiles(
+            ".json",
+            &serde_json::json!({ "sector_profiles": [&new_profile, &conflicting] }).to_string(),
+        );
+        assert!(register_sector_profiles(file.path()).is_err());
+        assert!(sector_shape(SECTOR_SIZE_1_MIB).is_err());
+
+        let mut changed = new_profile.clone();
+        changed.porep_partitions = 2;
+        let file = write_profiles(
+            ".json",
+            &serde_json::json!({ "sector_profiles": [&new_profile, &changed] }).to_string(),
+        );
+        assert!(register_sector_profiles(file.path()).is_err());
+        assert!(sector_shape(SECTOR_SIZE_1_MIB).is_err());
+        assert!(!PUBLISHED_SECTOR_SIZES
+            .read()
+            .expect("PUBLISHED_SECTOR_SIZES poisoned")
+            .contains(&SECTOR_SIZE_1_MIB));
+    }
+}
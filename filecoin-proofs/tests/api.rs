@@ -1,8 +1,8 @@
 use std::collections::BTreeMap;
-use std::fs::{read_dir, remove_file};
+use std::fs::{create_dir, metadata, read_dir, remove_file, rename, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Once;
+use std::sync::{Arc, Mutex, Once};
 
 use anyhow::{ensure, Result};
 use bellperson::groth16;
@@ -11,26 +11,48 @@ use blstrs::{Bls12, Scalar as Fr};
 use ff::Field;
 use filecoin_hashers::Hasher;
 use filecoin_proofs::{
-    add_piece, aggregate_seal_commit_proofs, clear_cache, compute_comm_d, fauxrep_aux,
-    generate_fallback_sector_challenges, generate_piece_commitment, generate_single_vanilla_proof,
-    generate_single_window_post_with_vanilla, generate_window_post,
+    add_piece, aggregate_seal_commit_proofs, check_sector_integrity, check_tree_r_last,
+    clear_cache, commitment_from_fr, compact_seal_commit_phase1_output, compute_comm_d,
+    decode_from, encode_into, expand_seal_commit_phase1_output, fauxrep_aux, fauxrep_with_pieces,
+    generate_fallback_sector_challenges, generate_piece_commitment, generate_piece_inclusion_proof,
+    generate_single_vanilla_proof, generate_single_window_post_with_vanilla, generate_window_post,
     generate_window_post_with_vanilla, generate_winning_post,
     generate_winning_post_sector_challenge, generate_winning_post_with_vanilla,
-    get_num_partition_for_fallback_post, get_seal_inputs, merge_window_post_partition_proofs,
-    seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
-    unseal_range, validate_cache_for_commit, validate_cache_for_precommit_phase2,
-    verify_aggregate_seal_commit_proofs, verify_seal, verify_window_post, verify_winning_post,
-    Commitment, DefaultTreeDomain, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-    PoRepProofPartitions, PoStConfig, PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo,
-    SealCommitOutput, SealPreCommitOutput, SealPreCommitPhase1Output, SectorShape16KiB,
-    SectorShape2KiB, SectorShape32KiB, SectorShape4KiB, SectorSize, UnpaddedByteIndex,
-    UnpaddedBytesAmount, POREP_PARTITIONS, SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB,
-    SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB, WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT,
-    WINNING_POST_CHALLENGE_COUNT, WINNING_POST_SECTOR_COUNT,
+    get_num_partition_for_fallback_post, get_seal_inputs, is_cancelled, materialized_bytes,
+    merge_seal_commit_partition_proofs, merge_window_post_partition_proofs,
+    pieces::get_piece_start_byte,
+    prove_replica_update, read_piece_from_unsealed,
+    registry::{self, RegisteredSealProof},
+    resume_pre_commit_phase1, resume_pre_commit_phase1_with_control, seal_commit_phase1,
+    seal_commit_phase1_enveloped, seal_commit_phase2, seal_commit_phase2_partition,
+    seal_pre_commit_phase1, seal_pre_commit_phase1_enveloped, seal_pre_commit_phase1_with_control,
+    seal_pre_commit_phase2, seal_pre_commit_phase2_enveloped, seal_pre_commit_phase2_with_control,
+    unseal_piece, unseal_range, unseal_range_mapped, validate_cache_for_commit,
+    validate_cache_for_precommit_phase2, verify_aggregate_seal_commit_proofs,
+    verify_piece_inclusion_proof, verify_replica_update, verify_seal, verify_unsealed_sector,
+    verify_window_post, verify_winning_post, Commitment, CompactSealCommitPhase1Output, DataTree,
+    DefaultTreeDomain, FauxPiece, IntegrityCheck, MerkleTreeTrait, PaddedBytesAmount,
+    PartitionSnarkProof, PieceInclusionProof, PieceInfo, PoRepConfig, PoRepProofPartitions,
+    PoStConfig, PoStType, PrivateReplicaInfo, Progress, ProverId, PublicReplicaInfo,
+    SealCommitOutput, SealCommitPhase1Output, SealControl, SealEnvelope, SealPreCommitOutput,
+    SealPreCommitPhase1Output, SealPreCommitPhase1Progress, SectorShape16KiB, SectorShape2KiB,
+    SectorShape32KiB, SectorShape4KiB, SectorSize, SparseFileReader, SparseFileWriter, StoreConfig,
+    UnpaddedByteIndex, UnpaddedBytesAmount, UnsealedFormat, POREP_PARTITIONS, SECTOR_SIZE_16_KIB,
+    SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB,
+    WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT,
+    WINNING_POST_SECTOR_COUNT,
 };
+use fr32::write_unpadded;
 use rand::{random, Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
-use storage_proofs_core::{api_version::ApiVersion, is_legacy_porep_id, sector::SectorId};
+use storage_proofs_core::{
+    api_version::ApiVersion,
+    cache_key::CacheKey,
+    is_legacy_porep_id,
+    merkle::create_base_merkle_tree,
+    sector::SectorId,
+    util::{default_rows_to_discard, NODE_SIZE},
+};
 use tempfile::{tempdir, NamedTempFile, TempDir};
 
 // Use a fixed PoRep ID, so that the parents cache can be re-used between some tests.
@@ -429,84 +451,1219 @@ fn aggregate_proofs<Tree: 'static + MerkleTreeTrait>(
     )
 }
 
-fn get_layer_file_paths(cache_dir: &tempfile::TempDir) -> Vec<PathBuf> {
-    let mut list: Vec<_> = read_dir(&cache_dir)
-        .unwrap_or_else(|_| panic!("failed to read directory {:?}", cache_dir))
-        .filter_map(|entry| {
-            let cur = entry.expect("reading directory failed");
-            let entry_path = cur.path();
-            let entry_str = entry_path.to_str().expect("failed to get string from path");
-            if entry_str.contains("data-layer") {
-                Some(entry_path.clone())
-            } else {
-                None
-            }
-        })
+#[test]
+fn test_empty_sector_update_skip_proofs_2kib_base_8() -> Result<()> {
+    run_empty_sector_update::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, true)
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_2kib_base_8() -> Result<()> {
+    run_empty_sector_update::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, false)
+}
+
+fn run_empty_sector_update<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    skip_proofs: bool,
+) -> Result<()> {
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let prover_id = [9u8; 32];
+    let porep_id = ARBITRARY_POREP_ID_V1_1_0;
+    let config = porep_config(sector_size, porep_id, ApiVersion::V1_1_0);
+
+    // The committed capacity sector, whose replica becomes the sector key.
+    let (_, sector_key_file, comm_r_old, sector_key_cache_dir) = create_seal::<_, Tree>(
+        &mut rng,
+        sector_size,
+        prover_id,
+        true,
+        &porep_id,
+        ApiVersion::V1_1_0,
+    )?;
+
+    let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece =
+        UnpaddedBytesAmount::from(PaddedBytesAmount(config.sector_size.into()));
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let mut staged_data_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_data_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let new_replica_file = NamedTempFile::new()?;
+    let new_cache_dir = tempdir()?;
+    let encoded = encode_into::<_, Tree>(
+        config,
+        new_replica_file.path(),
+        new_cache_dir.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        staged_data_file.path(),
+        &piece_infos,
+    )?;
+    assert_eq!(
+        encoded.comm_d_new,
+        compute_comm_d(config.sector_size, &piece_infos)?
+    );
+    assert_ne!(encoded.comm_r_new, comm_r_old);
+
+    // Decoding with the sector key recovers the staged data.
+    let out_data_file = NamedTempFile::new()?;
+    decode_from::<_, Tree>(
+        config,
+        out_data_file.path(),
+        new_replica_file.path(),
+        sector_key_file.path(),
+    )?;
+    let mut staged_bytes = Vec::new();
+    staged_data_file.reopen()?.read_to_end(&mut staged_bytes)?;
+    let mut decoded_bytes = Vec::new();
+    out_data_file.reopen()?.read_to_end(&mut decoded_bytes)?;
+    assert_eq!(decoded_bytes, staged_bytes);
+
+    if skip_proofs {
+        return Ok(());
+    }
+
+    let proof = prove_replica_update::<_, Tree>(
+        config,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        new_replica_file.path(),
+        new_cache_dir.path(),
+    )?;
+    assert!(verify_replica_update::<Tree>(
+        config,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        &proof,
+    )?);
+
+    // The proof does not verify for another data commitment.
+    let mut wrong_comm_d = encoded.comm_d_new;
+    wrong_comm_d[0] ^= 1;
+    assert!(!verify_replica_update::<Tree>(
+        config,
+        comm_r_old,
+        encoded.comm_r_new,
+        wrong_comm_d,
+        &proof,
+    )?);
+
+    Ok(())
+}
+
+#[test]
+fn test_unseal_range_skip_proofs_2kib_base_8() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let prover_id = [11u8; 32];
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (mut piece_file, piece_bytes) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let check_ranges = || -> Result<()> {
+        for &(offset, num_bytes) in &[(0, 254), (3, 1000), (508, 508), (1778, 254)] {
+            let mut unseal_file = NamedTempFile::new()?;
+            let written = unseal_range::<_, _, _, SectorShape2KiB>(
+                config,
+                cache_dir.path(),
+                sealed_sector_file.reopen()?,
+                unseal_file.reopen()?,
+                prover_id,
+                sector_id,
+                pre_commit_output.comm_d,
+                ticket,
+                UnpaddedByteIndex(offset),
+                UnpaddedBytesAmount(num_bytes),
+            )?;
+            assert_eq!(written, UnpaddedBytesAmount(num_bytes));
+
+            let mut contents = vec![];
+            unseal_file.read_to_end(&mut contents)?;
+            let range = offset as usize..(offset + num_bytes) as usize;
+            assert_eq!(&piece_bytes[range.clone()], &contents[..]);
+
+            let mut mapped_contents = vec![];
+            let written = unseal_range_mapped::<_, _, SectorShape2KiB>(
+                config,
+                cache_dir.path(),
+                sealed_sector_file.path().to_path_buf(),
+                &mut mapped_contents,
+                prover_id,
+                sector_id,
+                pre_commit_output.comm_d,
+                ticket,
+                UnpaddedByteIndex(offset),
+                UnpaddedBytesAmount(num_bytes),
+            )?;
+            assert_eq!(written, UnpaddedBytesAmount(num_bytes));
+            assert_eq!(&piece_bytes[range], &mapped_contents[..]);
+        }
+        Ok(())
+    };
+
+    // Decoded with the last layer labels left in the cache by sealing, which are only hashed
+    // once, after which the manifest records their fingerprint.
+    check_ranges()?;
+    let progress =
+        SealPreCommitPhase1Progress::load(cache_dir.path())?.expect("missing progress manifest");
+    let last = progress.layers.last().expect("no layers in the manifest");
+    assert!(last.fingerprint.is_some(), "fingerprint was not recorded");
+
+    // Labels which no longer match the digest in the progress manifest are regenerated.
+    let last_layer_path = get_layer_file_paths(&cache_dir)
+        .pop()
+        .expect("no layers in the cache dir");
+    let mut last_layer = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&last_layer_path)?;
+    let mut label = [0u8; 32];
+    last_layer.seek(SeekFrom::Start(32))?;
+    last_layer.read_exact(&mut label)?;
+    label[0] ^= 1;
+    last_layer.seek(SeekFrom::Start(32))?;
+    last_layer.write_all(&label)?;
+    drop(last_layer);
+    check_ranges()?;
+
+    // Decoded with regenerated labels.
+    clear_cache::<SectorShape2KiB>(cache_dir.path())?;
+    check_ranges()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_unseal_piece_2kib_base_8() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let config = porep_config(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+    let prover_id = [13u8; 32];
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    let mut piece_infos = Vec::new();
+    let piece_bytes = generate_piece_fixtures(&mut rng);
+    for bytes in &piece_bytes {
+        let piece_lengths: Vec<UnpaddedBytesAmount> = piece_infos
+            .iter()
+            .map(|piece: &PieceInfo| piece.size)
+            .collect();
+        let (piece_info, _) = add_piece(
+            &bytes[..],
+            &mut staged_sector_file,
+            UnpaddedBytesAmount(bytes.len() as u64),
+            &piece_lengths,
+        )?;
+        piece_infos.push(piece_info);
+    }
+    staged_sector_file.as_file().set_len(SECTOR_SIZE_2_KIB)?;
+
+    let cache_dir = tempdir()?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let missing_unsealed_path = cache_dir.path().join("missing-unsealed");
+    for (piece_info, bytes) in piece_infos.iter().zip(&piece_bytes) {
+        let mut from_unsealed = Vec::new();
+        let written = read_piece_from_unsealed(
+            staged_sector_file.path(),
+            &piece_infos,
+            &piece_info.commitment,
+            &mut from_unsealed,
+            true,
+        )?;
+        assert_eq!(written, piece_info.size);
+        assert_eq!(&from_unsealed, bytes);
+
+        for unsealed_path in &[staged_sector_file.path(), missing_unsealed_path.as_path()] {
+            let mut unsealed = Vec::new();
+            unseal_piece::<_, _, _, SectorShape2KiB>(
+                config,
+                cache_dir.path(),
+                sealed_sector_file.path(),
+                unsealed_path,
+                &mut unsealed,
+                prover_id,
+                sector_id,
+                pre_commit_output.comm_d,
+                ticket,
+                &piece_infos,
+                &piece_info.commitment,
+            )?;
+            assert_eq!(&unsealed, bytes);
+        }
+    }
+
+    // A corrupted unsealed copy is only noticed when the commitment is checked.
+    let mut staged = std::fs::read(staged_sector_file.path())?;
+    staged[600] ^= 1;
+    std::fs::write(staged_sector_file.path(), &staged)?;
+    let comm_p = &piece_infos[1].commitment;
+    assert!(read_piece_from_unsealed(
+        staged_sector_file.path(),
+        &piece_infos,
+        comm_p,
+        &mut Vec::new(),
+        true
+    )
+    .is_err());
+    read_piece_from_unsealed(
+        staged_sector_file.path(),
+        &piece_infos,
+        comm_p,
+        &mut Vec::new(),
+        false,
+    )?;
+
+    assert!(read_piece_from_unsealed(
+        staged_sector_file.path(),
+        &piece_infos,
+        &[1; 32],
+        &mut Vec::new(),
+        false
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_seal_envelope_moved_cache_2kib_base_8() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let prover_id = [14u8; 32];
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let seed = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (mut piece_file, _) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+    let sealed_sector_file = NamedTempFile::new()?;
+
+    // Every phase runs against another location of the cache directory.
+    let root_dir = tempdir()?;
+    let cache_paths: Vec<PathBuf> = (0..4)
+        .map(|i| root_dir.path().join(format!("cache-{}", i)))
         .collect();
-    list.sort();
-    list
+    create_dir(&cache_paths[0])?;
+
+    let envelope = seal_pre_commit_phase1_enveloped::<_, _, _, SectorShape2KiB>(
+        config,
+        &cache_paths[0],
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    let bytes = envelope.to_bytes()?;
+    rename(&cache_paths[0], &cache_paths[1])?;
+
+    let envelope = SealEnvelope::<SealPreCommitPhase1Output<SectorShape2KiB>>::from_bytes(&bytes)?;
+    let pre_commit_output = seal_pre_commit_phase2_enveloped(
+        config,
+        envelope,
+        &cache_paths[1],
+        sealed_sector_file.path(),
+    )?;
+    rename(&cache_paths[1], &cache_paths[2])?;
+
+    let envelope = seal_commit_phase1_enveloped::<_, SectorShape2KiB>(
+        config,
+        cache_paths[2].as_path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output.clone(),
+        &piece_infos,
+    )?;
+    let json = envelope.to_json()?;
+
+    let other_config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_0_0, ApiVersion::V1_0_0);
+    assert!(
+        SealEnvelope::<SealCommitPhase1Output<SectorShape2KiB>>::from_json(&json)?
+            .into_commit_phase1_output(other_config)
+            .is_err()
+    );
+    let phase1_output = SealEnvelope::<SealCommitPhase1Output<SectorShape2KiB>>::from_json(&json)?
+        .into_commit_phase1_output(config)?;
+    assert_eq!(phase1_output.comm_r, pre_commit_output.comm_r);
+    assert_eq!(phase1_output.comm_d, pre_commit_output.comm_d);
+
+    // t_aux is stored relative to the cache directory, so the moved cache is still cleared.
+    rename(&cache_paths[2], &cache_paths[3])?;
+    clear_cache::<SectorShape2KiB>(&cache_paths[3])?;
+    let layers_left = read_dir(&cache_paths[3])?
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map(|entry| entry.file_name().to_string_lossy().contains("data-layer"))
+                .unwrap_or(false)
+        })
+        .count();
+    assert_eq!(layers_left, 0);
+
+    Ok(())
 }
 
-fn clear_cache_dir_keep_data_layer(cache_dir: &TempDir) {
-    for entry in read_dir(&cache_dir).expect("faailed to read directory") {
-        let entry_path = entry.expect("failed get directory entry").path();
-        if entry_path.is_file() {
-            // delete everything except the data-layers
-            if !entry_path
-                .to_str()
-                .expect("failed to get string from path")
-                .contains("data-layer")
-            {
-                remove_file(entry_path).expect("failed to remove file")
-            }
-        }
+#[test]
+fn test_check_sector_integrity_2kib_base_8() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let prover_id = [12u8; 32];
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (mut piece_file, _) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let (_, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let SealPreCommitOutput { comm_r, comm_d } = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let check = |comm_r| {
+        check_sector_integrity::<SectorShape2KiB>(
+            config,
+            cache_dir.path(),
+            sealed_sector_file.path(),
+            comm_r,
+            comm_d,
+        )
+    };
+
+    let report = check(comm_r)?;
+    assert!(report.is_healthy(), "{:?}", report);
+    assert_eq!(report.comm_r_last, IntegrityCheck::Passed);
+    assert_eq!(report.comm_r, IntegrityCheck::Passed);
+    assert_eq!(report.comm_d, IntegrityCheck::Passed);
+    assert_eq!(report.columns, IntegrityCheck::Passed);
+    assert!(!report.column_samples.is_empty());
+    assert!(report.tree_r_last_roots.iter().all(|root| root.matches()));
+    assert!(report.cache_files.iter().all(|file| file.is_valid()));
+
+    let check_tree_r_last = || {
+        check_tree_r_last::<SectorShape2KiB>(
+            SectorSize(sector_size),
+            cache_dir.path(),
+            sealed_sector_file.path(),
+        )
+    };
+    let tree_r_last = check_tree_r_last()?;
+    assert!(tree_r_last.is_healthy(), "{:?}", tree_r_last);
+    assert_eq!(
+        tree_r_last.cached_comm_r_last,
+        Some(tree_r_last.comm_r_last)
+    );
+
+    let report = check(comm_d)?;
+    assert!(!report.is_healthy());
+    assert!(report.comm_r.is_failed());
+
+    // The sector stays provable once the temporary files are gone.
+    clear_cache::<SectorShape2KiB>(cache_dir.path())?;
+    let report = check(comm_r)?;
+    assert!(report.is_healthy(), "{:?}", report);
+    assert!(matches!(report.columns, IntegrityCheck::Skipped(_)));
+    assert!(report.column_samples.is_empty());
+
+    let tree_r_last_path = report
+        .cache_files
+        .iter()
+        .find(|file| file.id == "tree-r-last")
+        .map(|file| file.path.clone())
+        .expect("tree-r-last is always reported");
+    let tree_r_last_len = metadata(&tree_r_last_path)?.len();
+    OpenOptions::new()
+        .write(true)
+        .open(&tree_r_last_path)?
+        .set_len(tree_r_last_len - 32)?;
+    let report = check(comm_r)?;
+    assert!(!report.is_healthy());
+    assert!(report
+        .cache_files
+        .iter()
+        .any(|file| file.id == "tree-r-last" && !file.is_valid()));
+    assert_eq!(report.tree_r_last_roots[0].cached, None);
+    assert_eq!(report.comm_r_last, IntegrityCheck::Passed);
+
+    let tree_r_last = check_tree_r_last()?;
+    assert!(!tree_r_last.is_healthy());
+    assert!(tree_r_last.p_aux_matches());
+    assert_eq!(tree_r_last.cached_comm_r_last, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_seal_commit_phase2_partition_invalid_input_2kib_base_8() -> Result<()> {
+    let (config, phase1_output, prover_id, sector_id) =
+        run_seal_commit_phase1_2kib(ARBITRARY_POREP_ID_V1_1_0)?;
+
+    let partitions = usize::from(config.partitions);
+    assert!(
+        seal_commit_phase2_partition::<SectorShape2KiB>(config, &phase1_output, partitions)
+            .is_err()
+    );
+    assert!(merge_seal_commit_partition_proofs::<SectorShape2KiB>(
+        config,
+        &phase1_output,
+        prover_id,
+        sector_id,
+        Vec::new()
+    )
+    .is_err());
+    assert!(merge_seal_commit_partition_proofs::<SectorShape2KiB>(
+        config,
+        &phase1_output,
+        prover_id,
+        sector_id,
+        vec![PartitionSnarkProof(vec![0u8; 1]); partitions]
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_seal_commit_phase2_partition_2kib_base_8() -> Result<()> {
+    let (config, phase1_output, prover_id, sector_id) =
+        run_seal_commit_phase1_2kib(ARBITRARY_POREP_ID_V1_1_0)?;
+
+    let partition_proofs = (0..usize::from(config.partitions))
+        .map(|k| seal_commit_phase2_partition::<SectorShape2KiB>(config, &phase1_output, k))
+        .collect::<Result<Vec<_>>>()?;
+    let output = merge_seal_commit_partition_proofs::<SectorShape2KiB>(
+        config,
+        &phase1_output,
+        prover_id,
+        sector_id,
+        partition_proofs,
+    )?;
+
+    assert!(verify_seal::<SectorShape2KiB>(
+        config,
+        phase1_output.comm_r,
+        phase1_output.comm_d,
+        prover_id,
+        sector_id,
+        phase1_output.ticket,
+        phase1_output.seed,
+        &output.proof,
+    )?);
+
+    Ok(())
+}
+
+#[test]
+fn test_compact_seal_commit_phase1_output_2kib_base_8() -> Result<()> {
+    let (config, phase1_output, _, _) = run_seal_commit_phase1_2kib(ARBITRARY_POREP_ID_V1_1_0)?;
+
+    let compact_bytes = serialize(&compact_seal_commit_phase1_output(&phase1_output))?;
+    let full_bytes = serialize(&phase1_output)?;
+    assert!(compact_bytes.len() < full_bytes.len());
+
+    let compact: CompactSealCommitPhase1Output<SectorShape2KiB> =
+        bincode::deserialize(&compact_bytes)?;
+    let expanded = expand_seal_commit_phase1_output(config, compact)?;
+    assert_eq!(serialize(&expanded)?, full_bytes);
+
+    let mut tampered: CompactSealCommitPhase1Output<SectorShape2KiB> =
+        bincode::deserialize(&compact_bytes)?;
+    tampered.seed[0] ^= 1;
+    assert!(expand_seal_commit_phase1_output(config, tampered).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_registered_proof_configs() -> Result<()> {
+    let v1 = RegisteredSealProof::StackedDrg2KiBV1;
+    let v1_1 = RegisteredSealProof::StackedDrg2KiBV1_1;
+    assert_eq!(v1.porep_id()?[..8], 0u64.to_le_bytes());
+    assert_eq!(v1_1.porep_id()?[..8], 5u64.to_le_bytes());
+    assert_eq!(v1_1.porep_id()?[8..], [0u8; 24]);
+    assert_eq!(serde_json::to_string(&v1_1)?, "\"StackedDrg2KiBV1_1\"");
+
+    let config = RegisteredSealProof::StackedDrg32GiBV1_1.as_porep_config()?;
+    assert_eq!(u64::from(config.sector_size), SECTOR_SIZE_32_GIB);
+    assert_eq!(config.partitions.0, 10);
+    assert!(config.api_version == ApiVersion::V1_1_0);
+
+    let winning = RegisteredSealProof::StackedDrg32GiBV1.registered_winning_post_proof();
+    let post_config = winning.as_post_config()?;
+    assert_eq!(post_config.typ, PoStType::Winning);
+    assert_eq!(post_config.sector_count, WINNING_POST_SECTOR_COUNT);
+    assert_eq!(post_config.challenge_count, WINNING_POST_CHALLENGE_COUNT);
+
+    let window = RegisteredSealProof::StackedDrg32GiBV1.registered_window_post_proof();
+    let post_config = window.as_post_config()?;
+    assert_eq!(post_config.typ, PoStType::Window);
+    assert_eq!(post_config.sector_count, 2349);
+    assert_eq!(post_config.challenge_count, WINDOW_POST_CHALLENGE_COUNT);
+
+    // Sizes with a chain proof id map to its variant, the others carry size and version.
+    assert_eq!(
+        RegisteredSealProof::new(SectorSize(SECTOR_SIZE_2_KIB), ApiVersion::V1_1_0)?,
+        v1_1
+    );
+    let v1_1_4kib = RegisteredSealProof::new(SectorSize(SECTOR_SIZE_4_KIB), ApiVersion::V1_1_0)?;
+    assert_eq!(
+        v1_1_4kib,
+        RegisteredSealProof::StackedDrg {
+            sector_size: SECTOR_SIZE_4_KIB,
+            api_version: ApiVersion::V1_1_0,
+        }
+    );
+    assert_eq!(v1_1_4kib.registered_proof_id(), None);
+    assert!(!is_legacy_porep_id(v1_1_4kib.porep_id()?));
+    assert_ne!(
+        RegisteredSealProof::new(SectorSize(SECTOR_SIZE_4_KIB), ApiVersion::V1_0_0)?.porep_id()?,
+        v1_1_4kib.porep_id()?
+    );
+    let config = v1_1_4kib.as_porep_config()?;
+    assert_eq!(u64::from(config.sector_size), SECTOR_SIZE_4_KIB);
+    assert!(config.api_version == ApiVersion::V1_1_0);
+    let window_4kib = v1_1_4kib.registered_window_post_proof();
+    assert_eq!(window_4kib.typ(), PoStType::Window);
+    assert!(window_4kib.as_post_config()?.api_version == ApiVersion::V1_1_0);
+    assert_eq!(
+        serde_json::from_str::<RegisteredSealProof>(&serde_json::to_string(&v1_1_4kib)?)?,
+        v1_1_4kib
+    );
+
+    // StackedDrg must not be used for sizes with a chain proof id.
+    let chain_size = RegisteredSealProof::StackedDrg {
+        sector_size: SECTOR_SIZE_2_KIB,
+        api_version: ApiVersion::V1_1_0,
+    };
+    assert!(chain_size.porep_id().is_err());
+    assert!(chain_size.as_porep_config().is_err());
+    assert!(
+        serde_json::from_str::<RegisteredSealProof>(&serde_json::to_string(&chain_size)?).is_err()
+    );
+
+    assert!(RegisteredSealProof::new(SectorSize(3 << 10), ApiVersion::V1_1_0).is_err());
+    let unknown = RegisteredSealProof::StackedDrg {
+        sector_size: 3 << 10,
+        api_version: ApiVersion::V1_1_0,
+    };
+    assert!(unknown.as_porep_config().is_err());
+    assert!(unknown
+        .registered_window_post_proof()
+        .as_post_config()
+        .is_err());
+
+    // Each PoSt proof only runs its own kind of PoSt.
+    assert!(window
+        .generate_winning_post_sector_challenge(&[1; 32], 10, [2; 32])
+        .is_err());
+    assert!(winning
+        .generate_window_post(&[1; 32], &BTreeMap::new(), [2; 32])
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_registered_proof_lifecycle_2kib() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let registered_proof = RegisteredSealProof::StackedDrg2KiBV1_1;
+    let sector_size = u64::from(registered_proof.sector_size());
+
+    let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+    let ticket = rng.gen();
+    let seed = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (mut piece_file, piece_bytes) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
+
+    // The phase outputs are plain data, so that they can be handed over between processes.
+    let phase1_output = registered_proof.seal_pre_commit_phase1(
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    let phase1_output: registry::SealPreCommitPhase1Output =
+        bincode::deserialize(&serialize(&phase1_output)?)?;
+    assert!(RegisteredSealProof::StackedDrg2KiBV1
+        .seal_pre_commit_phase2(
+            phase1_output.clone(),
+            cache_dir.path(),
+            sealed_sector_file.path(),
+        )
+        .is_err());
+    let pre_commit_output = registered_proof.seal_pre_commit_phase2(
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    let comm_r = pre_commit_output.comm_r;
+    let comm_d = pre_commit_output.comm_d;
+
+    let phase1_output = registered_proof.seal_commit_phase1(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output,
+        &piece_infos,
+    )?;
+    let phase1_output: registry::SealCommitPhase1Output =
+        bincode::deserialize(&serialize(&phase1_output)?)?;
+    let commit_output = registered_proof.seal_commit_phase2(phase1_output, prover_id, sector_id)?;
+
+    assert!(registered_proof.verify_seal(
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &commit_output.proof,
+    )?);
+    assert!(!registered_proof.verify_seal(
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        ticket,
+        &commit_output.proof,
+    )?);
+
+    let mut unsealed = Vec::new();
+    registered_proof.unseal_range(
+        cache_dir.path(),
+        sealed_sector_file.as_file(),
+        &mut unsealed,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+    )?;
+    assert_eq!(&piece_bytes[508..508 + 508], &unsealed[..]);
+
+    registered_proof.clear_cache(cache_dir.path())?;
+
+    let random_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
+    let mut randomness = [0u8; 32];
+    randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+
+    let priv_replicas = vec![(
+        sector_id,
+        registry::PrivateReplicaInfo {
+            replica: sealed_sector_file.path().into(),
+            comm_r,
+            cache_dir: cache_dir.path().into(),
+        },
+    )];
+    let pub_replicas = vec![(sector_id, PublicReplicaInfo::new(comm_r)?)];
+
+    let winning = registered_proof.registered_winning_post_proof();
+    let challenged_sectors =
+        winning.generate_winning_post_sector_challenge(&randomness, 1, prover_id)?;
+    assert_eq!(challenged_sectors, vec![0]);
+    let proof = winning.generate_winning_post(&randomness, &priv_replicas, prover_id)?;
+    assert!(winning.verify_winning_post(&randomness, &pub_replicas, prover_id, &proof)?);
+
+    let window = registered_proof.registered_window_post_proof();
+    let priv_replicas: BTreeMap<_, _> = priv_replicas.into_iter().collect();
+    let pub_replicas: BTreeMap<_, _> = pub_replicas.into_iter().collect();
+    let proof = window.generate_window_post(&randomness, &priv_replicas, prover_id)?;
+    assert!(window.verify_window_post(&randomness, &pub_replicas, prover_id, &proof)?);
+
+    Ok(())
+}
+
+fn run_seal_commit_phase1_2kib(
+    porep_id: [u8; 32],
+) -> Result<(
+    PoRepConfig,
+    SealCommitPhase1Output<SectorShape2KiB>,
+    ProverId,
+    SectorId,
+)> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let config = porep_config(SECTOR_SIZE_2_KIB, porep_id, ApiVersion::V1_1_0);
+    let prover_id = [13u8; 32];
+    let ticket = rng.gen();
+    let seed = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (mut piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let (piece_infos, phase1_output) = run_seal_pre_commit_phase1::<SectorShape2KiB>(
+        config,
+        prover_id,
+        sector_id,
+        ticket,
+        &cache_dir,
+        &mut piece_file,
+        &sealed_sector_file,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+    let phase1_output = seal_commit_phase1::<_, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output,
+        &piece_infos,
+    )?;
+
+    Ok((config, phase1_output, prover_id, sector_id))
+}
+
+fn get_layer_file_paths(cache_dir: &tempfile::TempDir) -> Vec<PathBuf> {
+    let mut list: Vec<_> = read_dir(&cache_dir)
+        .unwrap_or_else(|_| panic!("failed to read directory {:?}", cache_dir))
+        .filter_map(|entry| {
+            let cur = entry.expect("reading directory failed");
+            let entry_path = cur.path();
+            let entry_str = entry_path.to_str().expect("failed to get string from path");
+            if entry_str.contains("data-layer") {
+                Some(entry_path.clone())
+            } else {
+                None
+            }
+        })
+        .collect();
+    list.sort();
+    list
+}
+
+fn clear_cache_dir_keep_data_layer(cache_dir: &TempDir) {
+    for entry in read_dir(&cache_dir).expect("faailed to read directory") {
+        let entry_path = entry.expect("failed get directory entry").path();
+        if entry_path.is_file() {
+            // delete everything except the data-layers
+            if !entry_path
+                .to_str()
+                .expect("failed to get string from path")
+                .contains("data-layer")
+            {
+                remove_file(entry_path).expect("failed to remove file")
+            }
+        }
+    }
+}
+
+#[test]
+fn test_resumable_seal_skip_proofs_v1() {
+    let porep_id_v1: u64 = 0; // This is a RegisteredSealProof value
+
+    let mut porep_id = [0u8; 32];
+    porep_id[..8].copy_from_slice(&porep_id_v1.to_le_bytes());
+    assert!(is_legacy_porep_id(porep_id));
+    run_resumable_seal::<SectorShape2KiB>(true, 0, &porep_id, ApiVersion::V1_0_0);
+    run_resumable_seal::<SectorShape2KiB>(true, 1, &porep_id, ApiVersion::V1_0_0);
+}
+
+#[test]
+fn test_resumable_seal_skip_proofs_v1_1() {
+    let porep_id_v1_1: u64 = 5; // This is a RegisteredSealProof value
+
+    let mut porep_id = [0u8; 32];
+    porep_id[..8].copy_from_slice(&porep_id_v1_1.to_le_bytes());
+    assert!(!is_legacy_porep_id(porep_id));
+    run_resumable_seal::<SectorShape2KiB>(true, 0, &porep_id, ApiVersion::V1_1_0);
+    run_resumable_seal::<SectorShape2KiB>(true, 1, &porep_id, ApiVersion::V1_1_0);
+}
+
+#[test]
+#[ignore]
+fn test_resumable_seal_v1() {
+    let porep_id_v1: u64 = 0; // This is a RegisteredSealProof value
+
+    let mut porep_id = [0u8; 32];
+    porep_id[..8].copy_from_slice(&porep_id_v1.to_le_bytes());
+    assert!(is_legacy_porep_id(porep_id));
+    run_resumable_seal::<SectorShape2KiB>(false, 0, &porep_id, ApiVersion::V1_0_0);
+    run_resumable_seal::<SectorShape2KiB>(false, 1, &porep_id, ApiVersion::V1_0_0);
+}
+
+#[test]
+#[ignore]
+fn test_resumable_seal_v1_1() {
+    let porep_id_v1_1: u64 = 5; // This is a RegisteredSealProof value
+
+    let mut porep_id = [0u8; 32];
+    porep_id[..8].copy_from_slice(&porep_id_v1_1.to_le_bytes());
+    assert!(!is_legacy_porep_id(porep_id));
+    run_resumable_seal::<SectorShape2KiB>(false, 0, &porep_id, ApiVersion::V1_1_0);
+    run_resumable_seal::<SectorShape2KiB>(false, 1, &porep_id, ApiVersion::V1_1_0);
+}
+
+#[test]
+fn test_resume_pre_commit_phase1_v1_1() -> Result<()> {
+    init_logger();
+
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
+
+    // Resuming requires a progress manifest.
+    assert!(resume_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )
+    .is_err());
+
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+
+    let progress =
+        SealPreCommitPhase1Progress::load(cache_dir.path())?.expect("missing progress manifest");
+    assert_eq!(progress.comm_d, Some(phase1_output.comm_d));
+    assert_eq!(progress.completed_layers(), 2);
+
+    let layers = get_layer_file_paths(&cache_dir);
+    assert_eq!(layers.len(), 2, "not all expected layers were created");
+    let layer_bytes = layers
+        .iter()
+        .map(std::fs::read)
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    // Simulate an interruption while the second layer was written, after the first layer
+    // got corrupted on disk.
+    let mut interrupted = progress.clone();
+    interrupted.layers.truncate(1);
+    interrupted.persist(cache_dir.path())?;
+    let mut corrupted = layer_bytes[0].clone();
+    corrupted[0] ^= 1;
+    std::fs::write(&layers[0], &corrupted)?;
+
+    let resumed_output = resume_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    assert_eq!(resumed_output.comm_d, phase1_output.comm_d);
+
+    let resumed_progress =
+        SealPreCommitPhase1Progress::load(cache_dir.path())?.expect("missing progress manifest");
+    assert_eq!(resumed_progress, progress);
+    for (layer, bytes) in layers.iter().zip(layer_bytes.iter()) {
+        assert_eq!(&std::fs::read(layer)?, bytes, "layer was not regenerated");
     }
+
+    Ok(())
 }
 
 #[test]
-fn test_resumable_seal_skip_proofs_v1() {
-    let porep_id_v1: u64 = 0; // This is a RegisteredSealProof value
+fn test_seal_pre_commit_with_control_v1_1() -> Result<()> {
+    init_logger();
 
-    let mut porep_id = [0u8; 32];
-    porep_id[..8].copy_from_slice(&porep_id_v1.to_le_bytes());
-    assert!(is_legacy_porep_id(porep_id));
-    run_resumable_seal::<SectorShape2KiB>(true, 0, &porep_id, ApiVersion::V1_0_0);
-    run_resumable_seal::<SectorShape2KiB>(true, 1, &porep_id, ApiVersion::V1_0_0);
-}
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(&mut rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
 
-#[test]
-fn test_resumable_seal_skip_proofs_v1_1() {
-    let porep_id_v1_1: u64 = 5; // This is a RegisteredSealProof value
+    let config = porep_config(sector_size, ARBITRARY_POREP_ID_V1_1_0, ApiVersion::V1_1_0);
+    let ticket = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
 
-    let mut porep_id = [0u8; 32];
-    porep_id[..8].copy_from_slice(&porep_id_v1_1.to_le_bytes());
-    assert!(!is_legacy_porep_id(porep_id));
-    run_resumable_seal::<SectorShape2KiB>(true, 0, &porep_id, ApiVersion::V1_1_0);
-    run_resumable_seal::<SectorShape2KiB>(true, 1, &porep_id, ApiVersion::V1_1_0);
-}
+    let (mut piece_file, _piece_bytes) = generate_piece_file(sector_size)?;
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
 
-#[test]
-#[ignore]
-fn test_resumable_seal_v1() {
-    let porep_id_v1: u64 = 0; // This is a RegisteredSealProof value
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir()?;
 
-    let mut porep_id = [0u8; 32];
-    porep_id[..8].copy_from_slice(&porep_id_v1.to_le_bytes());
-    assert!(is_legacy_porep_id(porep_id));
-    run_resumable_seal::<SectorShape2KiB>(false, 0, &porep_id, ApiVersion::V1_0_0);
-    run_resumable_seal::<SectorShape2KiB>(false, 1, &porep_id, ApiVersion::V1_0_0);
-}
+    // A job cancelled up front does not label anything.
+    let cancelled = SealControl::new();
+    cancelled.cancel();
+    let err = seal_pre_commit_phase1_with_control::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+        &cancelled,
+    )
+    .expect_err("cancelled sealing must fail");
+    assert!(is_cancelled(&err));
+    assert!(get_layer_file_paths(&cache_dir).is_empty());
+
+    // Cancel as soon as the first layer is complete.
+    let canceller = SealControl::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let control = {
+        let canceller = canceller.clone();
+        let events = events.clone();
+        canceller.clone().with_progress(move |progress| {
+            if let Progress::Labels {
+                layer: 1,
+                nodes,
+                total,
+                ..
+            } = progress
+            {
+                if nodes == total {
+                    canceller.cancel();
+                }
+            }
+            events.lock().expect("poisoned").push(progress);
+        })
+    };
+    let err = seal_pre_commit_phase1_with_control::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+        &control,
+    )
+    .expect_err("cancelled sealing must fail");
+    assert!(is_cancelled(&err));
+    assert_eq!(get_layer_file_paths(&cache_dir).len(), 1);
+    let progress =
+        SealPreCommitPhase1Progress::load(cache_dir.path())?.expect("missing progress manifest");
+    assert_eq!(progress.completed_layers(), 1);
+
+    let nodes = (sector_size / 32) as usize;
+    assert_eq!(
+        events.lock().expect("poisoned").last(),
+        Some(&Progress::Labels {
+            layer: 1,
+            layers: 2,
+            nodes,
+            total: nodes,
+        })
+    );
 
-#[test]
-#[ignore]
-fn test_resumable_seal_v1_1() {
-    let porep_id_v1_1: u64 = 5; // This is a RegisteredSealProof value
+    // The cancelled job can be resumed.
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let control = {
+        let events = events.clone();
+        SealControl::new().with_progress(move |progress| {
+            events.lock().expect("poisoned").push(progress);
+        })
+    };
+    let phase1_output = resume_pre_commit_phase1_with_control::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+        &control,
+    )?;
+    assert_eq!(get_layer_file_paths(&cache_dir).len(), 2);
+    assert_eq!(
+        events.lock().expect("poisoned").last(),
+        Some(&Progress::Labels {
+            layer: 2,
+            layers: 2,
+            nodes,
+            total: nodes,
+        })
+    );
 
-    let mut porep_id = [0u8; 32];
-    porep_id[..8].copy_from_slice(&porep_id_v1_1.to_le_bytes());
-    assert!(!is_legacy_porep_id(porep_id));
-    run_resumable_seal::<SectorShape2KiB>(false, 0, &porep_id, ApiVersion::V1_1_0);
-    run_resumable_seal::<SectorShape2KiB>(false, 1, &porep_id, ApiVersion::V1_1_0);
+    events.lock().expect("poisoned").clear();
+    seal_pre_commit_phase2_with_control::<_, _, SectorShape2KiB>(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        &control,
+    )?;
+    assert_eq!(
+        *events.lock().expect("poisoned"),
+        vec![
+            Progress::TreeC { built: 1, total: 1 },
+            Progress::TreeRLast { built: 1, total: 1 },
+        ]
+    );
+
+    Ok(())
 }
 
 /// Create a seal, delete a layer and resume
@@ -1351,6 +2508,15 @@ fn generate_piece_file(sector_size: u64) -> Result<(NamedTempFile, Vec<u8>)> {
     Ok((piece_file, piece_bytes))
 }
 
+/// Random data of pieces of 127, 508 and 254 bytes. Added to a 2KiB sector in this order, the
+/// 508 byte piece is aligned after the first one, leaving the last quarter unused.
+fn generate_piece_fixtures<R: Rng>(rng: &mut R) -> Vec<Vec<u8>> {
+    [127, 508, 254]
+        .iter()
+        .map(|size| (0..*size).map(|_| rng.gen()).collect())
+        .collect()
+}
+
 fn porep_config(sector_size: u64, porep_id: [u8; 32], api_version: ApiVersion) -> PoRepConfig {
     PoRepConfig {
         sector_size: SectorSize(sector_size),
@@ -1700,6 +2866,441 @@ fn create_fake_seal<R: rand::Rng, Tree: 'static + MerkleTreeTrait>(
     Ok((sector_id, sealed_sector_file, comm_r, cache_dir))
 }
 
+fn read_tree_d(cache_path: &Path) -> Result<Vec<u8>> {
+    let tree_d_path =
+        StoreConfig::data_path(&cache_path.to_path_buf(), &CacheKey::CommDTree.to_string());
+    Ok(std::fs::read(tree_d_path)?)
+}
+
+#[test]
+fn test_fauxrep_with_pieces_2kib() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let config = porep_config(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+
+    // A file piece, which is zero padded to 508 bytes, followed by a piece that is only known
+    // by its commitment.
+    let file_bytes: Vec<u8> = (0..500).map(|_| rng.gen()).collect();
+    let mut piece_file = NamedTempFile::new()?;
+    piece_file.write_all(&file_bytes)?;
+    let mut padded_file_bytes = file_bytes.clone();
+    padded_file_bytes.resize(508, 0);
+    let file_piece_info =
+        generate_piece_commitment(&padded_file_bytes[..], UnpaddedBytesAmount(508))?;
+    let info_bytes: Vec<u8> = (0..1016).map(|_| rng.gen()).collect();
+    let info_piece_info = generate_piece_commitment(&info_bytes[..], UnpaddedBytesAmount(1016))?;
+    let pieces = vec![
+        FauxPiece::File(piece_file.path().to_path_buf()),
+        FauxPiece::Info(info_piece_info.clone()),
+    ];
+
+    let run = |seed: &[u8; 32]| -> Result<_> {
+        let cache_dir = tempdir()?;
+        let unsealed_file = NamedTempFile::new()?;
+        let sealed_file = NamedTempFile::new()?;
+        let output = fauxrep_with_pieces::<_, _, _, SectorShape2KiB>(
+            config,
+            seed,
+            cache_dir.path(),
+            unsealed_file.path(),
+            sealed_file.path(),
+            &pieces,
+        )?;
+        let unsealed = std::fs::read(unsealed_file.path())?;
+        let tree_d = read_tree_d(cache_dir.path())?;
+        Ok((output, unsealed, tree_d))
+    };
+
+    let (output, unsealed, tree_d) = run(&[1; 32])?;
+    assert_eq!(output.piece_infos, vec![file_piece_info, info_piece_info]);
+    assert_eq!(
+        output.comm_d,
+        compute_comm_d(config.sector_size, &output.piece_infos)?
+    );
+    assert_eq!(unsealed.len() as u64, SECTOR_SIZE_2_KIB);
+    assert_eq!(&tree_d[tree_d.len() - NODE_SIZE..], &output.comm_d[..]);
+
+    // The same seed always results in the same sector.
+    let (same_output, same_unsealed, same_tree_d) = run(&[1; 32])?;
+    assert_eq!(same_output.comm_r, output.comm_r);
+    assert_eq!(same_unsealed, unsealed);
+    assert_eq!(same_tree_d, tree_d);
+
+    // Another seed changes the generated data, but not the commitments of the pieces.
+    let (other_output, other_unsealed, _) = run(&[2; 32])?;
+    assert_ne!(other_output.comm_r, output.comm_r);
+    assert_ne!(other_unsealed, unsealed);
+    assert_eq!(other_output.comm_d, output.comm_d);
+
+    Ok(())
+}
+
+#[test]
+fn test_fauxrep_with_pieces_tree_d_matches_sealing() -> Result<()> {
+    init_logger();
+
+    let config = porep_config(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+    let (piece_file, _) = generate_piece_file(SECTOR_SIZE_2_KIB)?;
+    let cache_dir = tempdir()?;
+    let unsealed_file = NamedTempFile::new()?;
+    let sealed_file = NamedTempFile::new()?;
+
+    let output = fauxrep_with_pieces::<_, _, _, SectorShape2KiB>(
+        config,
+        &[3; 32],
+        cache_dir.path(),
+        unsealed_file.path(),
+        sealed_file.path(),
+        &[FauxPiece::File(piece_file.path().to_path_buf())],
+    )?;
+
+    // Without generated pieces, tree-d is the one sealing builds over the unsealed data.
+    let leafs = SECTOR_SIZE_2_KIB as usize / NODE_SIZE;
+    let expected_cache_dir = tempdir()?;
+    let expected_config = StoreConfig::new(
+        expected_cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(leafs, 2),
+    );
+    let unsealed = std::fs::read(unsealed_file.path())?;
+    let expected_tree_d =
+        create_base_merkle_tree::<DataTree>(Some(expected_config), leafs, &unsealed)?;
+    let expected_comm_d: Fr = expected_tree_d.root().into();
+
+    assert_eq!(output.comm_d, commitment_from_fr(expected_comm_d));
+    assert_eq!(
+        read_tree_d(cache_dir.path())?,
+        read_tree_d(expected_cache_dir.path())?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_piece_inclusion_proofs_2kib() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let config = porep_config(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+
+    let piece_infos = generate_piece_fixtures(&mut rng)
+        .iter()
+        .map(|bytes| {
+            let size = UnpaddedBytesAmount(bytes.len() as u64);
+            generate_piece_commitment(&bytes[..], size)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let pieces: Vec<FauxPiece> = piece_infos.iter().cloned().map(FauxPiece::Info).collect();
+
+    let cache_dir = tempdir()?;
+    let unsealed_file = NamedTempFile::new()?;
+    let sealed_file = NamedTempFile::new()?;
+    let output = fauxrep_with_pieces::<_, _, _, SectorShape2KiB>(
+        config,
+        &[1; 32],
+        cache_dir.path(),
+        unsealed_file.path(),
+        sealed_file.path(),
+        &pieces,
+    )?;
+
+    let piece_sizes: Vec<UnpaddedBytesAmount> = piece_infos.iter().map(|p| p.size).collect();
+    let offsets: Vec<UnpaddedByteIndex> = (0..piece_infos.len())
+        .map(|i| get_piece_start_byte(&piece_sizes[..i], piece_sizes[i]))
+        .collect();
+
+    for (i, piece_info) in piece_infos.iter().enumerate() {
+        let proof = generate_piece_inclusion_proof(cache_dir.path(), &piece_infos, i)?;
+        let proof: PieceInclusionProof = serde_json::from_str(&serde_json::to_string(&proof)?)?;
+
+        assert!(verify_piece_inclusion_proof(
+            config.sector_size,
+            &output.comm_d,
+            &piece_info.commitment,
+            offsets[i],
+            piece_info.size,
+            &proof,
+        )?);
+
+        // Another commitment, offset or path must not verify.
+        let other = &piece_infos[(i + 1) % piece_infos.len()];
+        assert!(!verify_piece_inclusion_proof(
+            config.sector_size,
+            &output.comm_d,
+            &other.commitment,
+            offsets[i],
+            piece_info.size,
+            &proof,
+        )?);
+        let other_offset = UnpaddedByteIndex(u64::from(offsets[i]) + u64::from(piece_info.size));
+        assert!(!verify_piece_inclusion_proof(
+            config.sector_size,
+            &output.comm_d,
+            &piece_info.commitment,
+            other_offset,
+            piece_info.size,
+            &proof,
+        )?);
+        let mut tampered = proof.clone();
+        tampered.path[0][0] ^= 1;
+        assert!(!verify_piece_inclusion_proof(
+            config.sector_size,
+            &output.comm_d,
+            &piece_info.commitment,
+            offsets[i],
+            piece_info.size,
+            &tampered,
+        )?);
+    }
+
+    // Pieces that could not have been placed by add_piece are rejected.
+    assert!(verify_piece_inclusion_proof(
+        config.sector_size,
+        &output.comm_d,
+        &piece_infos[1].commitment,
+        UnpaddedByteIndex(127),
+        piece_infos[1].size,
+        &PieceInclusionProof::default(),
+    )
+    .is_err());
+
+    // A node of tree-d is no piece of a size at another level, although its path leads to
+    // comm_d: the left half of the sector is not a 127 byte piece at its start.
+    let tree_d = read_tree_d(cache_dir.path())?;
+    let half_level = tree_d.len() - 3 * NODE_SIZE;
+    let mut left_half = [0u8; 32];
+    left_half.copy_from_slice(&tree_d[half_level..half_level + NODE_SIZE]);
+    let mut right_half = [0u8; 32];
+    right_half.copy_from_slice(&tree_d[half_level + NODE_SIZE..half_level + 2 * NODE_SIZE]);
+    assert!(!verify_piece_inclusion_proof(
+        config.sector_size,
+        &output.comm_d,
+        &left_half,
+        UnpaddedByteIndex(0),
+        UnpaddedBytesAmount(127),
+        &PieceInclusionProof {
+            path: vec![right_half]
+        },
+    )?);
+    assert!(verify_piece_inclusion_proof(
+        config.sector_size,
+        &output.comm_d,
+        &left_half,
+        UnpaddedByteIndex(0),
+        UnpaddedBytesAmount(1016),
+        &PieceInclusionProof {
+            path: vec![right_half]
+        },
+    )?);
+
+    // A piece that is not in the sector has no proof.
+    let mut wrong_piece_infos = piece_infos.clone();
+    wrong_piece_infos[1].commitment = piece_infos[2].commitment;
+    assert!(generate_piece_inclusion_proof(cache_dir.path(), &wrong_piece_infos, 1).is_err());
+    assert!(generate_piece_inclusion_proof(cache_dir.path(), &piece_infos, 3).is_err());
+
+    // Tree-d is not consistent below the roots of generated pieces, such as a 127 byte piece
+    // at the start of the 508 byte one.
+    let inner_piece_infos = vec![
+        piece_infos[0].clone(),
+        piece_infos[0].clone(),
+        piece_infos[2].clone(),
+        piece_infos[0].clone(),
+    ];
+    let err = generate_piece_inclusion_proof(cache_dir.path(), &inner_piece_infos, 3)
+        .expect_err("piece inside a generated piece has a proof");
+    assert!(err.to_string().contains("inside a generated faux piece"));
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_unsealed_sector_2kib() -> Result<()> {
+    init_logger();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let config = porep_config(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+
+    let mut piece_files = Vec::new();
+    for bytes in generate_piece_fixtures(&mut rng) {
+        let mut piece_file = NamedTempFile::new()?;
+        piece_file.write_all(&bytes)?;
+        piece_files.push(piece_file);
+    }
+    let pieces: Vec<FauxPiece> = piece_files
+        .iter()
+        .map(|file| FauxPiece::File(file.path().to_path_buf()))
+        .collect();
+
+    let cache_dir = tempdir()?;
+    let unsealed_file = NamedTempFile::new()?;
+    let sealed_file = NamedTempFile::new()?;
+    let output = fauxrep_with_pieces::<_, _, _, SectorShape2KiB>(
+        config,
+        &[1; 32],
+        cache_dir.path(),
+        unsealed_file.path(),
+        sealed_file.path(),
+        &pieces,
+    )?;
+    let sector_size = config.sector_size;
+    let piece_infos = &output.piece_infos;
+
+    // The bit padded sector, the raw bytes of the whole sector and the raw bytes up to the
+    // end of the last piece all match.
+    let padded = std::fs::read(unsealed_file.path())?;
+    let mut raw = Vec::new();
+    write_unpadded(&padded, &mut raw, 0, 2032)?;
+    let raw_file = NamedTempFile::new()?;
+    std::fs::write(raw_file.path(), &raw)?;
+    let truncated_raw_file = NamedTempFile::new()?;
+    std::fs::write(truncated_raw_file.path(), &raw[..1270])?;
+
+    for (path, format) in &[
+        (unsealed_file.path(), UnsealedFormat::Padded),
+        (raw_file.path(), UnsealedFormat::Unpadded),
+        (truncated_raw_file.path(), UnsealedFormat::Unpadded),
+    ] {
+        let report =
+            verify_unsealed_sector(sector_size, path, *format, piece_infos, output.comm_d)?;
+        assert!(report.is_healthy(), "{:?}", report);
+        assert_eq!(report.comm_d, output.comm_d);
+    }
+
+    // A file that does not have the given format is rejected.
+    assert!(verify_unsealed_sector(
+        sector_size,
+        unsealed_file.path(),
+        UnsealedFormat::Unpadded,
+        piece_infos,
+        output.comm_d
+    )
+    .is_err());
+    assert!(verify_unsealed_sector(
+        sector_size,
+        raw_file.path(),
+        UnsealedFormat::Padded,
+        piece_infos,
+        output.comm_d
+    )
+    .is_err());
+
+    // A flipped bit is traced back to its piece.
+    let mut corrupted = raw.clone();
+    corrupted[600] ^= 1;
+    std::fs::write(raw_file.path(), &corrupted)?;
+    let report = verify_unsealed_sector(
+        sector_size,
+        raw_file.path(),
+        UnsealedFormat::Unpadded,
+        piece_infos,
+        output.comm_d,
+    )?;
+    assert!(!report.comm_d_matches);
+    assert!(report.piece_infos_match);
+    assert_eq!(report.corrupted_pieces, vec![1]);
+
+    // Data outside of the pieces only changes comm_d.
+    let mut corrupted = raw;
+    corrupted[2000] ^= 1;
+    std::fs::write(raw_file.path(), &corrupted)?;
+    let report = verify_unsealed_sector(
+        sector_size,
+        raw_file.path(),
+        UnsealedFormat::Unpadded,
+        piece_infos,
+        output.comm_d,
+    )?;
+    assert!(!report.comm_d_matches);
+    assert!(report.corrupted_pieces.is_empty());
+
+    // Piece infos that do not belong to the sector.
+    let mut wrong_piece_infos = piece_infos.clone();
+    wrong_piece_infos.swap(0, 2);
+    wrong_piece_infos[0].size = piece_infos[0].size;
+    wrong_piece_infos[2].size = piece_infos[2].size;
+    let report = verify_unsealed_sector(
+        sector_size,
+        unsealed_file.path(),
+        UnsealedFormat::Padded,
+        &wrong_piece_infos,
+        output.comm_d,
+    )?;
+    assert!(report.comm_d_matches);
+    assert!(!report.piece_infos_match);
+    assert_eq!(report.corrupted_pieces, vec![0, 2]);
+
+    Ok(())
+}
+
+#[test]
+fn test_sparse_add_piece_32kib() -> Result<()> {
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SectorSize(SECTOR_SIZE_32_KIB);
+    let data: Vec<u8> = (0..1016).map(|_| rng.gen()).collect();
+    let zeroes = vec![0u8; 16256];
+
+    // The same pieces, staged densely and sparsely: a small piece of data followed by a large
+    // zero piece, which needs a lot of alignment.
+    let dense_file = NamedTempFile::new()?;
+    let sparse_file = NamedTempFile::new()?;
+    let mut dense = dense_file.reopen()?;
+    let mut sparse = SparseFileWriter::new(sparse_file.reopen()?)?;
+    let mut piece_infos = Vec::new();
+    let mut piece_sizes = Vec::new();
+    for piece in &[&data, &zeroes] {
+        let piece_size = UnpaddedBytesAmount(piece.len() as u64);
+        let (dense_info, _) = add_piece(&piece[..], &mut dense, piece_size, &piece_sizes)?;
+        let (sparse_info, _) = add_piece(&piece[..], &mut sparse, piece_size, &piece_sizes)?;
+        assert_eq!(dense_info, sparse_info);
+
+        piece_infos.push(sparse_info);
+        piece_sizes.push(piece_size);
+    }
+    dense.set_len(SECTOR_SIZE_32_KIB)?;
+    sparse.into_inner()?.set_len(SECTOR_SIZE_32_KIB)?;
+
+    assert_eq!(
+        std::fs::read(dense_file.path())?,
+        std::fs::read(sparse_file.path())?
+    );
+    assert!(materialized_bytes(sparse_file.path())? < SECTOR_SIZE_32_KIB);
+
+    // Reading the holes gives the zeros the dense file holds.
+    let mut sparse_bytes = Vec::new();
+    SparseFileReader::new(sparse_file.reopen()?)?.read_to_end(&mut sparse_bytes)?;
+    assert_eq!(sparse_bytes, std::fs::read(dense_file.path())?);
+
+    let comm_d = compute_comm_d(sector_size, &piece_infos)?;
+    let report = verify_unsealed_sector(
+        sector_size,
+        sparse_file.path(),
+        UnsealedFormat::Padded,
+        &piece_infos,
+        comm_d,
+    )?;
+    assert!(report.is_healthy(), "{:?}", report);
+
+    Ok(())
+}
+
 #[test]
 fn test_aggregate_proof_encode_decode() -> Result<()> {
     // This byte vector is a natively serialized aggregate proof generated from the
@@ -1,17 +1,23 @@
 use std::io::{Cursor, Read};
 use std::iter::Iterator;
+use std::slice;
 
 use anyhow::Result;
 use blstrs::Scalar as Fr;
 use filecoin_proofs::{
-    add_piece, commitment_from_fr,
+    add_piece, commitment_from_fr, generate_piece_commitment,
     pieces::{
-        compute_comm_d, get_piece_alignment, get_piece_start_byte, piece_hash, verify_pieces,
-        zero_padding, EmptySource, PieceAlignment,
+        compute_comm_d, generate_sub_piece_inclusion_proof, get_piece_alignment,
+        get_piece_start_byte, piece_hash, plan_aggregate, plan_sector_layout, read_aggregate_index,
+        sum_piece_bytes_with_alignment, verify_pieces, verify_sub_piece_inclusion_proof,
+        write_aggregate, zero_commitment, zero_padding, EmptySource, PieceAlignment,
+        PieceCandidate,
     },
-    Commitment, DataTree, DefaultPieceHasher, PaddedBytesAmount, PieceInfo, SectorSize,
-    UnpaddedByteIndex, UnpaddedBytesAmount, DRG_DEGREE, EXP_DEGREE, TEST_SEED,
+    AggregatePiece, Commitment, DataTree, DefaultPieceHasher, PaddedBytesAmount, PieceInfo,
+    SectorSize, SegmentDescriptor, SubPieceInclusionProof, UnpaddedByteIndex, UnpaddedBytesAmount,
+    DRG_DEGREE, EXP_DEGREE, TEST_SEED,
 };
+use fr32::Fr32Layout;
 use rand::{Rng, RngCore, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use storage_proofs_core::{
@@ -48,6 +54,29 @@ fn test_compute_comm_d_empty() {
     );
 }
 
+#[test]
+fn test_zero_commitment() -> Result<()> {
+    assert_eq!(zero_commitment(PaddedBytesAmount(32))?, [0u8; 32]);
+    for size in &[128u64, 2048, 16384] {
+        let padded = PaddedBytesAmount(*size);
+        let zeroes = vec![0u8; *size as usize];
+        let tree = create_base_merkle_tree::<DataTree>(None, zeroes.len() / NODE_SIZE, &zeroes)?;
+        let root: Fr = tree.root().into();
+        let expected = commitment_from_fr(root);
+
+        assert_eq!(zero_commitment(padded)?, expected);
+        assert_eq!(
+            zero_padding(UnpaddedBytesAmount::from(padded))?.commitment,
+            expected
+        );
+        assert_eq!(compute_comm_d(SectorSize(*size), &[])?, expected);
+    }
+    assert!(zero_commitment(PaddedBytesAmount(0)).is_err());
+    assert!(zero_commitment(PaddedBytesAmount(96)).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_get_piece_alignment() {
     let table = vec![
@@ -288,8 +317,6 @@ fn test_verify_padded_pieces() {
 #[test]
 #[ignore] // slow test
 fn test_verify_random_pieces() -> Result<()> {
-    use filecoin_proofs::pieces::sum_piece_bytes_with_alignment;
-
     let rng = &mut XorShiftRng::from_seed(TEST_SEED);
 
     for sector_size in &[
@@ -421,3 +448,240 @@ fn prev_power_of_two(mut x: u32) -> u32 {
     x |= x >> 16;
     x - (x >> 1)
 }
+
+#[test]
+fn test_aggregate_pieces() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let mut sources = Vec::new();
+    let mut piece_infos = Vec::new();
+    for size in &[127, 508, 254, 127] {
+        let mut bytes = vec![0u8; *size];
+        rng.fill_bytes(&mut bytes);
+        piece_infos.push(generate_piece_commitment(
+            Cursor::new(&bytes),
+            UnpaddedBytesAmount(*size as u64),
+        )?);
+        sources.push(bytes);
+    }
+
+    for aggregate_size in &[PaddedBytesAmount(8 * 1024), PaddedBytesAmount(1024 * 1024)] {
+        let mut aggregate_bytes = Vec::new();
+        let aggregate = write_aggregate(
+            *aggregate_size,
+            piece_infos
+                .iter()
+                .cloned()
+                .zip(sources.iter().map(Cursor::new))
+                .collect(),
+            &mut aggregate_bytes,
+        )?;
+
+        // The aggregate is added to a sector like any other piece.
+        let unpadded_size = UnpaddedBytesAmount::from(*aggregate_size);
+        assert_eq!(aggregate_bytes.len() as u64, u64::from(unpadded_size));
+        assert_eq!(
+            generate_piece_commitment(Cursor::new(&aggregate_bytes), unpadded_size)?,
+            aggregate.piece_info
+        );
+
+        let offsets: Vec<u64> = aggregate
+            .segments
+            .iter()
+            .map(|segment| u64::from(segment.offset))
+            .collect();
+        assert_eq!(offsets, vec![0, 512, 1024, 1280]);
+        assert_eq!(
+            read_aggregate_index(Cursor::new(&aggregate_bytes), *aggregate_size)?,
+            aggregate.segments
+        );
+
+        for (i, segment) in aggregate.segments.iter().enumerate() {
+            assert_eq!(segment.comm_p, piece_infos[i].commitment);
+            let start = Fr32Layout::unpadded_len(u64::from(segment.offset)) as usize;
+            assert_eq!(
+                &aggregate_bytes[start..start + sources[i].len()],
+                &sources[i][..]
+            );
+
+            let proof = generate_sub_piece_inclusion_proof(&aggregate, i)?;
+            let proof: SubPieceInclusionProof =
+                serde_json::from_str(&serde_json::to_string(&proof)?)?;
+            assert!(verify_sub_piece_inclusion_proof(
+                &aggregate.piece_info,
+                segment,
+                &proof
+            )?);
+
+            // Another descriptor or a descriptor with a stale checksum must not verify.
+            let other = &aggregate.segments[(i + 1) % aggregate.segments.len()];
+            assert!(!verify_sub_piece_inclusion_proof(
+                &aggregate.piece_info,
+                other,
+                &proof
+            )?);
+            let mut moved = segment.clone();
+            moved.offset = PaddedBytesAmount(u64::from(segment.offset) + u64::from(segment.size));
+            assert!(!verify_sub_piece_inclusion_proof(
+                &aggregate.piece_info,
+                &moved,
+                &proof
+            )?);
+            let moved = SegmentDescriptor::new(segment.comm_p, moved.offset, moved.size);
+            assert!(!verify_sub_piece_inclusion_proof(
+                &aggregate.piece_info,
+                &moved,
+                &proof
+            )?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_aggregate_pieces_invalid() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let mut bytes = vec![0u8; 508];
+    rng.fill_bytes(&mut bytes);
+    let piece_info = generate_piece_commitment(Cursor::new(&bytes), UnpaddedBytesAmount(508))?;
+
+    // Too many pieces for the index, a piece that overlaps the index and an aggregate that is
+    // not a power of two.
+    assert!(plan_aggregate(PaddedBytesAmount(8 * 1024), &vec![piece_info.clone(); 5]).is_err());
+    assert!(plan_aggregate(PaddedBytesAmount(512), slice::from_ref(&piece_info)).is_err());
+    assert!(plan_aggregate(PaddedBytesAmount(8 * 1000), slice::from_ref(&piece_info)).is_err());
+
+    // A source that does not match the commitment of its piece.
+    let mut other_bytes = bytes.clone();
+    other_bytes[0] ^= 1;
+    assert!(write_aggregate(
+        PaddedBytesAmount(8 * 1024),
+        vec![(piece_info.clone(), Cursor::new(&other_bytes))],
+        &mut Vec::new(),
+    )
+    .is_err());
+    assert!(write_aggregate(
+        PaddedBytesAmount(8 * 1024),
+        vec![(piece_info.clone(), Cursor::new(&bytes[..254]))],
+        &mut Vec::new(),
+    )
+    .is_err());
+
+    // Overlapping segments are rejected when deserializing an aggregate or proving with it.
+    let mut aggregate = write_aggregate(
+        PaddedBytesAmount(8 * 1024),
+        vec![(piece_info.clone(), Cursor::new(&bytes))],
+        &mut Vec::new(),
+    )?;
+    assert!(serde_json::from_str::<AggregatePiece>(&serde_json::to_string(&aggregate)?).is_ok());
+    aggregate.segments.push(SegmentDescriptor::new(
+        piece_info.commitment,
+        PaddedBytesAmount(0),
+        PaddedBytesAmount(1024),
+    ));
+    assert!(serde_json::from_str::<AggregatePiece>(&serde_json::to_string(&aggregate)?).is_err());
+    assert!(generate_sub_piece_inclusion_proof(&aggregate, 0).is_err());
+
+    Ok(())
+}
+
+fn candidate(
+    rng: &mut XorShiftRng,
+    size: u64,
+    deadline: Option<u64>,
+    priority: u32,
+) -> PieceCandidate {
+    let mut commitment = [0u8; 32];
+    rng.fill_bytes(&mut commitment[..31]);
+    PieceCandidate {
+        piece_info: PieceInfo::new(commitment, UnpaddedBytesAmount(size))
+            .expect("failed to create piece info"),
+        deadline,
+        priority,
+    }
+}
+
+#[test]
+fn test_plan_sector_layout() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SectorSize(2048);
+
+    // Adding these in the given order pads the 1016 byte pieces, so only two of them fit.
+    let candidates: Vec<PieceCandidate> = [127, 1016, 254, 508, 1016]
+        .iter()
+        .map(|size| candidate(rng, *size, None, 0))
+        .collect();
+    let sizes: Vec<UnpaddedBytesAmount> = candidates
+        .iter()
+        .map(|candidate| candidate.piece_info.size)
+        .collect();
+    assert_eq!(
+        sum_piece_bytes_with_alignment(&sizes[..2]),
+        UnpaddedBytesAmount(2032)
+    );
+
+    let layout = plan_sector_layout(sector_size, &candidates)?;
+    let chosen: Vec<usize> = layout.pieces.iter().map(|piece| piece.candidate).collect();
+    assert_eq!(chosen, vec![1, 4]);
+    assert_eq!(layout.unplaced, vec![0, 2, 3]);
+    assert_eq!(layout.free_bytes, UnpaddedBytesAmount(0));
+
+    // Without the second large piece, the rest fills up the sector from the largest piece on.
+    let layout = plan_sector_layout(sector_size, &candidates[..4])?;
+    let chosen: Vec<usize> = layout.pieces.iter().map(|piece| piece.candidate).collect();
+    assert_eq!(chosen, vec![1, 3, 2, 0]);
+    assert_eq!(layout.free_bytes, UnpaddedBytesAmount(127));
+
+    // The planned offsets and alignment are those of add_piece, and comm_d follows the pieces.
+    for (i, piece) in layout.pieces.iter().enumerate() {
+        let previous: Vec<UnpaddedBytesAmount> = layout.pieces[..i]
+            .iter()
+            .map(|piece| piece.piece_info.size)
+            .collect();
+        assert_eq!(
+            piece.offset,
+            get_piece_start_byte(&previous, piece.piece_info.size)
+        );
+        assert_eq!(piece.alignment.left_bytes, UnpaddedBytesAmount(0));
+        assert_eq!(piece.piece_info, layout.piece_infos[i]);
+    }
+    assert_eq!(
+        layout.comm_d,
+        compute_comm_d(sector_size, &layout.piece_infos)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_plan_sector_layout_ranks() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SectorSize(2048);
+
+    // Priority wins over filling the sector, and deadlines win over priority.
+    let candidates = vec![
+        candidate(rng, 1016, None, 0),
+        candidate(rng, 1016, None, 0),
+        candidate(rng, 127, None, 1),
+        candidate(rng, 1016, Some(10), 0),
+        candidate(rng, 508, Some(5), 0),
+    ];
+
+    let layout = plan_sector_layout(sector_size, &candidates[..3])?;
+    let chosen: Vec<usize> = layout.pieces.iter().map(|piece| piece.candidate).collect();
+    assert_eq!(chosen, vec![0, 2]);
+    assert_eq!(layout.unplaced, vec![1]);
+
+    let layout = plan_sector_layout(sector_size, &candidates)?;
+    let chosen: Vec<usize> = layout.pieces.iter().map(|piece| piece.candidate).collect();
+    assert_eq!(chosen, vec![3, 4, 2]);
+    assert_eq!(layout.unplaced, vec![0, 1]);
+
+    let mut invalid = candidates;
+    invalid[0].piece_info.size = UnpaddedBytesAmount(1000);
+    assert!(plan_sector_layout(sector_size, &invalid).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,375 @@
+use std::ops::Range;
+
+/// Offset and range arithmetic of `Fr32` bit padded data, for random access into it.
+///
+/// Every 254 bits of unpadded data are stored in a 32 byte element, followed by 2 bits of
+/// padding. So 127 unpadded bytes take up a block of 128 padded bytes, and within a block the
+/// unpadded bytes are generally not byte aligned in the padded layout. Padded data is assumed to
+/// start at offset 0, all offsets and lengths are in bytes unless noted otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fr32Layout;
+
+/// A bit position in padded data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PaddedPosition {
+    /// The byte holding the bit.
+    pub byte: u64,
+    /// The offset of the bit within `byte`, from the least significant bit, in `0..8`.
+    pub bit: u8,
+}
+
+impl PaddedPosition {
+    pub(crate) fn from_bits(bits: u64) -> Self {
+        PaddedPosition {
+            byte: bits / 8,
+            bit: (bits % 8) as u8,
+        }
+    }
+
+    /// Returns the position as a number of bits.
+    pub fn total_bits(&self) -> u64 {
+        self.byte * 8 + self.bit as u64
+    }
+
+    /// Returns the number of bytes up to and including a partially covered last byte.
+    pub fn bytes_needed(&self) -> u64 {
+        self.byte + if self.bit == 0 { 0 } else { 1 }
+    }
+
+    /// Returns the element after the one holding the position.
+    pub fn next_element(&self) -> u64 {
+        self.total_bits() / Fr32Layout::ELEMENT_BITS + 1
+    }
+
+    /// Returns the number of unpadded bits of the element from the position on. The position
+    /// must not be in the padding of its element.
+    pub fn data_bits_left(&self) -> u64 {
+        Fr32Layout::DATA_BITS - self.total_bits() % Fr32Layout::ELEMENT_BITS
+    }
+}
+
+/// Where a range of unpadded bytes is stored in padded data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PaddedRange {
+    /// The position of the first bit of the range.
+    pub start: PaddedPosition,
+    /// The position just past the last bit of the range, before the padding of its element if
+    /// the range ends with an element.
+    pub end: PaddedPosition,
+    /// The first element holding data of the range.
+    pub first_element: u64,
+    /// The number of elements holding data of the range.
+    pub num_elements: u64,
+}
+
+impl PaddedRange {
+    /// Returns the padded bytes holding the range. The first and the last of them may also hold
+    /// data outside of the range.
+    pub fn bytes(&self) -> Range<u64> {
+        if self.is_empty() {
+            return self.start.byte..self.start.byte;
+        }
+        self.start.byte..self.end.bytes_needed()
+    }
+
+    /// Returns the padded bytes of the whole elements holding the range, which is what has to be
+    /// read to decode it element by element.
+    pub fn element_bytes(&self) -> Range<u64> {
+        let start = self.first_element * Fr32Layout::ELEMENT_BYTES;
+        start..start + self.num_elements * Fr32Layout::ELEMENT_BYTES
+    }
+
+    /// Returns if the first element also holds data before the range.
+    pub fn starts_in_element(&self) -> bool {
+        self.start.total_bits() % Fr32Layout::ELEMENT_BITS != 0
+    }
+
+    /// Returns if the last element also holds data after the range.
+    pub fn ends_in_element(&self) -> bool {
+        self.num_elements != 0
+            && self.end.total_bits() % Fr32Layout::ELEMENT_BITS != Fr32Layout::DATA_BITS
+    }
+
+    /// Returns if the range is empty, it is stored in no bytes then.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+}
+
+impl Fr32Layout {
+    /// The number of unpadded bits in an element.
+    pub const DATA_BITS: u64 = 254;
+    /// The number of bits of an element, including its padding.
+    pub const ELEMENT_BITS: u64 = 256;
+    /// The number of bytes of an element, including its padding.
+    pub const ELEMENT_BYTES: u64 = Self::ELEMENT_BITS / 8;
+    /// The number of unpadded bytes in a block, the smallest byte aligned group of elements.
+    pub const UNPADDED_BLOCK_BYTES: u64 = 127;
+    /// The number of bytes of a block, including its padding.
+    pub const PADDED_BLOCK_BYTES: u64 = 128;
+
+    /// Returns where unpadded bit `unpadded_bit` is stored.
+    pub fn padded_bit(unpadded_bit: u64) -> u64 {
+        unpadded_bit / Self::DATA_BITS * Self::ELEMENT_BITS + unpadded_bit % Self::DATA_BITS
+    }
+
+    /// Returns the number of unpadded bits stored before padded bit `padded_bit`. This is the
+    /// inverse of `padded_bit`, the bits of padding map to the end of their element's data.
+    pub fn unpadded_bit(padded_bit: u64) -> u64 {
+        let in_element = padded_bit % Self::ELEMENT_BITS;
+        padded_bit / Self::ELEMENT_BITS * Self::DATA_BITS + in_element.min(Self::DATA_BITS)
+    }
+
+    /// Returns where the first bit of unpadded byte `offset` is stored.
+    pub fn padded_position(offset: u64) -> PaddedPosition {
+        PaddedPosition::from_bits(Self::padded_bit(offset * 8))
+    }
+
+    /// Returns the number of padded bytes which store `len` unpadded bytes, the last one of them
+    /// possibly only partially.
+    pub fn padded_len(len: u64) -> u64 {
+        PaddedPosition::from_bits(Self::padded_bit(len * 8)).bytes_needed()
+    }
+
+    /// Returns the number of whole unpadded bytes stored in `padded_len` padded bytes.
+    pub fn unpadded_len(padded_len: u64) -> u64 {
+        Self::unpadded_bit(padded_len * 8) / 8
+    }
+
+    /// Returns where the unpadded bytes `range` are stored.
+    pub fn padded_range(range: Range<u64>) -> PaddedRange {
+        let start = Self::padded_position(range.start);
+        if range.end <= range.start {
+            return PaddedRange {
+                start,
+                end: start,
+                first_element: start.total_bits() / Self::ELEMENT_BITS,
+                num_elements: 0,
+            };
+        }
+
+        // The end is right after the last bit, which is not necessarily the padded end bit.
+        let last_bit = Self::padded_bit(range.end * 8 - 1);
+        let first_element = start.total_bits() / Self::ELEMENT_BITS;
+        PaddedRange {
+            start,
+            end: PaddedPosition::from_bits(last_bit + 1),
+            first_element,
+            num_elements: last_bit / Self::ELEMENT_BITS + 1 - first_element,
+        }
+    }
+
+    /// Returns the unpadded bytes which are stored entirely within the padded bytes `range`.
+    pub fn unpadded_range(range: Range<u64>) -> Range<u64> {
+        let start_bit = Self::unpadded_bit(range.start * 8);
+        let end_bit = Self::unpadded_bit(range.end.max(range.start) * 8);
+        let start = (start_bit + 7) / 8;
+        let end = end_bit / 8;
+
+        start..end.max(start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Cursor, Read};
+
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::{to_padded_bytes, to_unpadded_bytes, Fr32Reader};
+
+    /// Positions of the first unpadded bits, by counting.
+    fn padded_bits(num_bits: u64) -> Vec<u64> {
+        let mut padded_bits = Vec::new();
+        let mut padded_bit = 0;
+        while (padded_bits.len() as u64) < num_bits {
+            if padded_bit % 256 < 254 {
+                padded_bits.push(padded_bit);
+            }
+            padded_bit += 1;
+        }
+        padded_bits
+    }
+
+    fn bit(bytes: &[u8], bit: u64) -> bool {
+        bytes[(bit / 8) as usize] >> (bit % 8) & 1 == 1
+    }
+
+    #[test]
+    fn test_bit_offsets() {
+        let padded_bits = padded_bits(8 * 1024);
+        for (unpadded_bit, padded_bit) in padded_bits.iter().enumerate() {
+            assert_eq!(Fr32Layout::padded_bit(unpadded_bit as u64), *padded_bit);
+            assert_eq!(Fr32Layout::unpadded_bit(*padded_bit), unpadded_bit as u64);
+        }
+
+        // Padding bits belong to the end of their element's data.
+        let mut unpadded_bits_before = 0;
+        for padded_bit in 0..8 * 1024 {
+            assert_eq!(Fr32Layout::unpadded_bit(padded_bit), unpadded_bits_before);
+            if padded_bits.binary_search(&padded_bit).is_ok() {
+                unpadded_bits_before += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_position() {
+        let mut bits = 0;
+        for byte in 0..10 {
+            for bit in 0..8 {
+                let position = PaddedPosition { byte, bit };
+                assert_eq!(position.total_bits(), bits);
+                assert_eq!(PaddedPosition::from_bits(bits), position);
+                assert_eq!(position.bytes_needed(), (bits + 7) / 8);
+                bits += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_element_boundaries() {
+        let padded_bits = padded_bits(8 * 1024);
+        for padded_bit in &padded_bits {
+            let position = PaddedPosition::from_bits(*padded_bit);
+
+            // Counts the bits up to the padding of the element, and then past it.
+            let mut bit = *padded_bit;
+            while bit % 256 < 254 {
+                bit += 1;
+            }
+            assert_eq!(position.data_bits_left(), bit - padded_bit);
+            while bit % 256 != 0 {
+                bit += 1;
+            }
+            assert_eq!(position.next_element() * 256, bit);
+        }
+    }
+
+    #[test]
+    fn test_lengths() {
+        for len in 0..4096 {
+            assert_eq!(
+                Fr32Layout::padded_len(len),
+                to_padded_bytes(len as usize) as u64
+            );
+            assert_eq!(Fr32Layout::unpadded_len(len), to_unpadded_bytes(len));
+        }
+        assert_eq!(Fr32Layout::padded_len(127), 128);
+        assert_eq!(Fr32Layout::unpadded_len(128), 127);
+        assert_eq!(Fr32Layout::padded_len(32), 33);
+        assert_eq!(Fr32Layout::unpadded_len(32), 31);
+    }
+
+    #[test]
+    fn test_padded_range() {
+        let max_len = 300;
+        let padded_bits = padded_bits(8 * max_len);
+
+        for start in 0..=max_len {
+            for end in start..=max_len {
+                let range = Fr32Layout::padded_range(start..end);
+                assert_eq!(range.start, Fr32Layout::padded_position(start));
+                assert_eq!(range.is_empty(), start == end);
+                if start == end {
+                    assert!(range.bytes().is_empty());
+                    assert!(range.element_bytes().is_empty());
+                    continue;
+                }
+
+                let first = padded_bits[(start * 8) as usize];
+                let last = padded_bits[(end * 8 - 1) as usize];
+                assert_eq!(range.start.total_bits(), first);
+                assert_eq!(range.end.total_bits(), last + 1);
+                assert_eq!(range.bytes(), first / 8..last / 8 + 1);
+                assert_eq!(
+                    range.element_bytes(),
+                    first / 256 * 32..(last / 256 + 1) * 32
+                );
+                assert_eq!(range.starts_in_element(), start * 8 % 254 != 0);
+                assert_eq!(range.ends_in_element(), end * 8 % 254 != 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_padded_range_data() {
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        let mut data = vec![0u8; 160];
+        rng.fill_bytes(&mut data);
+
+        let mut padded = Vec::new();
+        Fr32Reader::new(Cursor::new(&data))
+            .read_to_end(&mut padded)
+            .expect("in-memory read failed");
+
+        // Reads the range back from only its padded bytes, skipping the padding bits.
+        for start in 0..data.len() as u64 {
+            for end in start + 1..=data.len() as u64 {
+                let range = Fr32Layout::padded_range(start..end);
+                let bytes = range.bytes();
+                let stored = &padded[bytes.start as usize..bytes.end as usize];
+
+                let mut unpadded = vec![0u8; (end - start) as usize];
+                let mut padded_bit = range.start.total_bits();
+                for unpadded_bit in 0..(end - start) * 8 {
+                    if padded_bit % 256 >= 254 {
+                        padded_bit += 256 - padded_bit % 256;
+                    }
+                    if bit(stored, padded_bit - bytes.start * 8) {
+                        unpadded[(unpadded_bit / 8) as usize] |= 1 << (unpadded_bit % 8);
+                    }
+                    padded_bit += 1;
+                }
+                assert_eq!(padded_bit, range.end.total_bits());
+                assert_eq!(&unpadded[..], &data[start as usize..end as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpadded_range() {
+        let max_len = 140;
+        let padded_bits = padded_bits(8 * max_len);
+
+        for start in 0..=max_len {
+            for end in 0..=max_len {
+                let contained: Vec<u64> = (0..max_len)
+                    .filter(|byte| {
+                        let bits = &padded_bits[(byte * 8) as usize..(byte * 8 + 8) as usize];
+                        bits.iter().all(|bit| (start * 8..end * 8).contains(bit))
+                    })
+                    .collect();
+
+                let range = Fr32Layout::unpadded_range(start..end);
+                if contained.is_empty() {
+                    assert!(range.is_empty(), "{}..{}: {:?}", start, end, range);
+                } else {
+                    assert_eq!(range, contained[0]..contained[contained.len() - 1] + 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for start in 0..=300 {
+            for end in start..=300 {
+                // The padded bytes of a range hold at least the range.
+                let bytes = Fr32Layout::padded_range(start..end).bytes();
+                let unpadded = Fr32Layout::unpadded_range(bytes);
+                if start == end {
+                    assert!(unpadded.is_empty());
+                } else {
+                    assert!(unpadded.start <= start && end <= unpadded.end);
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
 mod convert;
+mod layout;
 mod padding;
 mod reader;
+mod unpadder;
 
 pub use convert::*;
+pub use layout::*;
 pub use padding::*;
 pub use reader::*;
+pub use unpadder::*;
@@ -1,7 +1,9 @@
 use std::cmp::{min, Ordering};
 use std::io::{self, Error, ErrorKind, Write};
 
-/** PaddingMap represents a mapping between data and its padded equivalent.
+use crate::{Fr32Layout, PaddedPosition};
+
+/* The padded layout of `Fr32Layout`, and how data maps to its padded equivalent.
 
 The padding process takes a *byte-aligned stream* of unpadded *raw* data
 as input and returns another byte stream where padding is applied every
@@ -66,9 +68,9 @@ that there's only a single number of valid data bits in the last byte,
 and hence a number of data bits in total, that maps to a byte-aligned
 (multiple of 8) raw data stream that could have been used as input.
 
-# Example: `FR32_PADDING_MAP`
+# Example: `Fr32Layout`
 
-In this case the `PaddingMap` is defined with a data unit of 254 bits that
+In this case the layout has a data unit of 254 bits that
 are byte aligned to a 256-bit (32-byte) element. If the user writes as input,
 say, 40 bytes (320 bits) of raw input data to the padding process the resulting
 layout would be, at the element (byte) level:
@@ -148,147 +150,27 @@ an additional summary of what was already discussed.
  * Embedded raw data: view of the input raw data when it has been decomposed in
    bit streams and padded in the resulting output.
 
-**/
-#[derive(Debug)]
-struct PaddingMap {
-    /// The number of bits of raw data in an element.
-    data_bits: usize,
-    /// Number of bits in an element: `data_bits` + `pad_bits()`. Its value
-    /// is fixed to the next byte-aligned size after `data_bits` (sub-byte padding).
-    element_bits: usize,
-}
-// TODO: Optimization: Evaluate saving the state of a (un)padding operation
-// inside (e.g., as a cursor like in `BitVec`), maybe not in this structure but
-// in a new `Padder` structure which would remember the positions (remaining
-// data bits in the element, etc.) to avoid recalculating them each time across
-// different (un)pad calls.
-
-// This is the padding map corresponding to Fr32.
-// Most of the code in this module is general-purpose and could move elsewhere.
-// The application-specific wrappers which implicitly use Fr32 embed the FR32_PADDING_MAP.
-const FR32_PADDING_MAP: PaddingMap = PaddingMap {
-    data_bits: 254,
-    element_bits: 256,
-};
+*/
 
 pub fn to_unpadded_bytes(padded_bytes: u64) -> u64 {
-    FR32_PADDING_MAP.transform_byte_offset(padded_bytes as usize, false) as u64
+    Fr32Layout::unpadded_len(padded_bytes)
 }
 
 pub fn to_padded_bytes(unpadded_bytes: usize) -> usize {
-    FR32_PADDING_MAP.transform_byte_offset(unpadded_bytes, true)
-}
-
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// BitByte represents a size expressed in bytes extended
-// with bit precision, that is, not rounded.
-// Invariant: it is an error for bits to be > 7.
-#[derive(Debug)]
-struct BitByte {
-    bytes: usize,
-    bits: usize,
+    Fr32Layout::padded_len(unpadded_bytes as u64) as usize
 }
 
-impl BitByte {
-    // Create a BitByte from number of bits. Guaranteed to return a well-formed value (bits < 8)
-    fn from_bits(bits: usize) -> BitByte {
-        BitByte {
-            bytes: bits / 8,
-            bits: bits % 8,
-        }
-    }
-
-    // How many bits in the BitByte (inverse of from_bits).
-    fn total_bits(&self) -> usize {
-        self.bytes * 8 + self.bits
-    }
-
-    // How many distinct bytes are needed to represent data of this size?
-    fn bytes_needed(&self) -> usize {
-        self.bytes + if self.bits == 0 { 0 } else { 1 }
-    }
-}
-
-impl PaddingMap {
-    fn pad_bits(&self) -> usize {
-        self.element_bits - self.data_bits
-    }
-
-    // Transform an offset (either a position or a size) *expressed in
-    // bits* in a raw byte-aligned data stream to its equivalent in a
-    // generated padded bit stream, that is, not byte aligned (so we
-    // don't count the extra bits here). If `padding` is `false` calculate
-    // the inverse transformation.
-    fn transform_bit_offset(&self, pos: usize, padding: bool) -> usize {
-        // Set the sizes we're converting to and from.
-        let (from_size, to_size) = if padding {
-            (self.data_bits, self.element_bits)
-        } else {
-            (self.element_bits, self.data_bits)
-        };
-
-        // For both the padding and unpadding cases the operation is the same.
-        // The quotient is the number of full, either elements, in the padded layout,
-        // or groups of `data_bits`, in the raw data input (that will be converted
-        // to full elements).
-        // The remainder (in both cases) is the last *incomplete* part of either of
-        // the two. Even in the padded layout, if there is an incomplete element it
-        // has to consist *only* of data (see `PaddingMap#padded-layout`). That amount
-        // of spare raw data doesn't need conversion, it can just be added to the new
-        // position.
-        let (full_elements, incomplete_data) = div_rem(pos, from_size);
-        (full_elements * to_size) + incomplete_data
-    }
-
-    // Similar to `transform_bit_pos` this function transforms an offset
-    // expressed in bytes, that is, we are taking into account the extra
-    // bits here.
-    // TODO: Evaluate the relationship between this function and `transform_bit_offset`,
-    // it seems the two could be merged, or at least restructured to better expose
-    // their differences.
-    fn transform_byte_offset(&self, pos: usize, padding: bool) -> usize {
-        let transformed_bit_pos = self.transform_bit_offset(pos * 8, padding);
-
-        let transformed_byte_pos = transformed_bit_pos as f64 / 8.;
-        // TODO: Optimization: It might end up being cheaper to avoid this
-        // float conversion and use / and %.
-
-        // When padding, the final bits in the bit stream will grow into the
-        // last (potentially incomplete) byte of the byte stream, so round the
-        // number up (`ceil`). When unpadding, there's no way to know a priori
-        // how many valid bits are in the last byte, we have to choose the number
-        // that fits in a byte-aligned raw data stream, so round the number down
-        // to that (`floor`).
-        (if padding {
-            transformed_byte_pos.ceil()
-        } else {
-            transformed_byte_pos.floor()
-        }) as usize
-    }
-
-    // From the `position` specified, it returns:
-    // - the absolute position of the start of the next element,
-    //   in bytes (since elements -with padding- are byte aligned).
-    // - the number of bits left to read (write) from (to) the current
-    //   data unit (assuming it's full).
-    fn next_boundary(&self, position: &BitByte) -> (usize, usize) {
-        let position_bits = position.total_bits();
-
-        let (_, bits_after_last_boundary) = div_rem(position_bits, self.element_bits);
-
-        let remaining_data_unit_bits = self.data_bits - bits_after_last_boundary;
-
-        let next_element_position_bits = position_bits + remaining_data_unit_bits + self.pad_bits();
-
-        (next_element_position_bits / 8, remaining_data_unit_bits)
-    }
+// Returns the number of bytes holding `bits`, the last one of them possibly only partially.
+#[inline]
+fn bytes_needed(bits: usize) -> usize {
+    PaddedPosition::from_bits(bits as u64).bytes_needed() as usize
 }
 
-#[inline]
-fn div_rem(a: usize, b: usize) -> (usize, usize) {
-    let div = a / b;
-    let rem = a % b;
-    (div, rem)
+// Returns the number of raw data bytes stored in the padded `source` from raw data byte
+// `offset` on, the last one of them possibly only partially.
+fn raw_data_bytes_available(source: &[u8], offset: usize) -> usize {
+    let source_bits = Fr32Layout::unpadded_bit(source.len() as u64 * 8);
+    bytes_needed(source_bits.saturating_sub(offset as u64 * 8) as usize)
 }
 
 // TODO: The following extraction functions could be moved to a different file.
@@ -482,11 +364,11 @@ fn extract_bits_and_shift(input: &[u8], pos: usize, num_bits: usize, new_offset:
     // Determine from `pos` the number of full bytes that can be completely skipped
     // (`skip_bytes`), and the number of bits within the first byte of interest that
     // we'll start extracting from (`extraction_offset`).
-    let (skip_bytes, extraction_offset) = div_rem(pos, 8);
+    let (skip_bytes, extraction_offset) = (pos / 8, pos % 8);
 
     // (1).
     let input = &input[skip_bytes..];
-    let input = &input[..BitByte::from_bits(extraction_offset + num_bits).bytes_needed()];
+    let input = &input[..bytes_needed(extraction_offset + num_bits)];
 
     // (2).
     let mut output = match new_offset.cmp(&extraction_offset) {
@@ -507,7 +389,7 @@ fn extract_bits_and_shift(input: &[u8], pos: usize, num_bits: usize, new_offset:
     // After the shift we may not need the last byte of the `output` (either
     // because the left shift extended it by one byte or because the right shift
     // move the extraction span below that threshold).
-    if output.len() > BitByte::from_bits(new_offset + num_bits).bytes_needed() {
+    if output.len() > bytes_needed(new_offset + num_bits) {
         output.pop();
     }
     // TODO: Optimization: A more specialized shift would have just dropped
@@ -574,11 +456,7 @@ where
 {
     // Check that there's actually `len` raw data bytes encoded inside
     // `source` starting at `offset`.
-    let read_pos = BitByte::from_bits(FR32_PADDING_MAP.transform_bit_offset(offset * 8, true));
-    let raw_data_size = BitByte::from_bits(
-        FR32_PADDING_MAP.transform_bit_offset(source.len() * 8 - read_pos.total_bits(), false),
-    )
-    .bytes_needed();
+    let raw_data_size = raw_data_bytes_available(source, offset);
     if raw_data_size < len {
         return Err(Error::new(
             ErrorKind::Other,
@@ -603,7 +481,7 @@ where
     for chunk in source.chunks(chunk_size) {
         let write_len = min(len, chunk.len());
 
-        written += write_unpadded_aux(&FR32_PADDING_MAP, source, target, offset, write_len)?;
+        written += write_unpadded_aux(source, target, offset, write_len)?;
         offset += write_len;
         len -= write_len;
     }
@@ -631,7 +509,6 @@ layout is byte-aligned (no extra bits) the data inside it isn't (since
 we pad at the bit-level).
 **/
 fn write_unpadded_aux<W: ?Sized>(
-    padding_map: &PaddingMap,
     source: &[u8],
     target: &mut W,
     write_pos: usize,
@@ -642,30 +519,17 @@ where
 {
     // Position of the reader in the padded bit stream layout, deduced from
     // the position of the writer (`write_pos`) in the raw data layout.
-    let mut read_pos = BitByte::from_bits(padding_map.transform_bit_offset(write_pos * 8, true));
+    let mut read_pos = Fr32Layout::padded_position(write_pos as u64);
 
     // Specify the maximum data to recover (write) in bits, since the data unit
     // in the element (in contrast with the original raw data that generated it)
     // is not byte aligned.
     let max_write_size_bits = max_write_size * 8;
 
-    // Estimate how many bytes we'll need for the `raw_data` to allocate
-    // them all at once. We need to take into account both how much do
-    // we have left to read *and* write, and even then, since we may start
-    // in the middle of an element (`write_pos`) there's some variability
-    // as to how many padding bits will be encountered.
-    // Allow then an *over*-estimation error of 1 byte: `transform_bit_offset`
-    // has the implicit assumption that the data provided is starting at the
-    // beginning of an element, i.e., the padding bits are as far as possible,
-    // which maximizes the chances of not getting an extra `pad_bits` in the
-    // `source` (which are unpadded away and not carried to the `target`). That
-    // is, in this context `transform_bit_offset` is optimistic about the number
-    // of raw data bits we'll be able to recover from a fixed number of `source`
-    // bits.
-    let mut raw_data_size = BitByte::from_bits(
-        padding_map.transform_bit_offset(source.len() * 8 - read_pos.total_bits(), false),
-    )
-    .bytes_needed();
+    // How many bytes we'll need for the `raw_data`, to allocate them all at
+    // once: what is left to read from `write_pos` on, but no more than what
+    // we have left to write.
+    let mut raw_data_size = raw_data_bytes_available(source, write_pos);
     raw_data_size = min(raw_data_size, max_write_size);
 
     // Recovered raw data unpadded from the `source` which will
@@ -680,16 +544,20 @@ where
     let mut write_bit_offset = 0;
 
     // If there is no more data to read or no more space to write stop.
-    while read_pos.bytes < source.len() && written_bits < max_write_size_bits {
+    while (read_pos.byte as usize) < source.len() && written_bits < max_write_size_bits {
         // (1): Find the element boundary and, assuming that there is a full
         //      unit of data (which actually may be incomplete), how many bits
         //      are left to read from `read_pos`.
-        let (next_element_position, mut bits_to_extract) = padding_map.next_boundary(&read_pos);
+        let next_element_position = read_pos.next_element() * Fr32Layout::ELEMENT_BYTES;
+        let mut bits_to_extract = read_pos.data_bits_left() as usize;
 
         // (2): As the element may be incomplete check how much data is
         //      actually available so as not to access the `source` past
         //      its limit.
-        bits_to_extract = min(bits_to_extract, source.len() * 8 - read_pos.total_bits());
+        bits_to_extract = min(
+            bits_to_extract,
+            source.len() * 8 - read_pos.total_bits() as usize,
+        );
 
         // (3): Don't read more than `max_write_size`.
         let bits_left_to_write = max_write_size_bits - written_bits;
@@ -702,7 +570,7 @@ where
         // the same (since the added padding bits shift it).
         let mut recovered = extract_bits_and_shift(
             source,
-            read_pos.total_bits(),
+            read_pos.total_bits() as usize,
             bits_to_extract,
             write_bit_offset,
         );
@@ -726,9 +594,9 @@ where
 
         // Position the reader in the next element boundary, this will be ignored
         // if we already hit limits (2) or (3) (in that case this was the last iteration).
-        read_pos = BitByte {
-            bytes: next_element_position,
-            bits: 0,
+        read_pos = PaddedPosition {
+            byte: next_element_position,
+            bit: 0,
         };
     }
 
@@ -736,10 +604,8 @@ where
     // Instead, write it incrementally –
     // but ONLY when the bits waiting in bits_out are byte-aligned. i.e. a multiple of 8
 
-    // Check that our estimated size was correct, allow it to be overestimated
-    // (not *under*) by 1 byte.
-    debug_assert!(raw_data_size - raw_data.len() <= 1);
-    debug_assert!(raw_data_size >= raw_data.len());
+    // Check that our estimated size was correct.
+    debug_assert_eq!(raw_data_size, raw_data.len());
 
     target.write_all(&raw_data)?;
 
@@ -764,18 +630,6 @@ mod tests {
         0xe5,
     ];
 
-    #[test]
-    fn test_position() {
-        let mut bits = 0;
-        for i in 0..10 {
-            for j in 0..8 {
-                let position = BitByte { bytes: i, bits: j };
-                assert_eq!(position.total_bits(), bits);
-                bits += 1;
-            }
-        }
-    }
-
     // Test the `extract_bits_le` function against the `BitVec` functionality
     // (assumed to be correct).
     #[test]
@@ -846,16 +700,16 @@ mod tests {
 
         for data_unit in raw_data
             .into_iter()
-            .chunks(FR32_PADDING_MAP.data_bits)
+            .chunks(Fr32Layout::DATA_BITS as usize)
             .into_iter()
         {
             padded_data.extend(data_unit);
 
             // To avoid reconverting the iterator, we deduce if we need the padding
             // by the length of `padded_data`: a full data unit would not leave the
-            // padded layout aligned (it would leave it unaligned by just `pad_bits()`).
+            // padded layout aligned (it would leave it unaligned by just its padding bits).
             if padded_data.len() % 8 != 0 {
-                for _ in 0..FR32_PADDING_MAP.pad_bits() {
+                for _ in Fr32Layout::DATA_BITS..Fr32Layout::ELEMENT_BITS {
                     padded_data.push(false);
                 }
             }
@@ -876,10 +730,7 @@ mod tests {
             .read_to_end(&mut padded)
             .expect("in-memory read failed");
 
-        assert_eq!(
-            padded.len(),
-            FR32_PADDING_MAP.transform_byte_offset(len, true)
-        );
+        assert_eq!(padded.len() as u64, Fr32Layout::padded_len(len as u64));
 
         let mut unpadded = Vec::new();
         let unpadded_written =
@@ -0,0 +1,349 @@
+use std::cmp::min;
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem::size_of;
+
+use crate::{
+    reader::{MASK_SKIP_HIGH_2, NUM_BYTES_IN_BLOCK, NUM_BYTES_OUT_BLOCK, NUM_U128S_PER_BLOCK},
+    to_unpadded_bytes,
+};
+
+/// An `io::Reader` that converts `Fr32` padded input back into unpadded output, the inverse of
+/// `Fr32Reader`.
+///
+/// Seeking is done in unpadded bytes and requires the padded data to start at the beginning of
+/// the source.
+pub struct Fr32Unpadder<R> {
+    /// The padded source.
+    source: R,
+    /// Currently read padded block.
+    in_buffer: [u8; NUM_BYTES_OUT_BLOCK],
+    /// Currently writing out block, only the first 127 bytes are ever valid.
+    out_buffer: [u8; NUM_BYTES_OUT_BLOCK],
+    /// The current offset into the `out_buffer` in bytes.
+    out_offset: usize,
+    /// How many bytes of the `out_buffer` are valid.
+    available_bytes: usize,
+    /// Bytes to skip in the next block, after a seek into its middle.
+    skip_bytes: usize,
+    /// The position in the unpadded output.
+    position: u64,
+    /// Are we done reading?
+    done: bool,
+}
+
+macro_rules! unprocess_fr {
+    (
+        $out_buffer:expr,
+        $in0:expr,
+        $in1:expr,
+        $bit_offset:expr
+    ) => {{
+        let in1 = $in1 & MASK_SKIP_HIGH_2; // skip high 2 bits
+        $out_buffer[0] |= $in0 << $bit_offset;
+        $out_buffer[1] = $in0 >> 128 - $bit_offset;
+        $out_buffer[1] |= in1 << $bit_offset;
+        $out_buffer[2] = in1 >> 128 - $bit_offset;
+    }};
+}
+
+impl<R: Read> Fr32Unpadder<R> {
+    pub fn new(source: R) -> Self {
+        Fr32Unpadder {
+            source,
+            in_buffer: [0; NUM_BYTES_OUT_BLOCK],
+            out_buffer: [0; NUM_BYTES_OUT_BLOCK],
+            out_offset: 0,
+            available_bytes: 0,
+            skip_bytes: 0,
+            position: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the padded source.
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Processes a single block in in_buffer, writing the result to out_buffer.
+    fn process_block(&mut self) {
+        let mut in_buffer = [0u128; NUM_U128S_PER_BLOCK];
+        for (val, bytes) in in_buffer
+            .iter_mut()
+            .zip(self.in_buffer.chunks_exact(size_of::<u128>()))
+        {
+            *val = u128::from_le_bytes(bytes.try_into().expect("chunk of 16 bytes"));
+        }
+        let mut out = [0u128; NUM_U128S_PER_BLOCK];
+
+        // 0..254
+        {
+            out[0] = in_buffer[0];
+            out[1] = in_buffer[1] & MASK_SKIP_HIGH_2;
+        }
+        // 254..508
+        unprocess_fr!(&mut out[1..], in_buffer[2], in_buffer[3], 126);
+        // 508..762
+        unprocess_fr!(&mut out[3..], in_buffer[4], in_buffer[5], 124);
+        // 762..1016
+        unprocess_fr!(&mut out[5..], in_buffer[6], in_buffer[7], 122);
+
+        for (bytes, val) in self
+            .out_buffer
+            .chunks_exact_mut(size_of::<u128>())
+            .zip(out.iter())
+        {
+            bytes.copy_from_slice(&val.to_le_bytes());
+        }
+
+        // Reset buffer offset.
+        self.out_offset = 0;
+    }
+
+    fn fill_in_buffer(&mut self) -> io::Result<usize> {
+        let mut bytes_read = 0;
+        let mut buf = &mut self.in_buffer[..];
+
+        while !buf.is_empty() {
+            match self.source.read(buf) {
+                Ok(0) => {
+                    break;
+                }
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    bytes_read += n;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Clear unfilled memory.
+        for val in &mut self.in_buffer[bytes_read..] {
+            *val = 0;
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Read> Read for Fr32Unpadder<R> {
+    fn read(&mut self, target: &mut [u8]) -> io::Result<usize> {
+        // The number of bytes already read and written into `target`.
+        let mut bytes_read = 0;
+
+        while bytes_read < target.len() {
+            // Load and process the next block, if no bytes are available anymore.
+            if self.out_offset == self.available_bytes {
+                if self.done {
+                    break;
+                }
+
+                let padded_bytes = self.fill_in_buffer()?;
+                // Only a full block reads as all 127 bytes, a partial one rounds down.
+                self.done = padded_bytes < NUM_BYTES_OUT_BLOCK;
+                if padded_bytes == 0 {
+                    self.available_bytes = 0;
+                    self.out_offset = 0;
+                    break;
+                }
+
+                self.process_block();
+                self.available_bytes = to_unpadded_bytes(padded_bytes as u64) as usize;
+                self.out_offset = min(self.skip_bytes, self.available_bytes);
+                self.skip_bytes = 0;
+                continue;
+            }
+
+            // Write out as many bytes as available and requested.
+            let len = min(
+                self.available_bytes - self.out_offset,
+                target.len() - bytes_read,
+            );
+            target[bytes_read..bytes_read + len]
+                .copy_from_slice(&self.out_buffer[self.out_offset..self.out_offset + len]);
+            bytes_read += len;
+            self.out_offset += len;
+        }
+
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for Fr32Unpadder<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if let SeekFrom::Current(0) = pos {
+            return Ok(self.position);
+        }
+
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => offset_by(self.position, delta),
+            SeekFrom::End(delta) => {
+                let padded_len = self.source.seek(SeekFrom::End(0))?;
+                offset_by(to_unpadded_bytes(padded_len), delta)
+            }
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        // Map the position onto the start of its padded block, the rest is skipped.
+        let block = position / NUM_BYTES_IN_BLOCK as u64;
+        self.source
+            .seek(SeekFrom::Start(block * NUM_BYTES_OUT_BLOCK as u64))?;
+        self.out_offset = 0;
+        self.available_bytes = 0;
+        self.skip_bytes = (position % NUM_BYTES_IN_BLOCK as u64) as usize;
+        self.position = position;
+        self.done = false;
+
+        Ok(position)
+    }
+}
+
+fn offset_by(position: u64, delta: i64) -> Option<u64> {
+    if delta < 0 {
+        position.checked_sub(delta.unsigned_abs())
+    } else {
+        position.checked_add(delta as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+    use proptest::{collection::vec, prelude::any, proptest};
+    use rand::random;
+
+    use crate::{write_unpadded, Fr32Reader};
+
+    fn pad(data: &[u8]) -> Vec<u8> {
+        let mut padded = Vec::new();
+        Fr32Reader::new(Cursor::new(data))
+            .read_to_end(&mut padded)
+            .expect("in-memory read failed");
+        padded
+    }
+
+    /// Checks that `unpadded` is `data`, followed by the zeroes of the last padded `Fr32`.
+    fn assert_unpadded(data: &[u8], padded: &[u8], unpadded: &[u8]) {
+        assert_eq!(
+            unpadded.len() as u64,
+            to_unpadded_bytes(padded.len() as u64)
+        );
+        assert_eq!(&unpadded[..data.len()], data);
+        assert!(unpadded[data.len()..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_simple_127() {
+        let data = vec![255u8; 127];
+        let padded = pad(&data);
+
+        let mut unpadded = Vec::new();
+        Fr32Unpadder::new(Cursor::new(&padded))
+            .read_to_end(&mut unpadded)
+            .expect("in-memory read failed");
+
+        assert_eq!(unpadded, data);
+    }
+
+    #[test]
+    fn test_matches_write_unpadded() {
+        let data: Vec<u8> = (0..127 * 9).map(|_| random::<u8>()).collect();
+        let padded = pad(&data);
+
+        let mut expected = Vec::new();
+        write_unpadded(&padded, &mut expected, 0, data.len()).expect("write_unpadded failed");
+
+        let mut unpadded = Vec::new();
+        Fr32Unpadder::new(Cursor::new(&padded))
+            .read_to_end(&mut unpadded)
+            .expect("in-memory read failed");
+
+        assert_eq!(unpadded, expected);
+    }
+
+    #[test]
+    fn test_seek_relative() {
+        let padded = pad(&[1u8; 254]);
+        let mut unpadder = Fr32Unpadder::new(Cursor::new(&padded));
+
+        assert!(unpadder.seek(SeekFrom::Current(-1)).is_err());
+        assert_eq!(unpadder.seek(SeekFrom::End(-4)).expect("seek failed"), 250);
+        assert_eq!(
+            unpadder.seek(SeekFrom::Current(2)).expect("seek failed"),
+            252
+        );
+
+        let mut unpadded = Vec::new();
+        unpadder
+            .read_to_end(&mut unpadded)
+            .expect("in-memory read failed");
+        assert_eq!(unpadded, vec![1, 1]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(data in vec(any::<u8>(), 0..1024)) {
+            let padded = pad(&data);
+
+            let mut unpadded = Vec::new();
+            Fr32Unpadder::new(Cursor::new(&padded))
+                .read_to_end(&mut unpadded)
+                .expect("in-memory read failed");
+
+            assert_unpadded(&data, &padded, &unpadded);
+        }
+
+        #[test]
+        fn test_roundtrip_chained(data in vec(any::<u8>(), 0..1024), chunk_size in 1usize..300) {
+            let mut unpadder = Fr32Unpadder::new(Fr32Reader::new(Cursor::new(&data)));
+
+            // Read in small chunks, which do not line up with the blocks.
+            let mut unpadded = Vec::new();
+            let mut chunk = vec![0u8; chunk_size];
+            loop {
+                let n = unpadder.read(&mut chunk).expect("in-memory read failed");
+                if n == 0 {
+                    break;
+                }
+                unpadded.extend_from_slice(&chunk[..n]);
+            }
+
+            assert_unpadded(&data, &pad(&data), &unpadded);
+        }
+
+        #[test]
+        fn test_seek(data in vec(any::<u8>(), 1..1024), offset in any::<usize>(), len in 0usize..300) {
+            let offset = offset % data.len();
+            let len = min(len, data.len() - offset);
+            let padded = pad(&data);
+
+            let mut unpadder = Fr32Unpadder::new(Cursor::new(&padded));
+            // Read a little first, so that seeking has to drop a buffered block.
+            let mut byte = [0u8];
+            unpadder.read_exact(&mut byte).expect("in-memory read failed");
+            assert_eq!(unpadder.seek(SeekFrom::Start(offset as u64)).expect("seek failed"), offset as u64);
+
+            let mut unpadded = vec![0u8; len];
+            unpadder.read_exact(&mut unpadded).expect("in-memory read failed");
+            assert_eq!(&unpadded[..], &data[offset..offset + len]);
+            assert_eq!(unpadder.stream_position().expect("seek failed"), (offset + len) as u64);
+
+            let mut expected = Vec::new();
+            write_unpadded(&padded, &mut expected, offset, len).expect("write_unpadded failed");
+            assert_eq!(unpadded, expected);
+        }
+    }
+}
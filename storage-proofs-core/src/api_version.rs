@@ -3,8 +3,9 @@ use std::str::FromStr;
 
 use anyhow::{format_err, Error, Result};
 use semver::Version;
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum ApiVersion {
     V1_0_0,
     V1_1_0,
@@ -7,6 +7,8 @@ pub enum CacheKey {
     CommDTree,
     CommCTree,
     CommRLastTree,
+    PreCommitPhase1Progress,
+    FauxPieceRoots,
 }
 
 impl Display for CacheKey {
@@ -17,6 +19,8 @@ impl Display for CacheKey {
             CacheKey::CommDTree => write!(f, "tree-d"),
             CacheKey::CommCTree => write!(f, "tree-c"),
             CacheKey::CommRLastTree => write!(f, "tree-r-last"),
+            CacheKey::PreCommitPhase1Progress => write!(f, "pc1-progress"),
+            CacheKey::FauxPieceRoots => write!(f, "faux-piece-roots"),
         }
     }
 }
@@ -18,11 +18,13 @@ use rayon::prelude::{
 };
 
 use crate::{
+    control::{Progress, SealControl},
     error::Result,
     multi_proof::MultiProof,
     parameter_cache::{CacheableParameters, ParameterSetMetadata},
     partitions::partition_count,
     proof::ProofScheme,
+    synthesis::{self, SynthesizedCircuit},
 };
 
 #[derive(Clone)]
@@ -83,22 +85,46 @@ where
         pub_in: &S::PublicInputs,
         priv_in: &S::PrivateInputs,
         groth_params: &'b groth16::MappedParameters<Bls12>,
+    ) -> Result<MultiProof<'b>> {
+        Self::prove_with_control(
+            pub_params,
+            pub_in,
+            priv_in,
+            groth_params,
+            &SealControl::default(),
+        )
+    }
+
+    /// Like `prove`, but reports progress to `control` and stops with `Error::Cancelled`
+    /// before generating the vanilla or the circuit proofs, if `control` was cancelled.
+    fn prove_with_control<'b>(
+        pub_params: &PublicParams<'a, S>,
+        pub_in: &S::PublicInputs,
+        priv_in: &S::PrivateInputs,
+        groth_params: &'b groth16::MappedParameters<Bls12>,
+        control: &SealControl,
     ) -> Result<MultiProof<'b>> {
         let partition_count = Self::partition_count(pub_params);
 
         // This will always run at least once, since there cannot be zero partitions.
         ensure!(partition_count > 0, "There must be partitions");
 
+        control.check()?;
         info!("vanilla_proofs:start");
         let vanilla_proofs =
             S::prove_all_partitions(&pub_params.vanilla_params, pub_in, priv_in, partition_count)?;
 
         info!("vanilla_proofs:finish");
+        control.report(Progress::VanillaProofs {
+            done: partition_count,
+            total: partition_count,
+        });
 
         let sanity_check =
             S::verify_all_partitions(&pub_params.vanilla_params, pub_in, &vanilla_proofs)?;
         ensure!(sanity_check, "sanity check failed");
 
+        control.check()?;
         info!("snark_proof:start");
         let groth_proofs = Self::circuit_proofs(
             pub_in,
@@ -108,6 +134,10 @@ where
             pub_params.priority,
         )?;
         info!("snark_proof:finish");
+        control.report(Progress::SnarkProofs {
+            done: partition_count,
+            total: partition_count,
+        });
 
         Ok(MultiProof::new(groth_proofs, &groth_params.pvk))
     }
@@ -237,12 +267,38 @@ where
         priority: bool,
     ) -> Result<Vec<groth16::Proof<Bls12>>> {
         let mut rng = OsRng;
+        let circuits = Self::partition_circuits(pub_in, vanilla_proofs, pub_params)?;
+
+        let groth_proofs = if priority {
+            create_random_proof_batch_in_priority(circuits, groth_params, &mut rng)?
+        } else {
+            create_random_proof_batch(circuits, groth_params, &mut rng)?
+        };
+
+        groth_proofs
+            .into_iter()
+            .map(|groth_proof| {
+                let mut proof_vec = Vec::new();
+                groth_proof.write(&mut proof_vec)?;
+                let gp = groth16::Proof::<Bls12>::read(&proof_vec[..])?;
+                Ok(gp)
+            })
+            .collect()
+    }
+
+    /// partition_circuits constructs the circuit of each partition, the k-th vanilla proof
+    /// belonging to partition k.
+    fn partition_circuits(
+        pub_in: &S::PublicInputs,
+        vanilla_proofs: Vec<S::Proof>,
+        pub_params: &S::PublicParams,
+    ) -> Result<Vec<C>> {
         ensure!(
             !vanilla_proofs.is_empty(),
             "cannot create a circuit proof over missing vanilla proofs"
         );
 
-        let circuits = vanilla_proofs
+        vanilla_proofs
             .into_par_iter()
             .enumerate()
             .map(|(k, vanilla_proof)| {
@@ -254,23 +310,32 @@ where
                     Some(k),
                 )
             })
-            .collect::<Result<Vec<_>>>()?;
+            .collect()
+    }
 
-        let groth_proofs = if priority {
-            create_random_proof_batch_in_priority(circuits, groth_params, &mut rng)?
-        } else {
-            create_random_proof_batch(circuits, groth_params, &mut rng)?
-        };
+    /// synthesize_circuits is the first half of circuit_proofs: it synthesizes the circuit of
+    /// each partition and returns the assignments, without generating groth proofs. The result
+    /// can be written to disk and proven later, or in another process, with prove_synthesized.
+    fn synthesize_circuits(
+        pub_in: &S::PublicInputs,
+        vanilla_proofs: Vec<S::Proof>,
+        pub_params: &S::PublicParams,
+    ) -> Result<Vec<SynthesizedCircuit>> {
+        let circuits = Self::partition_circuits(pub_in, vanilla_proofs, pub_params)?;
 
-        groth_proofs
-            .into_iter()
-            .map(|groth_proof| {
-                let mut proof_vec = Vec::new();
-                groth_proof.write(&mut proof_vec)?;
-                let gp = groth16::Proof::<Bls12>::read(&proof_vec[..])?;
-                Ok(gp)
-            })
-            .collect()
+        synthesis::synthesize_circuits(circuits)
+    }
+
+    /// prove_synthesized is the second half of circuit_proofs: it generates a groth proof for
+    /// each circuit returned by synthesize_circuits, in the same order.
+    fn prove_synthesized(
+        synthesized: Vec<SynthesizedCircuit>,
+        groth_params: &groth16::MappedParameters<Bls12>,
+        priority: bool,
+    ) -> Result<Vec<groth16::Proof<Bls12>>> {
+        let mut rng = OsRng;
+
+        synthesis::prove_synthesized(synthesized, groth_params, &mut rng, priority)
     }
 
     /// Given a prover_srs key, a list of groth16 proofs, and an ordered list of seeds
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::error::{Error, Result};
+
+/// Progress reported by long running sealing and proving operations.
+///
+/// Node and tree counts are cumulative within their stage, so a sink can simply display the
+/// latest event without keeping any state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// `nodes` out of `total` labels of `layer` (1-based, out of `layers`) have been generated.
+    Labels {
+        layer: usize,
+        layers: usize,
+        nodes: usize,
+        total: usize,
+    },
+    /// `built` out of `total` base trees of tree_c have been built.
+    TreeC { built: usize, total: usize },
+    /// `built` out of `total` base trees of tree_r_last have been encoded and built.
+    TreeRLast { built: usize, total: usize },
+    /// Vanilla proofs for `done` out of `total` partitions were generated.
+    VanillaProofs { done: usize, total: usize },
+    /// Groth16 proofs for `done` out of `total` partitions were generated.
+    SnarkProofs { done: usize, total: usize },
+}
+
+/// Receives the `Progress` events of an operation.
+///
+/// Events are delivered from the thread doing the work, so implementations should return
+/// quickly, e.g. by forwarding the event over a channel.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, progress: Progress);
+}
+
+impl<F: Fn(Progress) + Send + Sync> ProgressSink for F {
+    fn report(&self, progress: Progress) {
+        self(progress)
+    }
+}
+
+/// Allows to cancel, and observe the progress of, a sealing or proving operation.
+///
+/// Cancellation is cooperative: the operation checks for it at safe points only, that is where
+/// stopping leaves the cache directory in a state from which the operation can be started over
+/// (or resumed). It then returns `Error::Cancelled`. Clones share the same cancellation state,
+/// so a clone can be handed to the thread that decides to abort.
+#[derive(Clone, Default)]
+pub struct SealControl {
+    cancelled: Arc<AtomicBool>,
+    sink: Option<Arc<dyn ProgressSink>>,
+}
+
+impl fmt::Debug for SealControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealControl")
+            .field("cancelled", &self.is_cancelled())
+            .field("sink", &self.sink.is_some())
+            .finish()
+    }
+}
+
+impl SealControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports all progress of the controlled operation to `sink`.
+    pub fn with_progress<S: ProgressSink + 'static>(mut self, sink: S) -> Self {
+        self.sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Requests the controlled operation to stop at its next safe point.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Marks a safe point, returning `Error::Cancelled` if cancellation was requested.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled.into());
+        }
+        Ok(())
+    }
+
+    pub fn report(&self, progress: Progress) {
+        if let Some(sink) = &self.sink {
+            sink.report(progress);
+        }
+    }
+}
+
+/// Returns true if `err` was caused by cancelling the operation through a `SealControl`.
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain()
+        .any(|cause| matches!(cause.downcast_ref::<Error>(), Some(Error::Cancelled)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use anyhow::Context;
+
+    #[test]
+    fn test_cancel_shared_between_clones() {
+        let control = SealControl::new();
+        let clone = control.clone();
+        assert!(control.check().is_ok());
+
+        clone.cancel();
+        assert!(control.is_cancelled());
+
+        let err = control
+            .check()
+            .context("while sealing")
+            .expect_err("cancelled control must fail");
+        assert!(is_cancelled(&err));
+        assert!(!is_cancelled(&anyhow::anyhow!("other")));
+    }
+
+    #[test]
+    fn test_progress_sink() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let control =
+            SealControl::new().with_progress(move |p| recorded.lock().expect("poisoned").push(p));
+
+        control.report(Progress::TreeC { built: 1, total: 8 });
+        SealControl::default().report(Progress::TreeC { built: 2, total: 8 });
+
+        assert_eq!(
+            *events.lock().expect("poisoned"),
+            vec![Progress::TreeC { built: 1, total: 8 }]
+        );
+    }
+}
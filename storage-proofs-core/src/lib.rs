@@ -14,6 +14,7 @@ use std::convert::TryInto;
 pub mod api_version;
 pub mod cache_key;
 pub mod compound_proof;
+pub mod control;
 pub mod crypto;
 pub mod data;
 pub mod drgraph;
@@ -29,6 +30,7 @@ pub mod por;
 pub mod proof;
 pub mod sector;
 pub mod settings;
+pub mod synthesis;
 pub mod test_helper;
 pub mod util;
 
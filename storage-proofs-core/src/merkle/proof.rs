@@ -1,12 +1,13 @@
 #![allow(clippy::len_without_is_empty)]
 
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::slice::Iter;
 
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use blstrs::Scalar as Fr;
-use filecoin_hashers::{Hasher, PoseidonArity};
+use filecoin_hashers::{Domain, Hasher, PoseidonArity};
 use generic_array::typenum::{Unsigned, U0};
 use merkletree::hash::Algorithm;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -473,6 +474,185 @@ impl<
     }
 }
 
+impl<
+        H: Hasher,
+        BaseArity: PoseidonArity,
+        SubTreeArity: PoseidonArity,
+        TopTreeArity: PoseidonArity,
+    > MerkleProof<H, BaseArity, SubTreeArity, TopTreeArity>
+{
+    /// Converts the proof into a `CompactMerkleProof`, adding its root and path nodes to `pool`.
+    pub fn to_compact(&self, pool: &mut NodePool<H::Domain>) -> CompactMerkleProof<H::Domain> {
+        let (paths, root, leaf) = match self.data {
+            ProofData::Single(ref proof) => (
+                vec![compact_path(&proof.path, pool)],
+                proof.root,
+                proof.leaf,
+            ),
+            ProofData::Sub(ref proof) => (
+                vec![
+                    compact_path(&proof.base_proof, pool),
+                    compact_path(&proof.sub_proof, pool),
+                ],
+                proof.root,
+                proof.leaf,
+            ),
+            ProofData::Top(ref proof) => (
+                vec![
+                    compact_path(&proof.base_proof, pool),
+                    compact_path(&proof.sub_proof, pool),
+                    compact_path(&proof.top_proof, pool),
+                ],
+                proof.root,
+                proof.leaf,
+            ),
+        };
+
+        CompactMerkleProof {
+            leaf,
+            root: pool.insert(root),
+            paths,
+        }
+    }
+
+    /// Rebuilds a proof from its `CompactMerkleProof`, looking its nodes up in `pool`.
+    pub fn from_compact(
+        compact: &CompactMerkleProof<H::Domain>,
+        pool: &NodePool<H::Domain>,
+    ) -> Result<Self> {
+        let root = pool.get(compact.root)?;
+        let leaf = compact.leaf;
+
+        let data = match compact.paths.as_slice() {
+            [base] => ProofData::Single(SingleProof::new(expand_path(base, pool)?, root, leaf)),
+            [base, sub] => ProofData::Sub(SubProof::new(
+                expand_path(base, pool)?,
+                expand_path(sub, pool)?,
+                root,
+                leaf,
+            )),
+            [base, sub, top] => ProofData::Top(TopProof::new(
+                expand_path(base, pool)?,
+                expand_path(sub, pool)?,
+                expand_path(top, pool)?,
+                root,
+                leaf,
+            )),
+            paths => bail!(
+                "compact merkle proof has {} paths, expected 1 to 3",
+                paths.len()
+            ),
+        };
+
+        Ok(MerkleProof { data })
+    }
+}
+
+/// Nodes referenced by a set of `CompactMerkleProof`s, each stored once.
+///
+/// Proofs into the same tree share its root and, for nearby or numerous challenges, most of
+/// the path nodes close to it, so the pool is much smaller than the paths it replaces.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct NodePool<D: Domain> {
+    nodes: Vec<D>,
+    /// Position of each node in `nodes`. It is not serialized, so a pool that was
+    /// deserialized does not deduplicate nodes inserted afterwards.
+    #[serde(skip)]
+    indices: HashMap<D, u32>,
+}
+
+impl<D: Domain> NodePool<D> {
+    pub fn new() -> Self {
+        NodePool {
+            nodes: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Adds `node` unless it is already present, and returns its index.
+    pub fn insert(&mut self, node: D) -> u32 {
+        let nodes = &mut self.nodes;
+        *self.indices.entry(node).or_insert_with(|| {
+            nodes.push(node);
+            (nodes.len() - 1) as u32
+        })
+    }
+
+    pub fn get(&self, index: u32) -> Result<D> {
+        self.nodes
+            .get(index as usize)
+            .copied()
+            .with_context(|| format!("node {} is not in the pool of {}", index, self.nodes.len()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// A `MerkleProof` whose root and path nodes are stored as indices into a `NodePool`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct CompactMerkleProof<D: Domain> {
+    leaf: D,
+    root: u32,
+    /// The base, sub and top tree paths, as far as present. Each level holds the indices of
+    /// the sibling nodes and the position of the node among them.
+    paths: Vec<Vec<(Vec<u32>, u8)>>,
+}
+
+fn compact_path<H: Hasher, Arity: PoseidonArity>(
+    path: &InclusionPath<H, Arity>,
+    pool: &mut NodePool<H::Domain>,
+) -> Vec<(Vec<u32>, u8)> {
+    path.iter()
+        .map(|element| {
+            let hashes = element
+                .hashes
+                .iter()
+                .map(|hash| pool.insert(*hash))
+                .collect();
+            (hashes, element.index as u8)
+        })
+        .collect()
+}
+
+fn expand_path<H: Hasher, Arity: PoseidonArity>(
+    path: &[(Vec<u32>, u8)],
+    pool: &NodePool<H::Domain>,
+) -> Result<InclusionPath<H, Arity>> {
+    let arity = Arity::to_usize();
+    ensure!(
+        arity > 0,
+        "compact merkle proof has more paths than the tree has layers"
+    );
+
+    path.iter()
+        .map(|(hashes, index)| {
+            ensure!(
+                hashes.len() == arity - 1 && (*index as usize) < arity,
+                "invalid compact merkle proof path element for arity {}",
+                arity
+            );
+
+            Ok(PathElement {
+                hashes: hashes
+                    .iter()
+                    .map(|&hash| pool.get(hash))
+                    .collect::<Result<_>>()?,
+                index: *index as usize,
+                _arity: PhantomData,
+            })
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Into::into)
+}
+
 /// Converts a merkle_light proof to a SingleProof
 fn proof_to_single<H: Hasher, Arity: PoseidonArity, TargetArity: PoseidonArity>(
     proof: &merkletree::proof::Proof<H::Domain, Arity>,
@@ -733,6 +913,75 @@ mod tests {
         }
     }
 
+    fn compact_merklepaths<
+        H: 'static + Hasher,
+        A: 'static + PoseidonArity,
+        B: 'static + PoseidonArity,
+        C: 'static + PoseidonArity,
+    >() {
+        type Tree<H, A, B, C> = MerkleTreeWrapper<H, DiskStore<<H as Hasher>::Domain>, A, B, C>;
+        let nodes = 64 * get_base_tree_count::<Tree<H, A, B, C>>();
+
+        let mut rng = thread_rng();
+        let (_data, tree) = generate_tree::<Tree<H, A, B, C>, _>(&mut rng, nodes, None);
+
+        let proofs: Vec<_> = (0..nodes)
+            .map(|i| tree.gen_proof(i).expect("gen_proof failure"))
+            .collect();
+
+        let mut pool = NodePool::new();
+        let compact: Vec<_> = proofs
+            .iter()
+            .map(|proof| proof.to_compact(&mut pool))
+            .collect();
+        let path_nodes: usize = proofs
+            .iter()
+            .map(|proof| {
+                proof
+                    .path()
+                    .iter()
+                    .map(|(hashes, _)| hashes.len())
+                    .sum::<usize>()
+            })
+            .sum();
+        assert!(pool.len() < path_nodes);
+
+        let pool: NodePool<H::Domain> =
+            bincode::deserialize(&bincode::serialize(&pool).expect("serialize failure"))
+                .expect("deserialize failure");
+        for (i, (proof, compact)) in proofs.iter().zip(compact.iter()).enumerate() {
+            let decoded = MerkleProof::<H, A, B, C>::from_compact(compact, &pool)
+                .expect("from_compact failure");
+            assert!(decoded.verify());
+            assert!(decoded.validate(i));
+            assert_eq!(decoded.root(), proof.root());
+            assert_eq!(decoded.leaf(), proof.leaf());
+            assert_eq!(decoded.path(), proof.path());
+        }
+
+        assert!(MerkleProof::<H, A, B, C>::from_compact(&compact[0], &NodePool::new()).is_err());
+    }
+
+    #[test]
+    fn compact_merklepaths_poseidon_8() {
+        compact_merklepaths::<PoseidonHasher, U8, U0, U0>();
+    }
+
+    #[test]
+    fn compact_merklepaths_poseidon_8_2() {
+        compact_merklepaths::<PoseidonHasher, U8, U2, U0>();
+    }
+
+    #[test]
+    fn compact_merklepaths_poseidon_8_4_2() {
+        compact_merklepaths::<PoseidonHasher, U8, U4, U2>();
+    }
+
+    #[test]
+    fn compact_merklepaths_sha256_2() {
+        compact_merklepaths::<Sha256Hasher, U2, U0, U0>();
+    }
+
     #[test]
     fn merklepath_poseidon_2() {
         merklepath::<
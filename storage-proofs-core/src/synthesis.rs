@@ -0,0 +1,252 @@
+//! Circuit synthesis and Groth16 proving as two separate steps.
+//!
+//! `bellperson::groth16::create_random_proof_batch` synthesizes circuits and proves them in a
+//! single call. `synthesize_circuits` stops after synthesis and returns a serializable
+//! `SynthesizedCircuit` per circuit, which `prove_synthesized` turns into a proof later, possibly
+//! in another process or on another host. Both steps are bellperson's own
+//! `synthesize_circuits_batch` and `prove_from_assignments`, so the resulting proofs are
+//! interchangeable with the ones `create_random_proof_batch` creates.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use anyhow::{ensure, Context};
+use bellperson::{
+    groth16::{self, ParameterSource, Proof, ProvingAssignment},
+    multiexp::DensityTracker,
+    Circuit,
+};
+use blstrs::{Bls12, Scalar as Fr};
+use ff::Field;
+use log::info;
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Result;
+
+/// The witness of a synthesized circuit: the evaluations of the A, B and C polynomials at each
+/// constraint, the input and auxiliary assignments, and which variables the A and B queries use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SynthesizedCircuit(ProvingAssignment<Fr>);
+
+impl SynthesizedCircuit {
+    pub fn num_constraints(&self) -> usize {
+        self.0.a.len()
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.0.input_assignment.len()
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.0.aux_assignment.len()
+    }
+
+    /// Writes the circuit to `path`, e.g. a spool directory shared with the proving process.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("could not create synthesized circuit {:?}", path))?;
+        bincode::serialize_into(BufWriter::new(file), self)
+            .with_context(|| format!("could not write synthesized circuit {:?}", path))
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("could not open synthesized circuit {:?}", path))?;
+        bincode::deserialize_from(BufReader::new(file))
+            .with_context(|| format!("could not read synthesized circuit {:?}", path))
+    }
+}
+
+/// The serialized form of a `SynthesizedCircuit`.
+#[derive(Serialize)]
+struct SynthesizedCircuitRef<'a> {
+    #[serde(with = "scalars")]
+    a: &'a [Fr],
+    #[serde(with = "scalars")]
+    b: &'a [Fr],
+    #[serde(with = "scalars")]
+    c: &'a [Fr],
+    #[serde(with = "scalars")]
+    input_assignment: &'a [Fr],
+    #[serde(with = "scalars")]
+    aux_assignment: &'a [Fr],
+    #[serde(with = "density")]
+    a_aux_density: &'a DensityTracker,
+    #[serde(with = "density")]
+    b_input_density: &'a DensityTracker,
+    #[serde(with = "density")]
+    b_aux_density: &'a DensityTracker,
+}
+
+#[derive(Deserialize)]
+struct SynthesizedCircuitOwned {
+    #[serde(deserialize_with = "scalars::deserialize")]
+    a: Vec<Fr>,
+    #[serde(deserialize_with = "scalars::deserialize")]
+    b: Vec<Fr>,
+    #[serde(deserialize_with = "scalars::deserialize")]
+    c: Vec<Fr>,
+    #[serde(deserialize_with = "scalars::deserialize")]
+    input_assignment: Vec<Fr>,
+    #[serde(deserialize_with = "scalars::deserialize")]
+    aux_assignment: Vec<Fr>,
+    #[serde(deserialize_with = "density::deserialize")]
+    a_aux_density: DensityTracker,
+    #[serde(deserialize_with = "density::deserialize")]
+    b_input_density: DensityTracker,
+    #[serde(deserialize_with = "density::deserialize")]
+    b_aux_density: DensityTracker,
+}
+
+impl Serialize for SynthesizedCircuit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        SynthesizedCircuitRef {
+            a: &self.0.a,
+            b: &self.0.b,
+            c: &self.0.c,
+            input_assignment: &self.0.input_assignment,
+            aux_assignment: &self.0.aux_assignment,
+            a_aux_density: &self.0.a_aux_density,
+            b_input_density: &self.0.b_input_density,
+            b_aux_density: &self.0.b_aux_density,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SynthesizedCircuit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let owned = SynthesizedCircuitOwned::deserialize(deserializer)?;
+
+        Ok(SynthesizedCircuit(ProvingAssignment {
+            a_aux_density: owned.a_aux_density,
+            b_input_density: owned.b_input_density,
+            b_aux_density: owned.b_aux_density,
+            a: owned.a,
+            b: owned.b,
+            c: owned.c,
+            input_assignment: owned.input_assignment,
+            aux_assignment: owned.aux_assignment,
+        }))
+    }
+}
+
+/// Synthesizes `circuits` in parallel, without proving them.
+pub fn synthesize_circuits<C>(circuits: Vec<C>) -> Result<Vec<SynthesizedCircuit>>
+where
+    C: Circuit<Fr> + Send,
+{
+    info!("synthesize_circuits:start");
+    let synthesized = groth16::synthesize_circuits_batch(circuits)?
+        .into_iter()
+        .map(SynthesizedCircuit)
+        .collect();
+    info!("synthesize_circuits:finish");
+
+    Ok(synthesized)
+}
+
+/// Generates Groth16 proofs for circuits returned by `synthesize_circuits`. All circuits must
+/// be instances of the circuit `params` were generated for.
+pub fn prove_synthesized<P, R>(
+    circuits: Vec<SynthesizedCircuit>,
+    params: P,
+    rng: &mut R,
+    priority: bool,
+) -> Result<Vec<Proof<Bls12>>>
+where
+    P: ParameterSource<Bls12>,
+    R: RngCore,
+{
+    info!("prove_synthesized:start");
+    ensure!(!circuits.is_empty(), "no synthesized circuits to prove");
+
+    // bellperson only asserts this, checking it here turns mixed up spool files into an error.
+    let first = &circuits[0].0;
+    for SynthesizedCircuit(circuit) in &circuits {
+        ensure!(
+            circuit.a.len() == first.a.len()
+                && circuit.input_assignment.len() == first.input_assignment.len()
+                && circuit.aux_assignment.len() == first.aux_assignment.len()
+                && circuit.a_aux_density.get_total_density()
+                    == first.a_aux_density.get_total_density()
+                && circuit.b_input_density.get_total_density()
+                    == first.b_input_density.get_total_density()
+                && circuit.b_aux_density.get_total_density()
+                    == first.b_aux_density.get_total_density(),
+            "only identical circuits can be proven together"
+        );
+    }
+
+    let r_s: Vec<Fr> = (0..circuits.len()).map(|_| Fr::random(&mut *rng)).collect();
+    let s_s: Vec<Fr> = (0..circuits.len()).map(|_| Fr::random(&mut *rng)).collect();
+    let assignments = circuits.into_iter().map(|circuit| circuit.0).collect();
+
+    let proofs = groth16::prove_from_assignments(assignments, params, r_s, s_s, priority)?;
+    info!("prove_synthesized:finish");
+
+    Ok(proofs)
+}
+
+mod scalars {
+    use blstrs::Scalar as Fr;
+    use ff::PrimeField;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(scalars: &[Fr], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(scalars.iter().map(|scalar| scalar.to_repr()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Fr>, D::Error> {
+        let reprs: Vec<[u8; 32]> = Vec::deserialize(deserializer)?;
+        reprs
+            .into_iter()
+            .map(|repr| {
+                Option::from(Fr::from_repr(repr)).ok_or_else(|| D::Error::custom("invalid scalar"))
+            })
+            .collect()
+    }
+}
+
+/// Densities are stored as the number of tracked variables and the indices of the used ones.
+mod density {
+    use bellperson::multiexp::{DensityTracker, QueryDensity};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        density: &DensityTracker,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let used: Vec<u64> = density
+            .iter()
+            .enumerate()
+            .filter(|(_, used)| *used)
+            .map(|(i, _)| i as u64)
+            .collect();
+
+        (density.get_query_size().unwrap_or(0) as u64, used).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DensityTracker, D::Error> {
+        let (len, used): (u64, Vec<u64>) = Deserialize::deserialize(deserializer)?;
+
+        let mut density = DensityTracker::new();
+        for _ in 0..len {
+            density.add_element();
+        }
+        for i in used {
+            if i >= len {
+                return Err(D::Error::custom("density index out of range"));
+            }
+            density.inc(i as usize);
+        }
+
+        Ok(density)
+    }
+}
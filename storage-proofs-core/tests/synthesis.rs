@@ -0,0 +1,138 @@
+use bellperson::{
+    groth16::{generate_random_parameters, prepare_verifying_key, verify_proof},
+    Circuit, ConstraintSystem, SynthesisError,
+};
+use blstrs::Scalar as Fr;
+use ff::Field;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    synthesis::{prove_synthesized, synthesize_circuits, SynthesizedCircuit},
+    TEST_SEED,
+};
+use tempfile::tempdir;
+
+/// Proves knowledge of `x` such that `x^3 + x + 5 == out`, with `out` public.
+#[derive(Clone)]
+struct CubeCircuit {
+    x: Option<Fr>,
+}
+
+impl CubeCircuit {
+    fn out(x: Fr) -> Fr {
+        x.square() * x + x + Fr::from(5u64)
+    }
+}
+
+impl Circuit<Fr> for CubeCircuit {
+    fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let x_val = self.x;
+        let x = cs.alloc(|| "x", || x_val.ok_or(SynthesisError::AssignmentMissing))?;
+
+        let x_sq_val = x_val.map(|x| x.square());
+        let x_sq = cs.alloc(
+            || "x_sq",
+            || x_sq_val.ok_or(SynthesisError::AssignmentMissing),
+        )?;
+        cs.enforce(|| "x_sq = x * x", |lc| lc + x, |lc| lc + x, |lc| lc + x_sq);
+
+        let x_cube_val = x_sq_val.and_then(|x_sq| x_val.map(|x| x_sq * x));
+        let x_cube = cs.alloc(
+            || "x_cube",
+            || x_cube_val.ok_or(SynthesisError::AssignmentMissing),
+        )?;
+        cs.enforce(
+            || "x_cube = x_sq * x",
+            |lc| lc + x_sq,
+            |lc| lc + x,
+            |lc| lc + x_cube,
+        );
+
+        let out = cs.alloc_input(
+            || "out",
+            || {
+                x_val
+                    .map(CubeCircuit::out)
+                    .ok_or(SynthesisError::AssignmentMissing)
+            },
+        )?;
+        cs.enforce(
+            || "out = x_cube + x + 5",
+            |lc| lc + x_cube + x + (Fr::from(5u64), CS::one()),
+            |lc| lc + CS::one(),
+            |lc| lc + out,
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_synthesized_circuits_prove_through_spool() {
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+    let params = generate_random_parameters(CubeCircuit { x: None }, &mut rng)
+        .expect("failed to generate parameters");
+    let pvk = prepare_verifying_key(&params.vk);
+
+    let xs: Vec<Fr> = (0..3).map(|_| Fr::random(&mut rng)).collect();
+    let circuits = xs.iter().map(|x| CubeCircuit { x: Some(*x) }).collect();
+    let synthesized = synthesize_circuits(circuits).expect("failed to synthesize");
+    assert_eq!(synthesized.len(), xs.len());
+    // The one variable and `out`.
+    assert_eq!(synthesized[0].num_inputs(), 2);
+    assert_eq!(synthesized[0].num_aux(), 3);
+
+    let spool = tempdir().expect("tempdir failure");
+    for (i, circuit) in synthesized.iter().enumerate() {
+        circuit
+            .write_to_file(spool.path().join(format!("circuit-{}", i)))
+            .expect("failed to write synthesized circuit");
+    }
+    let spooled: Vec<SynthesizedCircuit> = (0..xs.len())
+        .map(|i| {
+            SynthesizedCircuit::read_from_file(spool.path().join(format!("circuit-{}", i)))
+                .expect("failed to read synthesized circuit")
+        })
+        .collect();
+    assert_eq!(spooled, synthesized);
+
+    let proofs = prove_synthesized(spooled, &params, &mut rng, false).expect("failed to prove");
+    assert_eq!(proofs.len(), xs.len());
+
+    for (proof, x) in proofs.iter().zip(xs.iter()) {
+        let out = CubeCircuit::out(*x);
+        assert!(verify_proof(&pvk, proof, &[out]).expect("failed to verify"));
+        assert!(!verify_proof(&pvk, proof, &[out + Fr::one()]).expect("failed to verify"));
+    }
+}
+
+#[test]
+fn test_prove_synthesized_rejects_mismatched_circuits() {
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+
+    let params = generate_random_parameters(CubeCircuit { x: None }, &mut rng)
+        .expect("failed to generate parameters");
+
+    assert!(prove_synthesized(Vec::new(), &params, &mut rng, false).is_err());
+
+    let mut synthesized = synthesize_circuits(vec![CubeCircuit {
+        x: Some(Fr::random(&mut rng)),
+    }])
+    .expect("failed to synthesize");
+    synthesized.extend(synthesize_circuits(vec![SquareCircuit]).expect("failed to synthesize"));
+
+    assert!(prove_synthesized(synthesized, &params, &mut rng, false).is_err());
+}
+
+/// A circuit of a different shape than `CubeCircuit`.
+struct SquareCircuit;
+
+impl Circuit<Fr> for SquareCircuit {
+    fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let x = cs.alloc(|| "x", || Ok(Fr::one()))?;
+        cs.enforce(|| "x * x = x", |lc| lc + x, |lc| lc + x, |lc| lc + x);
+
+        Ok(())
+    }
+}
@@ -10,10 +10,9 @@ use merkletree::store::StoreConfig;
 use storage_proofs_core::{error::Result, merkle::BinaryMerkleTree, proof::ProofScheme, Data};
 
 pub mod drg;
+pub mod encode;
 pub mod stacked;
 
-mod encode;
-
 pub const MAX_LEGACY_POREP_REGISTERED_PROOF_ID: u64 = 4;
 
 pub trait PoRep<'a, H: Hasher, G: Hasher>: ProofScheme<'a> {
@@ -0,0 +1,219 @@
+use filecoin_hashers::Hasher;
+use generic_array::typenum::U2;
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::{
+    error::Result,
+    merkle::{CompactMerkleProof, MerkleProof, MerkleTreeTrait, NodePool},
+};
+
+use crate::stacked::vanilla::{
+    Column, ColumnProof, EncodingProof, LabelingProof, Proof, ReplicaColumnProof,
+};
+
+type TreeProof<Tree> = MerkleProof<
+    <Tree as MerkleTreeTrait>::Hasher,
+    <Tree as MerkleTreeTrait>::Arity,
+    <Tree as MerkleTreeTrait>::SubTreeArity,
+    <Tree as MerkleTreeTrait>::TopTreeArity,
+>;
+type TreeDomain<Tree> = <<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain;
+
+/// Vanilla proofs of all partitions of a sector, with the merkle path nodes of all challenges
+/// stored once per tree.
+///
+/// Every challenge proves inclusion in the same tree-d, tree-c and tree-r-last, so their paths
+/// meet near the roots, where most of the nodes of a proof are. Converting back with
+/// `into_proofs` is lossless; the result still has to be verified like any other proof that
+/// was received from elsewhere.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompactProofs<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound(
+        serialize = "NodePool<G::Domain>: Serialize",
+        deserialize = "NodePool<G::Domain>: Deserialize<'de>"
+    ))]
+    comm_d_nodes: NodePool<G::Domain>,
+    #[serde(bound(
+        serialize = "NodePool<TreeDomain<Tree>>: Serialize",
+        deserialize = "NodePool<TreeDomain<Tree>>: Deserialize<'de>"
+    ))]
+    tree_nodes: NodePool<TreeDomain<Tree>>,
+    #[serde(bound(
+        serialize = "CompactProof<Tree, G>: Serialize",
+        deserialize = "CompactProof<Tree, G>: Deserialize<'de>"
+    ))]
+    partitions: Vec<Vec<CompactProof<Tree, G>>>,
+}
+
+impl<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> CompactProofs<Tree, G> {
+    pub fn from_proofs(partitions: &[Vec<Proof<Tree, G>>]) -> Self {
+        let mut comm_d_nodes = NodePool::new();
+        let mut tree_nodes = NodePool::new();
+
+        let partitions = partitions
+            .iter()
+            .map(|proofs| {
+                proofs
+                    .iter()
+                    .map(|proof| CompactProof::new(proof, &mut comm_d_nodes, &mut tree_nodes))
+                    .collect()
+            })
+            .collect();
+
+        CompactProofs {
+            comm_d_nodes,
+            tree_nodes,
+            partitions,
+        }
+    }
+
+    pub fn into_proofs(self) -> Result<Vec<Vec<Proof<Tree, G>>>> {
+        let comm_d_nodes = &self.comm_d_nodes;
+        let tree_nodes = &self.tree_nodes;
+
+        self.partitions
+            .into_iter()
+            .map(|proofs| {
+                proofs
+                    .into_iter()
+                    .map(|proof| proof.into_proof(comm_d_nodes, tree_nodes))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompactProof<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound(
+        serialize = "CompactMerkleProof<G::Domain>: Serialize",
+        deserialize = "CompactMerkleProof<G::Domain>: Deserialize<'de>"
+    ))]
+    comm_d_proof: CompactMerkleProof<G::Domain>,
+    #[serde(bound(
+        serialize = "CompactMerkleProof<TreeDomain<Tree>>: Serialize",
+        deserialize = "CompactMerkleProof<TreeDomain<Tree>>: Deserialize<'de>"
+    ))]
+    comm_r_last_proof: CompactMerkleProof<TreeDomain<Tree>>,
+    #[serde(bound(
+        serialize = "CompactColumnProof<Tree::Hasher>: Serialize",
+        deserialize = "CompactColumnProof<Tree::Hasher>: Deserialize<'de>"
+    ))]
+    c_x: CompactColumnProof<Tree::Hasher>,
+    #[serde(bound(
+        serialize = "CompactColumnProof<Tree::Hasher>: Serialize",
+        deserialize = "CompactColumnProof<Tree::Hasher>: Deserialize<'de>"
+    ))]
+    drg_parents: Vec<CompactColumnProof<Tree::Hasher>>,
+    #[serde(bound(
+        serialize = "CompactColumnProof<Tree::Hasher>: Serialize",
+        deserialize = "CompactColumnProof<Tree::Hasher>: Deserialize<'de>"
+    ))]
+    exp_parents: Vec<CompactColumnProof<Tree::Hasher>>,
+    #[serde(bound(
+        serialize = "LabelingProof<Tree::Hasher>: Serialize",
+        deserialize = "LabelingProof<Tree::Hasher>: Deserialize<'de>"
+    ))]
+    labeling_proofs: Vec<LabelingProof<Tree::Hasher>>,
+    #[serde(bound(
+        serialize = "EncodingProof<Tree::Hasher>: Serialize",
+        deserialize = "EncodingProof<Tree::Hasher>: Deserialize<'de>"
+    ))]
+    encoding_proof: EncodingProof<Tree::Hasher>,
+}
+
+impl<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> CompactProof<Tree, G> {
+    fn new(
+        proof: &Proof<Tree, G>,
+        comm_d_nodes: &mut NodePool<G::Domain>,
+        tree_nodes: &mut NodePool<TreeDomain<Tree>>,
+    ) -> Self {
+        let columns = &proof.replica_column_proofs;
+
+        CompactProof {
+            comm_d_proof: proof.comm_d_proofs.to_compact(comm_d_nodes),
+            comm_r_last_proof: proof.comm_r_last_proof.to_compact(tree_nodes),
+            c_x: CompactColumnProof::new::<Tree>(&columns.c_x, tree_nodes),
+            drg_parents: compact_columns::<Tree>(&columns.drg_parents, tree_nodes),
+            exp_parents: compact_columns::<Tree>(&columns.exp_parents, tree_nodes),
+            labeling_proofs: proof.labeling_proofs.clone(),
+            encoding_proof: proof.encoding_proof.clone(),
+        }
+    }
+
+    fn into_proof(
+        self,
+        comm_d_nodes: &NodePool<G::Domain>,
+        tree_nodes: &NodePool<TreeDomain<Tree>>,
+    ) -> Result<Proof<Tree, G>> {
+        Ok(Proof {
+            comm_d_proofs: MerkleProof::<G, U2>::from_compact(&self.comm_d_proof, comm_d_nodes)?,
+            comm_r_last_proof: TreeProof::<Tree>::from_compact(
+                &self.comm_r_last_proof,
+                tree_nodes,
+            )?,
+            replica_column_proofs: ReplicaColumnProof {
+                c_x: self.c_x.into_column_proof::<Tree>(tree_nodes)?,
+                drg_parents: expand_columns::<Tree>(self.drg_parents, tree_nodes)?,
+                exp_parents: expand_columns::<Tree>(self.exp_parents, tree_nodes)?,
+            },
+            labeling_proofs: self.labeling_proofs,
+            encoding_proof: self.encoding_proof,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompactColumnProof<H: Hasher> {
+    #[serde(bound(
+        serialize = "Column<H>: Serialize",
+        deserialize = "Column<H>: Deserialize<'de>"
+    ))]
+    column: Column<H>,
+    #[serde(bound(
+        serialize = "CompactMerkleProof<H::Domain>: Serialize",
+        deserialize = "CompactMerkleProof<H::Domain>: Deserialize<'de>"
+    ))]
+    inclusion_proof: CompactMerkleProof<H::Domain>,
+}
+
+impl<H: 'static + Hasher> CompactColumnProof<H> {
+    fn new<Tree: 'static + MerkleTreeTrait<Hasher = H>>(
+        column_proof: &ColumnProof<TreeProof<Tree>>,
+        tree_nodes: &mut NodePool<H::Domain>,
+    ) -> Self {
+        CompactColumnProof {
+            column: column_proof.column.clone(),
+            inclusion_proof: column_proof.inclusion_proof.to_compact(tree_nodes),
+        }
+    }
+
+    fn into_column_proof<Tree: 'static + MerkleTreeTrait<Hasher = H>>(
+        self,
+        tree_nodes: &NodePool<H::Domain>,
+    ) -> Result<ColumnProof<TreeProof<Tree>>> {
+        ColumnProof::from_column(
+            self.column,
+            TreeProof::<Tree>::from_compact(&self.inclusion_proof, tree_nodes)?,
+        )
+    }
+}
+
+fn compact_columns<Tree: 'static + MerkleTreeTrait>(
+    column_proofs: &[ColumnProof<TreeProof<Tree>>],
+    tree_nodes: &mut NodePool<TreeDomain<Tree>>,
+) -> Vec<CompactColumnProof<Tree::Hasher>> {
+    column_proofs
+        .iter()
+        .map(|column_proof| CompactColumnProof::new::<Tree>(column_proof, tree_nodes))
+        .collect()
+}
+
+fn expand_columns<Tree: 'static + MerkleTreeTrait>(
+    column_proofs: Vec<CompactColumnProof<Tree::Hasher>>,
+    tree_nodes: &NodePool<TreeDomain<Tree>>,
+) -> Result<Vec<ColumnProof<TreeProof<Tree>>>> {
+    column_proofs
+        .into_iter()
+        .map(|column_proof| column_proof.into_column_proof::<Tree>(tree_nodes))
+        .collect()
+}
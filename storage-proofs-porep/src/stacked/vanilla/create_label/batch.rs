@@ -0,0 +1,238 @@
+use std::cmp::min;
+use std::marker::PhantomData;
+use std::mem;
+
+use anyhow::{ensure, Context, Result};
+use filecoin_hashers::Hasher;
+use log::info;
+use merkletree::store::StoreConfig;
+use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+use sha2raw::Sha256;
+use storage_proofs_core::{
+    control::{Progress, SealControl},
+    drgraph::Graph,
+    merkle::MerkleTreeTrait,
+    util::{data_at_node_offset, NODE_SIZE},
+};
+
+use crate::stacked::vanilla::{
+    cache::ParentCache,
+    create_label::{prepare_layers, read_layer, write_layer, PROGRESS_NODE_INTERVAL},
+    graph::DEGREE,
+    proof::LayerState,
+    Labels, StackedBucketGraph,
+};
+
+/// Number of nodes whose parents are read from the `ParentCache` at once, to then be used for
+/// all sectors of a batch.
+const PARENTS_CHUNK_NODES: usize = 1 << 12;
+
+/// Labelling state of a single sector within a batch.
+struct SectorLabels<'a, T> {
+    replica_id: &'a T,
+    layer_states: Vec<LayerState>,
+    layer_labels: Vec<u8>,
+    exp_labels: Vec<u8>,
+}
+
+/// Generates the labels of several sectors, which share the graph (i.e. sector size and
+/// porep_id) but have different replica ids.
+///
+/// The sectors are labelled in lockstep: the parents of a chunk of nodes are read from the
+/// `parents_cache` once and then used to label that chunk of every sector, with the sectors
+/// being labelled in parallel. As with the single core labelling, two layers are kept in
+/// memory per sector.
+///
+/// Layers which are already on disk for some of the sectors are not generated again for
+/// these.
+#[allow(clippy::type_complexity)]
+pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]> + Sync>(
+    graph: &StackedBucketGraph<Tree::Hasher>,
+    parents_cache: &mut ParentCache,
+    layers: usize,
+    replica_ids: &[T],
+    configs: Vec<StoreConfig>,
+    control: &SealControl,
+) -> Result<Vec<(Labels<Tree>, Vec<LayerState>)>> {
+    ensure!(
+        replica_ids.len() == configs.len(),
+        "got {} replica ids for {} store configs",
+        replica_ids.len(),
+        configs.len()
+    );
+    info!("generate labels for {} sectors", replica_ids.len());
+
+    let total = graph.size();
+    let layer_size = total * NODE_SIZE;
+    let mut sectors = replica_ids
+        .iter()
+        .zip(configs.iter())
+        .map(|(replica_id, config)| SectorLabels {
+            replica_id,
+            layer_states: prepare_layers::<Tree>(graph, config, layers),
+            layer_labels: vec![0u8; layer_size],
+            exp_labels: vec![0u8; layer_size],
+        })
+        .collect::<Vec<_>>();
+
+    let mut parents = vec![0u32; PARENTS_CHUNK_NODES * DEGREE];
+    for layer in 1..=layers {
+        info!("generating layer: {}", layer);
+
+        let mut pending = 0;
+        for sector in sectors.iter_mut() {
+            let layer_state = &sector.layer_states[layer - 1];
+            if layer_state.generated {
+                info!(
+                    "skipping layer {} of {}, already generated",
+                    layer,
+                    layer_state.config.path.display()
+                );
+
+                // load the already generated layer into exp_labels
+                read_layer(&layer_state.config, &mut sector.exp_labels)?;
+            } else {
+                pending += 1;
+            }
+        }
+
+        if pending > 0 {
+            parents_cache.reset()?;
+
+            for chunk_start in (0..total).step_by(PARENTS_CHUNK_NODES) {
+                // Nothing of a partially generated layer is persisted, so it is safe to stop here.
+                if chunk_start % PROGRESS_NODE_INTERVAL == 0 {
+                    control.check()?;
+                    if chunk_start > 0 {
+                        control.report(Progress::Labels {
+                            layer,
+                            layers,
+                            nodes: chunk_start,
+                            total,
+                        });
+                    }
+                }
+
+                let chunk_end = min(chunk_start + PARENTS_CHUNK_NODES, total);
+                let chunk_parents = &mut parents[..(chunk_end - chunk_start) * DEGREE];
+                for (node, node_parents) in
+                    (chunk_start..chunk_end).zip(chunk_parents.chunks_exact_mut(DEGREE))
+                {
+                    node_parents.copy_from_slice(&parents_cache.read(node as u32)?);
+                }
+                let chunk_parents = &*chunk_parents;
+
+                sectors
+                    .par_iter_mut()
+                    .filter(|sector| !sector.layer_states[layer - 1].generated)
+                    .for_each(|sector| {
+                        let exp_labels = if layer == 1 {
+                            None
+                        } else {
+                            Some(&sector.exp_labels[..])
+                        };
+                        for (node, node_parents) in
+                            (chunk_start..chunk_end).zip(chunk_parents.chunks_exact(DEGREE))
+                        {
+                            create_label(
+                                graph,
+                                node_parents,
+                                sector.replica_id,
+                                &mut sector.layer_labels,
+                                exp_labels,
+                                layer,
+                                node,
+                            );
+                        }
+                    });
+            }
+
+            for sector in sectors
+                .iter_mut()
+                .filter(|sector| !sector.layer_states[layer - 1].generated)
+            {
+                // Write the result to disk to avoid keeping it in memory all the time.
+                let layer_config = &sector.layer_states[layer - 1].config;
+
+                info!("  storing labels on disk");
+                write_layer(&sector.layer_labels, layer_config)
+                    .context("failed to store labels")?;
+
+                info!(
+                    "  generated layer {} store with id {} in {}",
+                    layer,
+                    layer_config.id,
+                    layer_config.path.display()
+                );
+
+                mem::swap(&mut sector.layer_labels, &mut sector.exp_labels);
+            }
+        }
+
+        control.report(Progress::Labels {
+            layer,
+            layers,
+            nodes: total,
+            total,
+        });
+    }
+
+    Ok(sectors
+        .into_iter()
+        .map(|sector| {
+            (
+                Labels::<Tree> {
+                    labels: sector
+                        .layer_states
+                        .iter()
+                        .map(|s| s.config.clone())
+                        .collect(),
+                    _h: PhantomData,
+                },
+                sector.layer_states,
+            )
+        })
+        .collect())
+}
+
+/// Creates the label of `node`, based on its already known `parents`.
+fn create_label<H: Hasher, T: AsRef<[u8]>>(
+    graph: &StackedBucketGraph<H>,
+    parents: &[u32],
+    replica_id: &T,
+    layer_labels: &mut [u8],
+    exp_labels: Option<&[u8]>,
+    layer_index: usize,
+    node: usize,
+) {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 32];
+
+    buffer[..4].copy_from_slice(&(layer_index as u32).to_be_bytes());
+    buffer[4..12].copy_from_slice(&(node as u64).to_be_bytes());
+    hasher.input(&[replica_id.as_ref(), &buffer[..]][..]);
+
+    // hash parents for all non 0 nodes
+    let hash = if node > 0 {
+        // prefetch previous node, which is always a parent
+        let prev = &layer_labels[(node - 1) * NODE_SIZE..node * NODE_SIZE];
+        prefetch!(prev.as_ptr() as *const i8);
+
+        match exp_labels {
+            Some(exp_labels) => {
+                graph.copy_parents_data_inner_exp(parents, &*layer_labels, exp_labels, hasher)
+            }
+            None => graph.copy_parents_data_inner(parents, &*layer_labels, hasher),
+        }
+    } else {
+        hasher.finish()
+    };
+
+    // store the newly generated key
+    let start = data_at_node_offset(node);
+    let end = start + NODE_SIZE;
+    layer_labels[start..end].copy_from_slice(&hash[..]);
+
+    // strip last two bits, to ensure result is in Fr.
+    layer_labels[end - 1] &= 0b0011_1111;
+}
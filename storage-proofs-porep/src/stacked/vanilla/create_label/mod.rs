@@ -11,10 +11,19 @@ use storage_proofs_core::{
 
 use crate::stacked::vanilla::{proof::LayerState, StackedBucketGraph};
 
+pub mod batch;
 #[cfg(feature = "multicore-sdr")]
 pub mod multi;
 pub mod single;
 
+/// Invoked once the labels of a layer are complete, either because they were just generated
+/// or because they were loaded from disk. Receives the (1-based) layer index, the
+/// `StoreConfig` the layer is stored under and the labels themselves.
+pub type LayerCallback<'a> = dyn FnMut(usize, &StoreConfig, &[u8]) -> Result<()> + 'a;
+
+/// Number of nodes after which label progress is reported while generating a layer.
+pub(crate) const PROGRESS_NODE_INTERVAL: usize = 1 << 16;
+
 /// Prepares the necessary `StoreConfig`s with which the layers are stored.
 /// Also checks for already existing layers and marks them as such.
 pub fn prepare_layers<Tree: 'static + MerkleTreeTrait>(
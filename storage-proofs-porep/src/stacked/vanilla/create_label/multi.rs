@@ -20,6 +20,7 @@ use mapr::MmapMut;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use storage_proofs_core::{
     cache_key::CacheKey,
+    control::{Progress, SealControl},
     drgraph::{Graph, BASE_DEGREE},
     merkle::MerkleTreeTrait,
     settings::SETTINGS,
@@ -29,7 +30,9 @@ use storage_proofs_core::{
 use crate::stacked::vanilla::{
     cache::ParentCache,
     cores::{bind_core, checkout_core_group, CoreIndex},
-    create_label::{prepare_layers, read_layer, write_layer},
+    create_label::{
+        prepare_layers, read_layer, write_layer, LayerCallback, PROGRESS_NODE_INTERVAL,
+    },
     graph::{StackedBucketGraph, DEGREE, EXP_DEGREE},
     memory_handling::{setup_create_label_memory, CacheReader},
     params::{Labels, LabelsCache},
@@ -200,6 +203,7 @@ fn create_label_runner(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_layer_labels(
     parents_cache: &CacheReader<u32>,
     replica_id: &[u8],
@@ -208,6 +212,7 @@ fn create_layer_labels(
     num_nodes: u64,
     cur_layer: u32,
     core_group: Arc<Option<MutexGuard<'_, Vec<CoreIndex>>>>,
+    on_progress: &dyn Fn(usize),
 ) {
     info!("Creating labels for layer {}", cur_layer);
     // num_producers is the number of producer threads
@@ -315,6 +320,7 @@ fn create_layer_labels(
         // Skip first node.
         parents_cache.store_consumer(1);
         let mut i = 1;
+        let mut next_progress = PROGRESS_NODE_INTERVAL as u64;
         while i < num_nodes {
             // Ensure next buffer is ready
             let mut counted = false;
@@ -423,6 +429,11 @@ fn create_layer_labels(
                 i += 1;
                 cur_slot = (cur_slot + 1) % lookahead;
             }
+
+            if i >= next_progress && i < num_nodes {
+                on_progress(i as usize);
+                next_progress = i + PROGRESS_NODE_INTERVAL as u64;
+            }
         }
 
         debug!("PRODUCER NOT READY: {} times", count_not_ready);
@@ -441,6 +452,8 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     layers: usize,
     replica_id: T,
     config: StoreConfig,
+    control: &SealControl,
+    on_layer: &mut LayerCallback<'_>,
 ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
     info!("create labels");
 
@@ -478,9 +491,20 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
             // load the already generated layer into exp_labels
             read_layer(&layer_state.config, &mut exp_labels)?;
+            on_layer(layer, &layer_state.config, &exp_labels)?;
+            control.report(Progress::Labels {
+                layer,
+                layers,
+                nodes: graph.size(),
+                total: graph.size(),
+            });
             continue;
         }
 
+        // The producer threads can only be stopped once a layer is complete, so cancellation
+        // is honoured between layers.
+        control.check()?;
+
         // Cache reset happens in two parts.
         // The second part (the finish) happens before each layer but the first.
         if layers != 1 {
@@ -499,6 +523,14 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             node_count,
             layer as u32,
             core_group.clone(),
+            &|nodes| {
+                control.report(Progress::Labels {
+                    layer,
+                    layers,
+                    nodes,
+                    total: graph.size(),
+                })
+            },
         );
 
         // Cache reset happens in two parts.
@@ -518,6 +550,13 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
                 "  generated layer {} store with id {}",
                 layer, layer_config.id
             );
+            on_layer(layer, layer_config, &exp_labels)?;
+            control.report(Progress::Labels {
+                layer,
+                layers,
+                nodes: graph.size(),
+                total: graph.size(),
+            });
         }
     }
 
@@ -589,6 +628,7 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             node_count,
             layer as u32,
             core_group.clone(),
+            &|_| {},
         );
 
         // Cache reset happens in two parts.
@@ -8,6 +8,8 @@ use log::info;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use sha2raw::Sha256;
 use storage_proofs_core::{
+    cache_key::CacheKey,
+    control::{Progress, SealControl},
     drgraph::Graph,
     merkle::MerkleTreeTrait,
     util::{data_at_node_offset, NODE_SIZE},
@@ -15,7 +17,9 @@ use storage_proofs_core::{
 
 use crate::stacked::vanilla::{
     cache::ParentCache,
-    create_label::{prepare_layers, read_layer, write_layer},
+    create_label::{
+        prepare_layers, read_layer, write_layer, LayerCallback, PROGRESS_NODE_INTERVAL,
+    },
     proof::LayerState,
     Labels, LabelsCache, StackedBucketGraph,
 };
@@ -27,6 +31,8 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     layers: usize,
     replica_id: T,
     config: StoreConfig,
+    control: &SealControl,
+    on_layer: &mut LayerCallback<'_>,
 ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
     info!("generate labels");
 
@@ -37,6 +43,7 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
     let mut layer_labels = vec![0u8; layer_size]; // Buffer for labels of the current layer
     let mut exp_labels = vec![0u8; layer_size]; // Buffer for labels of the previous layer, needed for expander parents
 
+    let total = graph.size();
     for (layer, layer_state) in (1..=layers).zip(layer_states.iter()) {
         info!("generating layer: {}", layer);
         if layer_state.generated {
@@ -44,13 +51,33 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
             // load the already generated layer into exp_labels
             read_layer(&layer_state.config, &mut exp_labels)?;
+            on_layer(layer, &layer_state.config, &exp_labels)?;
+            control.report(Progress::Labels {
+                layer,
+                layers,
+                nodes: total,
+                total,
+            });
             continue;
         }
 
         parents_cache.reset()?;
 
-        if layer == 1 {
-            for node in 0..graph.size() {
+        for node in 0..total {
+            // Nothing of a partially generated layer is persisted, so it is safe to stop here.
+            if node % PROGRESS_NODE_INTERVAL == 0 {
+                control.check()?;
+                if node > 0 {
+                    control.report(Progress::Labels {
+                        layer,
+                        layers,
+                        nodes: node,
+                        total,
+                    });
+                }
+            }
+
+            if layer == 1 {
                 create_label(
                     graph,
                     Some(parents_cache),
@@ -59,9 +86,7 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
                     layer,
                     node,
                 )?;
-            }
-        } else {
-            for node in 0..graph.size() {
+            } else {
                 create_label_exp(
                     graph,
                     Some(parents_cache),
@@ -84,6 +109,13 @@ pub fn create_labels_for_encoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
             "  generated layer {} store with id {}",
             layer, layer_config.id
         );
+        on_layer(layer, layer_config, &layer_labels)?;
+        control.report(Progress::Labels {
+            layer,
+            layers,
+            nodes: total,
+            total,
+        });
 
         info!("  setting exp parents");
         mem::swap(&mut layer_labels, &mut exp_labels);
@@ -148,11 +180,13 @@ pub fn create_labels_for_decoding<Tree: 'static + MerkleTreeTrait, T: AsRef<[u8]
 
         // Write the result to disk to avoid keeping it in memory all the time.
         info!("  storing labels on disk");
-        write_layer(&layer_labels, &config)?;
+        let layer_config =
+            StoreConfig::from_config(&config, CacheKey::label_layer(layer), Some(graph.size()));
+        write_layer(&layer_labels, &layer_config)?;
 
         let layer_store: DiskStore<<Tree::Hasher as Hasher>::Domain> =
-            DiskStore::new_from_disk(graph.size(), Tree::Arity::to_usize(), &config)?;
-        info!("  generated layer {} store with id {}", layer, config.id);
+            DiskStore::new_from_disk(graph.size(), Tree::Arity::to_usize(), &layer_config)?;
+        info!("  generated layer {} store with id {}", layer, layer_config.id);
 
         info!("  setting exp parents");
         mem::swap(&mut layer_labels, &mut exp_labels);
@@ -182,7 +182,7 @@ where
         }
     }
 
-    fn copy_parents_data_inner_exp(
+    pub(crate) fn copy_parents_data_inner_exp(
         &self,
         cache_parents: &[u32],
         base_data: &[u8],
@@ -221,7 +221,7 @@ where
         hasher.finish_with(parents[8])
     }
 
-    fn copy_parents_data_inner(
+    pub(crate) fn copy_parents_data_inner(
         &self,
         cache_parents: &[u32],
         base_data: &[u8],
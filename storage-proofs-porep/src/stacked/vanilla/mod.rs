@@ -8,6 +8,7 @@ mod cache;
 mod challenges;
 mod column;
 mod column_proof;
+mod compact_proof;
 #[cfg(feature = "multicore-sdr")]
 mod cores;
 mod encoding_proof;
@@ -25,6 +26,7 @@ mod utils;
 pub use challenges::{ChallengeRequirements, LayerChallenges};
 pub use column::Column;
 pub use column_proof::ColumnProof;
+pub use compact_proof::CompactProofs;
 pub use encoding_proof::EncodingProof;
 pub use graph::{StackedBucketGraph, StackedGraph, EXP_DEGREE};
 pub use labeling_proof::LabelingProof;
@@ -5,7 +5,7 @@ use std::panic::panic_any;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
-use anyhow::Context;
+use anyhow::{ensure, Context};
 use bincode::deserialize;
 use fdlimit::raise_fd_limit;
 use filecoin_hashers::{poseidon::PoseidonHasher, Domain, HashFunction, Hasher, PoseidonArity};
@@ -14,13 +14,14 @@ use lazy_static::lazy_static;
 use log::{error, info, trace, warn};
 use merkletree::{
     merkle::{get_merkle_tree_len, is_merkle_tree_size_valid},
-    store::{Store, StoreConfig},
+    store::{DiskStore, Store, StoreConfig},
 };
 use rayon::prelude::{
     IndexedParallelIterator, IntoParallelIterator, ParallelIterator, ParallelSliceMut,
 };
 use storage_proofs_core::{
     cache_key::CacheKey,
+    control::{Progress, SealControl},
     data::Data,
     drgraph::Graph,
     error::Result,
@@ -40,7 +41,7 @@ use crate::{
     stacked::vanilla::{
         challenges::LayerChallenges,
         column::Column,
-        create_label,
+        create_label::{self, LayerCallback},
         graph::StackedBucketGraph,
         hash::hash_single_column,
         params::{
@@ -308,12 +309,176 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(())
     }
 
+    /// Decodes, in place, the `num_nodes` replica nodes starting at node `first_node`.
+    ///
+    /// `data` holds only the replica nodes of the range, not the whole sector. The keys are read
+    /// from the last layer labels in the cache directory of `config` if they are still present
+    /// and belong to `replica_id`, which avoids regenerating all the layers of the sector.
+    /// Otherwise the labels are regenerated as for `extract_all`.
+    pub fn extract_range(
+        pp: &PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        data: &mut [u8],
+        config: StoreConfig,
+        first_node: usize,
+        num_nodes: usize,
+    ) -> Result<()> {
+        trace!("extract_range");
+
+        let last_layer_labels = Self::last_layer_labels_for_decoding(pp, replica_id, config)?;
+
+        Self::decode_range(&last_layer_labels, data, first_node, num_nodes)
+    }
+
+    /// Returns the last layer labels needed to decode a replica, opening the ones persisted
+    /// during replication if available and regenerating all the layers otherwise.
+    ///
+    /// Callers decoding a replica piecewise should call this once and use `decode_range`.
+    pub fn last_layer_labels_for_decoding(
+        pp: &PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+    ) -> Result<DiskStore<<Tree::Hasher as Hasher>::Domain>> {
+        if let Some(labels) =
+            Self::cached_last_layer_labels(&pp.graph, &pp.layer_challenges, replica_id, &config)?
+        {
+            return Ok(labels);
+        }
+
+        info!("last layer labels not found, regenerating all layers");
+        Self::regenerate_last_layer_labels(pp, replica_id, config)
+    }
+
+    /// Regenerates all the layers for decoding, replacing any labels in the cache directory of
+    /// `config`, and returns the last one.
+    pub fn regenerate_last_layer_labels(
+        pp: &PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+    ) -> Result<DiskStore<<Tree::Hasher as Hasher>::Domain>> {
+        let mut labels = Self::generate_labels_for_decoding(
+            &pp.graph,
+            &pp.layer_challenges,
+            replica_id,
+            config,
+        )?;
+
+        labels
+            .labels
+            .pop()
+            .context("no layers were generated for decoding")
+    }
+
+    /// Decodes, in place, the `num_nodes` replica nodes in `data` starting at node `first_node`,
+    /// using the keys from `last_layer_labels`.
+    pub fn decode_range(
+        last_layer_labels: &DiskStore<<Tree::Hasher as Hasher>::Domain>,
+        data: &mut [u8],
+        first_node: usize,
+        num_nodes: usize,
+    ) -> Result<()> {
+        let nodes = Store::len(last_layer_labels);
+        ensure!(
+            first_node + num_nodes <= nodes,
+            "node range {}..{} is out of bounds for {} nodes",
+            first_node,
+            first_node + num_nodes,
+            nodes
+        );
+        ensure!(
+            data.len() == num_nodes * NODE_SIZE,
+            "expected {} bytes of replica data, got {}",
+            num_nodes * NODE_SIZE,
+            data.len()
+        );
+
+        let keys = last_layer_labels.read_range(first_node..first_node + num_nodes)?;
+        for (key, encoded_node_bytes) in keys.into_iter().zip(data.chunks_mut(NODE_SIZE)) {
+            let encoded_node =
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(encoded_node_bytes)?;
+            let data_node = decode::<<Tree::Hasher as Hasher>::Domain>(key, encoded_node);
+
+            encoded_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+        }
+
+        Ok(())
+    }
+
+    /// Opens the last layer labels persisted during replication, if they are still in the
+    /// cache directory of `config`, have the expected size and were generated for `replica_id`.
+    ///
+    /// The label of the first node depends on nothing but the replica id and the layer, so it
+    /// is recomputed to tell the labels of another replica (e.g. from another ticket) apart.
+    fn cached_last_layer_labels(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: &StoreConfig,
+    ) -> Result<Option<DiskStore<<Tree::Hasher as Hasher>::Domain>>> {
+        let layers = layer_challenges.layers();
+        let layer_config =
+            StoreConfig::from_config(config, CacheKey::label_layer(layers), Some(graph.size()));
+        let data_path = StoreConfig::data_path(&layer_config.path, &layer_config.id);
+        match fs::metadata(&data_path) {
+            Ok(metadata) if metadata.len() == (graph.size() * NODE_SIZE) as u64 => {
+                let store =
+                    DiskStore::new_from_disk(graph.size(), Tree::Arity::to_usize(), &layer_config)
+                        .with_context(|| format!("could not open labels at {:?}", data_path))?;
+
+                let mut expected = [0u8; NODE_SIZE];
+                create_label::single::create_label(
+                    graph,
+                    None,
+                    replica_id,
+                    &mut expected,
+                    layers,
+                    0,
+                )?;
+                let first: <Tree::Hasher as Hasher>::Domain = store.read_at(0)?;
+                if AsRef::<[u8]>::as_ref(&first) != &expected[..] {
+                    warn!(
+                        "ignoring last layer labels at {:?}, generated for another replica",
+                        data_path
+                    );
+                    return Ok(None);
+                }
+
+                Ok(Some(store))
+            }
+            Ok(_) => {
+                warn!("ignoring truncated last layer labels at {:?}", data_path);
+                Ok(None)
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
     /// Generates the layers as needed for encoding.
     pub fn generate_labels_for_encoding(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
+    ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
+        Self::generate_labels_for_encoding_with_callback(
+            graph,
+            layer_challenges,
+            replica_id,
+            config,
+            &SealControl::default(),
+            &mut |_, _, _| Ok(()),
+        )
+    }
+
+    /// Generates the layers as needed for encoding, calling `on_layer` as soon as each layer
+    /// is available on disk. Labelling is stopped once `control` is cancelled.
+    pub fn generate_labels_for_encoding_with_callback(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        control: &SealControl,
+        on_layer: &mut LayerCallback<'_>,
     ) -> Result<(Labels<Tree>, Vec<LayerState>)> {
         let mut parent_cache = graph.parent_cache()?;
 
@@ -327,6 +492,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     layer_challenges.layers(),
                     replica_id,
                     config,
+                    control,
+                    on_layer,
                 )
             } else {
                 info!("single core replication");
@@ -336,6 +503,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     layer_challenges.layers(),
                     replica_id,
                     config,
+                    control,
+                    on_layer,
                 )
             }
         }
@@ -349,10 +518,35 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 layer_challenges.layers(),
                 replica_id,
                 config,
+                control,
+                on_layer,
             )
         }
     }
 
+    /// Generates the layers of several sectors at once, as needed for encoding. The sectors
+    /// are labelled in lockstep, sharing the reads of the parents cache.
+    #[allow(clippy::type_complexity)]
+    pub fn generate_labels_for_encoding_batch(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        replica_ids: &[<Tree::Hasher as Hasher>::Domain],
+        configs: Vec<StoreConfig>,
+        control: &SealControl,
+    ) -> Result<Vec<(Labels<Tree>, Vec<LayerState>)>> {
+        let mut parent_cache = graph.parent_cache()?;
+
+        info!("batched replication of {} sectors", replica_ids.len());
+        create_label::batch::create_labels_for_encoding(
+            graph,
+            &mut parent_cache,
+            layer_challenges.layers(),
+            replica_ids,
+            configs,
+            control,
+        )
+    }
+
     /// Generates the layers, as needed for decoding.
     pub fn generate_labels_for_decoding(
         graph: &StackedBucketGraph<Tree::Hasher>,
@@ -424,6 +618,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        control: &SealControl,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
@@ -432,13 +627,19 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         if SETTINGS.use_gpu_column_builder
             && TypeId::of::<Tree::Hasher>() == TypeId::of::<PoseidonHasher>()
         {
-            Self::generate_tree_c_gpu::<ColumnArity, TreeArity>(
+            control.check()?;
+            let tree_c = Self::generate_tree_c_gpu::<ColumnArity, TreeArity>(
                 layers,
                 nodes_count,
                 tree_count,
                 configs,
                 labels,
-            )
+            )?;
+            control.report(Progress::TreeC {
+                built: tree_count,
+                total: tree_count,
+            });
+            Ok(tree_c)
         } else {
             Self::generate_tree_c_cpu::<ColumnArity, TreeArity>(
                 layers,
@@ -446,6 +647,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_count,
                 configs,
                 labels,
+                control,
             )
         }
     }
@@ -457,6 +659,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        control: &SealControl,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
@@ -468,6 +671,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_count,
             configs,
             labels,
+            control,
         )
     }
 
@@ -741,6 +945,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        control: &SealControl,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: PoseidonArity,
@@ -752,6 +957,9 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
             let mut trees = Vec::with_capacity(tree_count);
             for (i, config) in configs.iter().enumerate() {
+                // Base trees are rebuilt from the labels on every run, so stopping is safe.
+                control.check()?;
+
                 let mut hashes: Vec<<Tree::Hasher as Hasher>::Domain> =
                     vec![<Tree::Hasher as Hasher>::Domain::default(); nodes_count];
 
@@ -793,6 +1001,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                         config.clone(),
                     ),
                 );
+                control.report(Progress::TreeC {
+                    built: i + 1,
+                    total: tree_count,
+                });
             }
 
             assert_eq!(tree_count, trees.len());
@@ -811,6 +1023,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        control: &SealControl,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
@@ -819,14 +1032,20 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         if SETTINGS.use_gpu_tree_builder
             && TypeId::of::<Tree::Hasher>() == TypeId::of::<PoseidonHasher>()
         {
-            Self::generate_tree_r_last_gpu::<TreeArity>(
+            control.check()?;
+            let tree_r_last = Self::generate_tree_r_last_gpu::<TreeArity>(
                 data,
                 nodes_count,
                 tree_count,
                 tree_r_last_config,
                 replica_path,
                 labels,
-            )
+            )?;
+            control.report(Progress::TreeRLast {
+                built: tree_count,
+                total: tree_count,
+            });
+            Ok(tree_r_last)
         } else {
             Self::generate_tree_r_last_cpu::<TreeArity>(
                 data,
@@ -835,6 +1054,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_r_last_config,
                 replica_path,
                 labels,
+                control,
             )
         }
     }
@@ -847,6 +1067,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        control: &SealControl,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
@@ -858,6 +1079,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_r_last_config,
             replica_path,
             labels,
+            control,
         )
     }
 
@@ -1084,10 +1306,15 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
         labels: &LabelsCache<Tree>,
+        control: &SealControl,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
     {
+        // The replica is encoded in place below, stopping half way would leave it partially
+        // encoded. This is the last point at which the replication can be cancelled.
+        control.check()?;
+
         let (configs, replica_config) = split_config_and_replica(
             tree_r_last_config.clone(),
             replica_path,
@@ -1145,6 +1372,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 config.clone(),
             )
             .with_context(|| format!("failed tree_r_last CPU {}/{}", i + 1, tree_count))?;
+            control.report(Progress::TreeRLast {
+                built: i + 1,
+                total: tree_count,
+            });
 
             start = end;
             end += size / tree_count;
@@ -1181,10 +1412,12 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             config,
             replica_path,
             labels,
+            &SealControl::default(),
         )
         .context("failed to transform")
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn transform_and_replicate_layers_inner(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
@@ -1193,6 +1426,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         config: StoreConfig,
         replica_path: PathBuf,
         label_configs: Labels<Tree>,
+        control: &SealControl,
     ) -> Result<TransformedLayers<Tree, G>> {
         trace!("transform_and_replicate_layers");
         let nodes_count = graph.size();
@@ -1277,6 +1511,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     tree_count,
                     configs,
                     &labels,
+                    control,
                 )?;
                 tree_c.root()
             }
@@ -1287,6 +1522,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     tree_count,
                     configs,
                     &labels,
+                    control,
                 )?;
                 tree_c.root()
             }
@@ -1297,6 +1533,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     tree_count,
                     configs,
                     &labels,
+                    control,
                 )?;
                 tree_c.root()
             }
@@ -1338,6 +1575,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_r_last_config.clone(),
                 replica_path.clone(),
                 &labels,
+                control,
             )
             .context("failed to generate tree_r_last")
         })?;
@@ -1376,17 +1614,84 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         pp: &'a PublicParams<Tree>,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
+    ) -> Result<Labels<Tree>> {
+        Self::replicate_phase1_with_control(pp, replica_id, config, &SealControl::default())
+    }
+
+    /// Phase1 of replication, reporting the labelling progress to and stopping when cancelled
+    /// through `control`.
+    pub fn replicate_phase1_with_control(
+        pp: &'a PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        control: &SealControl,
+    ) -> Result<Labels<Tree>> {
+        Self::replicate_phase1_with_callback(pp, replica_id, config, control, &mut |_, _, _| Ok(()))
+    }
+
+    /// Phase1 of replication, calling `on_layer` as soon as each layer is available on disk,
+    /// e.g. to record progress that allows resuming an interrupted replication.
+    pub fn replicate_phase1_with_callback(
+        pp: &'a PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        control: &SealControl,
+        on_layer: &mut LayerCallback<'_>,
     ) -> Result<Labels<Tree>> {
         info!("replicate_phase1");
 
         let labels = measure_op(Operation::EncodeWindowTimeAll, || {
-            Self::generate_labels_for_encoding(&pp.graph, &pp.layer_challenges, replica_id, config)
+            Self::generate_labels_for_encoding_with_callback(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_id,
+                config,
+                control,
+                on_layer,
+            )
         })?
         .0;
 
         Ok(labels)
     }
 
+    /// Phase1 of replication for several sectors of the same size and porep_id at once. Each
+    /// sector is labelled with its own replica id and its layers are stored as described by
+    /// the corresponding entry of `configs`.
+    pub fn replicate_phase1_batch(
+        pp: &'a PublicParams<Tree>,
+        replica_ids: &[<Tree::Hasher as Hasher>::Domain],
+        configs: Vec<StoreConfig>,
+    ) -> Result<Vec<Labels<Tree>>> {
+        Self::replicate_phase1_batch_with_control(pp, replica_ids, configs, &SealControl::default())
+    }
+
+    /// Like `replicate_phase1_batch`, reporting the labelling progress of the batch to and
+    /// stopping when cancelled through `control`.
+    pub fn replicate_phase1_batch_with_control(
+        pp: &'a PublicParams<Tree>,
+        replica_ids: &[<Tree::Hasher as Hasher>::Domain],
+        configs: Vec<StoreConfig>,
+        control: &SealControl,
+    ) -> Result<Vec<Labels<Tree>>> {
+        info!("replicate_phase1_batch");
+
+        let labels = measure_op(Operation::EncodeWindowTimeAll, || {
+            Self::generate_labels_for_encoding_batch(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_ids,
+                configs,
+                control,
+            )
+        })?
+        .into_iter()
+        .map(|(labels, _)| labels)
+        .collect();
+
+        Ok(labels)
+    }
+
     /// Phase2 of replication.
     #[allow(clippy::type_complexity)]
     pub fn replicate_phase2(
@@ -1399,6 +1704,34 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     ) -> Result<(
         <Self as PoRep<'a, Tree::Hasher, G>>::Tau,
         <Self as PoRep<'a, Tree::Hasher, G>>::ProverAux,
+    )> {
+        Self::replicate_phase2_with_control(
+            pp,
+            label_configs,
+            data,
+            data_tree,
+            config,
+            replica_path,
+            &SealControl::default(),
+        )
+    }
+
+    /// Phase2 of replication, reporting the tree building progress to `control`.
+    ///
+    /// Cancellation is honoured while building tree_c, but not anymore once the replica is
+    /// being encoded as part of building tree_r_last.
+    #[allow(clippy::type_complexity)]
+    pub fn replicate_phase2_with_control(
+        pp: &'a PublicParams<Tree>,
+        label_configs: Labels<Tree>,
+        data: Data<'a>,
+        data_tree: BinaryMerkleTree<G>,
+        config: StoreConfig,
+        replica_path: PathBuf,
+        control: &SealControl,
+    ) -> Result<(
+        <Self as PoRep<'a, Tree::Hasher, G>>::Tau,
+        <Self as PoRep<'a, Tree::Hasher, G>>::ProverAux,
     )> {
         info!("replicate_phase2");
 
@@ -1410,6 +1743,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             config,
             replica_path,
             label_configs,
+            control,
         )?;
 
         Ok((tau, (paux, taux)))
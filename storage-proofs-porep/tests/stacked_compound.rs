@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use bellperson::{
     util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem},
     Circuit,
@@ -16,6 +18,9 @@ use storage_proofs_core::{
     compound_proof::{self, CompoundProof},
     drgraph::BASE_DEGREE,
     merkle::{get_base_tree_count, DiskTree, MerkleTreeTrait},
+    multi_proof::MultiProof,
+    proof::ProofScheme,
+    synthesis::SynthesizedCircuit,
     test_helper::setup_replica,
     util::default_rows_to_discard,
     TEST_SEED,
@@ -47,20 +52,100 @@ fn test_stacked_compound_poseidon_top_8_4_2() {
     test_stacked_compound::<DiskTree<PoseidonHasher, U8, U4, U2>>();
 }
 
-fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
-    let nodes = 8 * get_base_tree_count::<Tree>();
+#[test]
+#[ignore]
+fn test_stacked_compound_spooled_2kib_poseidon_base_8() {
+    type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let cache_dir = tempdir().unwrap();
+    let (public_params, public_inputs, private_inputs, t_aux_orig) =
+        replicate_for_test::<Tree>(2048 / 32, cache_dir.path(), &mut rng);
+
+    let groth_params = <StackedCompound<Tree, Sha256Hasher> as CompoundProof<
+        StackedDrg<'_, Tree, Sha256Hasher>,
+        _,
+    >>::groth_params(Some(&mut rng), &public_params.vanilla_params)
+    .expect("failed to generate groth params");
+
+    let partition_count = StackedCompound::<Tree, Sha256Hasher>::partition_count(&public_params);
+    let vanilla_proofs = StackedDrg::<Tree, Sha256Hasher>::prove_all_partitions(
+        &public_params.vanilla_params,
+        &public_inputs,
+        &private_inputs,
+        partition_count,
+    )
+    .expect("failed to generate vanilla proofs");
+    TemporaryAux::<Tree, Sha256Hasher>::clear_temp(t_aux_orig).expect("t_aux delete failed");
+
+    // Synthesize, spool the circuits to disk and prove them from there, as a separate proving
+    // process would.
+    let synthesized = StackedCompound::<Tree, Sha256Hasher>::synthesize_circuits(
+        &public_inputs,
+        vanilla_proofs,
+        &public_params.vanilla_params,
+    )
+    .expect("failed to synthesize");
+    assert_eq!(synthesized.len(), partition_count);
+
+    let spool = tempdir().unwrap();
+    for (k, circuit) in synthesized.iter().enumerate() {
+        circuit
+            .write_to_file(spool.path().join(format!("partition-{}", k)))
+            .expect("failed to spool circuit");
+    }
+    drop(synthesized);
+
+    let spooled = (0..partition_count)
+        .map(|k| SynthesizedCircuit::read_from_file(spool.path().join(format!("partition-{}", k))))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("failed to read spooled circuits");
+
+    let groth_proofs =
+        StackedCompound::<Tree, Sha256Hasher>::prove_synthesized(spooled, &groth_params, false)
+            .expect("failed to prove spooled circuits");
+    let proof = MultiProof::new(groth_proofs, &groth_params.pvk);
 
+    let verified = StackedCompound::verify(
+        &public_params,
+        &public_inputs,
+        &proof,
+        &ChallengeRequirements {
+            minimum_challenges: 1,
+        },
+    )
+    .expect("failed while verifying");
+    assert!(verified);
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
+type TestPublicInputs<Tree> = PublicInputs<
+    <<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain,
+    <Sha256Hasher as Hasher>::Domain,
+>;
+
+/// Replicates `nodes` nodes of random data in `cache_dir` and returns the compound public
+/// params, the public and private inputs for proving it, and the t_aux to clear afterwards.
+fn replicate_for_test<Tree: 'static + MerkleTreeTrait>(
+    nodes: usize,
+    cache_dir: &Path,
+    rng: &mut XorShiftRng,
+) -> (
+    compound_proof::PublicParams<'static, StackedDrg<'static, Tree, Sha256Hasher>>,
+    TestPublicInputs<Tree>,
+    PrivateInputs<Tree, Sha256Hasher>,
+    TemporaryAux<Tree, Sha256Hasher>,
+) {
     let degree = BASE_DEGREE;
     let expansion_degree = EXP_DEGREE;
     let num_layers = 2;
     let layer_challenges = LayerChallenges::new(num_layers, 1);
     let partition_count = 1;
 
-    let mut rng = XorShiftRng::from_seed(TEST_SEED);
-
-    let replica_id: Fr = Fr::random(&mut rng);
+    let replica_id: Fr = Fr::random(&mut *rng);
     let data: Vec<u8> = (0..nodes)
-        .flat_map(|_| fr_into_bytes(&Fr::random(&mut rng)))
+        .flat_map(|_| fr_into_bytes(&Fr::random(&mut *rng)))
         .collect();
 
     let arbitrary_porep_id = [55; 32];
@@ -79,15 +164,14 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
 
     // MT for original data is always named tree-d, and it will be
     // referenced later in the process as such.
-    let cache_dir = tempdir().unwrap();
     let config = StoreConfig::new(
-        cache_dir.path(),
+        cache_dir,
         CacheKey::CommDTree.to_string(),
         default_rows_to_discard(nodes, BINARY_ARITY),
     );
 
     // Generate a replica path.
-    let replica_path = cache_dir.path().join("replica-path");
+    let replica_path = cache_dir.join("replica-path");
     let mut mmapped_data = setup_replica(&data, &replica_path);
 
     let public_params = StackedCompound::setup(&setup_params).expect("setup failed");
@@ -106,13 +190,12 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
     assert_ne!(data, copied, "replication did not change data");
 
     let seed = rng.gen();
-    let public_inputs =
-        PublicInputs::<<Tree::Hasher as Hasher>::Domain, <Sha256Hasher as Hasher>::Domain> {
-            replica_id: replica_id.into(),
-            seed,
-            tau: Some(tau),
-            k: None,
-        };
+    let public_inputs = PublicInputs {
+        replica_id: replica_id.into(),
+        seed,
+        tau: Some(tau),
+        k: None,
+    };
 
     // Store a copy of the t_aux for later resource deletion.
     let t_aux_orig = t_aux.clone();
@@ -124,6 +207,17 @@ fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
 
     let private_inputs = PrivateInputs::<Tree, Sha256Hasher> { p_aux, t_aux };
 
+    (public_params, public_inputs, private_inputs, t_aux_orig)
+}
+
+fn test_stacked_compound<Tree: 'static + MerkleTreeTrait>() {
+    let nodes = 8 * get_base_tree_count::<Tree>();
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let cache_dir = tempdir().unwrap();
+    let (public_params, public_inputs, private_inputs, t_aux_orig) =
+        replicate_for_test::<Tree>(nodes, cache_dir.path(), &mut rng);
+
     {
         let (circuit, inputs) =
             StackedCompound::circuit_for_test(&public_params, &public_inputs, &private_inputs)
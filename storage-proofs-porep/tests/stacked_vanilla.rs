@@ -1,4 +1,4 @@
-use std::fs::remove_file;
+use std::fs::{copy, read, remove_file};
 
 use blstrs::Scalar as Fr;
 use ff::{Field, PrimeField};
@@ -29,7 +29,7 @@ use storage_proofs_porep::{
     },
     PoRep,
 };
-use tempfile::tempdir;
+use tempfile::{tempdir, TempDir};
 
 const DEFAULT_STACKED_LAYERS: usize = 11;
 
@@ -179,6 +179,130 @@ fn test_extract_all<Tree: 'static + MerkleTreeTrait>() {
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_extract_range_sha256_base_8() {
+    test_extract_range::<DiskTree<Sha256Hasher, U8, U0, U0>>();
+}
+
+#[test]
+fn test_stacked_porep_extract_range_poseidon_top_8_8_2() {
+    test_extract_range::<DiskTree<PoseidonHasher, U8, U8, U2>>();
+}
+
+fn test_extract_range<Tree: 'static + MerkleTreeTrait>() {
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let replica_id: <Tree::Hasher as Hasher>::Domain =
+        <Tree::Hasher as Hasher>::Domain::random(&mut rng);
+    let nodes = 64 * get_base_tree_count::<Tree>();
+
+    let data: Vec<u8> = (0..nodes)
+        .flat_map(|_| {
+            let v = <Tree::Hasher as Hasher>::Domain::random(&mut rng);
+            v.into_bytes()
+        })
+        .collect();
+
+    let cache_dir = tempdir().expect("tempdir failure");
+    let config = StoreConfig::new(
+        cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+
+    let replica_path = cache_dir.path().join("replica-path");
+    let mut mmapped_data = setup_replica(&data, &replica_path);
+
+    let layer_challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: layer_challenges.clone(),
+        api_version: ApiVersion::V1_1_0,
+    };
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    StackedDrg::<Tree, Blake2sHasher>::replicate(
+        &pp,
+        &replica_id,
+        (mmapped_data.as_mut()).into(),
+        None,
+        config.clone(),
+        replica_path,
+    )
+    .expect("replication failed");
+    let replica = mmapped_data.to_vec();
+
+    let extract_range = |first_node: usize, num_nodes: usize| {
+        let range = first_node * NODE_SIZE..(first_node + num_nodes) * NODE_SIZE;
+        let mut decoded = replica[range.clone()].to_vec();
+        StackedDrg::<Tree, Blake2sHasher>::extract_range(
+            &pp,
+            &replica_id,
+            &mut decoded,
+            config.clone(),
+            first_node,
+            num_nodes,
+        )
+        .expect("failed to extract range");
+        assert_eq!(&data[range], &decoded[..]);
+    };
+
+    // The last layer labels are still in the cache dir.
+    extract_range(0, 1);
+    extract_range(5, 17);
+    extract_range(nodes - 3, 3);
+
+    // Labels of another replica, as left behind by sealing with another ticket, are ignored.
+    let last_layer_config = StoreConfig::from_config(
+        &config,
+        CacheKey::label_layer(DEFAULT_STACKED_LAYERS),
+        Some(nodes),
+    );
+    let last_layer_path = StoreConfig::data_path(&last_layer_config.path, &last_layer_config.id);
+    let other_cache_dir = tempdir().expect("tempdir failure");
+    let other_config = StoreConfig::new(
+        other_cache_dir.path(),
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(nodes, BINARY_ARITY),
+    );
+    let other_replica_path = other_cache_dir.path().join("replica-path");
+    let mut other_mmapped_data = setup_replica(&data, &other_replica_path);
+    StackedDrg::<Tree, Blake2sHasher>::replicate(
+        &pp,
+        &<Tree::Hasher as Hasher>::Domain::random(&mut rng),
+        (other_mmapped_data.as_mut()).into(),
+        None,
+        other_config,
+        other_replica_path,
+    )
+    .expect("replication failed");
+    copy(
+        StoreConfig::data_path(&other_cache_dir.path().to_path_buf(), &last_layer_config.id),
+        &last_layer_path,
+    )
+    .expect("failed to copy last layer of another replica");
+    extract_range(3, 11);
+
+    // Without them, the labels are regenerated.
+    remove_file(&last_layer_path).expect("failed to delete last layer cache");
+    extract_range(7, 9);
+
+    let mut out_of_bounds = vec![0u8; 2 * NODE_SIZE];
+    assert!(StackedDrg::<Tree, Blake2sHasher>::extract_range(
+        &pp,
+        &replica_id,
+        &mut out_of_bounds,
+        config,
+        nodes - 1,
+        2,
+    )
+    .is_err());
+
+    cache_dir.close().expect("Failed to remove cache dir");
+}
+
 #[test]
 fn test_stacked_porep_resume_seal() {
     // pretty_env_logger::try_init().ok();
@@ -307,6 +431,84 @@ fn test_stacked_porep_resume_seal() {
     cache_dir.close().expect("Failed to remove cache dir");
 }
 
+#[test]
+fn test_stacked_porep_replicate_phase1_batch() {
+    type Tree = DiskTree<PoseidonHasher, U8, U0, U0>;
+
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    // Large enough for the parents to be read in more than one chunk.
+    let nodes = 8192 * get_base_tree_count::<Tree>();
+    let layer_challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
+
+    let sp = SetupParams {
+        nodes,
+        degree: BASE_DEGREE,
+        expansion_degree: EXP_DEGREE,
+        porep_id: [32; 32],
+        layer_challenges: layer_challenges.clone(),
+        api_version: ApiVersion::V1_1_0,
+    };
+
+    let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+    let replica_ids: Vec<<PoseidonHasher as Hasher>::Domain> = (0..3)
+        .map(|_| <PoseidonHasher as Hasher>::Domain::random(&mut rng))
+        .collect();
+    let cache_dirs: Vec<_> = replica_ids
+        .iter()
+        .map(|_| tempdir().expect("tempdir failure"))
+        .collect();
+    let store_config = |dir: &TempDir| {
+        StoreConfig::new(
+            dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        )
+    };
+    let configs: Vec<_> = cache_dirs.iter().map(store_config).collect();
+
+    // The first two layers of one of the sectors are on disk already.
+    let (_, label_states) = StackedDrg::<Tree, Blake2sHasher>::generate_labels_for_encoding(
+        &pp.graph,
+        &layer_challenges,
+        &replica_ids[1],
+        configs[1].clone(),
+    )
+    .expect("label generation failed");
+    for label_state in &label_states[2..] {
+        let config = &label_state.config;
+        let data_path = StoreConfig::data_path(&config.path, &config.id);
+        remove_file(data_path).expect("failed to delete layer cache");
+    }
+
+    let batch_labels =
+        StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_batch(&pp, &replica_ids, configs)
+            .expect("batch labelling failed");
+    assert_eq!(batch_labels.len(), replica_ids.len());
+
+    for (replica_id, labels) in replica_ids.iter().zip(batch_labels.iter()) {
+        let expected_dir = tempdir().expect("tempdir failure");
+        let expected_labels = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(
+            &pp,
+            replica_id,
+            store_config(&expected_dir),
+        )
+        .expect("labelling failed");
+
+        assert_eq!(labels.labels.len(), DEFAULT_STACKED_LAYERS);
+        for (config, expected_config) in labels.labels.iter().zip(expected_labels.labels.iter()) {
+            let layer = read(StoreConfig::data_path(&config.path, &config.id))
+                .expect("failed to read layer");
+            let expected_layer = read(StoreConfig::data_path(
+                &expected_config.path,
+                &expected_config.id,
+            ))
+            .expect("failed to read layer");
+            assert_eq!(layer, expected_layer, "layer {} differs", config.id);
+        }
+    }
+}
+
 table_tests! {
     test_prove_verify_fixed {
        test_stacked_porep_prove_verify(64);
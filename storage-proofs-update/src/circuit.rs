@@ -0,0 +1,271 @@
+use bellperson::{gadgets::num::AllocatedNum, Circuit, ConstraintSystem, SynthesisError};
+use blstrs::Scalar as Fr;
+use filecoin_hashers::{HashFunction, Hasher, PoseidonArity};
+use generic_array::typenum::{U0, U2};
+use storage_proofs_core::{
+    compound_proof::CircuitComponent,
+    gadgets::{
+        constraint, encode,
+        por::{AuthPath, PoRCircuit},
+        variables::Root,
+    },
+    merkle::{DiskStore, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
+};
+
+use crate::vanilla::ChallengeProof;
+
+type TreeAuthPath<T> = AuthPath<
+    <T as MerkleTreeTrait>::Hasher,
+    <T as MerkleTreeTrait>::Arity,
+    <T as MerkleTreeTrait>::SubTreeArity,
+    <T as MerkleTreeTrait>::TopTreeArity,
+>;
+
+/// This is the `EmptySectorUpdate` circuit.
+pub struct EmptySectorUpdateCircuit<Tree: MerkleTreeTrait, G: Hasher> {
+    /// Public input: replica commitment of the committed capacity sector.
+    pub comm_r_old: Option<Fr>,
+    /// Public input: commitment to the new data.
+    pub comm_d_new: Option<Fr>,
+    /// Public input: replica commitment of the updated sector.
+    pub comm_r_new: Option<Fr>,
+
+    /// Private input: comm_c, shared by both replicas.
+    pub comm_c: Option<Fr>,
+    /// Private input: root of tree_r_last of the committed capacity sector.
+    pub comm_r_last_old: Option<Fr>,
+    /// Private input: root of tree_r_last of the updated sector.
+    pub comm_r_last_new: Option<Fr>,
+
+    pub challenges: Vec<Challenge<Tree, G>>,
+}
+
+// We must manually implement Clone for all types generic over MerkleTreeTrait (instead of using
+// #[derive(Clone)]) because derive(Clone) will only expand for MerkleTreeTrait types that also
+// implement Clone. Not every MerkleTreeTrait type is Clone-able because not all merkel Store's are
+// Clone-able, therefore deriving Clone would impl Clone for less than all possible Tree types.
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for EmptySectorUpdateCircuit<Tree, G> {
+    fn clone(&self) -> Self {
+        EmptySectorUpdateCircuit {
+            comm_r_old: self.comm_r_old,
+            comm_d_new: self.comm_d_new,
+            comm_r_new: self.comm_r_new,
+            comm_c: self.comm_c,
+            comm_r_last_old: self.comm_r_last_old,
+            comm_r_last_new: self.comm_r_last_new,
+            challenges: self.challenges.clone(),
+        }
+    }
+}
+
+/// Openings of a single challenged node.
+pub struct Challenge<Tree: MerkleTreeTrait, G: Hasher> {
+    pub data_leaf: Option<Fr>,
+    pub data_path: AuthPath<G, U2, U0, U0>,
+    pub sector_key_leaf: Option<Fr>,
+    pub sector_key_path: TreeAuthPath<Tree>,
+    pub replica_path: TreeAuthPath<Tree>,
+}
+
+// We must manually implement Clone for all types generic over MerkleTreeTrait (instead of using
+// #derive(Clone)).
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for Challenge<Tree, G> {
+    fn clone(&self) -> Self {
+        Challenge {
+            data_leaf: self.data_leaf,
+            data_path: self.data_path.clone(),
+            sector_key_leaf: self.sector_key_leaf,
+            sector_key_path: self.sector_key_path.clone(),
+            replica_path: self.replica_path.clone(),
+        }
+    }
+}
+
+impl<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> Challenge<Tree, G> {
+    pub fn circuit(vanilla_proof: &ChallengeProof<Tree, G>) -> Self {
+        let ChallengeProof {
+            sector_key_proof,
+            replica_proof,
+            data_proof,
+        } = vanilla_proof;
+
+        Challenge {
+            data_leaf: Some(data_proof.leaf().into()),
+            data_path: data_proof.as_options().into(),
+            sector_key_leaf: Some(sector_key_proof.leaf().into()),
+            sector_key_path: sector_key_proof.as_options().into(),
+            replica_path: replica_proof.as_options().into(),
+        }
+    }
+
+    pub fn blank(nodes: usize) -> Self {
+        Challenge {
+            data_leaf: None,
+            data_path: AuthPath::blank(nodes),
+            sector_key_leaf: None,
+            sector_key_path: AuthPath::blank(nodes),
+            replica_path: AuthPath::blank(nodes),
+        }
+    }
+
+    fn synthesize<CS: ConstraintSystem<Fr>>(
+        self,
+        mut cs: CS,
+        comm_d_new: &AllocatedNum<Fr>,
+        comm_r_last_old: &AllocatedNum<Fr>,
+        comm_r_last_new: &AllocatedNum<Fr>,
+    ) -> Result<(), SynthesisError> {
+        let Challenge {
+            data_leaf,
+            data_path,
+            sector_key_leaf,
+            sector_key_path,
+            replica_path,
+        } = self;
+
+        // -- verify the data node is part of comm_d_new
+        let data_num = AllocatedNum::alloc(cs.namespace(|| "data_leaf"), || {
+            data_leaf.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        enforce_inclusion(
+            cs.namespace(|| "comm_d_new_inclusion"),
+            data_path,
+            comm_d_new,
+            &data_num,
+        )?;
+
+        // -- verify the sector key node is part of comm_r_last_old
+        let sector_key_num = AllocatedNum::alloc(cs.namespace(|| "sector_key_leaf"), || {
+            sector_key_leaf.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        enforce_inclusion(
+            cs.namespace(|| "comm_r_last_old_inclusion"),
+            sector_key_path,
+            comm_r_last_old,
+            &sector_key_num,
+        )?;
+
+        // -- verify the encoded node is part of comm_r_last_new
+        let replica_num = encode::encode(cs.namespace(|| "encode"), &sector_key_num, &data_num)?;
+        enforce_inclusion(
+            cs.namespace(|| "comm_r_last_new_inclusion"),
+            replica_path,
+            comm_r_last_new,
+            &replica_num,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> CircuitComponent for EmptySectorUpdateCircuit<Tree, G> {
+    type ComponentPrivateInputs = ();
+}
+
+impl<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> Circuit<Fr>
+    for EmptySectorUpdateCircuit<Tree, G>
+{
+    fn synthesize<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let EmptySectorUpdateCircuit {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            comm_c,
+            comm_r_last_old,
+            comm_r_last_new,
+            challenges,
+        } = self;
+
+        // Allocate public inputs
+        let comm_r_old_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_old"), || {
+            comm_r_old.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        comm_r_old_num.inputize(cs.namespace(|| "comm_r_old_input"))?;
+
+        let comm_d_new_num = AllocatedNum::alloc(cs.namespace(|| "comm_d_new"), || {
+            comm_d_new.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        comm_d_new_num.inputize(cs.namespace(|| "comm_d_new_input"))?;
+
+        let comm_r_new_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_new"), || {
+            comm_r_new.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        comm_r_new_num.inputize(cs.namespace(|| "comm_r_new_input"))?;
+
+        // Allocate private inputs
+        let comm_c_num = AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
+            comm_c.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        let comm_r_last_old_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_last_old"), || {
+            comm_r_last_old.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        let comm_r_last_new_num = AllocatedNum::alloc(cs.namespace(|| "comm_r_last_new"), || {
+            comm_r_last_new.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        // Verify H(comm_c || comm_r_last_old) == comm_r_old
+        {
+            let hash_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                cs.namespace(|| "H_comm_c_comm_r_last_old"),
+                &comm_c_num,
+                &comm_r_last_old_num,
+            )?;
+
+            constraint::equal(
+                cs,
+                || "enforce_comm_r_old_is_hash_of_comm_c_comm_r_last_old",
+                &comm_r_old_num,
+                &hash_num,
+            );
+        }
+
+        // Verify H(comm_c || comm_r_last_new) == comm_r_new
+        {
+            let hash_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                cs.namespace(|| "H_comm_c_comm_r_last_new"),
+                &comm_c_num,
+                &comm_r_last_new_num,
+            )?;
+
+            constraint::equal(
+                cs,
+                || "enforce_comm_r_new_is_hash_of_comm_c_comm_r_last_new",
+                &comm_r_new_num,
+                &hash_num,
+            );
+        }
+
+        for (i, challenge) in challenges.into_iter().enumerate() {
+            challenge.synthesize(
+                cs.namespace(|| format!("challenge_{}", i)),
+                &comm_d_new_num,
+                &comm_r_last_old_num,
+                &comm_r_last_new_num,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn enforce_inclusion<H, U, V, W, CS: ConstraintSystem<Fr>>(
+    cs: CS,
+    path: AuthPath<H, U, V, W>,
+    root: &AllocatedNum<Fr>,
+    leaf: &AllocatedNum<Fr>,
+) -> Result<(), SynthesisError>
+where
+    H: 'static + Hasher,
+    U: 'static + PoseidonArity,
+    V: 'static + PoseidonArity,
+    W: 'static + PoseidonArity,
+{
+    let root = Root::from_allocated::<CS>(root.clone());
+    let leaf = Root::from_allocated::<CS>(leaf.clone());
+
+    PoRCircuit::<MerkleTreeWrapper<H, DiskStore<H::Domain>, U, V, W>>::synthesize(
+        cs, leaf, path, root, true,
+    )?;
+
+    Ok(())
+}
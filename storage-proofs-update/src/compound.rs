@@ -0,0 +1,143 @@
+use std::marker::PhantomData;
+
+use anyhow::ensure;
+use bellperson::Circuit;
+use blstrs::Scalar as Fr;
+use filecoin_hashers::Hasher;
+use storage_proofs_core::{
+    compound_proof::{CircuitComponent, CompoundProof},
+    error::Result,
+    gadgets::por::PoRCompound,
+    merkle::{BinaryMerkleTree, MerkleTreeTrait},
+    parameter_cache::{CacheableParameters, ParameterSetMetadata},
+    por,
+    proof::ProofScheme,
+};
+
+use crate::{
+    circuit::{Challenge, EmptySectorUpdateCircuit},
+    vanilla::{generate_leaf_challenges, EmptySectorUpdate},
+};
+
+pub struct EmptySectorUpdateCompound<Tree, G>
+where
+    Tree: MerkleTreeTrait,
+    G: Hasher,
+{
+    _t: PhantomData<Tree>,
+    _g: PhantomData<G>,
+}
+
+impl<C: Circuit<Fr>, P: ParameterSetMetadata, Tree: MerkleTreeTrait, G: Hasher>
+    CacheableParameters<C, P> for EmptySectorUpdateCompound<Tree, G>
+{
+    fn cache_prefix() -> String {
+        format!("empty-sector-update-{}-{}", Tree::display(), G::name())
+    }
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher>
+    CompoundProof<'a, EmptySectorUpdate<'a, Tree, G>, EmptySectorUpdateCircuit<Tree, G>>
+    for EmptySectorUpdateCompound<Tree, G>
+{
+    fn generate_public_inputs(
+        pub_inputs: &<EmptySectorUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicInputs,
+        pub_params: &<EmptySectorUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicParams,
+        k: Option<usize>,
+    ) -> Result<Vec<Fr>> {
+        let mut inputs = vec![
+            pub_inputs.comm_r_old.into(),
+            pub_inputs.comm_d_new.into(),
+            pub_inputs.comm_r_new.into(),
+        ];
+
+        let por_params = por::PublicParams {
+            leaves: pub_params.nodes(),
+            private: true,
+        };
+
+        let challenges =
+            generate_leaf_challenges(pub_params, pub_inputs.comm_r_new, k.unwrap_or(0));
+        for challenge in challenges {
+            // comm_d_new inclusion proof for the data node
+            inputs.extend(generate_inclusion_inputs::<BinaryMerkleTree<G>>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+            // comm_r_last_old inclusion proof for the sector key node
+            inputs.extend(generate_inclusion_inputs::<Tree>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+            // comm_r_last_new inclusion proof for the replica node
+            inputs.extend(generate_inclusion_inputs::<Tree>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+        }
+
+        Ok(inputs)
+    }
+
+    fn circuit(
+        pub_inputs: &<EmptySectorUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicInputs,
+        _priv_inputs: <EmptySectorUpdateCircuit<Tree, G> as CircuitComponent>::ComponentPrivateInputs,
+        vanilla_proof: &<EmptySectorUpdate<'a, Tree, G> as ProofScheme<'a>>::Proof,
+        pub_params: &<EmptySectorUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicParams,
+        _k: Option<usize>,
+    ) -> Result<EmptySectorUpdateCircuit<Tree, G>> {
+        ensure!(
+            vanilla_proof.challenge_proofs.len() == pub_params.challenge_count,
+            "vanilla proofs must equal challenge_count: {} != {}",
+            vanilla_proof.challenge_proofs.len(),
+            pub_params.challenge_count,
+        );
+
+        Ok(EmptySectorUpdateCircuit {
+            comm_r_old: Some(pub_inputs.comm_r_old.into()),
+            comm_d_new: Some(pub_inputs.comm_d_new.into()),
+            comm_r_new: Some(pub_inputs.comm_r_new.into()),
+            comm_c: Some(vanilla_proof.comm_c.into()),
+            comm_r_last_old: Some(vanilla_proof.comm_r_last_old()?.into()),
+            comm_r_last_new: Some(vanilla_proof.comm_r_last_new()?.into()),
+            challenges: vanilla_proof
+                .challenge_proofs
+                .iter()
+                .map(Challenge::circuit)
+                .collect(),
+        })
+    }
+
+    fn blank_circuit(
+        pub_params: &<EmptySectorUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicParams,
+    ) -> EmptySectorUpdateCircuit<Tree, G> {
+        EmptySectorUpdateCircuit {
+            comm_r_old: None,
+            comm_d_new: None,
+            comm_r_new: None,
+            comm_c: None,
+            comm_r_last_old: None,
+            comm_r_last_new: None,
+            challenges: (0..pub_params.challenge_count)
+                .map(|_| Challenge::blank(pub_params.nodes()))
+                .collect(),
+        }
+    }
+}
+
+/// Helper to generate public inputs for inclusion proofs.
+fn generate_inclusion_inputs<Tree: 'static + MerkleTreeTrait>(
+    por_params: &por::PublicParams,
+    challenge: usize,
+    k: Option<usize>,
+) -> Result<Vec<Fr>> {
+    let pub_inputs = por::PublicInputs::<<Tree::Hasher as Hasher>::Domain> {
+        challenge,
+        commitment: None,
+    };
+
+    PoRCompound::<Tree>::generate_public_inputs(&pub_inputs, por_params, k)
+}
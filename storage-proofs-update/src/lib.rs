@@ -0,0 +1,10 @@
+#![deny(clippy::all, clippy::perf, clippy::correctness, rust_2018_idioms)]
+#![warn(clippy::unwrap_used)]
+
+mod circuit;
+mod compound;
+mod vanilla;
+
+pub use circuit::*;
+pub use compound::*;
+pub use vanilla::*;
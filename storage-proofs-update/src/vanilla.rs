@@ -0,0 +1,507 @@
+use std::fs::remove_file;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context};
+use blstrs::Scalar as Fr;
+use byteorder::{ByteOrder, LittleEndian};
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use generic_array::typenum::{Unsigned, U0, U2};
+use log::{error, info, trace};
+use merkletree::store::StoreConfig;
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+    ParallelSlice, ParallelSliceMut,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage_proofs_core::{
+    error::Result,
+    merkle::{
+        create_lc_tree, get_base_tree_count, split_config_and_replica, BinaryMerkleTree, LCTree,
+        MerkleProof, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper,
+    },
+    parameter_cache::ParameterSetMetadata,
+    proof::{NoRequirements, ProofScheme},
+    util::{default_rows_to_discard, NODE_SIZE},
+};
+use storage_proofs_porep::encode::{decode, encode};
+
+#[derive(Debug, Clone)]
+pub struct SetupParams {
+    /// Size of the sector in bytes.
+    pub sector_size: u64,
+    /// Number of challenges per partition.
+    pub challenge_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicParams {
+    /// Size of the sector in bytes.
+    pub sector_size: u64,
+    /// Number of challenges per partition.
+    pub challenge_count: usize,
+}
+
+impl PublicParams {
+    /// Number of nodes in the sector.
+    pub fn nodes(&self) -> usize {
+        self.sector_size as usize / NODE_SIZE
+    }
+}
+
+impl ParameterSetMetadata for PublicParams {
+    fn identifier(&self) -> String {
+        format!(
+            "EmptySectorUpdate::PublicParams{{sector_size: {}, challenge_count: {}}}",
+            self.sector_size(),
+            self.challenge_count,
+        )
+    }
+
+    fn sector_size(&self) -> u64 {
+        self.sector_size
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicInputs<T: Domain, S: Domain> {
+    /// The replica commitment of the committed capacity sector.
+    #[serde(bound = "")]
+    pub comm_r_old: T,
+    /// The commitment to the data encoded into the sector.
+    #[serde(bound = "")]
+    pub comm_d_new: S,
+    /// The replica commitment of the updated sector.
+    #[serde(bound = "")]
+    pub comm_r_new: T,
+    /// Partition index
+    pub k: Option<usize>,
+}
+
+type ReplicaTree<Tree> = MerkleTreeWrapper<
+    <Tree as MerkleTreeTrait>::Hasher,
+    <Tree as MerkleTreeTrait>::Store,
+    <Tree as MerkleTreeTrait>::Arity,
+    <Tree as MerkleTreeTrait>::SubTreeArity,
+    <Tree as MerkleTreeTrait>::TopTreeArity,
+>;
+
+#[derive(Debug)]
+pub struct PrivateInputs<'a, Tree: MerkleTreeTrait, G: Hasher> {
+    /// comm_c of the committed capacity sector, which is shared by the updated sector.
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    /// tree_r_last of the committed capacity sector, i.e. over the sector key.
+    pub tree_r_last_old: &'a ReplicaTree<Tree>,
+    /// tree_r_last of the updated replica.
+    pub tree_r_last_new: &'a ReplicaTree<Tree>,
+    /// tree_d over the new data.
+    pub tree_d_new: &'a BinaryMerkleTree<G>,
+}
+
+/// Proof for a single challenged node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeProof<Tree: MerkleTreeTrait, G: Hasher> {
+    /// Inclusion proof of the challenged node in the sector key, the old replica.
+    #[serde(bound(
+        serialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Deserialize<'de>"
+    ))]
+    pub sector_key_proof:
+        MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    /// Inclusion proof of the challenged node in the updated replica.
+    #[serde(bound(
+        serialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Deserialize<'de>"
+    ))]
+    pub replica_proof:
+        MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    /// Inclusion proof of the challenged node in the new data.
+    #[serde(bound(
+        serialize = "MerkleProof<G, U2>: Serialize",
+        deserialize = "MerkleProof<G, U2>: Deserialize<'de>"
+    ))]
+    pub data_proof: MerkleProof<G, U2>,
+}
+
+// We must manually implement Clone for all types generic over MerkleTreeTrait (instead of using
+// #[derive(Clone)]).
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for ChallengeProof<Tree, G> {
+    fn clone(&self) -> Self {
+        Self {
+            sector_key_proof: self.sector_key_proof.clone(),
+            replica_proof: self.replica_proof.clone(),
+            data_proof: self.data_proof.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> ChallengeProof<Tree, G> {
+    /// Verifies that all inclusion proofs open `challenge`, and that the replica node is the
+    /// encoding of the data node with the sector key node.
+    pub fn verify(&self, challenge: usize) -> bool {
+        if !self.sector_key_proof.validate(challenge)
+            || !self.replica_proof.validate(challenge)
+            || !self.data_proof.validate(challenge)
+        {
+            return false;
+        }
+
+        let data: Fr = self.data_proof.leaf().into();
+        let encoded =
+            encode::<<Tree::Hasher as Hasher>::Domain>(self.sector_key_proof.leaf(), data.into());
+
+        encoded == self.replica_proof.leaf()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Proof<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound = "")]
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    #[serde(bound(
+        serialize = "ChallengeProof<Tree, G>: Serialize",
+        deserialize = "ChallengeProof<Tree, G>: Deserialize<'de>"
+    ))]
+    pub challenge_proofs: Vec<ChallengeProof<Tree, G>>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for Proof<Tree, G> {
+    fn clone(&self) -> Self {
+        Self {
+            comm_c: self.comm_c,
+            challenge_proofs: self.challenge_proofs.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Proof<Tree, G> {
+    /// Returns the root of the sector key's tree_r_last, as opened by the challenge proofs.
+    ///
+    /// Fails for a proof without challenge proofs, which can only come from deserialization.
+    pub fn comm_r_last_old(&self) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        let challenge_proof = self
+            .challenge_proofs
+            .first()
+            .context("proof has no challenge proofs")?;
+        Ok(challenge_proof.sector_key_proof.root())
+    }
+
+    /// Returns the root of the updated replica's tree_r_last, as opened by the challenge proofs.
+    ///
+    /// Fails for a proof without challenge proofs, which can only come from deserialization.
+    pub fn comm_r_last_new(&self) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        let challenge_proof = self
+            .challenge_proofs
+            .first()
+            .context("proof has no challenge proofs")?;
+        Ok(challenge_proof.replica_proof.root())
+    }
+}
+
+/// Generates the challenged nodes of partition `k`.
+///
+/// The challenges are derived from `comm_r_new`, which the prover can only know after
+/// encoding the new data into the sector.
+pub fn generate_leaf_challenges<T: Domain>(
+    pub_params: &PublicParams,
+    comm_r_new: T,
+    k: usize,
+) -> Vec<usize> {
+    let mut hasher = Sha256::new();
+    hasher.update(AsRef::<[u8]>::as_ref(&comm_r_new));
+
+    (0..pub_params.challenge_count)
+        .map(|i| {
+            let challenge_index = (k * pub_params.challenge_count + i) as u64;
+
+            let mut hasher = hasher.clone();
+            hasher.update(&challenge_index.to_le_bytes()[..]);
+            let hash = hasher.finalize();
+
+            (LittleEndian::read_u64(&hash[..8]) % pub_params.nodes() as u64) as usize
+        })
+        .collect()
+}
+
+/// Proof that a committed capacity sector was updated with new data, without sealing it again.
+///
+/// The replica of the committed capacity sector, the sector key, is reused as encoding key: every
+/// node of the updated replica is the field sum of the sector key node and the data node. Since
+/// tree_c is not touched, the updated sector shares comm_c with the old one, while its
+/// comm_r_last is the root of the tree_r_last built over the updated replica.
+#[derive(Debug)]
+pub struct EmptySectorUpdate<'a, Tree: MerkleTreeTrait, G: Hasher> {
+    _t: PhantomData<&'a Tree>,
+    _g: PhantomData<G>,
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> EmptySectorUpdate<'a, Tree, G> {
+    /// Encodes `data` into `sector_key`, writing the updated replica to `replica`.
+    pub fn encode_into(replica: &mut [u8], sector_key: &[u8], data: &[u8]) -> Result<()> {
+        ensure!(
+            sector_key.len() == data.len() && replica.len() == data.len(),
+            "sector key ({}), data ({}) and replica ({}) sizes differ",
+            sector_key.len(),
+            data.len(),
+            replica.len()
+        );
+        ensure!(
+            data.len() % NODE_SIZE == 0,
+            "data size must be a multiple of {}",
+            NODE_SIZE
+        );
+
+        replica
+            .par_chunks_mut(NODE_SIZE)
+            .zip(sector_key.par_chunks(NODE_SIZE))
+            .zip(data.par_chunks(NODE_SIZE))
+            .try_for_each(|((replica_node, key_node), data_node)| {
+                let key = <Tree::Hasher as Hasher>::Domain::try_from_bytes(key_node)?;
+                let value = <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node)?;
+                replica_node.copy_from_slice(AsRef::<[u8]>::as_ref(&encode(key, value)));
+
+                Ok(())
+            })
+    }
+
+    /// Decodes `replica` with `sector_key`, writing the data to `data`.
+    pub fn decode_from(data: &mut [u8], sector_key: &[u8], replica: &[u8]) -> Result<()> {
+        ensure!(
+            sector_key.len() == replica.len() && data.len() == replica.len(),
+            "sector key ({}), replica ({}) and data ({}) sizes differ",
+            sector_key.len(),
+            replica.len(),
+            data.len()
+        );
+        ensure!(
+            replica.len() % NODE_SIZE == 0,
+            "replica size must be a multiple of {}",
+            NODE_SIZE
+        );
+
+        data.par_chunks_mut(NODE_SIZE)
+            .zip(sector_key.par_chunks(NODE_SIZE))
+            .zip(replica.par_chunks(NODE_SIZE))
+            .try_for_each(|((data_node, key_node), replica_node)| {
+                let key = <Tree::Hasher as Hasher>::Domain::try_from_bytes(key_node)?;
+                let value = <Tree::Hasher as Hasher>::Domain::try_from_bytes(replica_node)?;
+                data_node.copy_from_slice(AsRef::<[u8]>::as_ref(&decode(key, value)));
+
+                Ok(())
+            })
+    }
+
+    /// Builds tree_r_last over `replica`, which must be the content of the file at
+    /// `replica_path`. The base trees are persisted according to `tree_r_last_config`.
+    pub fn generate_tree_r_last(
+        replica: &[u8],
+        tree_r_last_config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+        let tree_count = get_base_tree_count::<Tree>();
+        let nodes_count = replica.len() / NODE_SIZE / tree_count;
+
+        let (configs, replica_config) = split_config_and_replica(
+            tree_r_last_config.clone(),
+            replica_path,
+            nodes_count,
+            tree_count,
+        )?;
+
+        for (i, (config, base_replica)) in configs
+            .iter()
+            .zip(replica.chunks(nodes_count * NODE_SIZE))
+            .enumerate()
+        {
+            info!("building base tree_r_last {}/{}", i + 1, tree_count);
+
+            // Remove the tree_r_last store if it exists already
+            let tree_r_last_store_path = StoreConfig::data_path(&config.path, &config.id);
+            if tree_r_last_store_path.exists() {
+                remove_file(&tree_r_last_store_path).with_context(|| {
+                    format!("could not remove store={:?}", tree_r_last_store_path)
+                })?;
+            }
+
+            let leafs = base_replica.par_chunks(NODE_SIZE).map(|node| {
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(node)
+                    .expect("try from bytes failed")
+            });
+            LCTree::<Tree::Hasher, Tree::Arity, U0, U0>::from_par_iter_with_config(
+                leafs,
+                config.clone(),
+            )
+            .with_context(|| format!("failed tree_r_last {}/{}", i + 1, tree_count))?;
+        }
+
+        create_lc_tree::<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>(
+            tree_r_last_config
+                .size
+                .context("tree_r_last config has no size")?,
+            &configs,
+            &replica_config,
+        )
+    }
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'a>
+    for EmptySectorUpdate<'a, Tree, G>
+{
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<<Tree::Hasher as Hasher>::Domain, G::Domain>;
+    type PrivateInputs = PrivateInputs<'a, Tree, G>;
+    type Proof = Proof<Tree, G>;
+    type Requirements = NoRequirements;
+
+    fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        ensure!(
+            sp.sector_size as usize % NODE_SIZE == 0,
+            "sector size must be a multiple of {}",
+            NODE_SIZE
+        );
+        ensure!(sp.challenge_count > 0, "challenge count must not be zero");
+
+        Ok(PublicParams {
+            sector_size: sp.sector_size,
+            challenge_count: sp.challenge_count,
+        })
+    }
+
+    fn prove(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        priv_inputs: &Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        let PrivateInputs {
+            comm_c,
+            tree_r_last_old,
+            tree_r_last_new,
+            tree_d_new,
+        } = priv_inputs;
+
+        ensure!(
+            pub_inputs.comm_r_old
+                == <Tree::Hasher as Hasher>::Function::hash2(comm_c, &tree_r_last_old.root()),
+            "comm_r_old does not match comm_c and tree_r_last_old"
+        );
+        ensure!(
+            pub_inputs.comm_r_new
+                == <Tree::Hasher as Hasher>::Function::hash2(comm_c, &tree_r_last_new.root()),
+            "comm_r_new does not match comm_c and tree_r_last_new"
+        );
+        ensure!(
+            pub_inputs.comm_d_new == tree_d_new.root(),
+            "comm_d_new does not match tree_d_new"
+        );
+
+        let rows_to_discard =
+            default_rows_to_discard(tree_r_last_new.leafs(), Tree::Arity::to_usize());
+        trace!(
+            "generating update proof for tree leafs {} and arity {}",
+            tree_r_last_new.leafs(),
+            Tree::Arity::to_usize(),
+        );
+
+        let challenges =
+            generate_leaf_challenges(pub_params, pub_inputs.comm_r_new, pub_inputs.k.unwrap_or(0));
+        let challenge_proofs = challenges
+            .into_par_iter()
+            .map(|challenge| {
+                let proof = ChallengeProof {
+                    sector_key_proof: tree_r_last_old
+                        .gen_cached_proof(challenge, Some(rows_to_discard))?,
+                    replica_proof: tree_r_last_new
+                        .gen_cached_proof(challenge, Some(rows_to_discard))?,
+                    data_proof: tree_d_new.gen_proof(challenge)?,
+                };
+                ensure!(
+                    proof.verify(challenge),
+                    "generated invalid proof for challenge {}",
+                    challenge
+                );
+
+                Ok(proof)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Proof {
+            comm_c: *comm_c,
+            challenge_proofs,
+        })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        let challenges =
+            generate_leaf_challenges(pub_params, pub_inputs.comm_r_new, pub_inputs.k.unwrap_or(0));
+        ensure!(
+            proof.challenge_proofs.len() == challenges.len(),
+            "unexpected number of challenge proofs: {} != {}",
+            proof.challenge_proofs.len(),
+            challenges.len()
+        );
+
+        let comm_r_last_old = proof.comm_r_last_old()?;
+        let comm_r_last_new = proof.comm_r_last_new()?;
+
+        if <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &comm_r_last_old)
+            != pub_inputs.comm_r_old
+        {
+            error!("hash(comm_c || comm_r_last_old) != comm_r_old");
+            return Ok(false);
+        }
+        if <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &comm_r_last_new)
+            != pub_inputs.comm_r_new
+        {
+            error!("hash(comm_c || comm_r_last_new) != comm_r_new");
+            return Ok(false);
+        }
+
+        let nodes = pub_params.nodes();
+        let is_valid = challenges
+            .par_iter()
+            .zip(proof.challenge_proofs.par_iter())
+            .all(|(&challenge, challenge_proof)| {
+                let ChallengeProof {
+                    sector_key_proof,
+                    replica_proof,
+                    data_proof,
+                } = challenge_proof;
+
+                if sector_key_proof.root() != comm_r_last_old
+                    || replica_proof.root() != comm_r_last_new
+                    || data_proof.root() != pub_inputs.comm_d_new
+                {
+                    error!("inclusion proof roots do not match the commitments");
+                    return false;
+                }
+
+                if sector_key_proof.path().len() != sector_key_proof.expected_len(nodes)
+                    || replica_proof.path().len() != replica_proof.expected_len(nodes)
+                    || data_proof.path().len() != data_proof.expected_len(nodes)
+                {
+                    error!("wrong path length for challenge {}", challenge);
+                    return false;
+                }
+
+                if !challenge_proof.verify(challenge) {
+                    error!("invalid proof for challenge {}", challenge);
+                    return false;
+                }
+
+                true
+            });
+
+        Ok(is_valid)
+    }
+
+    fn with_partition(mut pub_in: Self::PublicInputs, k: Option<usize>) -> Self::PublicInputs {
+        pub_in.k = k;
+        pub_in
+    }
+}
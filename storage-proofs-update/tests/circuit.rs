@@ -0,0 +1,212 @@
+use std::fs::{self, File};
+
+use bellperson::{
+    util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem},
+    Circuit,
+};
+use blstrs::Scalar as Fr;
+use ff::Field;
+use filecoin_hashers::{
+    poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain, HashFunction, Hasher,
+};
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
+use merkletree::{
+    merkle::get_merkle_tree_len,
+    store::{LevelCacheStore, StoreConfig},
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    compound_proof::CompoundProof,
+    merkle::{
+        create_base_merkle_tree, generate_tree, get_base_tree_count, BinaryMerkleTree, LCTree,
+        MerkleTreeTrait,
+    },
+    proof::ProofScheme,
+    util::{default_rows_to_discard, NODE_SIZE},
+    TEST_SEED,
+};
+use storage_proofs_update::{
+    EmptySectorUpdate, EmptySectorUpdateCompound, PrivateInputs, PublicInputs, SetupParams,
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_empty_sector_update_circuit_base_8() {
+    test_empty_sector_update_circuit::<LCTree<PoseidonHasher, U8, U0, U0>>(2);
+}
+
+#[test]
+fn test_empty_sector_update_circuit_sub_8_4() {
+    test_empty_sector_update_circuit::<LCTree<PoseidonHasher, U8, U4, U0>>(1);
+}
+
+#[test]
+fn test_empty_sector_update_circuit_top_8_4_2() {
+    test_empty_sector_update_circuit::<LCTree<PoseidonHasher, U8, U4, U2>>(1);
+}
+
+fn test_empty_sector_update_circuit<Tree>(partitions: usize)
+where
+    Tree: 'static
+        + MerkleTreeTrait<
+            Store = LevelCacheStore<<<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain, File>,
+        >,
+{
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let nodes = 64 * tree_count;
+
+    let setup_params = SetupParams {
+        sector_size: (nodes * NODE_SIZE) as u64,
+        challenge_count: 3,
+    };
+    let pub_params =
+        EmptySectorUpdate::<Tree, Sha256Hasher>::setup(&setup_params).expect("setup failed");
+
+    let old_dir = tempdir().expect("tempdir failure");
+    let (sector_key, tree_r_last_old) =
+        generate_tree::<Tree, _>(rng, nodes, Some(old_dir.path().to_path_buf()));
+    let comm_c = <Tree::Hasher as Hasher>::Domain::random(rng);
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_old.root());
+
+    let data = (0..nodes)
+        .flat_map(|_| <Sha256Hasher as Hasher>::Domain::random(rng).into_bytes())
+        .collect::<Vec<u8>>();
+    let tree_d_new = create_base_merkle_tree::<BinaryMerkleTree<Sha256Hasher>>(None, nodes, &data)
+        .expect("failed to create tree_d");
+
+    let new_dir = tempdir().expect("tempdir failure");
+    let mut replica = vec![0u8; data.len()];
+    EmptySectorUpdate::<Tree, Sha256Hasher>::encode_into(&mut replica, &sector_key, &data)
+        .expect("encode failed");
+    let replica_path = new_dir.path().join("replica");
+    fs::write(&replica_path, &replica).expect("failed to write replica");
+
+    let base_tree_nodes = nodes / tree_count;
+    let config = StoreConfig::new(
+        new_dir.path(),
+        CacheKey::CommRLastTree.to_string(),
+        default_rows_to_discard(base_tree_nodes, Tree::Arity::to_usize()),
+    );
+    let tree_r_last_config = StoreConfig::from_config(
+        &config,
+        CacheKey::CommRLastTree.to_string(),
+        Some(
+            get_merkle_tree_len(base_tree_nodes, Tree::Arity::to_usize())
+                .expect("invalid tree size"),
+        ),
+    );
+    let tree_r_last_new = EmptySectorUpdate::<Tree, Sha256Hasher>::generate_tree_r_last(
+        &replica,
+        tree_r_last_config,
+        replica_path,
+    )
+    .expect("failed to build tree_r_last");
+    let comm_r_new = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_new.root());
+
+    let pub_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new: tree_d_new.root(),
+        comm_r_new,
+        k: None,
+    };
+    let priv_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+        comm_c,
+        tree_r_last_old: &tree_r_last_old,
+        tree_r_last_new: &tree_r_last_new,
+        tree_d_new: &tree_d_new,
+    };
+
+    let proofs = EmptySectorUpdate::<Tree, Sha256Hasher>::prove_all_partitions(
+        &pub_params,
+        &pub_inputs,
+        &priv_inputs,
+        partitions,
+    )
+    .expect("proving failed");
+    let is_valid = EmptySectorUpdate::<Tree, Sha256Hasher>::verify_all_partitions(
+        &pub_params,
+        &pub_inputs,
+        &proofs,
+    )
+    .expect("verification failed");
+    assert!(is_valid);
+
+    let (expected_inputs, expected_constraints) = {
+        let mut cs = MetricCS::<Fr>::new();
+        EmptySectorUpdateCompound::<Tree, Sha256Hasher>::blank_circuit(&pub_params)
+            .synthesize(&mut cs)
+            .expect("failed to synthesize blank circuit");
+
+        (cs.num_inputs(), cs.num_constraints())
+    };
+
+    for (k, proof) in proofs.iter().enumerate() {
+        let partition_inputs =
+            EmptySectorUpdate::<Tree, Sha256Hasher>::with_partition(pub_inputs.clone(), Some(k));
+        let circuit = EmptySectorUpdateCompound::<Tree, Sha256Hasher>::circuit(
+            &partition_inputs,
+            (),
+            proof,
+            &pub_params,
+            Some(k),
+        )
+        .expect("failed to create circuit");
+
+        {
+            let mut cs = TestConstraintSystem::<Fr>::new();
+            circuit
+                .clone()
+                .synthesize(&mut cs)
+                .expect("failed to synthesize circuit");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(cs.num_inputs(), expected_inputs, "wrong number of inputs");
+            assert_eq!(
+                cs.num_constraints(),
+                expected_constraints,
+                "wrong number of constraints"
+            );
+            assert_eq!(cs.get_input(0, "ONE"), Fr::one());
+
+            let generated_inputs =
+                EmptySectorUpdateCompound::<Tree, Sha256Hasher>::generate_public_inputs(
+                    &partition_inputs,
+                    &pub_params,
+                    Some(k),
+                )
+                .expect("generate_public_inputs failure");
+            let expected_inputs = cs.get_inputs();
+
+            for ((input, label), generated_input) in
+                expected_inputs.iter().skip(1).zip(generated_inputs.iter())
+            {
+                assert_eq!(input, generated_input, "{}", label);
+            }
+            assert_eq!(
+                generated_inputs.len(),
+                expected_inputs.len() - 1,
+                "inputs are not the same length"
+            );
+            assert!(
+                cs.verify(&generated_inputs),
+                "verification failed with TestContraintSystem and generated inputs"
+            );
+        }
+
+        // A replica node which is not the encoding of the data node must be rejected.
+        let mut tampered = circuit;
+        tampered.challenges[0].data_leaf = Some(Fr::one());
+        let mut cs = TestConstraintSystem::<Fr>::new();
+        tampered
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+        assert!(
+            !cs.is_satisfied(),
+            "tampered data leaf satisfied constraints"
+        );
+    }
+}
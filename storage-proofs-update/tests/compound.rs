@@ -0,0 +1,183 @@
+use std::fs::{self, File};
+
+use bellperson::{
+    util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem},
+    Circuit,
+};
+use filecoin_hashers::{
+    poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain, HashFunction, Hasher,
+};
+use generic_array::typenum::{Unsigned, U0, U4, U8};
+use merkletree::{
+    merkle::get_merkle_tree_len,
+    store::{LevelCacheStore, StoreConfig},
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    compound_proof::{self, CompoundProof},
+    merkle::{
+        create_base_merkle_tree, generate_tree, get_base_tree_count, BinaryMerkleTree, LCTree,
+        MerkleTreeTrait,
+    },
+    proof::NoRequirements,
+    util::{default_rows_to_discard, NODE_SIZE},
+    TEST_SEED,
+};
+use storage_proofs_update::{
+    EmptySectorUpdate, EmptySectorUpdateCompound, PrivateInputs, PublicInputs, SetupParams,
+};
+use tempfile::tempdir;
+
+#[ignore]
+#[test]
+fn test_empty_sector_update_compound_base_8() {
+    empty_sector_update::<LCTree<PoseidonHasher, U8, U0, U0>>(1);
+    empty_sector_update::<LCTree<PoseidonHasher, U8, U0, U0>>(2);
+}
+
+#[ignore]
+#[test]
+fn test_empty_sector_update_compound_sub_8_4() {
+    empty_sector_update::<LCTree<PoseidonHasher, U8, U4, U0>>(2);
+}
+
+fn empty_sector_update<Tree>(partitions: usize)
+where
+    Tree: 'static
+        + MerkleTreeTrait<
+            Store = LevelCacheStore<<<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain, File>,
+        >,
+{
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let nodes = 64 * tree_count;
+
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params: SetupParams {
+            sector_size: (nodes * NODE_SIZE) as u64,
+            challenge_count: 2,
+        },
+        partitions: Some(partitions),
+        priority: false,
+    };
+    let pub_params = EmptySectorUpdateCompound::<Tree, Sha256Hasher>::setup(&setup_params)
+        .expect("setup failed");
+
+    let old_dir = tempdir().expect("tempdir failure");
+    let (sector_key, tree_r_last_old) =
+        generate_tree::<Tree, _>(rng, nodes, Some(old_dir.path().to_path_buf()));
+    let comm_c = <Tree::Hasher as Hasher>::Domain::random(rng);
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_old.root());
+
+    let data = (0..nodes)
+        .flat_map(|_| <Sha256Hasher as Hasher>::Domain::random(rng).into_bytes())
+        .collect::<Vec<u8>>();
+    let tree_d_new = create_base_merkle_tree::<BinaryMerkleTree<Sha256Hasher>>(None, nodes, &data)
+        .expect("failed to create tree_d");
+
+    let new_dir = tempdir().expect("tempdir failure");
+    let mut replica = vec![0u8; data.len()];
+    EmptySectorUpdate::<Tree, Sha256Hasher>::encode_into(&mut replica, &sector_key, &data)
+        .expect("encode failed");
+    let replica_path = new_dir.path().join("replica");
+    fs::write(&replica_path, &replica).expect("failed to write replica");
+
+    let base_tree_nodes = nodes / tree_count;
+    let config = StoreConfig::new(
+        new_dir.path(),
+        CacheKey::CommRLastTree.to_string(),
+        default_rows_to_discard(base_tree_nodes, Tree::Arity::to_usize()),
+    );
+    let tree_r_last_config = StoreConfig::from_config(
+        &config,
+        CacheKey::CommRLastTree.to_string(),
+        Some(
+            get_merkle_tree_len(base_tree_nodes, Tree::Arity::to_usize())
+                .expect("invalid tree size"),
+        ),
+    );
+    let tree_r_last_new = EmptySectorUpdate::<Tree, Sha256Hasher>::generate_tree_r_last(
+        &replica,
+        tree_r_last_config,
+        replica_path,
+    )
+    .expect("failed to build tree_r_last");
+    let comm_r_new = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_new.root());
+
+    let pub_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new: tree_d_new.root(),
+        comm_r_new,
+        k: None,
+    };
+    let priv_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+        comm_c,
+        tree_r_last_old: &tree_r_last_old,
+        tree_r_last_new: &tree_r_last_new,
+        tree_d_new: &tree_d_new,
+    };
+
+    // Use this to debug differences between blank and regular circuit generation.
+    {
+        let circuits =
+            EmptySectorUpdateCompound::circuit_for_test_all(&pub_params, &pub_inputs, &priv_inputs)
+                .expect("circuit_for_test_all failure");
+        let blank_circuit = EmptySectorUpdateCompound::<Tree, Sha256Hasher>::blank_circuit(
+            &pub_params.vanilla_params,
+        );
+
+        let mut cs_blank = MetricCS::new();
+        blank_circuit
+            .synthesize(&mut cs_blank)
+            .expect("failed to synthesize");
+
+        let a = cs_blank.pretty_print_list();
+
+        for (circuit, inputs) in circuits.into_iter() {
+            let mut cs = TestConstraintSystem::new();
+            circuit.synthesize(&mut cs).expect("failed to synthesize");
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert!(
+                cs.verify(&inputs),
+                "verification failed with TestContraintSystem and generated inputs"
+            );
+
+            let b = cs.pretty_print_list();
+            for (i, (a, b)) in a.chunks(100).zip(b.chunks(100)).enumerate() {
+                assert_eq!(a, b, "failed at chunk {}", i);
+            }
+        }
+    }
+
+    let blank_groth_params = EmptySectorUpdateCompound::<Tree, Sha256Hasher>::groth_params(
+        Some(rng),
+        &pub_params.vanilla_params,
+    )
+    .expect("failed to generate groth params");
+
+    let proof = EmptySectorUpdateCompound::prove(
+        &pub_params,
+        &pub_inputs,
+        &priv_inputs,
+        &blank_groth_params,
+    )
+    .expect("failed while proving");
+
+    let verified =
+        EmptySectorUpdateCompound::verify(&pub_params, &pub_inputs, &proof, &NoRequirements)
+            .expect("failed while verifying");
+    assert!(verified);
+
+    // The proof is bound to the new data commitment.
+    let wrong_inputs = PublicInputs {
+        comm_d_new: <Sha256Hasher as Hasher>::Domain::random(rng),
+        ..pub_inputs
+    };
+    let verified =
+        EmptySectorUpdateCompound::verify(&pub_params, &wrong_inputs, &proof, &NoRequirements)
+            .expect("failed while verifying");
+    assert!(!verified);
+}
@@ -0,0 +1,236 @@
+use std::fs::{self, File};
+use std::path::Path;
+
+use filecoin_hashers::{
+    poseidon::PoseidonHasher, sha256::Sha256Hasher, Domain, HashFunction, Hasher,
+};
+use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
+use merkletree::{
+    merkle::get_merkle_tree_len,
+    store::{LevelCacheStore, StoreConfig},
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::{
+    cache_key::CacheKey,
+    merkle::{
+        create_base_merkle_tree, generate_tree, get_base_tree_count, BinaryMerkleTree, LCTree,
+        MerkleTreeTrait,
+    },
+    proof::ProofScheme,
+    util::{default_rows_to_discard, NODE_SIZE},
+    TEST_SEED,
+};
+use storage_proofs_update::{EmptySectorUpdate, PrivateInputs, Proof, PublicInputs, SetupParams};
+use tempfile::tempdir;
+
+#[test]
+fn test_empty_sector_update_encode_decode() {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let nodes = 64;
+
+    let random_bytes = |rng: &mut XorShiftRng| {
+        (0..nodes)
+            .flat_map(|_| <PoseidonHasher as Hasher>::Domain::random(rng).into_bytes())
+            .collect::<Vec<u8>>()
+    };
+    let sector_key = random_bytes(rng);
+    let data = random_bytes(rng);
+
+    let mut replica = vec![0u8; nodes * NODE_SIZE];
+    EmptySectorUpdate::<LCTree<PoseidonHasher, U8, U0, U0>, Sha256Hasher>::encode_into(
+        &mut replica,
+        &sector_key,
+        &data,
+    )
+    .expect("encode failed");
+    assert_ne!(replica, data);
+    assert_ne!(replica, sector_key);
+
+    let mut decoded = vec![0u8; nodes * NODE_SIZE];
+    EmptySectorUpdate::<LCTree<PoseidonHasher, U8, U0, U0>, Sha256Hasher>::decode_from(
+        &mut decoded,
+        &sector_key,
+        &replica,
+    )
+    .expect("decode failed");
+    assert_eq!(decoded, data);
+
+    assert!(
+        EmptySectorUpdate::<LCTree<PoseidonHasher, U8, U0, U0>, Sha256Hasher>::encode_into(
+            &mut replica[NODE_SIZE..],
+            &sector_key,
+            &data,
+        )
+        .is_err(),
+        "mismatching sizes must be rejected"
+    );
+}
+
+#[test]
+fn test_empty_sector_update_base_8() {
+    test_empty_sector_update::<LCTree<PoseidonHasher, U8, U0, U0>>(1);
+    test_empty_sector_update::<LCTree<PoseidonHasher, U8, U0, U0>>(2);
+}
+
+#[test]
+fn test_empty_sector_update_sub_8_4() {
+    test_empty_sector_update::<LCTree<PoseidonHasher, U8, U4, U0>>(1);
+}
+
+#[test]
+fn test_empty_sector_update_top_8_4_2() {
+    test_empty_sector_update::<LCTree<PoseidonHasher, U8, U4, U2>>(2);
+}
+
+fn test_empty_sector_update<Tree>(partitions: usize)
+where
+    Tree: 'static
+        + MerkleTreeTrait<
+            Store = LevelCacheStore<<<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain, File>,
+        >,
+{
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let nodes = 64 * tree_count;
+    let sector_size = (nodes * NODE_SIZE) as u64;
+
+    let setup_params = SetupParams {
+        sector_size,
+        challenge_count: 5,
+    };
+    let pub_params =
+        EmptySectorUpdate::<Tree, Sha256Hasher>::setup(&setup_params).expect("setup failed");
+
+    // The committed capacity sector, whose replica is the sector key.
+    let old_dir = tempdir().expect("tempdir failure");
+    let (sector_key, tree_r_last_old) =
+        generate_tree::<Tree, _>(rng, nodes, Some(old_dir.path().to_path_buf()));
+    let comm_c = <Tree::Hasher as Hasher>::Domain::random(rng);
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_old.root());
+
+    // The new data and its tree_d.
+    let data = (0..nodes)
+        .flat_map(|_| <Sha256Hasher as Hasher>::Domain::random(rng).into_bytes())
+        .collect::<Vec<u8>>();
+    let tree_d_new = create_base_merkle_tree::<BinaryMerkleTree<Sha256Hasher>>(None, nodes, &data)
+        .expect("failed to create tree_d");
+    let comm_d_new = tree_d_new.root();
+
+    // Encode the data into the sector key and build tree_r_last over the updated replica.
+    let new_dir = tempdir().expect("tempdir failure");
+    let mut replica = vec![0u8; data.len()];
+    EmptySectorUpdate::<Tree, Sha256Hasher>::encode_into(&mut replica, &sector_key, &data)
+        .expect("encode failed");
+    let tree_r_last_new = build_tree_r_last::<Tree>(new_dir.path(), &replica);
+    let comm_r_new = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_new.root());
+
+    let priv_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+        comm_c,
+        tree_r_last_old: &tree_r_last_old,
+        tree_r_last_new: &tree_r_last_new,
+        tree_d_new: &tree_d_new,
+    };
+
+    for k in 0..partitions {
+        let pub_inputs = PublicInputs {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            k: Some(k),
+        };
+
+        let proof =
+            EmptySectorUpdate::<Tree, Sha256Hasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+                .expect("proving failed");
+        assert_eq!(proof.challenge_proofs.len(), pub_params.challenge_count);
+
+        let is_valid =
+            EmptySectorUpdate::<Tree, Sha256Hasher>::verify(&pub_params, &pub_inputs, &proof)
+                .expect("verification failed");
+        assert!(is_valid, "valid proof for partition {} rejected", k);
+
+        // The proof must not verify for another data commitment.
+        let wrong_inputs = PublicInputs {
+            comm_d_new: <Sha256Hasher as Hasher>::Domain::random(rng),
+            ..pub_inputs.clone()
+        };
+        let is_valid =
+            EmptySectorUpdate::<Tree, Sha256Hasher>::verify(&pub_params, &wrong_inputs, &proof)
+                .expect("verification failed");
+        assert!(!is_valid, "proof for the wrong comm_d_new accepted");
+
+        // Nor for another partition, as the challenges differ.
+        let other_partition = PublicInputs {
+            k: Some(k + 1),
+            ..pub_inputs
+        };
+        let is_valid =
+            EmptySectorUpdate::<Tree, Sha256Hasher>::verify(&pub_params, &other_partition, &proof)
+                .expect("verification failed");
+        assert!(!is_valid, "proof for the wrong partition accepted");
+    }
+
+    // A proof without challenge proofs, as it could be deserialized, is rejected.
+    let empty_proof = Proof::<Tree, Sha256Hasher> {
+        comm_c,
+        challenge_proofs: Vec::new(),
+    };
+    assert!(empty_proof.comm_r_last_old().is_err());
+    assert!(empty_proof.comm_r_last_new().is_err());
+    let pub_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new,
+        comm_r_new,
+        k: None,
+    };
+    assert!(EmptySectorUpdate::<Tree, Sha256Hasher>::verify(
+        &pub_params,
+        &pub_inputs,
+        &empty_proof
+    )
+    .is_err());
+
+    // Proving must fail if the sector key was not used to encode the replica.
+    let pub_inputs = PublicInputs {
+        comm_r_old,
+        comm_d_new,
+        comm_r_new: <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_old.root()),
+        k: None,
+    };
+    assert!(
+        EmptySectorUpdate::<Tree, Sha256Hasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+            .is_err()
+    );
+}
+
+fn build_tree_r_last<Tree: 'static + MerkleTreeTrait>(
+    cache_dir: &Path,
+    replica: &[u8],
+) -> LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity> {
+    let base_tree_nodes = replica.len() / NODE_SIZE / get_base_tree_count::<Tree>();
+    let replica_path = cache_dir.join("replica");
+    fs::write(&replica_path, replica).expect("failed to write replica");
+
+    let config = StoreConfig::new(
+        cache_dir,
+        CacheKey::CommRLastTree.to_string(),
+        default_rows_to_discard(base_tree_nodes, Tree::Arity::to_usize()),
+    );
+    let tree_r_last_config = StoreConfig::from_config(
+        &config,
+        CacheKey::CommRLastTree.to_string(),
+        Some(
+            get_merkle_tree_len(base_tree_nodes, Tree::Arity::to_usize())
+                .expect("invalid tree size"),
+        ),
+    );
+
+    EmptySectorUpdate::<Tree, Sha256Hasher>::generate_tree_r_last(
+        replica,
+        tree_r_last_config,
+        replica_path,
+    )
+    .expect("failed to build tree_r_last")
+}